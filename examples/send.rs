@@ -1,4 +1,4 @@
-use coremidi::{Client, Destination, Destinations, EventBuffer, Protocol};
+use coremidi::{Client, Destination, Destinations, EventBuffer, Protocol, Timestamp};
 use std::env;
 use std::thread;
 use std::time::Duration;
@@ -16,9 +16,9 @@ fn main() {
     let client = Client::new("Example Client").unwrap();
     let output_port = client.output_port("Example Port").unwrap();
 
-    let note_on = EventBuffer::new(Protocol::Midi10).with_packet(0, &[0x2090407f]);
+    let note_on = EventBuffer::new(Protocol::Midi10).with_packet(Timestamp::Now, &[0x2090407f]);
 
-    let note_off = EventBuffer::new(Protocol::Midi10).with_packet(0, &[0x2080407f]);
+    let note_off = EventBuffer::new(Protocol::Midi10).with_packet(Timestamp::Now, &[0x2080407f]);
 
     for i in 0..10 {
         println!("[{}] Sending note ...", i);
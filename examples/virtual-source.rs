@@ -1,4 +1,4 @@
-use coremidi::{Client, PacketBuffer};
+use coremidi::{Client, PacketBuffer, Timestamp};
 use std::thread;
 use std::time::Duration;
 
@@ -22,10 +22,10 @@ fn main() {
 
 fn create_note_on(channel: u8, note: u8, velocity: u8) -> PacketBuffer {
     let data = &[0x90 | (channel & 0x0f), note & 0x7f, velocity & 0x7f];
-    PacketBuffer::new(0, data)
+    PacketBuffer::new(Timestamp::Now, data)
 }
 
 fn create_note_off(channel: u8, note: u8, velocity: u8) -> PacketBuffer {
     let data = &[0x80 | (channel & 0x0f), note & 0x7f, velocity & 0x7f];
-    PacketBuffer::new(0, data)
+    PacketBuffer::new(Timestamp::Now, data)
 }
@@ -0,0 +1,755 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Mutex};
+
+use coremidi_sys::{MIDIProtocolID, OSStatus};
+
+use crate::client::Client;
+use crate::endpoints::sources::Source;
+use crate::events::{EventList, Timestamp};
+use crate::host_time::timebase;
+use crate::packets::PacketList;
+use crate::ports::InputPortWithContext;
+use crate::protocol::Protocol;
+
+const MAGIC: &[u8; 8] = b"MIDICAPT";
+const VERSION: u16 = 1;
+
+const SOURCE_RECORD: u8 = 1;
+const PACKET_RECORD: u8 = 2;
+const EVENT_RECORD: u8 = 3;
+
+/// A source of captured traffic, identified by its unique ID.
+///
+/// It is used as the context of the [InputPortWithContext] created by a [CaptureRecorder],
+/// so every received list is tagged with the source it came from:
+///
+/// ```rust,no_run
+/// use coremidi::{CaptureRecorder, CaptureSource, Client, Protocol, Sources};
+/// let client = Client::new("example-client").unwrap();
+/// let recorder = CaptureRecorder::new();
+/// let mut input_port = recorder.input_port(&client, "capture", Protocol::Midi20).unwrap();
+/// for source in Sources {
+///     let context = CaptureSource::from(&source);
+///     input_port.connect_source(&source, context).unwrap();
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CaptureSource {
+    pub unique_id: u32,
+    pub name: String,
+}
+
+impl CaptureSource {
+    pub fn new(unique_id: u32, name: &str) -> Self {
+        Self {
+            unique_id,
+            name: name.to_string(),
+        }
+    }
+}
+
+impl From<&Source> for CaptureSource {
+    fn from(source: &Source) -> Self {
+        Self {
+            unique_id: source.unique_id().unwrap_or(0),
+            name: source.display_name().unwrap_or_default(),
+        }
+    }
+}
+
+/// The data of a captured packet.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CaptureData {
+    /// The bytes of a packet from a [PacketList].
+    Packet(Vec<u8>),
+    /// The Universal MIDI Packet words of a packet from an [EventList].
+    Event { protocol: Protocol, words: Vec<u32> },
+}
+
+/// A captured packet, with the unique ID of its source and its host timestamp.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaptureRecord {
+    pub source: u32,
+    pub timestamp: Timestamp,
+    pub data: CaptureData,
+}
+
+/// The traffic captured from a set of sources.
+///
+/// It can be saved and loaded as a capture file, made of a header and a sequence of
+/// records, with every integer stored in little endian:
+///
+/// - The header is the `MIDICAPT` magic, the version of the format (`u16`, currently 1),
+///   and the host timebase of the recording machine, as the numerator and denominator
+///   (`u32` each) of the fraction that converts host time ticks into nanoseconds.
+/// - A source record (kind 1) declares the display name of a source, and comes before
+///   the first packet of the source: the unique ID of the source (`u32`), the length of
+///   the name (`u16`) and the name in UTF-8. A later record with the same unique ID
+///   renames the source.
+/// - A packet record (kind 2) holds a packet of a [PacketList]: the unique ID of its source
+///   (`u32`), its host timestamp (`u64`), the number of bytes (`u16`) and the bytes.
+/// - An event record (kind 3) holds a packet of an [EventList]: the unique ID of its source
+///   (`u32`), its host timestamp (`u64`), the MIDI protocol ID (`u8`), the number of words
+///   (`u16`) and the words (`u32` each).
+///
+/// Each record starts with its kind as a byte. Sources without a unique ID use zero.
+///
+/// ```
+/// use coremidi::{Capture, CaptureData, CaptureSource, EventBuffer, Protocol, Timestamp};
+/// let mut capture = Capture::new();
+/// let source = CaptureSource::new(42, "Keyboard");
+/// let events = EventBuffer::new(Protocol::Midi20).with_packet(Timestamp::from_host_time(1000), &[0x40903c00, 0xffff0000]);
+/// capture.record_event_list(&source, &events);
+///
+/// let mut file = Vec::new();
+/// capture.write_to(&mut file).unwrap();
+/// let capture = Capture::read_from(file.as_slice()).unwrap();
+/// let record = &capture.records()[0];
+/// assert_eq!(capture.source(record.source), Some(&source));
+/// assert_eq!(record.data, CaptureData::Event { protocol: Protocol::Midi20, words: vec![0x40903c00, 0xffff0000] });
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capture {
+    timebase: (u32, u32),
+    sources: Vec<CaptureSource>,
+    records: Vec<CaptureRecord>,
+}
+
+impl Capture {
+    /// Create an empty capture with the timebase of this machine.
+    ///
+    pub fn new() -> Self {
+        Self {
+            timebase: timebase(),
+            sources: Vec::new(),
+            records: Vec::new(),
+        }
+    }
+
+    /// Get the fraction that converts the host time ticks of the timestamps into nanoseconds.
+    ///
+    pub fn timebase(&self) -> (u32, u32) {
+        self.timebase
+    }
+
+    /// Convert a timestamp of the capture into nanoseconds, using its timebase.
+    ///
+    pub fn nanos(&self, timestamp: Timestamp) -> u64 {
+        let (numer, denom) = self.timebase;
+        (timestamp.host_time() as u128 * numer as u128 / denom.max(1) as u128) as u64
+    }
+
+    /// Get the sources with captured traffic, in order of appearance.
+    ///
+    pub fn sources(&self) -> &[CaptureSource] {
+        &self.sources
+    }
+
+    /// Find a source by its unique ID.
+    ///
+    pub fn source(&self, unique_id: u32) -> Option<&CaptureSource> {
+        self.sources
+            .iter()
+            .find(|source| source.unique_id == unique_id)
+    }
+
+    /// Get the captured packets, in order of arrival.
+    ///
+    pub fn records(&self) -> &[CaptureRecord] {
+        &self.records
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Add the packets of a [PacketList] received from a source.
+    ///
+    pub fn record_packet_list(&mut self, source: &CaptureSource, packet_list: &PacketList) {
+        self.add_source(source);
+        for packet in packet_list.iter() {
+            self.records.push(CaptureRecord {
+                source: source.unique_id,
+                timestamp: packet.timestamp(),
+                data: CaptureData::Packet(packet.data().to_vec()),
+            });
+        }
+    }
+
+    /// Add the packets of an [EventList] received from a source.
+    ///
+    pub fn record_event_list(&mut self, source: &CaptureSource, event_list: &EventList) {
+        self.add_source(source);
+        let protocol = event_list.protocol();
+        for packet in event_list.iter() {
+            self.records.push(CaptureRecord {
+                source: source.unique_id,
+                timestamp: packet.timestamp(),
+                data: CaptureData::Event {
+                    protocol,
+                    words: packet.data().to_vec(),
+                },
+            });
+        }
+    }
+
+    /// Remove all the captured packets and sources.
+    ///
+    pub fn clear(&mut self) {
+        self.sources.clear();
+        self.records.clear();
+    }
+
+    /// Read a whole capture file.
+    ///
+    pub fn read_from<R: Read>(reader: R) -> Result<Self, CaptureError> {
+        let mut reader = CaptureReader::new(reader)?;
+        let mut records = Vec::new();
+        for record in &mut reader {
+            records.push(record?);
+        }
+        Ok(Self {
+            timebase: reader.timebase(),
+            sources: reader.sources,
+            records,
+        })
+    }
+
+    /// Write the capture as a capture file.
+    ///
+    pub fn write_to<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut writer = CaptureWriter::with_timebase(writer, self.timebase)?;
+        for source in &self.sources {
+            writer.write_source(source)?;
+        }
+        for record in &self.records {
+            writer.write_record(record)?;
+        }
+        writer.into_inner().flush()
+    }
+
+    fn add_source(&mut self, source: &CaptureSource) {
+        match self
+            .sources
+            .iter_mut()
+            .find(|known| known.unique_id == source.unique_id)
+        {
+            Some(known) if known.name != source.name => known.name = source.name.clone(),
+            Some(_) => {}
+            None => self.sources.push(source.clone()),
+        }
+    }
+}
+
+impl Default for Capture {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Records the traffic received by an input port into a shared [Capture].
+///
+/// The recorder can be cloned into the callbacks of other ports, such as the ones
+/// receiving [PacketList]s, to capture their traffic too.
+///
+/// ```rust,no_run
+/// use coremidi::{CaptureRecorder, CaptureSource, Client, Protocol, Source};
+/// let client = Client::new("example-client").unwrap();
+/// let recorder = CaptureRecorder::new();
+/// let mut input_port = recorder.input_port(&client, "capture", Protocol::Midi10).unwrap();
+/// let source = Source::from_index(0).unwrap();
+/// input_port.connect_source(&source, CaptureSource::from(&source)).unwrap();
+/// std::thread::sleep(std::time::Duration::from_secs(10));
+/// let file = std::fs::File::create("session.midicapt").unwrap();
+/// recorder.take().write_to(file).unwrap();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CaptureRecorder {
+    capture: Arc<Mutex<Capture>>,
+}
+
+impl CaptureRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an input port that records the [EventList]s received from the sources
+    /// connected to it, tagged with their context.
+    ///
+    pub fn input_port(
+        &self,
+        client: &Client,
+        name: &str,
+        protocol: Protocol,
+    ) -> Result<InputPortWithContext<CaptureSource>, OSStatus> {
+        let recorder = self.clone();
+        client.input_port_with_protocol(name, protocol, move |event_list, source| {
+            recorder.record_event_list(source, event_list)
+        })
+    }
+
+    /// Record the packets of a [PacketList] received from a source.
+    ///
+    pub fn record_packet_list(&self, source: &CaptureSource, packet_list: &PacketList) {
+        if let Ok(mut capture) = self.capture.lock() {
+            capture.record_packet_list(source, packet_list);
+        }
+    }
+
+    /// Record the packets of an [EventList] received from a source.
+    ///
+    pub fn record_event_list(&self, source: &CaptureSource, event_list: &EventList) {
+        if let Ok(mut capture) = self.capture.lock() {
+            capture.record_event_list(source, event_list);
+        }
+    }
+
+    /// Get a copy of the traffic recorded so far.
+    ///
+    pub fn capture(&self) -> Capture {
+        self.capture
+            .lock()
+            .map(|capture| capture.clone())
+            .unwrap_or_default()
+    }
+
+    /// Take the traffic recorded so far, starting a new capture.
+    ///
+    pub fn take(&self) -> Capture {
+        self.capture
+            .lock()
+            .map(|mut capture| std::mem::take(&mut *capture))
+            .unwrap_or_default()
+    }
+}
+
+/// Writes a capture file record by record, declaring each source before its first packet.
+///
+#[derive(Debug)]
+pub struct CaptureWriter<W: Write> {
+    writer: W,
+    sources: HashMap<u32, String>,
+}
+
+impl<W: Write> CaptureWriter<W> {
+    /// Start a capture file with the timebase of this machine.
+    ///
+    pub fn new(writer: W) -> io::Result<Self> {
+        Self::with_timebase(writer, timebase())
+    }
+
+    /// Start a capture file with the timebase of the machine where it was recorded.
+    ///
+    pub fn with_timebase(mut writer: W, timebase: (u32, u32)) -> io::Result<Self> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&timebase.0.to_le_bytes())?;
+        writer.write_all(&timebase.1.to_le_bytes())?;
+        Ok(Self {
+            writer,
+            sources: HashMap::new(),
+        })
+    }
+
+    /// Declare the name of a source, unless it was already declared with the same name.
+    ///
+    pub fn write_source(&mut self, source: &CaptureSource) -> io::Result<()> {
+        if self.sources.get(&source.unique_id) == Some(&source.name) {
+            return Ok(());
+        }
+        let name = truncate_utf8(&source.name, u16::MAX as usize);
+        self.writer.write_all(&[SOURCE_RECORD])?;
+        self.writer.write_all(&source.unique_id.to_le_bytes())?;
+        self.writer.write_all(&(name.len() as u16).to_le_bytes())?;
+        self.writer.write_all(name.as_bytes())?;
+        self.sources.insert(source.unique_id, source.name.clone());
+        Ok(())
+    }
+
+    /// Write a captured packet, declaring its source first if needed, without a name.
+    ///
+    pub fn write_record(&mut self, record: &CaptureRecord) -> io::Result<()> {
+        if !self.sources.contains_key(&record.source) {
+            self.write_source(&CaptureSource::new(record.source, ""))?;
+        }
+        let kind = match record.data {
+            CaptureData::Packet(_) => PACKET_RECORD,
+            CaptureData::Event { .. } => EVENT_RECORD,
+        };
+        self.writer.write_all(&[kind])?;
+        self.writer.write_all(&record.source.to_le_bytes())?;
+        self.writer
+            .write_all(&record.timestamp.host_time().to_le_bytes())?;
+        match &record.data {
+            CaptureData::Packet(data) => {
+                let data = &data[..data.len().min(u16::MAX as usize)];
+                self.writer.write_all(&(data.len() as u16).to_le_bytes())?;
+                self.writer.write_all(data)
+            }
+            CaptureData::Event { protocol, words } => {
+                let words = &words[..words.len().min(u16::MAX as usize)];
+                self.writer
+                    .write_all(&[MIDIProtocolID::from(*protocol) as u8])?;
+                self.writer.write_all(&(words.len() as u16).to_le_bytes())?;
+                for word in words {
+                    self.writer.write_all(&word.to_le_bytes())?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Reads a capture file record by record, keeping track of the declared sources.
+///
+/// ```
+/// use coremidi::{Capture, CaptureReader, CaptureSource, PacketBuffer, Timestamp};
+/// let mut capture = Capture::new();
+/// let packets = PacketBuffer::new(Timestamp::from_host_time(1000), &[0x90, 60, 100]);
+/// capture.record_packet_list(&CaptureSource::new(7, "Pads"), &packets);
+/// let mut file = Vec::new();
+/// capture.write_to(&mut file).unwrap();
+///
+/// let mut reader = CaptureReader::new(file.as_slice()).unwrap();
+/// let record = reader.next().unwrap().unwrap();
+/// assert_eq!(reader.source(record.source).unwrap().name, "Pads");
+/// assert!(reader.next().is_none());
+/// ```
+#[derive(Debug)]
+pub struct CaptureReader<R: Read> {
+    reader: R,
+    timebase: (u32, u32),
+    sources: Vec<CaptureSource>,
+    failed: bool,
+}
+
+impl<R: Read> CaptureReader<R> {
+    /// Start reading a capture file, checking its header.
+    ///
+    pub fn new(mut reader: R) -> Result<Self, CaptureError> {
+        let mut magic = [0; 8];
+        read_exact(&mut reader, &mut magic)?;
+        if &magic != MAGIC {
+            return Err(CaptureError::InvalidHeader);
+        }
+        let version = u16::from_le_bytes(read_array(&mut reader)?);
+        if version != VERSION {
+            return Err(CaptureError::UnsupportedVersion(version));
+        }
+        let numer = u32::from_le_bytes(read_array(&mut reader)?);
+        let denom = u32::from_le_bytes(read_array(&mut reader)?);
+        if numer == 0 || denom == 0 {
+            return Err(CaptureError::InvalidHeader);
+        }
+        Ok(Self {
+            reader,
+            timebase: (numer, denom),
+            sources: Vec::new(),
+            failed: false,
+        })
+    }
+
+    /// Get the fraction that converts the host time ticks of the timestamps into nanoseconds.
+    ///
+    pub fn timebase(&self) -> (u32, u32) {
+        self.timebase
+    }
+
+    /// Get the sources declared so far.
+    ///
+    pub fn sources(&self) -> &[CaptureSource] {
+        &self.sources
+    }
+
+    /// Find a source declared so far by its unique ID.
+    ///
+    pub fn source(&self, unique_id: u32) -> Option<&CaptureSource> {
+        self.sources
+            .iter()
+            .find(|source| source.unique_id == unique_id)
+    }
+
+    fn read_record(&mut self) -> Result<Option<CaptureRecord>, CaptureError> {
+        loop {
+            let mut kind = [0];
+            if self.reader.read(&mut kind)? == 0 {
+                return Ok(None);
+            }
+            let source = u32::from_le_bytes(read_array(&mut self.reader)?);
+            match kind[0] {
+                SOURCE_RECORD => {
+                    let length = u16::from_le_bytes(read_array(&mut self.reader)?) as usize;
+                    let mut name = vec![0; length];
+                    read_exact(&mut self.reader, &mut name)?;
+                    let name = String::from_utf8(name).map_err(|_| CaptureError::InvalidName)?;
+                    let source = CaptureSource {
+                        unique_id: source,
+                        name,
+                    };
+                    match self
+                        .sources
+                        .iter_mut()
+                        .find(|known| known.unique_id == source.unique_id)
+                    {
+                        Some(known) => *known = source,
+                        None => self.sources.push(source),
+                    }
+                }
+                PACKET_RECORD => {
+                    let timestamp = u64::from_le_bytes(read_array(&mut self.reader)?);
+                    let length = u16::from_le_bytes(read_array(&mut self.reader)?) as usize;
+                    let mut data = vec![0; length];
+                    read_exact(&mut self.reader, &mut data)?;
+                    return Ok(Some(CaptureRecord {
+                        source,
+                        timestamp: Timestamp::from_host_time(timestamp),
+                        data: CaptureData::Packet(data),
+                    }));
+                }
+                EVENT_RECORD => {
+                    let timestamp = u64::from_le_bytes(read_array(&mut self.reader)?);
+                    let [protocol] = read_array(&mut self.reader)?;
+                    let length = u16::from_le_bytes(read_array(&mut self.reader)?) as usize;
+                    let mut words = Vec::with_capacity(length);
+                    for _ in 0..length {
+                        words.push(u32::from_le_bytes(read_array(&mut self.reader)?));
+                    }
+                    return Ok(Some(CaptureRecord {
+                        source,
+                        timestamp: Timestamp::from_host_time(timestamp),
+                        data: CaptureData::Event {
+                            protocol: Protocol::from(protocol as MIDIProtocolID),
+                            words,
+                        },
+                    }));
+                }
+                kind => return Err(CaptureError::InvalidRecord(kind)),
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = Result<CaptureRecord, CaptureError>;
+
+    /// Read the next packet, stopping after the first error.
+    ///
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let result = self.read_record().transpose();
+        self.failed = matches!(result, Some(Err(_)));
+        result
+    }
+}
+
+/// An error reading a capture file.
+///
+#[derive(Debug)]
+pub enum CaptureError {
+    /// The file does not start with a valid capture header.
+    InvalidHeader,
+    /// The file was written with a version of the format not supported.
+    UnsupportedVersion(u16),
+    /// The file contains a record of an unknown kind.
+    InvalidRecord(u8),
+    /// The name of a source is not valid UTF-8.
+    InvalidName,
+    /// The file ended in the middle of a record.
+    UnexpectedEnd,
+    Io(io::Error),
+}
+
+impl fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidHeader => write!(f, "invalid capture file header"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported capture file version {}", version)
+            }
+            Self::InvalidRecord(kind) => write!(f, "invalid capture record kind {}", kind),
+            Self::InvalidName => write!(f, "invalid source name"),
+            Self::UnexpectedEnd => write!(f, "unexpected end of capture file"),
+            Self::Io(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for CaptureError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for CaptureError {
+    fn from(error: io::Error) -> Self {
+        if error.kind() == io::ErrorKind::UnexpectedEof {
+            Self::UnexpectedEnd
+        } else {
+            Self::Io(error)
+        }
+    }
+}
+
+fn read_exact<R: Read>(reader: &mut R, buffer: &mut [u8]) -> Result<(), CaptureError> {
+    Ok(reader.read_exact(buffer)?)
+}
+
+fn read_array<R: Read, const N: usize>(reader: &mut R) -> Result<[u8; N], CaptureError> {
+    let mut array = [0; N];
+    read_exact(reader, &mut array)?;
+    Ok(array)
+}
+
+fn truncate_utf8(text: &str, max_len: usize) -> &str {
+    let mut end = text.len().min(max_len);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::EventBuffer;
+    use crate::packets::PacketBuffer;
+
+    fn at(host_time: u64) -> Timestamp {
+        Timestamp::from_host_time(host_time)
+    }
+
+    fn capture() -> Capture {
+        let keyboard = CaptureSource::new(0x1234_5678, "Keyboard");
+        let pads = CaptureSource::new(7, "Pads é");
+        let mut capture = Capture::new();
+        let mut packets = PacketBuffer::new(at(10), &[0x90, 60, 100]);
+        packets.push_data(at(20), &[0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7]);
+        capture.record_packet_list(&keyboard, &packets);
+        let events = EventBuffer::new(Protocol::Midi20)
+            .with_packet(at(15), &[0x40903c00, 0xffff0000])
+            .with_packet(Timestamp::Now, &[0x10f80000]);
+        capture.record_event_list(&pads, &events);
+        capture
+    }
+
+    #[test]
+    fn record_lists() {
+        let capture = capture();
+        assert_eq!(capture.sources().len(), 2);
+        assert_eq!(capture.source(7).unwrap().name, "Pads é");
+        let records = capture.records();
+        assert_eq!(records.len(), 4);
+        assert_eq!(
+            records[1],
+            CaptureRecord {
+                source: 0x1234_5678,
+                timestamp: at(20),
+                data: CaptureData::Packet(vec![0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7]),
+            }
+        );
+        assert_eq!(
+            records[3],
+            CaptureRecord {
+                source: 7,
+                timestamp: Timestamp::Now,
+                data: CaptureData::Event {
+                    protocol: Protocol::Midi20,
+                    words: vec![0x10f80000],
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn file_round_trip() {
+        let capture = capture();
+        let mut file = Vec::new();
+        capture.write_to(&mut file).unwrap();
+        assert_eq!(&file[..8], b"MIDICAPT");
+        assert_eq!(&file[8..10], &[1, 0]);
+        assert_eq!(Capture::read_from(file.as_slice()).unwrap(), capture);
+    }
+
+    #[test]
+    fn writer_declares_sources() {
+        let mut writer = CaptureWriter::with_timebase(Vec::new(), (125, 3)).unwrap();
+        let record = CaptureRecord {
+            source: 3,
+            timestamp: at(24),
+            data: CaptureData::Packet(vec![0xF8]),
+        };
+        writer.write_record(&record).unwrap();
+        writer
+            .write_source(&CaptureSource::new(3, "Clock"))
+            .unwrap();
+        writer.write_record(&record).unwrap();
+        let file = writer.into_inner();
+
+        let mut reader = CaptureReader::new(file.as_slice()).unwrap();
+        assert_eq!(reader.timebase(), (125, 3));
+        assert_eq!(reader.next().unwrap().unwrap(), record);
+        assert_eq!(reader.source(3).unwrap().name, "");
+        assert_eq!(reader.next().unwrap().unwrap(), record);
+        assert_eq!(reader.source(3).unwrap().name, "Clock");
+        assert!(reader.next().is_none());
+
+        let capture = Capture::read_from(file.as_slice()).unwrap();
+        assert_eq!(capture.nanos(record.timestamp), 1000);
+    }
+
+    #[test]
+    fn invalid_files() {
+        let mut file = Vec::new();
+        capture().write_to(&mut file).unwrap();
+
+        assert!(matches!(
+            Capture::read_from(&file[1..]),
+            Err(CaptureError::InvalidHeader)
+        ));
+        let mut version = file.clone();
+        version[8] = 2;
+        assert!(matches!(
+            Capture::read_from(version.as_slice()),
+            Err(CaptureError::UnsupportedVersion(2))
+        ));
+        assert!(matches!(
+            Capture::read_from(&file[..file.len() - 1]),
+            Err(CaptureError::UnexpectedEnd)
+        ));
+        let mut kind = file[..18].to_vec();
+        kind.extend_from_slice(&[9, 0, 0, 0, 0]);
+        let mut reader = CaptureReader::new(kind.as_slice()).unwrap();
+        assert!(matches!(
+            reader.next(),
+            Some(Err(CaptureError::InvalidRecord(9)))
+        ));
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn recorder() {
+        let recorder = CaptureRecorder::new();
+        let source = CaptureSource::new(1, "Input");
+        let callback = {
+            let recorder = recorder.clone();
+            move |packet_list: &PacketList| recorder.record_packet_list(&source, packet_list)
+        };
+        callback(&PacketBuffer::new(at(5), &[0xFA]));
+        assert_eq!(recorder.capture().records().len(), 1);
+        assert_eq!(recorder.take().records().len(), 1);
+        assert!(recorder.capture().is_empty());
+    }
+}
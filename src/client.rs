@@ -4,6 +4,7 @@ use core_foundation::{
     string::CFString,
 };
 use std::cell::RefCell;
+use std::sync::Arc;
 use std::{mem::MaybeUninit, ops::Deref, os::raw::c_void, ptr};
 
 use coremidi_sys::{
@@ -13,13 +14,15 @@ use coremidi_sys::{
     MIDIPacketList, MIDIReadBlock, MIDIReceiveBlock, MIDISourceCreate,
 };
 
-use crate::ports::InputPortWithContext;
+use crate::panics::{ClientPanics, PanicGuard, PanicPolicy};
+use crate::ports::{context_callback, Contexts, InputPortWithContext};
+use crate::queue::{ump_queue, UmpReceiver};
 use crate::{
     endpoints::{destinations::VirtualDestination, sources::VirtualSource},
     notifications::Notification,
     object::Object,
     packets::PacketList,
-    ports::{InputPort, OutputPort},
+    ports::{InputPort, OutputPort, TaggedInputPort},
     result_from_status, EventList, Protocol,
 };
 
@@ -62,9 +65,14 @@ where
 /// ```rust,no_run
 /// let client = coremidi::Client::new("example-client").unwrap();
 /// ```
+///
+/// Panics in the callbacks given to a client are caught before reaching CoreMIDI,
+/// and handled according to its [PanicPolicy].
+///
 #[derive(Debug)]
 pub struct Client {
     object: Object,
+    panics: Arc<ClientPanics>,
 }
 
 impl Client {
@@ -84,7 +92,9 @@ impl Client {
     {
         let client_name = CFString::new(name);
         let mut client_ref = MaybeUninit::uninit();
-        let notify_block = Self::notify_block(callback.into());
+        let panics = Arc::new(ClientPanics::default());
+        let guard = PanicGuard::new("notification", panics.clone());
+        let notify_block = Self::notify_block(callback.into(), guard);
         let status = unsafe {
             MIDIClientCreateWithBlock(
                 client_name.as_concrete_TypeRef(),
@@ -96,6 +106,7 @@ impl Client {
             let client_ref = unsafe { client_ref.assume_init() };
             Client {
                 object: Object(client_ref),
+                panics,
             }
         })
     }
@@ -118,10 +129,41 @@ impl Client {
             let client_ref = unsafe { client_ref.assume_init() };
             Client {
                 object: Object(client_ref),
+                panics: Arc::new(ClientPanics::default()),
             }
         })
     }
 
+    /// Set what to do when any of the callbacks of this client panics,
+    /// including the ones of ports and virtual destinations already created.
+    ///
+    /// By default the panic is reported to stderr and the callback keeps being called.
+    ///
+    /// ```rust,no_run
+    /// use coremidi::{Client, PanicPolicy};
+    /// let client = Client::new("example-client").unwrap();
+    /// client.set_panic_policy(PanicPolicy::Poison);
+    /// ```
+    pub fn set_panic_policy(&self, policy: PanicPolicy) {
+        self.panics.set_policy(policy);
+    }
+
+    /// Get what is done when any of the callbacks of this client panics.
+    ///
+    pub fn panic_policy(&self) -> PanicPolicy {
+        self.panics.policy()
+    }
+
+    /// Get the number of panics caught in all the callbacks of this client.
+    ///
+    pub fn panic_count(&self) -> u64 {
+        self.panics.count()
+    }
+
+    fn panic_guard(&self, name: &'static str) -> Arc<PanicGuard> {
+        Arc::new(PanicGuard::new(name, self.panics.clone()))
+    }
+
     /// Creates an output port through which the client may send outgoing MIDI messages to any MIDI destination.
     /// See [MIDIOutputPortCreate](https://developer.apple.com/documentation/coremidi/1495166-midioutputportcreate).
     ///
@@ -152,7 +194,8 @@ impl Client {
     {
         let port_name = CFString::new(name);
         let mut port_ref = MaybeUninit::uninit();
-        let read_block = Self::read_block(callback);
+        let guard = self.panic_guard("input port");
+        let read_block = Self::read_block(callback, guard.clone());
         let status = unsafe {
             MIDIInputPortCreateWithBlock(
                 self.object.0,
@@ -163,7 +206,7 @@ impl Client {
         };
         result_from_status(status, || {
             let port_ref = unsafe { port_ref.assume_init() };
-            InputPort::new(port_ref)
+            InputPort::new(port_ref, guard)
         })
     }
 
@@ -178,11 +221,71 @@ impl Client {
         callback: F,
     ) -> Result<InputPortWithContext<T>, OSStatus>
     where
+        T: Send + 'static,
         F: FnMut(&EventList, &mut T) + Send + 'static,
     {
         let port_name = CFString::new(name);
         let mut port_ref = MaybeUninit::uninit();
-        let receive_block = Self::receive_block::<T, _>(callback);
+        let contexts = Arc::new(Contexts::new());
+        let guard = self.panic_guard("input port");
+        let receive_block =
+            Self::receive_block(context_callback(contexts.clone(), callback), guard.clone());
+        let status = unsafe {
+            MIDIInputPortCreateWithProtocol(
+                self.object.0,
+                port_name.as_concrete_TypeRef(),
+                protocol.into(),
+                port_ref.as_mut_ptr(),
+                receive_block.deref() as *const _ as MIDIReceiveBlock,
+            )
+        };
+        result_from_status(status, || {
+            let port_ref = unsafe { port_ref.assume_init() };
+            InputPortWithContext::new(port_ref, contexts, guard)
+        })
+    }
+
+    /// Creates an input port like [Client::input_port_with_protocol], that copies every
+    /// Universal MIDI Packet received into a lock-free queue of the given capacity,
+    /// without allocating nor locking in the CoreMIDI thread.
+    ///
+    /// Each source is connected with the tag of its messages, for example its unique id,
+    /// which CoreMIDI gives back with the events received from it.
+    /// Messages that don't fit in the queue are dropped and counted by the [UmpReceiver].
+    ///
+    /// Only Universal MIDI Packets are queued. With [Protocol::Midi10], CoreMIDI delivers
+    /// MIDI 1.0 messages as Universal MIDI Packets too, so there is no queue for the
+    /// [PacketList]s of [Client::input_port].
+    ///
+    /// ```rust,no_run
+    /// use coremidi::{Client, Protocol, Source};
+    /// let client = Client::new("example-client").unwrap();
+    /// let (input_port, mut receiver) = client.input_port_with_queue("example-port", Protocol::Midi20, 1024).unwrap();
+    /// let source = Source::from_index(0).unwrap();
+    /// input_port.connect_source(&source, source.unique_id().unwrap_or(0)).unwrap();
+    /// loop {
+    ///     for message in receiver.try_iter() {
+    ///         println!("{:08x} {}: {:08x?}", message.source(), message.timestamp(), message.words());
+    ///     }
+    ///     std::thread::sleep(std::time::Duration::from_millis(1));
+    /// }
+    /// ```
+    pub fn input_port_with_queue(
+        &self,
+        name: &str,
+        protocol: Protocol,
+        capacity: usize,
+    ) -> Result<(TaggedInputPort, UmpReceiver), OSStatus> {
+        let (mut sender, receiver) = ump_queue(capacity);
+        let port_name = CFString::new(name);
+        let mut port_ref = MaybeUninit::uninit();
+        let guard = self.panic_guard("input port");
+        let receive_block = Self::receive_block(
+            move |event_list, src_conn_ref_con| {
+                sender.push_event_list(src_conn_ref_con as usize as u32, event_list);
+            },
+            guard.clone(),
+        );
         let status = unsafe {
             MIDIInputPortCreateWithProtocol(
                 self.object.0,
@@ -194,7 +297,7 @@ impl Client {
         };
         result_from_status(status, || {
             let port_ref = unsafe { port_ref.assume_init() };
-            InputPortWithContext::<T>::new(port_ref)
+            (TaggedInputPort::new(port_ref, guard), receiver)
         })
     }
 
@@ -232,7 +335,7 @@ impl Client {
     {
         let virtual_destination_name = CFString::new(name);
         let mut virtual_destination = MaybeUninit::uninit();
-        let read_block = Self::read_block(callback);
+        let read_block = Self::read_block(callback, self.panic_guard("virtual destination"));
         let status = unsafe {
             MIDIDestinationCreateWithBlock(
                 self.object.0,
@@ -262,8 +365,10 @@ impl Client {
     {
         let virtual_destination_name = CFString::new(name);
         let mut virtual_destination = MaybeUninit::uninit();
-        let receive_block =
-            Self::receive_block::<(), _>(move |event_list, _| (callback)(event_list));
+        let receive_block = Self::receive_block(
+            move |event_list, _| (callback)(event_list),
+            self.panic_guard("virtual destination"),
+        );
         let status = unsafe {
             MIDIDestinationCreateWithProtocol(
                 self.object.0,
@@ -279,20 +384,26 @@ impl Client {
         })
     }
 
-    fn notify_block(callback: NotifyCallback) -> RcBlock<(*const MIDINotification,), ()> {
+    fn notify_block(
+        callback: NotifyCallback,
+        guard: PanicGuard,
+    ) -> RcBlock<(*const MIDINotification,), ()> {
         let notify_block = block::ConcreteBlock::new(move |message: *const MIDINotification| {
             let message = unsafe { &*message };
             if let Ok(notification) = Notification::try_from(message) {
-                match &callback {
+                guard.call(|| match &callback {
                     NotifyCallback::ByReference(f) => (f.borrow_mut())(&notification),
                     NotifyCallback::ByOwnership(f) => (f.borrow_mut())(notification),
-                }
+                });
             }
         });
         notify_block.copy()
     }
 
-    fn read_block<F>(callback: F) -> RcBlock<(*const MIDIPacketList, *mut c_void), ()>
+    fn read_block<F>(
+        callback: F,
+        guard: Arc<PanicGuard>,
+    ) -> RcBlock<(*const MIDIPacketList, *mut c_void), ()>
     where
         F: FnMut(&PacketList) + Send + 'static,
     {
@@ -300,26 +411,41 @@ impl Client {
         let read_block = block::ConcreteBlock::new(
             move |pktlist: *const MIDIPacketList, _src_conn_ref_con: *mut c_void| {
                 let packet_list = unsafe { &*(pktlist as *const PacketList) };
-                (callback.borrow_mut())(packet_list);
+                guard.call(|| (callback.borrow_mut())(packet_list));
             },
         );
         read_block.copy()
     }
 
-    fn receive_block<T, F>(callback: F) -> RcBlock<(*const MIDIEventList, *mut c_void), ()>
+    fn receive_block<F>(
+        callback: F,
+        guard: Arc<PanicGuard>,
+    ) -> RcBlock<(*const MIDIEventList, *mut c_void), ()>
     where
-        F: FnMut(&EventList, &mut T) + Send + 'static,
+        F: FnMut(&EventList, *mut c_void) + Send + 'static,
     {
-        let callback = RefCell::new(callback);
+        let handler = RefCell::new(Self::receive_handler(callback, guard));
         let receive_block = block::ConcreteBlock::new(
             move |evtlist: *const MIDIEventList, src_conn_ref_con: *mut c_void| {
-                let event_list = unsafe { &*(evtlist as *const EventList) };
-                let context = unsafe { &mut *(src_conn_ref_con as *mut T) };
-                (callback.borrow_mut())(event_list, context);
+                (handler.borrow_mut())(evtlist, src_conn_ref_con);
             },
         );
         receive_block.copy()
     }
+
+    /// The closure called by a receive block, with the arguments given by CoreMIDI.
+    pub(crate) fn receive_handler<F>(
+        mut callback: F,
+        guard: Arc<PanicGuard>,
+    ) -> impl FnMut(*const MIDIEventList, *mut c_void) + Send
+    where
+        F: FnMut(&EventList, *mut c_void) + Send + 'static,
+    {
+        move |evtlist, src_conn_ref_con| {
+            let event_list = unsafe { &*(evtlist as *const EventList) };
+            guard.call(|| callback(event_list, src_conn_ref_con));
+        }
+    }
 }
 
 impl Deref for Client {
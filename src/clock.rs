@@ -0,0 +1,698 @@
+use std::collections::VecDeque;
+
+use crate::events::{ump_word_count, EventBuffer, EventList, Timestamp};
+use crate::host_time::{nanos_to_ticks, ticks_to_nanos, HostClock, SystemClock};
+use crate::packets::{PacketBuffer, PacketList};
+
+pub(crate) const TIMING_CLOCK: u8 = 0xF8;
+pub(crate) const START: u8 = 0xFA;
+pub(crate) const CONTINUE: u8 = 0xFB;
+pub(crate) const STOP: u8 = 0xFC;
+pub(crate) const SONG_POSITION: u8 = 0xF2;
+
+/// The number of MIDI clock ticks per quarter note.
+pub const CLOCK_TICKS_PER_QUARTER: u32 = 24;
+
+pub(crate) const TICKS_PER_SIXTEENTH: u64 = 6;
+
+const NANOS_PER_MINUTE: f64 = 60e9;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Transport {
+    Start,
+    Stop,
+    Continue,
+    SongPosition(u16),
+    Tempo(f64),
+}
+
+/// A MIDI beat clock master that schedules its messages ahead of time.
+///
+/// Instead of sending every tick when it is due, the messages for a window of time are
+/// added to a buffer with their future timestamps, so they can be sent with a single call
+/// to [crate::OutputPort::send] and played on time by CoreMIDI.
+///
+/// Tempo and transport changes take effect at the first tick not generated yet,
+/// where the start, stop, continue and song position pointer messages are sent
+/// right before the timing clock.
+///
+/// ```
+/// use coremidi::{ClockMaster, PacketBuffer, Timestamp};
+/// let mut clock = ClockMaster::new(120.0, Timestamp::from_host_time(1000));
+/// clock.start();
+/// let mut buffer = PacketBuffer::with_capacity(256);
+/// assert_eq!(clock.fill(&mut buffer, Timestamp::from_host_time(1001)), 1);
+/// let packet = buffer.iter().next().unwrap();
+/// assert_eq!(packet.timestamp(), Timestamp::from_host_time(1000));
+/// let data: Vec<u8> = buffer.iter().flat_map(|packet| packet.data().to_vec()).collect();
+/// assert_eq!(data, vec![0xfa, 0xf8]);
+/// assert!(clock.is_playing());
+/// ```
+#[derive(Debug, Clone)]
+pub struct ClockMaster {
+    tempo: f64,
+    anchor_tick: u64,
+    anchor_timestamp: Timestamp,
+    next_tick: u64,
+    pending: VecDeque<Transport>,
+    playing: bool,
+    song_position_ticks: u64,
+    ticks_while_stopped: bool,
+}
+
+impl ClockMaster {
+    /// Create a clock with a tempo in beats per minute, with its first tick at a host timestamp,
+    /// where [Timestamp::Now] is taken as the current host time.
+    ///
+    pub fn new(tempo: f64, timestamp: Timestamp) -> Self {
+        Self {
+            tempo: tempo.max(1.0),
+            anchor_tick: 0,
+            anchor_timestamp: SystemClock.resolve(timestamp),
+            next_tick: 0,
+            pending: VecDeque::new(),
+            playing: false,
+            song_position_ticks: 0,
+            ticks_while_stopped: true,
+        }
+    }
+
+    /// Set whether the timing clock keeps running while the transport is stopped,
+    /// so that followers can lock to the tempo before starting. It is enabled by default.
+    ///
+    pub fn set_ticks_while_stopped(&mut self, enabled: bool) {
+        self.ticks_while_stopped = enabled;
+    }
+
+    /// Get the tempo of the next tick, in beats per minute.
+    ///
+    pub fn tempo(&self) -> f64 {
+        self.pending
+            .iter()
+            .rev()
+            .find_map(|transport| match transport {
+                Transport::Tempo(tempo) => Some(*tempo),
+                _ => None,
+            })
+            .unwrap_or(self.tempo)
+    }
+
+    /// Change the tempo, in beats per minute, from the next tick not generated yet.
+    ///
+    pub fn set_tempo(&mut self, tempo: f64) {
+        self.pending.push_back(Transport::Tempo(tempo.max(1.0)));
+    }
+
+    /// Start playing from the beginning of the song.
+    ///
+    pub fn start(&mut self) {
+        self.pending.push_back(Transport::Start);
+    }
+
+    /// Stop playing, keeping the song position.
+    ///
+    pub fn stop(&mut self) {
+        self.pending.push_back(Transport::Stop);
+    }
+
+    /// Continue playing from the current song position.
+    ///
+    pub fn resume(&mut self) {
+        self.pending.push_back(Transport::Continue);
+    }
+
+    /// Move the song position, in sixteenth notes from the beginning of the song.
+    /// It is ignored while playing.
+    ///
+    pub fn set_song_position(&mut self, sixteenths: u16) {
+        self.pending
+            .push_back(Transport::SongPosition(sixteenths & 0x3FFF));
+    }
+
+    /// Check whether the transport was playing at the last generated tick.
+    ///
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    /// Get the song position of the next tick, in clock ticks from the beginning of the song.
+    ///
+    pub fn song_position_ticks(&self) -> u64 {
+        self.song_position_ticks
+    }
+
+    /// Get the host timestamp of the next tick.
+    ///
+    pub fn next_tick_timestamp(&self) -> Timestamp {
+        self.tick_timestamp(self.next_tick)
+    }
+
+    /// Add the messages of the ticks with a timestamp before `until` to a buffer,
+    /// and return the number of ticks generated.
+    ///
+    pub fn fill(&mut self, buffer: &mut PacketBuffer, until: Timestamp) -> usize {
+        self.generate(until, |timestamp, message| {
+            buffer.push_data(timestamp, message);
+        })
+    }
+
+    /// Add the messages of the ticks with a timestamp before `until` to a buffer of
+    /// Universal MIDI Packets in a group, and return the number of ticks generated.
+    ///
+    pub fn fill_event_buffer(
+        &mut self,
+        buffer: &mut EventBuffer,
+        until: Timestamp,
+        group: u8,
+    ) -> usize {
+        self.generate(until, |timestamp, message| {
+            buffer.push(timestamp, &[system_message_word(group, message)]);
+        })
+    }
+
+    fn generate<F: FnMut(Timestamp, &[u8])>(&mut self, until: Timestamp, mut f: F) -> usize {
+        let mut ticks = 0;
+        loop {
+            self.apply_tempo_changes();
+            let timestamp = self.next_tick_timestamp();
+            if timestamp >= until {
+                break;
+            }
+            while let Some(transport) = self.pending.pop_front() {
+                match transport {
+                    Transport::Start => {
+                        self.playing = true;
+                        self.song_position_ticks = 0;
+                        f(timestamp, &[START]);
+                    }
+                    Transport::Stop => {
+                        self.playing = false;
+                        f(timestamp, &[STOP]);
+                    }
+                    Transport::Continue => {
+                        self.playing = true;
+                        f(timestamp, &[CONTINUE]);
+                    }
+                    Transport::SongPosition(sixteenths) if !self.playing => {
+                        self.song_position_ticks = sixteenths as u64 * TICKS_PER_SIXTEENTH;
+                        let (lsb, msb) = ((sixteenths & 0x7F) as u8, (sixteenths >> 7) as u8);
+                        f(timestamp, &[SONG_POSITION, lsb, msb]);
+                    }
+                    Transport::SongPosition(_) => {}
+                    Transport::Tempo(tempo) => self.change_tempo(tempo),
+                }
+            }
+            if self.playing || self.ticks_while_stopped {
+                f(timestamp, &[TIMING_CLOCK]);
+            }
+            if self.playing {
+                self.song_position_ticks += 1;
+            }
+            self.next_tick += 1;
+            ticks += 1;
+        }
+        ticks
+    }
+
+    fn apply_tempo_changes(&mut self) {
+        while let Some(Transport::Tempo(tempo)) = self.pending.front().copied() {
+            self.pending.pop_front();
+            self.change_tempo(tempo);
+        }
+    }
+
+    fn change_tempo(&mut self, tempo: f64) {
+        self.anchor_timestamp = self.next_tick_timestamp();
+        self.anchor_tick = self.next_tick;
+        self.tempo = tempo;
+    }
+
+    fn tick_timestamp(&self, tick: u64) -> Timestamp {
+        let ticks = (tick - self.anchor_tick) as f64;
+        let nanos = ticks * NANOS_PER_MINUTE / (self.tempo * CLOCK_TICKS_PER_QUARTER as f64);
+        Timestamp::from_host_time(
+            self.anchor_timestamp.host_time() + nanos_to_ticks(nanos.round() as u64),
+        )
+    }
+}
+
+/// A change of the transport detected by a [ClockFollower].
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockEvent {
+    Tick,
+    Start,
+    Stop,
+    Continue,
+    /// The song position pointer, in sixteenth notes from the beginning of the song.
+    SongPosition(u16),
+}
+
+/// Follows an external MIDI beat clock, estimating its tempo and song position.
+///
+/// Tick intervals are measured from the timestamps of the received packets.
+/// Intervals too far from the current estimate are discarded as jitter, unless they
+/// keep repeating, which means the tempo jumped. The estimate is smoothed with an
+/// exponential moving average.
+///
+/// It can be fed directly from the callback of an input port:
+///
+/// ```rust,no_run
+/// use std::sync::{Arc, Mutex};
+/// use coremidi::{Client, ClockFollower};
+///
+/// let follower = Arc::new(Mutex::new(ClockFollower::new()));
+/// let client = Client::new("Example Client").unwrap();
+/// let callback_follower = follower.clone();
+/// let _input_port = client
+///     .input_port("Clock Input", move |packet_list| {
+///         callback_follower.lock().unwrap().handle_packet_list(packet_list)
+///     })
+///     .unwrap();
+/// // ... connect the port to a source
+/// let follower = follower.lock().unwrap();
+/// println!("tempo: {:?} bpm, playing: {}", follower.tempo(), follower.is_playing());
+/// ```
+#[derive(Debug, Clone)]
+pub struct ClockFollower {
+    smoothing: f64,
+    jitter_tolerance: f64,
+    interval: Option<f64>,
+    last_tick: Option<Timestamp>,
+    outliers: u32,
+    playing: bool,
+    last_tick_position: Option<u64>,
+    next_tick_position: u64,
+}
+
+impl ClockFollower {
+    const MAX_OUTLIERS: u32 = 3;
+    const MAX_TICK_INTERVAL_NANOS: f64 = 250e6;
+
+    /// Create a follower with the default smoothing (0.1) and jitter tolerance (25%).
+    ///
+    pub fn new() -> Self {
+        Self {
+            smoothing: 0.1,
+            jitter_tolerance: 0.25,
+            interval: None,
+            last_tick: None,
+            outliers: 0,
+            playing: false,
+            last_tick_position: None,
+            next_tick_position: 0,
+        }
+    }
+
+    /// Set the weight of every new tick interval in the tempo estimate, from 0 to 1.
+    /// Lower values are smoother but slower to follow tempo changes.
+    ///
+    pub fn set_smoothing(&mut self, smoothing: f64) {
+        self.smoothing = smoothing.max(0.001).min(1.0);
+    }
+
+    /// Set the maximum relative deviation of a tick interval from the estimate
+    /// to be considered jitter instead of a tempo change.
+    ///
+    pub fn set_jitter_tolerance(&mut self, tolerance: f64) {
+        self.jitter_tolerance = tolerance.max(0.0);
+    }
+
+    /// Get the estimated tempo in beats per minute, once at least two ticks were received.
+    ///
+    pub fn tempo(&self) -> Option<f64> {
+        self.interval
+            .map(|interval| NANOS_PER_MINUTE / (interval * CLOCK_TICKS_PER_QUARTER as f64))
+    }
+
+    /// Check whether the transport is playing.
+    ///
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    /// Get the song position of the next tick, in clock ticks from the beginning of the song.
+    ///
+    pub fn song_position_ticks(&self) -> u64 {
+        self.next_tick_position
+    }
+
+    /// Get the song position in beats (quarter notes) at a host timestamp.
+    ///
+    /// While playing, it is extrapolated from the last tick using the estimated tempo,
+    /// up to the position of the next tick.
+    ///
+    pub fn beats_at(&self, timestamp: Timestamp) -> f64 {
+        let ticks = match (
+            self.playing,
+            self.last_tick_position,
+            self.last_tick,
+            self.interval,
+        ) {
+            (true, Some(position), Some(last_tick), Some(interval)) => {
+                let elapsed =
+                    ticks_to_nanos(timestamp.host_time().saturating_sub(last_tick.host_time()))
+                        as f64;
+                position as f64 + (elapsed / interval).min(1.0)
+            }
+            _ => self.next_tick_position as f64,
+        };
+        ticks / CLOCK_TICKS_PER_QUARTER as f64
+    }
+
+    /// Get the phase within the current beat at a host timestamp, from 0 to 1.
+    ///
+    pub fn beat_phase_at(&self, timestamp: Timestamp) -> f64 {
+        self.beats_at(timestamp).fract()
+    }
+
+    /// Update the state from the clock messages in a packet list, using their timestamps.
+    ///
+    pub fn handle_packet_list(&mut self, packet_list: &PacketList) {
+        for packet in packet_list.iter() {
+            let data = packet.data();
+            for (index, status) in data.iter().enumerate() {
+                let message = match *status {
+                    SONG_POSITION => match data.get(index + 1..index + 3) {
+                        Some(bytes) if bytes.iter().all(|b| *b < 0x80) => &data[index..index + 3],
+                        _ => continue,
+                    },
+                    _ => std::slice::from_ref(status),
+                };
+                self.handle_message(message, packet.timestamp());
+            }
+        }
+    }
+
+    /// Update the state from the system messages in a list of Universal MIDI Packets,
+    /// using their timestamps.
+    ///
+    pub fn handle_event_list(&mut self, event_list: &EventList) {
+        for packet in event_list.iter() {
+            let mut words = packet.data();
+            while let Some(&word) = words.first() {
+                let count = ump_word_count(word).min(words.len());
+                if word >> 28 == 0x1 {
+                    let message = [(word >> 16) as u8, (word >> 8) as u8, word as u8];
+                    self.handle_message(&message, packet.timestamp());
+                }
+                words = &words[count..];
+            }
+        }
+    }
+
+    /// Update the state from a single MIDI message received at a host timestamp,
+    /// returning the detected event if it is a clock message.
+    ///
+    pub fn handle_message(&mut self, message: &[u8], timestamp: Timestamp) -> Option<ClockEvent> {
+        match message {
+            [TIMING_CLOCK, ..] => {
+                self.handle_tick(timestamp);
+                Some(ClockEvent::Tick)
+            }
+            [START, ..] => {
+                self.playing = true;
+                self.last_tick_position = None;
+                self.next_tick_position = 0;
+                Some(ClockEvent::Start)
+            }
+            [CONTINUE, ..] => {
+                self.playing = true;
+                self.last_tick_position = None;
+                Some(ClockEvent::Continue)
+            }
+            [STOP, ..] => {
+                self.playing = false;
+                Some(ClockEvent::Stop)
+            }
+            [SONG_POSITION, lsb, msb, ..] if *lsb < 0x80 && *msb < 0x80 => {
+                let sixteenths = (*msb as u16) << 7 | *lsb as u16;
+                self.next_tick_position = sixteenths as u64 * TICKS_PER_SIXTEENTH;
+                self.last_tick_position = None;
+                Some(ClockEvent::SongPosition(sixteenths))
+            }
+            _ => None,
+        }
+    }
+
+    fn handle_tick(&mut self, timestamp: Timestamp) {
+        if let Some(last_tick) = self.last_tick {
+            let interval =
+                ticks_to_nanos(timestamp.host_time().saturating_sub(last_tick.host_time())) as f64;
+            self.update_interval(interval);
+        }
+        self.last_tick = Some(timestamp);
+        if self.playing {
+            self.last_tick_position = Some(self.next_tick_position);
+            self.next_tick_position += 1;
+        }
+    }
+
+    fn update_interval(&mut self, interval: f64) {
+        if interval <= 0.0 || interval > Self::MAX_TICK_INTERVAL_NANOS {
+            // The clock stopped for a while, or ticks were merged into the same packet
+            return;
+        }
+        match self.interval {
+            None => self.interval = Some(interval),
+            Some(estimate) => {
+                let deviation = (interval - estimate).abs() / estimate;
+                if deviation <= self.jitter_tolerance {
+                    self.outliers = 0;
+                    self.interval = Some(estimate + (interval - estimate) * self.smoothing);
+                } else {
+                    self.outliers += 1;
+                    if self.outliers >= Self::MAX_OUTLIERS {
+                        self.outliers = 0;
+                        self.interval = Some(interval);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Default for ClockFollower {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Encode a MIDI 1.0 system message as a Universal MIDI Packet word.
+pub(crate) fn system_message_word(group: u8, message: &[u8]) -> u32 {
+    let byte = |index: usize| message.get(index).copied().unwrap_or(0) as u32;
+    0x1 << 28 | ((group & 0x0F) as u32) << 24 | byte(0) << 16 | byte(1) << 8 | byte(2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::protocol::Protocol;
+    use std::time::Duration;
+
+    /// The host time of the start of the tests.
+    const START_HOST_TIME: u64 = 1_000_000;
+
+    fn at(nanos: u64) -> Timestamp {
+        Timestamp::from_host_time(START_HOST_TIME + nanos_to_ticks(nanos))
+    }
+
+    fn collect(clock: &mut ClockMaster, until_nanos: u64) -> Vec<(u64, Vec<u8>)> {
+        let mut events = Vec::new();
+        clock.generate(at(until_nanos), |timestamp, message| {
+            events.push((
+                ticks_to_nanos(timestamp.host_time() - START_HOST_TIME),
+                message.to_vec(),
+            ))
+        });
+        events
+    }
+
+    #[test]
+    fn ticks_at_tempo() {
+        let mut clock = ClockMaster::new(125.0, at(0));
+        // At 125 bpm a tick lasts 20 ms
+        let events = collect(&mut clock, 1_000_000_000);
+        assert_eq!(events.len(), 50);
+        assert_eq!(events[49], (980_000_000, vec![TIMING_CLOCK]));
+        assert!(!clock.is_playing());
+    }
+
+    #[test]
+    fn tempo_changes_at_tick_boundary() {
+        let mut clock = ClockMaster::new(125.0, at(0));
+        collect(&mut clock, 30_000_000);
+        clock.set_tempo(62.5);
+        assert_eq!(clock.tempo(), 62.5);
+        let events = collect(&mut clock, 200_000_000);
+        let timestamps: Vec<u64> = events.iter().map(|(timestamp, _)| *timestamp).collect();
+        assert_eq!(
+            timestamps,
+            vec![40_000_000, 80_000_000, 120_000_000, 160_000_000]
+        );
+    }
+
+    #[test]
+    fn transport_messages() {
+        let mut clock = ClockMaster::new(125.0, at(0));
+        clock.set_ticks_while_stopped(false);
+        clock.set_song_position(0x0081);
+        clock.resume();
+        let events = collect(&mut clock, 50_000_000);
+        assert_eq!(
+            events,
+            vec![
+                (0, vec![SONG_POSITION, 0x01, 0x01]),
+                (0, vec![CONTINUE]),
+                (0, vec![TIMING_CLOCK]),
+                (20_000_000, vec![TIMING_CLOCK]),
+                (40_000_000, vec![TIMING_CLOCK]),
+            ]
+        );
+        assert_eq!(clock.song_position_ticks(), 0x81 * 6 + 3);
+
+        clock.stop();
+        clock.set_song_position(0);
+        let events = collect(&mut clock, 100_000_000);
+        assert_eq!(
+            events,
+            vec![
+                (60_000_000, vec![STOP]),
+                (60_000_000, vec![SONG_POSITION, 0x00, 0x00])
+            ]
+        );
+        assert_eq!(clock.song_position_ticks(), 0);
+
+        clock.start();
+        clock.set_song_position(10);
+        let events = collect(&mut clock, 150_000_000);
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[0], (100_000_000, vec![START]));
+        assert_eq!(events[1], (100_000_000, vec![TIMING_CLOCK]));
+        assert_eq!(clock.song_position_ticks(), 3);
+    }
+
+    #[test]
+    fn event_buffer_words() {
+        let mut clock = ClockMaster::new(120.0, at(0));
+        clock.start();
+        let mut buffer = EventBuffer::new(Protocol::Midi20);
+        assert_eq!(
+            clock.fill_event_buffer(
+                &mut buffer,
+                Timestamp::from_host_time(START_HOST_TIME + 1),
+                3
+            ),
+            1
+        );
+        let words: Vec<u32> = buffer
+            .iter()
+            .flat_map(|packet| packet.data().to_vec())
+            .collect();
+        assert_eq!(words, vec![0x13FA0000, 0x13F80000]);
+        assert_eq!(
+            system_message_word(0, &[SONG_POSITION, 0x01, 0x02]),
+            0x10F20102
+        );
+    }
+
+    #[test]
+    fn starts_now() {
+        let before = SystemClock.now();
+        let mut clock = ClockMaster::new(125.0, Timestamp::Now);
+        let mut timestamps = Vec::new();
+        clock.generate(
+            SystemClock.after(Duration::from_millis(100)),
+            |timestamp, _| timestamps.push(timestamp),
+        );
+        assert!(timestamps.len() >= 5);
+        assert!(timestamps.iter().all(|timestamp| *timestamp >= before));
+    }
+
+    fn follow(follower: &mut ClockFollower, message: &[u8], nanos: u64) {
+        follower.handle_message(message, at(nanos));
+    }
+
+    #[test]
+    fn follower_tempo_with_jitter() {
+        let mut follower = ClockFollower::new();
+        assert_eq!(follower.tempo(), None);
+        // 120 bpm is a tick every 20.833 ms, with up to 1 ms of jitter
+        let interval = 60e9 / (120.0 * 24.0);
+        for tick in 0..200u64 {
+            let jitter = [0.0, 1e6, -1e6, 0.5e6][(tick % 4) as usize];
+            follow(
+                &mut follower,
+                &[TIMING_CLOCK],
+                (tick as f64 * interval + jitter) as u64,
+            );
+        }
+        assert!((follower.tempo().unwrap() - 120.0).abs() < 0.5);
+
+        // A single late tick is ignored
+        follow(
+            &mut follower,
+            &[TIMING_CLOCK],
+            (200.0 * interval + 15e6) as u64,
+        );
+        assert!((follower.tempo().unwrap() - 120.0).abs() < 0.5);
+
+        // But a tempo jump is followed
+        let start = 200.0 * interval + 15e6;
+        for tick in 1..=50u64 {
+            follow(
+                &mut follower,
+                &[TIMING_CLOCK],
+                (start + tick as f64 * interval / 2.0) as u64,
+            );
+        }
+        assert!((follower.tempo().unwrap() - 240.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn follower_transport_and_phase() {
+        let mut master = ClockMaster::new(125.0, at(0));
+        master.set_song_position(4);
+        master.resume();
+        let mut buffer = PacketBuffer::with_capacity(1024);
+        // 30 ticks of 20 ms
+        master.fill(&mut buffer, at(590_000_000));
+
+        let mut follower = ClockFollower::new();
+        follower.handle_packet_list(&buffer);
+        assert!(follower.is_playing());
+        assert!((follower.tempo().unwrap() - 125.0).abs() < 1e-6);
+        assert_eq!(follower.song_position_ticks(), 24 + 30);
+
+        let beats = follower.beats_at(at(585_000_000));
+        assert!((beats - (24.0 + 29.25) / 24.0).abs() < 1e-6);
+        assert!((follower.beat_phase_at(at(585_000_000)) - 5.25 / 24.0).abs() < 1e-6);
+        assert_eq!(follower.beats_at(at(700_000_000)), 54.0 / 24.0);
+
+        assert_eq!(
+            follower.handle_message(&[STOP], at(600_000_000)),
+            Some(ClockEvent::Stop)
+        );
+        assert_eq!(follower.beats_at(at(650_000_000)), 54.0 / 24.0);
+        assert_eq!(
+            follower.handle_message(&[SONG_POSITION, 0x00, 0x01], Timestamp::Now),
+            Some(ClockEvent::SongPosition(128))
+        );
+        assert_eq!(follower.song_position_ticks(), 128 * 6);
+    }
+
+    #[test]
+    fn follower_event_list() {
+        let mut master = ClockMaster::new(125.0, at(0));
+        master.start();
+        let mut buffer = EventBuffer::new(Protocol::Midi20);
+        buffer.push(Timestamp::Now, &[0x40903c00, 0xffff0000]);
+        master.fill_event_buffer(&mut buffer, at(50_000_000), 0);
+
+        let mut follower = ClockFollower::new();
+        follower.handle_event_list(&buffer);
+        assert!(follower.is_playing());
+        assert_eq!(follower.song_position_ticks(), 3);
+    }
+}
@@ -0,0 +1,461 @@
+use std::fmt::{self, Write};
+
+use crate::events::ump_word_count;
+
+const NOTE_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+/// The number of System Exclusive data bytes shown before truncating.
+const MAX_SYSEX_BYTES: usize = 16;
+
+/// Get the name of a note number, where 60 is `C4`.
+pub(crate) fn note_name(note: u8) -> String {
+    format!(
+        "{}{}",
+        NOTE_NAMES[(note % 12) as usize],
+        (note / 12) as i32 - 1
+    )
+}
+
+/// Write the MIDI 1.0 messages of a packet, separated by `; `.
+///
+/// Real-time messages are decoded wherever they appear, data bytes without a status
+/// byte use the running status, and incomplete or unexpected data is shown in hex.
+pub(crate) fn write_midi1_messages(f: &mut dyn Write, data: &[u8]) -> fmt::Result {
+    let mut separator = "";
+    let mut running_status = None;
+    let mut index = 0;
+    while index < data.len() {
+        f.write_str(separator)?;
+        separator = "; ";
+        let byte = data[index];
+        if byte >= 0xF8 {
+            f.write_str(system_name(byte))?;
+            index += 1;
+            continue;
+        }
+        if byte == 0xF0 {
+            let length = data[index + 1..]
+                .iter()
+                .position(|byte| *byte >= 0x80 && *byte < 0xF8)
+                .map(|end| end + 1)
+                .unwrap_or(data.len() - index);
+            let sysex: Vec<u8> = data[index..index + length]
+                .iter()
+                .copied()
+                .filter(|byte| *byte < 0xF8)
+                .collect();
+            let terminated = data.get(index + length) == Some(&0xF7);
+            write_sysex(f, &sysex, terminated)?;
+            for byte in data[index..index + length]
+                .iter()
+                .filter(|byte| **byte >= 0xF8)
+            {
+                write!(f, "; {}", system_name(*byte))?;
+            }
+            index += length + terminated as usize;
+            running_status = None;
+            continue;
+        }
+        let (status, start) = match byte {
+            0x80..=0xEF => {
+                running_status = Some(byte);
+                (byte, index + 1)
+            }
+            0xF1..=0xF7 => {
+                running_status = None;
+                (byte, index + 1)
+            }
+            _ => match running_status {
+                Some(status) => (status, index),
+                None => {
+                    let end = data[index..]
+                        .iter()
+                        .position(|byte| *byte >= 0x80)
+                        .map_or(data.len(), |end| index + end);
+                    write!(f, "Invalid ")?;
+                    write_hex_bytes(f, &data[index..end])?;
+                    index = end;
+                    continue;
+                }
+            },
+        };
+        let length = message_length(status);
+        let available = data[start..]
+            .iter()
+            .take(length)
+            .take_while(|byte| **byte < 0x80)
+            .count();
+        if available < length {
+            write!(f, "{} (truncated) ", status_name(status))?;
+            write_hex_bytes(f, &data[index..start + available])?;
+        } else {
+            write_named(f, status, None, &data[start..start + length])?;
+        }
+        index = start + available;
+    }
+    Ok(())
+}
+
+/// Write the Universal MIDI Packets of an event packet, separated by `; `.
+pub(crate) fn write_ump_messages(f: &mut dyn Write, words: &[u32]) -> fmt::Result {
+    let mut separator = "";
+    let mut rest = words;
+    while let Some(first) = rest.first() {
+        f.write_str(separator)?;
+        separator = "; ";
+        let length = ump_word_count(*first);
+        if length > rest.len() {
+            write!(f, "Truncated ")?;
+            write_hex_words(f, rest)?;
+            break;
+        }
+        let (message, tail) = rest.split_at(length);
+        write_ump_message(f, message)?;
+        rest = tail;
+    }
+    Ok(())
+}
+
+fn write_ump_message(f: &mut dyn Write, words: &[u32]) -> fmt::Result {
+    let word = words[0];
+    let group = (word >> 24 & 0x0F) as u8;
+    let status = (word >> 16 & 0xFF) as u8;
+    let channel = (status & 0x0F) + 1;
+    let byte = |shift: u32| (word >> shift & 0xFF) as u8;
+    match word >> 28 {
+        0x0 if word == 0 => write!(f, "NOOP"),
+        0x1 => {
+            let bytes = [status, byte(8), byte(0)];
+            let length = message_length(status).min(2);
+            if status >= 0xF1 && status != 0xF7 && bytes[1..=length].iter().all(|b| *b < 0x80) {
+                write!(f, "System ")?;
+                write_named(f, status, Some(group), &bytes[1..=length])
+            } else {
+                write!(f, "System g={} ", group)?;
+                write_hex_words(f, words)
+            }
+        }
+        0x2 if (0x80..0xF0).contains(&status) && byte(8) < 0x80 && byte(0) < 0x80 => {
+            let length = message_length(status);
+            write!(f, "MIDI1 ")?;
+            write_named(f, status, Some(group), &[byte(8), byte(0)][..length])
+        }
+        0x3 => {
+            let count = (status & 0x0F) as usize;
+            let kind = match status >> 4 {
+                0x0 => "Complete",
+                0x1 => "Start",
+                0x2 => "Continue",
+                0x3 => "End",
+                _ => "Invalid",
+            };
+            let bytes: Vec<u8> = [byte(8), byte(0)]
+                .iter()
+                .copied()
+                .chain(words[1].to_be_bytes().iter().copied())
+                .take(count.min(6))
+                .collect();
+            write!(f, "SysEx7 {} g={} ", kind, group)?;
+            write_hex_bytes(f, &bytes)
+        }
+        0x4 => write_midi2_message(f, words, group, status, channel),
+        kind => {
+            write!(f, "UMP type={:x} g={} ", kind, group)?;
+            write_hex_words(f, words)
+        }
+    }
+}
+
+fn write_midi2_message(
+    f: &mut dyn Write,
+    words: &[u32],
+    group: u8,
+    status: u8,
+    channel: u8,
+) -> fmt::Result {
+    let (first, value) = (words[0], words[1]);
+    let index = (first >> 8 & 0xFF) as u8;
+    let extra = (first & 0xFF) as u8;
+    let prefix = |name: &str| format!("MIDI2 {} g={} ch={}", name, group, channel);
+    match status & 0xF0 {
+        0x80 | 0x90 => {
+            let name = if status & 0xF0 == 0x80 {
+                "NoteOff"
+            } else {
+                "NoteOn"
+            };
+            write!(f, "{} note=", prefix(name))?;
+            write_note(f, index)?;
+            write!(f, " vel=0x{:04x}", value >> 16)?;
+            if extra != 0 || value & 0xFFFF != 0 {
+                write!(f, " attr={}:0x{:04x}", extra, value & 0xFFFF)?;
+            }
+            Ok(())
+        }
+        0xA0 => {
+            write!(f, "{} note=", prefix("PolyPressure"))?;
+            write_note(f, index)?;
+            write!(f, " val=0x{:08x}", value)
+        }
+        0xB0 => write!(f, "{} cc={} val=0x{:08x}", prefix("CC"), index, value),
+        0xC0 => {
+            write!(f, "{} program={}", prefix("ProgramChange"), value >> 24)?;
+            if extra & 1 != 0 {
+                write!(f, " bank={}:{}", value >> 8 & 0x7F, value & 0x7F)?;
+            }
+            Ok(())
+        }
+        0xD0 => write!(f, "{} val=0x{:08x}", prefix("ChannelPressure"), value),
+        0xE0 => write!(f, "{} val=0x{:08x}", prefix("PitchBend"), value),
+        0x20 | 0x30 => {
+            let name = if status & 0xF0 == 0x20 { "RPN" } else { "NRPN" };
+            write!(
+                f,
+                "{} bank={} index={} val=0x{:08x}",
+                prefix(name),
+                index,
+                extra,
+                value
+            )
+        }
+        _ => {
+            write!(f, "MIDI2 status=0x{:02x} g={} ", status, group)?;
+            write_hex_words(f, words)
+        }
+    }
+}
+
+fn write_named(f: &mut dyn Write, status: u8, group: Option<u8>, data: &[u8]) -> fmt::Result {
+    f.write_str(status_name(status))?;
+    if let Some(group) = group {
+        write!(f, " g={}", group)?;
+    }
+    let channel = (status & 0x0F) + 1;
+    let byte = |index: usize| data.get(index).copied().unwrap_or(0);
+    let fourteen_bits = || byte(0) as u16 | (byte(1) as u16) << 7;
+    match status & 0xF0 {
+        0x80 | 0x90 => {
+            write!(f, " ch={} note=", channel)?;
+            write_note(f, byte(0))?;
+            write!(f, " vel={}", byte(1))
+        }
+        0xA0 => {
+            write!(f, " ch={} note=", channel)?;
+            write_note(f, byte(0))?;
+            write!(f, " val={}", byte(1))
+        }
+        0xB0 => write!(f, " ch={} cc={} val={}", channel, byte(0), byte(1)),
+        0xC0 => write!(f, " ch={} program={}", channel, byte(0)),
+        0xD0 => write!(f, " ch={} val={}", channel, byte(0)),
+        0xE0 => write!(f, " ch={} val={}", channel, fourteen_bits()),
+        _ => match status {
+            0xF1 | 0xF3 => write!(f, " val={}", byte(0)),
+            0xF2 => write!(f, " val={}", fourteen_bits()),
+            _ => Ok(()),
+        },
+    }
+}
+
+fn write_sysex(f: &mut dyn Write, sysex: &[u8], terminated: bool) -> fmt::Result {
+    let length = sysex.len() + terminated as usize;
+    write!(f, "SysEx len={} [", length)?;
+    for (index, byte) in sysex.iter().take(MAX_SYSEX_BYTES).enumerate() {
+        let separator = if index > 0 { " " } else { "" };
+        write!(f, "{}{:02x}", separator, byte)?;
+    }
+    if sysex.len() > MAX_SYSEX_BYTES {
+        write!(f, " ...")?;
+    } else if terminated {
+        write!(f, " f7")?;
+    }
+    write!(f, "]")?;
+    if !terminated {
+        write!(f, " (unterminated)")?;
+    }
+    Ok(())
+}
+
+fn write_note(f: &mut dyn Write, note: u8) -> fmt::Result {
+    if note < 0x80 {
+        write!(f, "{}({})", note, note_name(note))
+    } else {
+        write!(f, "{}", note)
+    }
+}
+
+fn write_hex_bytes(f: &mut dyn Write, bytes: &[u8]) -> fmt::Result {
+    write!(f, "[")?;
+    for (index, byte) in bytes.iter().enumerate() {
+        let separator = if index > 0 { " " } else { "" };
+        write!(f, "{}{:02x}", separator, byte)?;
+    }
+    write!(f, "]")
+}
+
+fn write_hex_words(f: &mut dyn Write, words: &[u32]) -> fmt::Result {
+    write!(f, "[")?;
+    for (index, word) in words.iter().enumerate() {
+        let separator = if index > 0 { " " } else { "" };
+        write!(f, "{}{:08x}", separator, word)?;
+    }
+    write!(f, "]")
+}
+
+/// The number of data bytes following a status byte.
+fn message_length(status: u8) -> usize {
+    match status {
+        0xC0..=0xDF | 0xF1 | 0xF3 => 1,
+        0x80..=0xEF | 0xF2 => 2,
+        _ => 0,
+    }
+}
+
+fn status_name(status: u8) -> &'static str {
+    match status & 0xF0 {
+        0x80 => "NoteOff",
+        0x90 => "NoteOn",
+        0xA0 => "PolyPressure",
+        0xB0 => "CC",
+        0xC0 => "ProgramChange",
+        0xD0 => "ChannelPressure",
+        0xE0 => "PitchBend",
+        _ => system_name(status),
+    }
+}
+
+fn system_name(status: u8) -> &'static str {
+    match status {
+        0xF0 => "SysEx",
+        0xF1 => "MtcQuarterFrame",
+        0xF2 => "SongPosition",
+        0xF3 => "SongSelect",
+        0xF6 => "TuneRequest",
+        0xF7 => "EndOfExclusive",
+        0xF8 => "TimingClock",
+        0xFA => "Start",
+        0xFB => "Continue",
+        0xFC => "Stop",
+        0xFE => "ActiveSensing",
+        0xFF => "Reset",
+        _ => "Undefined",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn midi1(data: &[u8]) -> String {
+        let mut text = String::new();
+        write_midi1_messages(&mut text, data).unwrap();
+        text
+    }
+
+    fn ump(words: &[u32]) -> String {
+        let mut text = String::new();
+        write_ump_messages(&mut text, words).unwrap();
+        text
+    }
+
+    #[test]
+    fn note_names() {
+        assert_eq!(note_name(60), "C4");
+        assert_eq!(note_name(0), "C-1");
+        assert_eq!(note_name(61), "C#4");
+        assert_eq!(note_name(127), "G9");
+    }
+
+    #[test]
+    fn midi1_messages() {
+        assert_eq!(midi1(&[0x90, 60, 127]), "NoteOn ch=1 note=60(C4) vel=127");
+        assert_eq!(
+            midi1(&[0xB2, 7, 100, 74, 0, 0xF8, 0xE0, 0, 0x40]),
+            "CC ch=3 cc=7 val=100; CC ch=3 cc=74 val=0; TimingClock; PitchBend ch=1 val=8192"
+        );
+        assert_eq!(
+            midi1(&[0xC9, 5, 0xD0, 9, 0xA1, 64, 3, 0x8F, 0x3C, 0]),
+            "ProgramChange ch=10 program=5; ChannelPressure ch=1 val=9; \
+             PolyPressure ch=2 note=64(E4) val=3; NoteOff ch=16 note=60(C4) vel=0"
+        );
+        assert_eq!(
+            midi1(&[0xF2, 0x48, 0x01, 0xF3, 3, 0xF1, 0x21, 0xF6, 0xFA, 0xFF, 0xF4]),
+            "SongPosition val=200; SongSelect val=3; MtcQuarterFrame val=33; \
+             TuneRequest; Start; Reset; Undefined"
+        );
+    }
+
+    #[test]
+    fn sysex() {
+        assert_eq!(
+            midi1(&[0xF0, 0x7E, 0xF8, 0x7F, 0x06, 0x01, 0xF7, 0xFC]),
+            "SysEx len=6 [f0 7e 7f 06 01 f7]; TimingClock; Stop"
+        );
+        let long: Vec<u8> = std::iter::once(0xF0)
+            .chain(0..40)
+            .chain(std::iter::once(0xF7))
+            .collect();
+        assert_eq!(
+            midi1(&long),
+            "SysEx len=42 [f0 00 01 02 03 04 05 06 07 08 09 0a 0b 0c 0d 0e ...]"
+        );
+        assert_eq!(
+            midi1(&[0xF0, 0x43, 0x10]),
+            "SysEx len=3 [f0 43 10] (unterminated)"
+        );
+        assert_eq!(
+            midi1(&[0xF0, 0x43, 0x90, 60, 1]),
+            "SysEx len=2 [f0 43] (unterminated); NoteOn ch=1 note=60(C4) vel=1"
+        );
+    }
+
+    #[test]
+    fn malformed_midi1() {
+        assert_eq!(midi1(&[0x3C, 0x40]), "Invalid [3c 40]");
+        assert_eq!(
+            midi1(&[0x90, 60, 0x80, 60, 0]),
+            "NoteOn (truncated) [90 3c]; NoteOff ch=1 note=60(C4) vel=0"
+        );
+        assert_eq!(midi1(&[0xE0]), "PitchBend (truncated) [e0]");
+        assert_eq!(midi1(&[0xF7]), "EndOfExclusive");
+    }
+
+    #[test]
+    fn ump_messages() {
+        assert_eq!(
+            ump(&[0x40903c00, 0xffff0000]),
+            "MIDI2 NoteOn g=0 ch=1 note=60(C4) vel=0xffff"
+        );
+        assert_eq!(
+            ump(&[0x40b24a00, 0x80000000]),
+            "MIDI2 CC g=0 ch=3 cc=74 val=0x80000000"
+        );
+        assert_eq!(
+            ump(&[0x43c00001, 0x05000102, 0x40e00000, 0x80000000, 0x40203040, 0x1]),
+            "MIDI2 ProgramChange g=3 ch=1 program=5 bank=1:2; \
+             MIDI2 PitchBend g=0 ch=1 val=0x80000000; \
+             MIDI2 RPN g=0 ch=1 bank=48 index=64 val=0x00000001"
+        );
+        assert_eq!(
+            ump(&[0x21903c7f, 0x11f80000, 0x12f20102, 0x00000000]),
+            "MIDI1 NoteOn g=1 ch=1 note=60(C4) vel=127; System TimingClock g=1; \
+             System SongPosition g=2 val=257; NOOP"
+        );
+        assert_eq!(
+            ump(&[0x30167e7f, 0x06010203, 0x33310400, 0x00000000]),
+            "SysEx7 Start g=0 [7e 7f 06 01 02 03]; SysEx7 End g=3 [04]"
+        );
+    }
+
+    #[test]
+    fn malformed_ump() {
+        assert_eq!(ump(&[0x40903c00]), "Truncated [40903c00]");
+        assert_eq!(ump(&[0x20f80000]), "UMP type=2 g=0 [20f80000]");
+        assert_eq!(ump(&[0x10900000]), "System g=0 [10900000]");
+        assert_eq!(
+            ump(&[0xd0000000, 1, 2, 3]),
+            "UMP type=d g=0 [d0000000 00000001 00000002 00000003]"
+        );
+    }
+}
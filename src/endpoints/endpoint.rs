@@ -4,6 +4,7 @@ use std::ops::Deref;
 use coremidi_sys::{MIDIEndpointRef, MIDIFlushOutput};
 
 use crate::object::Object;
+use crate::scheduler::cancel_scheduled;
 
 /// A MIDI source or source, owned by an entity.
 /// See [MIDIEndpointRef](https://developer.apple.com/documentation/coremidi/midiendpointref).
@@ -22,10 +23,11 @@ impl Endpoint {
         }
     }
 
-    /// Unschedules previously-sent packets.
+    /// Unschedules previously-sent packets, including those still queued in a [crate::SendScheduler].
     /// See [MIDIFlushOutput](https://developer.apple.com/documentation/coremidi/1495312-midiflushoutput).
     ///
     pub fn flush(&self) -> Result<(), OSStatus> {
+        cancel_scheduled(Some(self.object.0));
         let status = unsafe { MIDIFlushOutput(self.object.0) };
         if status == 0 {
             Ok(())
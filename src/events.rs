@@ -1,16 +1,163 @@
+use std::borrow::Borrow;
 use std::fmt::Formatter;
 use std::marker::PhantomData;
 use std::mem::size_of;
+use std::num::NonZeroU64;
 use std::ops::Deref;
 use std::slice;
+use std::time::Duration;
 
 use coremidi_sys::{
     MIDIEventList, MIDIEventListAdd, MIDIEventListInit, MIDIEventPacket, MIDIEventPacketNext,
 };
 
+use crate::clock::system_message_word;
+use crate::decode::write_ump_messages;
+use crate::host_time::{nanos_to_ticks, ticks_to_nanos};
 use crate::protocol::Protocol;
+use crate::sysex::sysex7_words;
 
-pub type Timestamp = u64;
+/// The time at which packets or events are to be played, or at which they were received.
+///
+/// CoreMIDI represents it as a host time where zero means "now", which is modelled here
+/// by the [Timestamp::Now] variant. `Now` is ordered before any scheduled host time.
+///
+/// ```
+/// use coremidi::Timestamp;
+/// use std::time::Duration;
+///
+/// let timestamp = Timestamp::from_host_time(1000);
+/// assert_eq!(timestamp.host_time(), 1000);
+/// assert!(Timestamp::Now < timestamp);
+/// assert_eq!(Timestamp::from_host_time(0), Timestamp::Now);
+/// assert_eq!(Timestamp::Now.checked_add(Duration::from_millis(1)), None);
+/// assert_eq!(timestamp.checked_sub(Duration::ZERO), Some(timestamp));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Timestamp {
+    /// As soon as possible.
+    Now,
+    /// A host time in ticks, which is never zero.
+    HostTime(NonZeroU64),
+}
+
+impl Timestamp {
+    /// Create a timestamp from a host time in ticks, where zero means [Timestamp::Now].
+    ///
+    pub fn from_host_time(host_time: u64) -> Self {
+        match NonZeroU64::new(host_time) {
+            Some(host_time) => Self::HostTime(host_time),
+            None => Self::Now,
+        }
+    }
+
+    /// Get the host time in ticks, where zero means [Timestamp::Now].
+    ///
+    pub fn host_time(&self) -> u64 {
+        match *self {
+            Self::Now => 0,
+            Self::HostTime(host_time) => host_time.get(),
+        }
+    }
+
+    /// Check whether the timestamp means "now" rather than a scheduled time.
+    ///
+    pub fn is_now(&self) -> bool {
+        *self == Self::Now
+    }
+
+    /// Get the host time after a duration, or `None` for [Timestamp::Now] or on overflow.
+    ///
+    pub fn checked_add(&self, duration: Duration) -> Option<Self> {
+        match *self {
+            Self::Now => None,
+            Self::HostTime(host_time) => host_time
+                .get()
+                .checked_add(nanos_to_ticks(duration_nanos(duration)?))
+                .map(Self::from_host_time),
+        }
+    }
+
+    /// Get the host time before a duration, or `None` for [Timestamp::Now] or when
+    /// the result would not be a scheduled host time.
+    ///
+    pub fn checked_sub(&self, duration: Duration) -> Option<Self> {
+        match *self {
+            Self::Now => None,
+            Self::HostTime(host_time) => host_time
+                .get()
+                .checked_sub(nanos_to_ticks(duration_nanos(duration)?))
+                .and_then(NonZeroU64::new)
+                .map(Self::HostTime),
+        }
+    }
+
+    /// Get the duration elapsed from an earlier timestamp to this one, or `None` when
+    /// any of them is [Timestamp::Now] or the earlier one is actually later.
+    ///
+    pub fn checked_duration_since(&self, earlier: Timestamp) -> Option<Duration> {
+        match (*self, earlier) {
+            (Self::HostTime(later), Self::HostTime(earlier)) => later
+                .get()
+                .checked_sub(earlier.get())
+                .map(|ticks| Duration::from_nanos(ticks_to_nanos(ticks))),
+            _ => None,
+        }
+    }
+}
+
+fn duration_nanos(duration: Duration) -> Option<u64> {
+    u64::try_from(duration.as_nanos()).ok()
+}
+
+impl Default for Timestamp {
+    fn default() -> Self {
+        Self::Now
+    }
+}
+
+impl From<u64> for Timestamp {
+    fn from(host_time: u64) -> Self {
+        Self::from_host_time(host_time)
+    }
+}
+
+impl From<Timestamp> for u64 {
+    fn from(timestamp: Timestamp) -> Self {
+        timestamp.host_time()
+    }
+}
+
+impl std::fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Now => f.pad("now"),
+            Self::HostTime(host_time) => std::fmt::Display::fmt(host_time, f),
+        }
+    }
+}
+
+/// The error returned when pushing a packet whose timestamp is earlier than the previous one.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimestampOrderError {
+    /// The timestamp of the last packet in the buffer.
+    pub previous: Timestamp,
+    /// The rejected timestamp.
+    pub timestamp: Timestamp,
+}
+
+impl std::fmt::Display for TimestampOrderError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "timestamp {} is earlier than the previous one {}",
+            self.timestamp, self.previous
+        )
+    }
+}
+
+impl std::error::Error for TimestampOrderError {}
 
 /// A variable-length list of MIDI event packets
 /// See [MIDIEventList](https://developer.apple.com/documentation/coremidi/midieventlist)
@@ -49,8 +196,46 @@ impl EventList {
     pub(crate) unsafe fn as_ptr(&self) -> *const MIDIEventList {
         self as *const EventList as *const MIDIEventList
     }
+
+    /// Get the number of bytes used by the list, and the offset of its last packet.
+    fn layout(&self) -> (usize, usize) {
+        let start = self as *const EventList as usize;
+        let mut packet_ptr = std::ptr::addr_of!(self.0.packet) as *const MIDIEventPacket;
+        let mut layout = (packet_ptr as usize - start, packet_ptr as usize - start);
+        for _ in 0..self.len() {
+            let packet = unsafe { &*packet_ptr };
+            let end = packet.words.as_ptr() as usize + packet.wordCount as usize * size_of::<u32>();
+            layout = (end - start, packet_ptr as usize - start);
+            packet_ptr = unsafe { MIDIEventPacketNext(packet_ptr) };
+        }
+        layout
+    }
+}
+
+/// Copying an `EventList` gives an [EventBuffer] with the same packets, that is not bound
+/// to the lifetime of the original list, as the one given to an input callback.
+///
+/// ```
+/// use coremidi::{EventBuffer, EventList, Protocol, Timestamp};
+///
+/// let buffer = EventBuffer::new(Protocol::Midi20).with_packet(Timestamp::Now, &[0x40903c00, 0xffff0000]);
+/// let event_list: &EventList = &buffer;
+/// let owned: EventBuffer = event_list.to_owned();
+/// assert_eq!(owned.protocol(), Protocol::Midi20);
+/// assert_eq!(owned.iter().next().unwrap().data(), &[0x40903c00, 0xffff0000]);
+/// ```
+impl ToOwned for EventList {
+    type Owned = EventBuffer;
+
+    fn to_owned(&self) -> EventBuffer {
+        let mut buffer = EventBuffer::with_capacity(self.layout().0, self.protocol());
+        buffer.copy_from(self);
+        buffer
+    }
 }
 
+/// With the alternate flag, as in `{:#?}`, the messages of the packets are decoded.
+///
 impl std::fmt::Debug for EventList {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         writeln!(
@@ -60,7 +245,11 @@ impl std::fmt::Debug for EventList {
             self.len()
         )?;
         for packet in self.iter() {
-            writeln!(f, "{:?}", packet)?;
+            if f.alternate() {
+                writeln!(f, "{:#?}", packet)?;
+            } else {
+                writeln!(f, "{:?}", packet)?;
+            }
         }
         Ok(())
     }
@@ -91,7 +280,7 @@ pub struct EventPacket(MIDIEventPacket);
 
 impl EventPacket {
     pub fn timestamp(&self) -> Timestamp {
-        self.0.timeStamp as Timestamp
+        Timestamp::from_host_time(self.0.timeStamp)
     }
 
     /// Get the packet data. This method just gives raw MIDI words. You would need another
@@ -104,9 +293,39 @@ impl EventPacket {
     }
 }
 
+/// With the alternate flag, as in `{:#?}`, the messages are decoded.
+///
 impl std::fmt::Debug for EventPacket {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "  {:024}:", self.timestamp())?;
+        write!(f, "  {:024}:", self.timestamp().host_time())?;
+        if f.alternate() {
+            write!(f, " ")?;
+            return write_ump_messages(f, self.data());
+        }
+        for word in self.data().iter() {
+            write!(f, " {:08x}", word)?;
+        }
+        Ok(())
+    }
+}
+
+/// The words are shown in hex, or decoded into messages with the alternate flag, as in `{:#}`.
+/// Incomplete packets and unknown message types are shown in hex.
+///
+/// ```
+/// use coremidi::{EventBuffer, Protocol, Timestamp};
+/// let buffer = EventBuffer::new(Protocol::Midi20).with_packet(Timestamp::from_host_time(42), &[0x40b24a00, 0x80000000]);
+/// let packet = buffer.iter().next().unwrap();
+/// assert_eq!(format!("{}", packet), "000000000000002a: 40b24a00 80000000");
+/// assert_eq!(format!("{:#}", packet), "000000000000002a: MIDI2 CC g=0 ch=3 cc=74 val=0x80000000");
+/// ```
+impl std::fmt::Display for EventPacket {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:016x}:", self.timestamp().host_time())?;
+        if f.alternate() {
+            write!(f, " ")?;
+            return write_ump_messages(f, self.data());
+        }
         for word in self.data().iter() {
             write!(f, " {:08x}", word)?;
         }
@@ -162,14 +381,14 @@ impl EventBuffer {
     /// use coremidi::{Protocol, Timestamp, EventBuffer};
     ///
     /// let buffer = EventBuffer::new(Protocol::Midi20)
-    ///     .with_packet(0, &[0x40903c00, 0xffff0000]); // Note On for Middle C
+    ///     .with_packet(Timestamp::Now, &[0x40903c00, 0xffff0000]); // Note On for Middle C
     ///
     /// assert_eq!(buffer.len(), 1);
     /// assert_eq!(
     ///     buffer.iter()
     ///         .map(|packet| (packet.timestamp(), packet.data().to_vec()))
     ///         .collect::<Vec<(Timestamp, Vec<u32>)>>(),
-    ///     vec![(0, vec![0x40903c00, 0xffff0000])],
+    ///     vec![(Timestamp::Now, vec![0x40903c00, 0xffff0000])],
     /// )
     /// ```
     pub fn with_packet(mut self, timestamp: Timestamp, data: &[u32]) -> Self {
@@ -184,7 +403,7 @@ impl EventBuffer {
     /// The timestamp applies to the first MIDI word in the packet.
     ///
     /// An event must not have a timestamp that is smaller than that of a previous event
-    /// in the same `EventBuffer`
+    /// in the same `EventBuffer`, which [EventBuffer::try_push] checks.
     ///
     /// Example:
     ///
@@ -192,14 +411,14 @@ impl EventBuffer {
     /// use coremidi::{EventBuffer, Protocol, Timestamp};
     ///
     /// let mut buffer = EventBuffer::new(Protocol::Midi20);
-    /// buffer.push(0, &[0x40903c00, 0xffff0000]); // Note On for Middle C
+    /// buffer.push(Timestamp::Now, &[0x40903c00, 0xffff0000]); // Note On for Middle C
     ///
     /// assert_eq!(buffer.len(), 1);
     /// assert_eq!(
     ///     buffer.iter()
     ///         .map(|packet| (packet.timestamp(), packet.data().to_vec()))
     ///         .collect::<Vec<(Timestamp, Vec<u32>)>>(),
-    ///     vec![(0, vec![0x40903c00, 0xffff0000])],
+    ///     vec![(Timestamp::Now, vec![0x40903c00, 0xffff0000])],
     /// )
     /// ```
     pub fn push(&mut self, timestamp: Timestamp, data: &[u32]) -> &mut Self {
@@ -214,7 +433,7 @@ impl EventBuffer {
                 packet_list_ptr,
                 self.storage.capacity() as u64,
                 current_packet_ptr,
-                timestamp,
+                timestamp.host_time(),
                 data.len() as u64,
                 data.as_ptr(),
             )
@@ -227,6 +446,82 @@ impl EventBuffer {
         self
     }
 
+    /// Add a new event like [EventBuffer::push], but rejecting a timestamp that is
+    /// earlier than the one of the last packet in the buffer.
+    ///
+    /// Example:
+    ///
+    /// ```
+    /// use coremidi::{EventBuffer, Protocol, Timestamp};
+    ///
+    /// let mut buffer = EventBuffer::new(Protocol::Midi20);
+    /// buffer.try_push(Timestamp::from_host_time(20), &[0x40903c00, 0xffff0000]).unwrap();
+    ///
+    /// let result = buffer.try_push(Timestamp::from_host_time(10), &[0x40803c00, 0x00000000]);
+    /// assert_eq!(result.err().map(|error| error.previous), Some(Timestamp::from_host_time(20)));
+    /// assert_eq!(buffer.len(), 1);
+    /// ```
+    pub fn try_push(
+        &mut self,
+        timestamp: Timestamp,
+        data: &[u32],
+    ) -> Result<&mut Self, TimestampOrderError> {
+        match self.last_timestamp() {
+            Some(previous) if timestamp < previous => Err(TimestampOrderError {
+                previous,
+                timestamp,
+            }),
+            _ => Ok(self.push(timestamp, data)),
+        }
+    }
+
+    /// Add a System Exclusive message as a sequence of SysEx7 Universal MIDI Packets,
+    /// all of them with the same timestamp and group.
+    ///
+    /// The message can be given with or without its `F0` and `F7` framing bytes,
+    /// which are not transmitted in UMP.
+    ///
+    /// Example:
+    ///
+    /// ```
+    /// use coremidi::{EventBuffer, Protocol, Timestamp};
+    ///
+    /// let mut buffer = EventBuffer::new(Protocol::Midi10);
+    /// buffer.push_sysex7(Timestamp::Now, 0, &[0xf0, 0x7e, 0x7f, 0x06, 0x01, 0xf7]); // Identity Request
+    ///
+    /// assert_eq!(buffer.iter().next().unwrap().data(), &[0x30047e7f, 0x06010000]);
+    /// ```
+    pub fn push_sysex7(&mut self, timestamp: Timestamp, group: u8, message: &[u8]) -> &mut Self {
+        for packet in sysex7_words(group, message).chunks(2) {
+            self.push(timestamp, packet);
+        }
+        self
+    }
+
+    /// Replace the content of the buffer (including the protocol) with a copy of an `EventList`.
+    ///
+    /// It reuses the current storage, so it doesn't allocate when the list fits in [EventBuffer::capacity],
+    /// which makes it suitable for copying the lists received in input callbacks into preallocated buffers.
+    ///
+    /// ```
+    /// use coremidi::{EventBuffer, Protocol, Timestamp};
+    ///
+    /// let received = EventBuffer::new(Protocol::Midi10).with_packet(Timestamp::Now, &[0x2090407f]);
+    /// let mut buffer = EventBuffer::with_capacity(1024, Protocol::Midi20);
+    /// buffer.copy_from(&received);
+    /// assert_eq!(buffer.capacity(), 1024);
+    /// assert_eq!(buffer.protocol(), Protocol::Midi10);
+    /// assert_eq!(buffer.iter().next().unwrap().data(), &[0x2090407f]);
+    /// ```
+    pub fn copy_from(&mut self, event_list: &EventList) {
+        let (len, last_packet_offset) = event_list.layout();
+        unsafe {
+            self.storage
+                .copy_from_ptr(event_list as *const EventList as *const u8, len);
+        }
+        self.current_packet_offset = last_packet_offset;
+    }
+
     /// Clears the buffer, removing all packets.
     /// Note that this method has no effect on the allocated capacity of the buffer.
     pub fn clear(&mut self) {
@@ -238,6 +533,18 @@ impl EventBuffer {
         };
     }
 
+    fn last_timestamp(&self) -> Option<Timestamp> {
+        if self.as_ref().is_empty() {
+            None
+        } else {
+            let current_packet = unsafe {
+                &*(self.storage.as_ptr::<u8>().add(self.current_packet_offset)
+                    as *const EventPacket)
+            };
+            Some(current_packet.timestamp())
+        }
+    }
+
     fn ensure_capacity(&mut self, data_len: usize) {
         let next_capacity =
             self.aligned_bytes_len() + Self::PACKET_HEADER_SIZE + data_len * size_of::<u32>();
@@ -267,6 +574,12 @@ impl EventBuffer {
     }
 }
 
+impl Borrow<EventList> for EventBuffer {
+    fn borrow(&self) -> &EventList {
+        self.as_ref()
+    }
+}
+
 impl AsRef<EventList> for EventBuffer {
     #[inline]
     fn as_ref(&self) -> &EventList {
@@ -283,6 +596,27 @@ impl Deref for EventBuffer {
     }
 }
 
+/// Get the number of words of a Universal MIDI Packet from its first word.
+pub(crate) fn ump_word_count(word: u32) -> usize {
+    match word >> 28 {
+        0x0..=0x2 | 0x6 | 0x7 => 1,
+        0x3 | 0x4 | 0x8..=0xA => 2,
+        0xB | 0xC => 3,
+        _ => 4,
+    }
+}
+
+/// Convert a complete MIDI 1.0 message into Universal MIDI Packets, using SysEx7 packets
+/// for System Exclusive and MIDI 1.0 system or channel voice packets for the rest.
+pub(crate) fn midi1_message_words(group: u8, message: &[u8]) -> Vec<u32> {
+    match message.first() {
+        None => Vec::new(),
+        Some(0xF0) => sysex7_words(group, message),
+        Some(status) if *status >= 0xF0 => vec![system_message_word(group, message)],
+        Some(_) => vec![0x2 << 28 | (system_message_word(group, message) & 0x0FFF_FFFF)],
+    }
+}
+
 #[derive(Clone)]
 pub(crate) enum Storage {
     /// Inline stores the data directly on the stack, if it is small enough.
@@ -365,6 +699,12 @@ impl Storage {
         }
     }
 
+    /// Replace the first `len` bytes with the ones at `src`, growing only if they don't fit.
+    pub(crate) unsafe fn copy_from_ptr(&mut self, src: *const u8, len: usize) {
+        self.ensure_capacity(len);
+        std::ptr::copy_nonoverlapping(src, self.as_mut_ptr::<u8>(), len);
+    }
+
     #[inline]
     pub(crate) unsafe fn as_ptr<T>(&self) -> *const T {
         match *self {
@@ -397,6 +737,7 @@ mod tests {
         kMIDIProtocol_2_0, ByteCount, MIDIEventList, MIDIEventListAdd, MIDIEventListInit,
         MIDIProtocolID,
     };
+    use std::time::Duration;
 
     #[test]
     fn event_list_accessors() {
@@ -434,8 +775,8 @@ mod tests {
         assert_eq!(
             event_list
                 .iter()
-                .map(|packet| (packet.timestamp(), packet.data().to_vec()))
-                .collect::<Vec<(Timestamp, Vec<u32>)>>(),
+                .map(|packet| (packet.timestamp().host_time(), packet.data().to_vec()))
+                .collect::<Vec<(u64, Vec<u32>)>>(),
             vec![(10, vec![1, 2]), (20, vec![3, 4, 5]),]
         );
     }
@@ -468,15 +809,15 @@ mod tests {
     #[test]
     fn event_buffer_with_packet() {
         let event_buffer = EventBuffer::new(Protocol::Midi20)
-            .with_packet(10, &[1, 2])
-            .with_packet(20, &[3, 4, 5]);
+            .with_packet(Timestamp::from_host_time(10), &[1, 2])
+            .with_packet(Timestamp::from_host_time(20), &[3, 4, 5]);
 
         assert_eq!(event_buffer.len(), 2);
         assert_eq!(
             event_buffer
                 .iter()
-                .map(|packet| (packet.timestamp(), packet.data().to_vec()))
-                .collect::<Vec<(Timestamp, Vec<u32>)>>(),
+                .map(|packet| (packet.timestamp().host_time(), packet.data().to_vec()))
+                .collect::<Vec<(u64, Vec<u32>)>>(),
             vec![(10, vec![1, 2]), (20, vec![3, 4, 5]),]
         );
     }
@@ -484,14 +825,16 @@ mod tests {
     #[test]
     fn event_buffer_push_within_capacity() {
         let mut event_buffer = EventBuffer::new(Protocol::Midi20);
-        event_buffer.push(10, &[1, 2]).push(20, &[3, 4, 5]);
+        event_buffer
+            .push(Timestamp::from_host_time(10), &[1, 2])
+            .push(Timestamp::from_host_time(20), &[3, 4, 5]);
 
         assert_eq!(event_buffer.len(), 2);
         assert_eq!(
             event_buffer
                 .iter()
-                .map(|packet| (packet.timestamp(), packet.data().to_vec()))
-                .collect::<Vec<(Timestamp, Vec<u32>)>>(),
+                .map(|packet| (packet.timestamp().host_time(), packet.data().to_vec()))
+                .collect::<Vec<(u64, Vec<u32>)>>(),
             vec![(10, vec![1, 2]), (20, vec![3, 4, 5]),]
         );
     }
@@ -500,29 +843,64 @@ mod tests {
     fn event_buffer_push_over_capacity() {
         let mut event_buffer = EventBuffer::new(Protocol::Midi20);
         event_buffer
-            .push(10, &[1, 2])
-            .push(20, &[3, 4, 5, 6, 7, 8, 9, 10]);
+            .push(Timestamp::from_host_time(10), &[1, 2])
+            .push(Timestamp::from_host_time(20), &[3, 4, 5, 6, 7, 8, 9, 10]);
 
         assert_eq!(event_buffer.len(), 2);
         assert_eq!(
             event_buffer
                 .iter()
-                .map(|packet| (packet.timestamp(), packet.data().to_vec()))
-                .collect::<Vec<(Timestamp, Vec<u32>)>>(),
+                .map(|packet| (packet.timestamp().host_time(), packet.data().to_vec()))
+                .collect::<Vec<(u64, Vec<u32>)>>(),
             vec![(10, vec![1, 2]), (20, vec![3, 4, 5, 6, 7, 8, 9, 10])]
         );
     }
 
+    #[test]
+    fn event_buffer_try_push_after_clear() {
+        let mut event_buffer = EventBuffer::new(Protocol::Midi20);
+        event_buffer
+            .try_push(Timestamp::from_host_time(20), &[1, 2])
+            .unwrap();
+        assert!(event_buffer
+            .try_push(Timestamp::from_host_time(10), &[3, 4])
+            .is_err());
+        event_buffer.clear();
+        assert!(event_buffer
+            .try_push(Timestamp::from_host_time(10), &[3, 4])
+            .is_ok());
+        assert_eq!(event_buffer.len(), 1);
+    }
+
+    #[test]
+    fn timestamp_arithmetic() {
+        let timestamp = Timestamp::from_host_time(u64::MAX);
+        assert_eq!(timestamp.checked_add(Duration::from_secs(1)), None);
+        assert_eq!(
+            timestamp.checked_duration_since(Timestamp::from_host_time(u64::MAX)),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(timestamp.checked_duration_since(Timestamp::Now), None);
+        assert_eq!(
+            Timestamp::from_host_time(1).checked_sub(Duration::ZERO),
+            Some(Timestamp::from_host_time(1))
+        );
+        assert_eq!(Timestamp::Now.checked_sub(Duration::ZERO), None);
+        assert_eq!(u64::from(Timestamp::Now), 0);
+        assert_eq!(Timestamp::Now.to_string(), "now");
+    }
+
     #[test]
     fn event_buffer_clear() {
-        let mut event_buffer = EventBuffer::new(Protocol::Midi20).with_packet(10, &[1, 2]);
+        let mut event_buffer =
+            EventBuffer::new(Protocol::Midi20).with_packet(Timestamp::from_host_time(10), &[1, 2]);
 
         assert_eq!(event_buffer.len(), 1);
         assert_eq!(
             event_buffer
                 .iter()
-                .map(|packet| (packet.timestamp(), packet.data().to_vec()))
-                .collect::<Vec<(Timestamp, Vec<u32>)>>(),
+                .map(|packet| (packet.timestamp().host_time(), packet.data().to_vec()))
+                .collect::<Vec<(u64, Vec<u32>)>>(),
             vec![(10, vec![1, 2])]
         );
 
@@ -533,9 +911,43 @@ mod tests {
         assert_eq!(
             event_buffer
                 .iter()
-                .map(|packet| (packet.timestamp(), packet.data().to_vec()))
-                .collect::<Vec<(Timestamp, Vec<u32>)>>(),
+                .map(|packet| (packet.timestamp().host_time(), packet.data().to_vec()))
+                .collect::<Vec<(u64, Vec<u32>)>>(),
             vec![]
         );
     }
+
+    #[test]
+    fn copy_from_and_to_owned() {
+        let mut source = EventBuffer::new(Protocol::Midi20);
+        for i in 0..16u32 {
+            source.push(
+                Timestamp::from_host_time(i as u64 + 1),
+                &[0x40903c00 | i, 0xffff0000],
+            );
+        }
+        let packets = |list: &EventList| {
+            list.iter()
+                .map(|packet| (packet.timestamp(), packet.data().to_vec()))
+                .collect::<Vec<_>>()
+        };
+
+        let mut buffer = EventBuffer::with_capacity(1024, Protocol::Midi10);
+        let storage_ptr = unsafe { buffer.storage.as_ptr::<u8>() };
+        buffer.copy_from(&source);
+        assert_eq!(unsafe { buffer.storage.as_ptr::<u8>() }, storage_ptr);
+        assert_eq!(buffer.protocol(), Protocol::Midi20);
+        assert_eq!(packets(&buffer), packets(&source));
+
+        buffer.push(Timestamp::from_host_time(100), &[0x10f80000]);
+        assert_eq!(buffer.len(), 17);
+        assert_eq!(
+            buffer.last_timestamp(),
+            Some(Timestamp::from_host_time(100))
+        );
+
+        let list: &EventList = &source;
+        let owned = list.to_owned();
+        assert_eq!(packets(&owned), packets(&source));
+    }
 }
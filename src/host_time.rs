@@ -0,0 +1,327 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::events::Timestamp;
+
+static TIMEBASE: AtomicU64 = AtomicU64::new(0);
+
+#[cfg(target_os = "macos")]
+#[repr(C)]
+struct MachTimebaseInfo {
+    numer: u32,
+    denom: u32,
+}
+
+#[cfg(target_os = "macos")]
+extern "C" {
+    fn mach_timebase_info(info: *mut MachTimebaseInfo) -> i32;
+    fn mach_absolute_time() -> u64;
+}
+
+#[cfg(target_os = "macos")]
+fn load_timebase() -> (u32, u32) {
+    let mut info = MachTimebaseInfo { numer: 0, denom: 0 };
+    let status = unsafe { mach_timebase_info(&mut info) };
+    if status != 0 || info.numer == 0 || info.denom == 0 {
+        (1, 1)
+    } else {
+        (info.numer, info.denom)
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+fn load_timebase() -> (u32, u32) {
+    (1, 1)
+}
+
+#[cfg(target_os = "macos")]
+fn host_time_now() -> u64 {
+    unsafe { mach_absolute_time() }
+}
+
+/// Outside macOS the host time is the number of nanoseconds since the first time it was read,
+/// plus one, so that it is never 0, which is [Timestamp::Now].
+#[cfg(not(target_os = "macos"))]
+fn host_time_now() -> u64 {
+    use std::sync::Once;
+
+    static INIT: Once = Once::new();
+    static mut EPOCH: Option<Instant> = None;
+
+    INIT.call_once(|| unsafe { EPOCH = Some(Instant::now()) });
+    let epoch = unsafe { EPOCH.unwrap_or_else(Instant::now) };
+    (epoch.elapsed().as_nanos() as u64).saturating_add(1)
+}
+
+/// Get the fraction that converts host time ticks into nanoseconds.
+pub(crate) fn timebase() -> (u32, u32) {
+    let packed = TIMEBASE.load(Ordering::Relaxed);
+    if packed != 0 {
+        return ((packed >> 32) as u32, packed as u32);
+    }
+    let (numer, denom) = load_timebase();
+    TIMEBASE.store((numer as u64) << 32 | denom as u64, Ordering::Relaxed);
+    (numer, denom)
+}
+
+pub(crate) fn ticks_to_nanos(ticks: u64) -> u64 {
+    let (numer, denom) = timebase();
+    (ticks as u128 * numer as u128 / denom as u128) as u64
+}
+
+pub(crate) fn nanos_to_ticks(nanos: u64) -> u64 {
+    let (numer, denom) = timebase();
+    (nanos as u128 * denom as u128 / numer as u128) as u64
+}
+
+/// A source of host time, the time base used by the timestamps of packets and events.
+///
+/// The conversions between host time ticks and nanoseconds use the fraction returned by
+/// [HostClock::timebase], which is the host timebase unless a clock overrides it.
+///
+/// ```
+/// use coremidi::{HostClock, SystemClock};
+/// use std::time::Duration;
+/// let clock = SystemClock;
+/// let now = clock.now();
+/// let later = clock.after(Duration::from_millis(20));
+/// assert!(clock.duration_between(now, later) >= Duration::from_millis(20));
+/// ```
+pub trait HostClock {
+    /// Get the current host time.
+    ///
+    fn now(&self) -> Timestamp;
+
+    /// Get the fraction (numerator, denominator) that converts host time ticks into nanoseconds.
+    ///
+    fn timebase(&self) -> (u32, u32) {
+        timebase()
+    }
+
+    /// Convert host time ticks into nanoseconds.
+    ///
+    fn ticks_to_nanos(&self, ticks: u64) -> u64 {
+        let (numer, denom) = self.timebase();
+        (ticks as u128 * numer as u128 / denom as u128) as u64
+    }
+
+    /// Convert nanoseconds into host time ticks.
+    ///
+    fn nanos_to_ticks(&self, nanos: u64) -> u64 {
+        let (numer, denom) = self.timebase();
+        (nanos as u128 * denom as u128 / numer as u128) as u64
+    }
+
+    /// Convert host time ticks into a duration.
+    ///
+    fn ticks_to_duration(&self, ticks: u64) -> Duration {
+        Duration::from_nanos(self.ticks_to_nanos(ticks))
+    }
+
+    /// Convert a duration into host time ticks.
+    ///
+    fn duration_to_ticks(&self, duration: Duration) -> u64 {
+        self.nanos_to_ticks(duration.as_nanos().min(u64::MAX as u128) as u64)
+    }
+
+    /// Get the host time after a duration from now.
+    ///
+    fn after(&self, duration: Duration) -> Timestamp {
+        self.add(self.now(), duration)
+    }
+
+    /// Resolve [Timestamp::Now] into the current host time, leaving other timestamps as they are.
+    ///
+    fn resolve(&self, timestamp: Timestamp) -> Timestamp {
+        match timestamp {
+            Timestamp::Now => self.now(),
+            timestamp => timestamp,
+        }
+    }
+
+    /// Add a duration to a host time, saturating at the maximum host time.
+    /// [Timestamp::Now] is taken as the current host time.
+    ///
+    fn add(&self, timestamp: Timestamp, duration: Duration) -> Timestamp {
+        let host_time = self.resolve(timestamp).host_time();
+        Timestamp::from_host_time(host_time.saturating_add(self.duration_to_ticks(duration)))
+    }
+
+    /// Subtract a duration from a host time, saturating at [Timestamp::Now].
+    /// [Timestamp::Now] is taken as the current host time.
+    ///
+    fn sub(&self, timestamp: Timestamp, duration: Duration) -> Timestamp {
+        let host_time = self.resolve(timestamp).host_time();
+        Timestamp::from_host_time(host_time.saturating_sub(self.duration_to_ticks(duration)))
+    }
+
+    /// Get the duration from a host time to a later one, or zero if it is not later.
+    /// [Timestamp::Now] is taken as the current host time.
+    ///
+    fn duration_between(&self, earlier: Timestamp, later: Timestamp) -> Duration {
+        let earlier = self.resolve(earlier).host_time();
+        let later = self.resolve(later).host_time();
+        self.ticks_to_duration(later.saturating_sub(earlier))
+    }
+}
+
+/// The clock of the system, as used by CoreMIDI.
+///
+/// On macOS it reads `mach_absolute_time`. On other systems the host time is the number of
+/// nanoseconds since the first time the clock was read, starting at 1.
+///
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SystemClock;
+
+impl SystemClock {
+    /// Get the host time of an instant.
+    ///
+    pub fn timestamp_from_instant(&self, instant: Instant) -> Timestamp {
+        let (now_instant, now) = (Instant::now(), self.now());
+        if instant >= now_instant {
+            self.add(now, instant - now_instant)
+        } else {
+            self.sub(now, now_instant - instant)
+        }
+    }
+
+    /// Get the instant of a host time.
+    ///
+    pub fn instant_from_timestamp(&self, timestamp: Timestamp) -> Instant {
+        let (now_instant, now) = (Instant::now(), self.now());
+        if timestamp >= now {
+            now_instant + self.duration_between(now, timestamp)
+        } else {
+            now_instant - self.duration_between(timestamp, now)
+        }
+    }
+}
+
+impl HostClock for SystemClock {
+    fn now(&self) -> Timestamp {
+        Timestamp::from_host_time(host_time_now())
+    }
+}
+
+/// A clock that only moves when told to, to make code scheduling timestamps deterministic in tests.
+///
+/// ```
+/// use coremidi::{HostClock, ManualClock, Timestamp};
+/// use std::time::Duration;
+/// let clock = ManualClock::new(Timestamp::from_host_time(1000));
+/// clock.advance(Duration::from_millis(5));
+/// assert_eq!(
+///     clock.now().host_time(),
+///     1000 + clock.duration_to_ticks(Duration::from_millis(5))
+/// );
+/// ```
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    now: AtomicU64,
+}
+
+impl ManualClock {
+    /// Create a clock at a host time.
+    ///
+    pub fn new(now: Timestamp) -> Self {
+        Self {
+            now: AtomicU64::new(now.host_time()),
+        }
+    }
+
+    /// Set the current host time.
+    ///
+    pub fn set(&self, now: Timestamp) {
+        self.now.store(now.host_time(), Ordering::SeqCst);
+    }
+
+    /// Move the clock forward by a duration.
+    ///
+    pub fn advance(&self, duration: Duration) {
+        let ticks = self.duration_to_ticks(duration);
+        self.now.fetch_add(ticks, Ordering::SeqCst);
+    }
+}
+
+impl HostClock for ManualClock {
+    fn now(&self) -> Timestamp {
+        Timestamp::from_host_time(self.now.load(Ordering::SeqCst))
+    }
+}
+
+impl<C: HostClock + ?Sized> HostClock for std::sync::Arc<C> {
+    fn now(&self) -> Timestamp {
+        (**self).now()
+    }
+}
+
+impl<C: HostClock + ?Sized> HostClock for &C {
+    fn now(&self) -> Timestamp {
+        (**self).now()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions_round_trip() {
+        let clock = SystemClock;
+        let ticks = clock.duration_to_ticks(Duration::from_secs(3600));
+        assert_eq!(clock.ticks_to_duration(ticks), Duration::from_secs(3600));
+        let (early, late) = (Timestamp::from_host_time(10), Timestamp::from_host_time(20));
+        assert_eq!(clock.sub(early, Duration::from_secs(1)), Timestamp::Now);
+        assert_eq!(clock.duration_between(late, early), Duration::ZERO);
+    }
+
+    #[test]
+    fn conversions_use_overridden_timebase() {
+        struct SlowClock;
+        impl HostClock for SlowClock {
+            fn now(&self) -> Timestamp {
+                Timestamp::from_host_time(1)
+            }
+            fn timebase(&self) -> (u32, u32) {
+                (125, 3)
+            }
+        }
+        let clock = SlowClock;
+        assert_eq!(clock.ticks_to_nanos(24), 1_000);
+        assert_eq!(clock.nanos_to_ticks(1_000), 24);
+        assert_eq!(clock.duration_to_ticks(Duration::from_millis(1)), 24_000);
+        assert_eq!(
+            clock.duration_between(Timestamp::from_host_time(1), Timestamp::from_host_time(25)),
+            Duration::from_micros(1)
+        );
+    }
+
+    #[test]
+    fn system_clock_is_monotonic() {
+        let clock = SystemClock;
+        let first = clock.now();
+        assert!(!first.is_now());
+        std::thread::sleep(Duration::from_millis(2));
+        let second = clock.now();
+        assert!(clock.duration_between(first, second) >= Duration::from_millis(2));
+
+        let instant = Instant::now() + Duration::from_millis(50);
+        let timestamp = clock.timestamp_from_instant(instant);
+        let difference = clock.duration_between(clock.after(Duration::from_millis(50)), timestamp);
+        assert!(difference < Duration::from_millis(5));
+    }
+
+    #[test]
+    fn manual_clock() {
+        let start = Timestamp::from_host_time(1);
+        let clock = std::sync::Arc::new(ManualClock::new(start));
+        let shared = clock.clone();
+        shared.advance(Duration::from_millis(20));
+        assert_eq!(
+            clock.duration_between(start, clock.now()),
+            Duration::from_millis(20)
+        );
+        clock.set(Timestamp::from_host_time(5));
+        assert_eq!(shared.now(), Timestamp::from_host_time(5));
+    }
+}
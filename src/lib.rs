@@ -11,7 +11,7 @@ This library preserves the fundamental concepts behind the CoreMIDI framework, w
 Please see the [examples](https://github.com/chris-zen/coremidi/tree/master/examples) for getting an idea of how it looks like, but if you are eager to see an example, this is how you would send some note:
 
 ```rust,no_run
-use coremidi::{Client, Destination, EventBuffer, Protocol};
+use coremidi::{Client, Destination, EventBuffer, Protocol, Timestamp};
 use std::time::Duration;
 use std::thread;
 
@@ -19,8 +19,8 @@ fn main() {
     let client = coremidi::Client::new("example-client").unwrap();
     let output_port = client.output_port("example-port").unwrap();
     let destination = Destination::from_index(0).unwrap();
-    let note_on = EventBuffer::new(Protocol::Midi10).with_packet(0, &[0x2090407f]);
-    let note_off = EventBuffer::new(Protocol::Midi10).with_packet(0, &[0x2080407f]);
+    let note_on = EventBuffer::new(Protocol::Midi10).with_packet(Timestamp::Now, &[0x2090407f]);
+    let note_off = EventBuffer::new(Protocol::Midi10).with_packet(Timestamp::Now, &[0x2080407f]);
     output_port.send(&destination, &note_on).unwrap();
     thread::sleep(Duration::from_millis(1000));
     output_port.send(&destination, &note_off).unwrap();
@@ -39,42 +39,108 @@ For handling low level MIDI data you may look into:
 
 */
 
+mod capture;
 mod client;
+mod clock;
+mod decode;
 mod device;
 mod endpoints;
 mod entity;
 mod events;
+mod host_time;
+mod mmc;
+mod msc;
+mod mtc;
 mod notifications;
 mod object;
 mod packets;
+mod panics;
+mod player;
 mod ports;
 mod properties;
 mod protocol;
+mod queue;
+mod replay;
+mod sample_dump;
+mod scala;
+mod scheduler;
+#[cfg(feature = "serde")]
+mod serialization;
+mod smf;
+#[cfg(feature = "stream")]
+mod stream;
+mod sysex;
+mod syx;
+mod text;
+mod timecode;
+mod tuning;
 
 use core_foundation_sys::base::OSStatus;
 
 use coremidi_sys::{MIDIFlushOutput, MIDIRestart};
 
+pub use crate::capture::{
+    Capture, CaptureData, CaptureError, CaptureReader, CaptureRecord, CaptureRecorder,
+    CaptureSource, CaptureWriter,
+};
 pub use crate::client::{Client, NotifyCallback};
+pub use crate::clock::{ClockEvent, ClockFollower, ClockMaster, CLOCK_TICKS_PER_QUARTER};
 pub use crate::device::Device;
 pub use crate::endpoints::destinations::{Destination, Destinations, VirtualDestination};
 pub use crate::endpoints::endpoint::Endpoint;
 pub use crate::endpoints::sources::{Source, Sources, VirtualSource};
 pub use crate::entity::Entity;
-pub use crate::events::{EventBuffer, EventList, EventListIter, EventPacket, Timestamp};
+pub use crate::events::{
+    EventBuffer, EventList, EventListIter, EventPacket, Timestamp, TimestampOrderError,
+};
+pub use crate::host_time::{HostClock, ManualClock, SystemClock};
+pub use crate::mmc::{
+    MmcCommand, MmcError, MmcMessage, MmcResponse, MmcTrack, MmcTracks, MmcTransport,
+    TransportState, MMC_ALL_CALL,
+};
+pub use crate::msc::{
+    MscCommand, MscCommandFormat, MscCue, MscDeviceId, MscError, MscMessage, MSC_MAX_MESSAGE_SIZE,
+};
+pub use crate::mtc::{MtcDirection, MtcGenerator, MtcReader};
 pub use crate::notifications::{AddedRemovedInfo, IoErrorInfo, Notification, PropertyChangedInfo};
 pub use crate::object::{Object, ObjectType};
 pub use crate::packets::{Packet, PacketBuffer, PacketList, PacketListIterator};
-pub use crate::ports::{InputPort, InputPortWithContext, OutputPort};
+pub use crate::panics::PanicPolicy;
+pub use crate::player::SmfPlayer;
+pub use crate::ports::{InputPort, InputPortWithContext, OutputPort, TaggedInputPort};
 pub use crate::properties::{
     BooleanProperty, IntegerProperty, Properties, PropertyGetter, PropertySetter, StringProperty,
 };
 pub use crate::protocol::Protocol;
+pub use crate::queue::{ump_queue, UmpMessage, UmpReceiver, UmpSender, UmpTryIter};
+pub use crate::replay::CaptureReplay;
+pub use crate::sample_dump::{
+    decode_samples, encode_samples, LoopType, SampleDumpConfig, SampleDumpError, SampleDumpHeader,
+    SampleDumpMessage, SampleDumpReceiver, SampleDumpSender, SampleDumpState,
+    SAMPLE_DUMP_PACKET_DATA_SIZE,
+};
+pub use crate::scala::{ScalaError, ScalaKeyboardMapping, ScalaScale};
+pub use crate::scheduler::{ScheduledOutput, SendScheduler};
+pub use crate::smf::{
+    Smf, SmfDivision, SmfError, SmfEvent, SmfEventKind, SmfFormat, SmfMeta, SmfTempoChange,
+    SmfTempoMap, SmfTimedEvent, SmfTrack, SmfWriter,
+};
+#[cfg(feature = "stream")]
+pub use crate::stream::{MidiStream, OverflowPolicy};
+pub use crate::sysex::{PortLink, QueueLink, SysExAssembler, SysExLink, SysExLinkError};
+pub use crate::syx::{SysExFormat, SysExSender, SyxError, SyxFile, SyxWriter};
+pub use crate::text::MidiTextError;
+pub use crate::timecode::{FrameRate, MtcMessage, SmpteTime};
+pub use crate::tuning::{
+    NoteTuning, ScaleResolution, TuningError, TuningMessage, TuningPrograms, TuningTable,
+};
 
-/// Unschedules previously-sent packets for all the endpoints.
+/// Unschedules previously-sent packets for all the endpoints,
+/// including those still queued in a [SendScheduler].
 /// See [MIDIFlushOutput](https://developer.apple.com/documentation/coremidi/1495312-midiflushoutput).
 ///
 pub fn flush() -> Result<(), OSStatus> {
+    scheduler::cancel_scheduled(None);
     let status = unsafe { MIDIFlushOutput(0) };
     unit_result_from_status(status)
 }
@@ -0,0 +1,884 @@
+use std::fmt;
+
+use crate::events::Timestamp;
+use crate::packets::{PacketBuffer, PacketList};
+use crate::sysex::{SysExAssembler, SYSEX_END, SYSEX_START};
+use crate::timecode::{MtcMessage, QuarterFrames, SmpteTime};
+
+const REAL_TIME: u8 = 0x7F;
+const MMC_COMMAND: u8 = 0x06;
+const MMC_RESPONSE: u8 = 0x07;
+
+/// The device ID that addresses all the devices.
+pub const MMC_ALL_CALL: u8 = 0x7F;
+
+const STOP: u8 = 0x01;
+const PLAY: u8 = 0x02;
+const DEFERRED_PLAY: u8 = 0x03;
+const FAST_FORWARD: u8 = 0x04;
+const REWIND: u8 = 0x05;
+const RECORD_STROBE: u8 = 0x06;
+const RECORD_EXIT: u8 = 0x07;
+const RECORD_PAUSE: u8 = 0x08;
+const PAUSE: u8 = 0x09;
+const EJECT: u8 = 0x0A;
+const CHASE: u8 = 0x0B;
+const COMMAND_ERROR_RESET: u8 = 0x0C;
+const MMC_RESET: u8 = 0x0D;
+const WRITE: u8 = 0x40;
+const MASKED_WRITE: u8 = 0x41;
+const READ: u8 = 0x42;
+const LOCATE: u8 = 0x44;
+const SHUTTLE: u8 = 0x47;
+
+const LOCATE_TARGET: u8 = 0x01;
+const LOCATE_REGISTER: u8 = 0x00;
+
+const SELECTED_TIME_CODE: u8 = 0x01;
+const RECORD_READY: u8 = 0x4F;
+
+/// The longest track bitmap, so that the count of a Record Ready write,
+/// which includes the register and the bitmap length, fits in a data byte.
+const MAX_BITMAP_LEN: usize = 125;
+
+/// A set of tracks, encoded as the MMC track bitmap.
+///
+/// ```
+/// use coremidi::{MmcTrack, MmcTracks};
+/// let mut tracks = MmcTracks::new();
+/// tracks.set(MmcTrack::Audio(1), true);
+/// tracks.set(MmcTrack::Audio(3), true);
+/// assert_eq!(tracks.bytes(), &[0x20, 0x01]);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MmcTracks {
+    bitmap: Vec<u8>,
+}
+
+/// A track of a MMC track bitmap. Audio tracks are numbered from 1 to [MmcTracks::MAX_AUDIO_TRACK],
+/// and the other audio track numbers have no bit in the bitmap.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MmcTrack {
+    Video,
+    TimeCode,
+    AuxA,
+    AuxB,
+    Audio(u16),
+}
+
+impl MmcTrack {
+    fn bit(&self) -> Option<usize> {
+        match *self {
+            Self::Video => Some(0),
+            Self::TimeCode => Some(2),
+            Self::AuxA => Some(3),
+            Self::AuxB => Some(4),
+            Self::Audio(track @ 1..=MmcTracks::MAX_AUDIO_TRACK) => Some(track as usize + 4),
+            Self::Audio(_) => None,
+        }
+    }
+}
+
+impl MmcTracks {
+    /// The highest audio track number that fits in a bitmap.
+    pub const MAX_AUDIO_TRACK: u16 = (MAX_BITMAP_LEN * 7 - 5) as u16;
+
+    /// Create an empty set of tracks.
+    ///
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a set of tracks from a bitmap, with 7 tracks per byte,
+    /// ignoring the bytes after the longest bitmap.
+    ///
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let mut tracks = Self {
+            bitmap: bytes
+                .iter()
+                .take(MAX_BITMAP_LEN)
+                .map(|b| b & 0x7F)
+                .collect(),
+        };
+        tracks.trim();
+        tracks
+    }
+
+    /// Get the bitmap bytes.
+    ///
+    pub fn bytes(&self) -> &[u8] {
+        &self.bitmap
+    }
+
+    /// Check whether a track is in the set.
+    ///
+    pub fn contains(&self, track: MmcTrack) -> bool {
+        track.bit().map_or(false, |bit| {
+            self.bitmap
+                .get(bit / 7)
+                .map_or(false, |byte| byte & (1 << (bit % 7)) != 0)
+        })
+    }
+
+    /// Add or remove a track. Audio track 0 and the ones above [MmcTracks::MAX_AUDIO_TRACK]
+    /// are ignored.
+    ///
+    pub fn set(&mut self, track: MmcTrack, value: bool) {
+        let bit = match track.bit() {
+            Some(bit) => bit,
+            None => return,
+        };
+        if self.bitmap.len() <= bit / 7 {
+            self.bitmap.resize(bit / 7 + 1, 0);
+        }
+        if value {
+            self.bitmap[bit / 7] |= 1 << (bit % 7);
+        } else {
+            self.bitmap[bit / 7] &= !(1 << (bit % 7));
+        }
+        self.trim();
+    }
+
+    /// Check whether there are no tracks in the set.
+    ///
+    pub fn is_empty(&self) -> bool {
+        self.bitmap.is_empty()
+    }
+
+    fn masked_write(&mut self, byte: u8, mask: u8, data: u8) {
+        let index = byte as usize;
+        if index >= MAX_BITMAP_LEN {
+            return;
+        }
+        if self.bitmap.len() <= index {
+            self.bitmap.resize(index + 1, 0);
+        }
+        self.bitmap[index] = (self.bitmap[index] & !mask) | (data & mask & 0x7F);
+        self.trim();
+    }
+
+    fn trim(&mut self) {
+        while self.bitmap.last() == Some(&0) {
+            self.bitmap.pop();
+        }
+    }
+}
+
+/// A MIDI Machine Control command.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum MmcCommand {
+    Stop,
+    Play,
+    /// Play once the current locate has finished.
+    DeferredPlay,
+    FastForward,
+    Rewind,
+    /// Enter record on the armed tracks, starting play if needed.
+    RecordStrobe,
+    RecordExit,
+    RecordPause,
+    Pause,
+    Eject,
+    Chase,
+    CommandErrorReset,
+    Reset,
+    /// Locate to a time code.
+    Locate(SmpteTime),
+    /// Locate to the time code stored in a register.
+    LocateRegister(u8),
+    /// Move at a speed relative to play speed, negative for reverse.
+    Shuttle(f64),
+    /// Replace the armed tracks.
+    RecordReady(MmcTracks),
+    /// Update some bits of one byte of a bitmap register.
+    MaskedWrite {
+        register: u8,
+        byte: u8,
+        mask: u8,
+        data: u8,
+    },
+    /// Ask for the values of some registers, sent back as responses.
+    Read(Vec<u8>),
+    /// Any other command, with its data without the count byte.
+    Other {
+        command: u8,
+        data: Vec<u8>,
+    },
+}
+
+impl MmcCommand {
+    /// Create the command that arms or disarms a single track for recording.
+    /// For audio track 0 and the ones above [MmcTracks::MAX_AUDIO_TRACK] the write
+    /// has an empty mask, so it changes nothing.
+    ///
+    pub fn arm_track(track: MmcTrack, armed: bool) -> Self {
+        let (byte, mask) = track
+            .bit()
+            .map_or((MAX_BITMAP_LEN - 1, 0), |bit| (bit / 7, 1 << (bit % 7)));
+        Self::MaskedWrite {
+            register: RECORD_READY,
+            byte: byte as u8,
+            mask,
+            data: if armed { mask } else { 0 },
+        }
+    }
+
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        let simple = match self {
+            Self::Stop => STOP,
+            Self::Play => PLAY,
+            Self::DeferredPlay => DEFERRED_PLAY,
+            Self::FastForward => FAST_FORWARD,
+            Self::Rewind => REWIND,
+            Self::RecordStrobe => RECORD_STROBE,
+            Self::RecordExit => RECORD_EXIT,
+            Self::RecordPause => RECORD_PAUSE,
+            Self::Pause => PAUSE,
+            Self::Eject => EJECT,
+            Self::Chase => CHASE,
+            Self::CommandErrorReset => COMMAND_ERROR_RESET,
+            Self::Reset => MMC_RESET,
+            Self::Locate(time) => {
+                bytes.extend([LOCATE, 6, LOCATE_TARGET]);
+                bytes.extend(time.standard_bytes());
+                return;
+            }
+            Self::LocateRegister(register) => {
+                bytes.extend([LOCATE, 2, LOCATE_REGISTER, *register]);
+                return;
+            }
+            Self::Shuttle(speed) => {
+                bytes.extend([SHUTTLE, 3]);
+                bytes.extend(encode_speed(*speed));
+                return;
+            }
+            Self::RecordReady(tracks) => {
+                let bitmap = tracks.bytes();
+                bytes.extend([WRITE, bitmap.len() as u8 + 2, RECORD_READY]);
+                bytes.push(bitmap.len() as u8);
+                bytes.extend(bitmap);
+                return;
+            }
+            Self::MaskedWrite {
+                register,
+                byte,
+                mask,
+                data,
+            } => {
+                bytes.extend([MASKED_WRITE, 4, *register, *byte, *mask, *data]);
+                return;
+            }
+            Self::Read(registers) => {
+                bytes.extend([READ, registers.len() as u8]);
+                bytes.extend(registers);
+                return;
+            }
+            Self::Other { command, data } => {
+                bytes.push(*command);
+                if has_count(*command) {
+                    bytes.push(data.len() as u8);
+                }
+                bytes.extend(data);
+                return;
+            }
+        };
+        bytes.push(simple);
+    }
+
+    fn decode(command: u8, data: &[u8]) -> Self {
+        match (command, data) {
+            (STOP, []) => Self::Stop,
+            (PLAY, []) => Self::Play,
+            (DEFERRED_PLAY, []) => Self::DeferredPlay,
+            (FAST_FORWARD, []) => Self::FastForward,
+            (REWIND, []) => Self::Rewind,
+            (RECORD_STROBE, []) => Self::RecordStrobe,
+            (RECORD_EXIT, []) => Self::RecordExit,
+            (RECORD_PAUSE, []) => Self::RecordPause,
+            (PAUSE, []) => Self::Pause,
+            (EJECT, []) => Self::Eject,
+            (CHASE, []) => Self::Chase,
+            (COMMAND_ERROR_RESET, []) => Self::CommandErrorReset,
+            (MMC_RESET, []) => Self::Reset,
+            (LOCATE, [LOCATE_TARGET, hr, mn, sc, fr, ff]) => {
+                Self::Locate(SmpteTime::from_standard_bytes(*hr, *mn, *sc, *fr, *ff))
+            }
+            (LOCATE, [LOCATE_REGISTER, register]) => Self::LocateRegister(*register),
+            (SHUTTLE, [sh, sm, sl]) => Self::Shuttle(decode_speed(*sh, *sm, *sl)),
+            (WRITE, [RECORD_READY, len, bitmap @ ..]) if *len as usize == bitmap.len() => {
+                Self::RecordReady(MmcTracks::from_bytes(bitmap))
+            }
+            (MASKED_WRITE, [register, byte, mask, data]) => Self::MaskedWrite {
+                register: *register,
+                byte: *byte,
+                mask: *mask,
+                data: *data,
+            },
+            (READ, registers) => Self::Read(registers.to_vec()),
+            (command, data) => Self::Other {
+                command,
+                data: data.to_vec(),
+            },
+        }
+    }
+}
+
+/// A MIDI Machine Control response, sent by a device as the value of one of its registers.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum MmcResponse {
+    /// The current position of the device.
+    SelectedTimeCode(SmpteTime),
+    /// The armed tracks.
+    RecordReady(MmcTracks),
+    /// Any other register, with its data without the count byte.
+    Other { field: u8, data: Vec<u8> },
+}
+
+impl MmcResponse {
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        match self {
+            Self::SelectedTimeCode(time) => {
+                bytes.push(SELECTED_TIME_CODE);
+                bytes.extend(time.standard_bytes());
+            }
+            Self::RecordReady(tracks) => {
+                bytes.extend([RECORD_READY, tracks.bytes().len() as u8]);
+                bytes.extend(tracks.bytes());
+            }
+            Self::Other { field, data } => {
+                bytes.push(*field);
+                if has_count(*field) {
+                    bytes.push(data.len() as u8);
+                }
+                bytes.extend(data);
+            }
+        }
+    }
+
+    fn decode(field: u8, data: &[u8]) -> Self {
+        match (field, data) {
+            (SELECTED_TIME_CODE, [hr, mn, sc, fr, st]) => {
+                Self::SelectedTimeCode(SmpteTime::from_standard_bytes(*hr, *mn, *sc, *fr, *st))
+            }
+            (RECORD_READY, bitmap) => Self::RecordReady(MmcTracks::from_bytes(bitmap)),
+            (field, data) => Self::Other {
+                field,
+                data: data.to_vec(),
+            },
+        }
+    }
+}
+
+/// A MIDI Machine Control message, with one or more commands or responses.
+///
+/// ```
+/// use coremidi::{MmcCommand, MmcMessage, Timestamp, MMC_ALL_CALL};
+/// let message = MmcMessage::command(MMC_ALL_CALL, MmcCommand::Play);
+/// let buffer = message.to_packet_buffer(Timestamp::Now);
+/// assert_eq!(buffer.iter().next().unwrap().data(), &[0xf0, 0x7f, 0x7f, 0x06, 0x02, 0xf7]);
+/// assert_eq!(MmcMessage::from_packet_list(&buffer), vec![message]);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum MmcMessage {
+    Command {
+        device_id: u8,
+        commands: Vec<MmcCommand>,
+    },
+    Response {
+        device_id: u8,
+        responses: Vec<MmcResponse>,
+    },
+}
+
+impl MmcMessage {
+    /// Create a message with a single command.
+    ///
+    pub fn command(device_id: u8, command: MmcCommand) -> Self {
+        Self::Command {
+            device_id,
+            commands: vec![command],
+        }
+    }
+
+    /// Create a message with a single response.
+    ///
+    pub fn response(device_id: u8, response: MmcResponse) -> Self {
+        Self::Response {
+            device_id,
+            responses: vec![response],
+        }
+    }
+
+    /// Get the device ID the message was sent to (commands) or from (responses).
+    ///
+    pub fn device_id(&self) -> u8 {
+        match self {
+            Self::Command { device_id, .. } | Self::Response { device_id, .. } => *device_id,
+        }
+    }
+
+    /// Encode the message as SysEx bytes.
+    ///
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = vec![SYSEX_START, REAL_TIME, self.device_id() & 0x7F];
+        match self {
+            Self::Command { commands, .. } => {
+                bytes.push(MMC_COMMAND);
+                for command in commands {
+                    command.encode(&mut bytes);
+                }
+            }
+            Self::Response { responses, .. } => {
+                bytes.push(MMC_RESPONSE);
+                for response in responses {
+                    response.encode(&mut bytes);
+                }
+            }
+        }
+        bytes.push(SYSEX_END);
+        bytes
+    }
+
+    /// Decode a complete SysEx message, including its `F0` and `F7` bytes.
+    ///
+    pub fn decode(bytes: &[u8]) -> Result<Self, MmcError> {
+        let (device_id, kind, mut body) = match bytes {
+            [SYSEX_START, REAL_TIME, device_id, kind @ (MMC_COMMAND | MMC_RESPONSE), body @ .., SYSEX_END] => {
+                (*device_id, *kind, body)
+            }
+            _ => return Err(MmcError::InvalidMessage),
+        };
+        if body.iter().any(|b| *b > 0x7F) {
+            return Err(MmcError::InvalidMessage);
+        }
+        let mut items = Vec::new();
+        while let [id, rest @ ..] = body {
+            let (data, rest) = match (kind, *id, rest) {
+                (MMC_RESPONSE, 0x01..=0x1F, rest) if rest.len() >= 5 => rest.split_at(5),
+                (MMC_RESPONSE, 0x20..=0x3F, rest) if rest.len() >= 2 => rest.split_at(2),
+                (_, id, [count, rest @ ..]) if has_count(id) && rest.len() >= *count as usize => {
+                    rest.split_at(*count as usize)
+                }
+                (MMC_COMMAND, id, rest) if !has_count(id) => (&rest[..0], rest),
+                _ => return Err(MmcError::InvalidMessage),
+            };
+            items.push((*id, data));
+            body = rest;
+        }
+        Ok(match kind {
+            MMC_COMMAND => Self::Command {
+                device_id,
+                commands: items
+                    .into_iter()
+                    .map(|(id, data)| MmcCommand::decode(id, data))
+                    .collect(),
+            },
+            _ => Self::Response {
+                device_id,
+                responses: items
+                    .into_iter()
+                    .map(|(id, data)| MmcResponse::decode(id, data))
+                    .collect(),
+            },
+        })
+    }
+
+    /// Create a `PacketBuffer` with the message.
+    ///
+    pub fn to_packet_buffer(&self, timestamp: Timestamp) -> PacketBuffer {
+        PacketBuffer::new(timestamp, &self.encode())
+    }
+
+    /// Get the MMC messages from a packet list, ignoring any other message.
+    /// Messages split across several packet lists are handled by [MmcTransport].
+    ///
+    pub fn from_packet_list(packet_list: &PacketList) -> Vec<Self> {
+        let mut messages = Vec::new();
+        SysExAssembler::new().push_packet_list(packet_list, |bytes| {
+            messages.extend(Self::decode(bytes).ok());
+        });
+        messages
+    }
+}
+
+/// An error decoding a MIDI Machine Control message.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MmcError {
+    InvalidMessage,
+}
+
+impl fmt::Display for MmcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidMessage => write!(f, "invalid MIDI machine control message"),
+        }
+    }
+}
+
+impl std::error::Error for MmcError {}
+
+/// The motion state of a transport.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransportState {
+    Stopped,
+    Playing,
+    Paused,
+    FastForward,
+    Rewind,
+    /// Moving at a speed relative to play speed, negative for reverse.
+    Shuttle(f64),
+}
+
+/// Follows the transport of a device from the MMC messages and MIDI Time Code it sends or receives.
+///
+/// ```
+/// use coremidi::{MmcCommand, MmcMessage, MmcTransport, Timestamp, TransportState, MMC_ALL_CALL};
+/// let mut transport = MmcTransport::new(MMC_ALL_CALL);
+/// let message = MmcMessage::command(MMC_ALL_CALL, MmcCommand::RecordStrobe);
+/// transport.handle_packet_list(&message.to_packet_buffer(Timestamp::Now));
+/// assert_eq!(transport.state(), TransportState::Playing);
+/// assert!(transport.is_recording());
+/// ```
+#[derive(Debug)]
+pub struct MmcTransport {
+    device_id: u8,
+    state: TransportState,
+    recording: bool,
+    position: Option<SmpteTime>,
+    armed_tracks: MmcTracks,
+    assembler: SysExAssembler,
+    quarter_frames: QuarterFrames,
+}
+
+impl MmcTransport {
+    /// Create a follower for the messages to or from a device ID.
+    /// Messages to or from [MMC_ALL_CALL] are always followed.
+    ///
+    pub fn new(device_id: u8) -> Self {
+        Self {
+            device_id,
+            state: TransportState::Stopped,
+            recording: false,
+            position: None,
+            armed_tracks: MmcTracks::new(),
+            assembler: SysExAssembler::new(),
+            quarter_frames: QuarterFrames::default(),
+        }
+    }
+
+    /// Get the motion state.
+    ///
+    pub fn state(&self) -> TransportState {
+        self.state
+    }
+
+    /// Check whether the armed tracks are recording.
+    ///
+    pub fn is_recording(&self) -> bool {
+        self.recording
+    }
+
+    /// Get the last known position, from locate commands, responses or MIDI Time Code.
+    ///
+    pub fn position(&self) -> Option<SmpteTime> {
+        self.position
+    }
+
+    /// Get the tracks armed for recording.
+    ///
+    pub fn armed_tracks(&self) -> &MmcTracks {
+        &self.armed_tracks
+    }
+
+    /// Update the state from the MMC and MTC messages in a packet list.
+    /// SysEx messages can span several packet lists.
+    ///
+    pub fn handle_packet_list(&mut self, packet_list: &PacketList) {
+        for packet in packet_list.iter() {
+            let data = packet.data();
+            let mut sysex = Vec::new();
+            self.assembler
+                .push(data, |bytes| sysex.push(bytes.to_vec()));
+            for bytes in sysex {
+                if let Ok(message) = MmcMessage::decode(&bytes) {
+                    self.handle_message(&message);
+                } else if let Some(message) = MtcMessage::decode(&bytes) {
+                    self.handle_mtc(&message);
+                }
+            }
+            for message in MtcMessage::quarter_frames(data) {
+                self.handle_mtc(&message);
+            }
+        }
+    }
+
+    /// Update the state from a MMC message.
+    ///
+    pub fn handle_message(&mut self, message: &MmcMessage) {
+        let device_id = message.device_id();
+        if device_id != self.device_id && device_id != MMC_ALL_CALL {
+            return;
+        }
+        match message {
+            MmcMessage::Command { commands, .. } => {
+                for command in commands {
+                    self.handle_command(command);
+                }
+            }
+            MmcMessage::Response { responses, .. } => {
+                for response in responses {
+                    match response {
+                        MmcResponse::SelectedTimeCode(time) => self.position = Some(*time),
+                        MmcResponse::RecordReady(tracks) => self.armed_tracks = tracks.clone(),
+                        MmcResponse::Other { .. } => {}
+                    }
+                }
+            }
+        }
+    }
+
+    /// Update the position from a MIDI Time Code message.
+    ///
+    pub fn handle_mtc(&mut self, message: &MtcMessage) {
+        match message {
+            MtcMessage::FullFrame(time) => {
+                self.quarter_frames.reset();
+                self.position = Some(*time);
+            }
+            MtcMessage::QuarterFrame { piece, value } => {
+                if let Some(time) = self.quarter_frames.push(*piece, *value) {
+                    // The time code was sent along 2 frames.
+                    self.position = Some(time.add_frames(2));
+                }
+            }
+        }
+    }
+
+    fn handle_command(&mut self, command: &MmcCommand) {
+        match command {
+            MmcCommand::Stop | MmcCommand::Eject => {
+                self.state = TransportState::Stopped;
+                self.recording = false;
+            }
+            MmcCommand::Play | MmcCommand::DeferredPlay => self.state = TransportState::Playing,
+            MmcCommand::FastForward => self.state = TransportState::FastForward,
+            MmcCommand::Rewind => self.state = TransportState::Rewind,
+            MmcCommand::Pause => self.state = TransportState::Paused,
+            MmcCommand::Shuttle(speed) => self.state = TransportState::Shuttle(*speed),
+            MmcCommand::RecordStrobe => {
+                self.state = TransportState::Playing;
+                self.recording = true;
+            }
+            MmcCommand::RecordPause => {
+                self.state = TransportState::Paused;
+                self.recording = true;
+            }
+            MmcCommand::RecordExit => self.recording = false,
+            MmcCommand::Locate(time) => {
+                self.state = TransportState::Stopped;
+                self.position = Some(*time);
+            }
+            MmcCommand::RecordReady(tracks) => self.armed_tracks = tracks.clone(),
+            MmcCommand::MaskedWrite {
+                register: RECORD_READY,
+                byte,
+                mask,
+                data,
+            } => self.armed_tracks.masked_write(*byte, *mask, *data),
+            MmcCommand::Reset => {
+                self.state = TransportState::Stopped;
+                self.recording = false;
+                self.armed_tracks = MmcTracks::new();
+            }
+            _ => {}
+        }
+    }
+}
+
+fn has_count(id: u8) -> bool {
+    (0x40..=0x77).contains(&id)
+}
+
+/// Encode a speed in the MMC standard speed format: a sign bit, a 3-bit shift and 17 bits
+/// with `3 + shift` bits of integer part and `14 - shift` bits of fraction.
+fn encode_speed(speed: f64) -> [u8; 3] {
+    let sign = if speed < 0.0 { 0x40 } else { 0x00 };
+    let speed = speed.abs().min(1023.0);
+    let shift = (0..7)
+        .find(|shift| speed < (8u32 << shift) as f64)
+        .unwrap_or(7);
+    let value = ((speed * (1u32 << (14 - shift)) as f64).round() as u32).min(0x1FFFF);
+    [
+        sign | (shift as u8) << 3 | (value >> 14) as u8,
+        ((value >> 7) & 0x7F) as u8,
+        (value & 0x7F) as u8,
+    ]
+}
+
+fn decode_speed(sh: u8, sm: u8, sl: u8) -> f64 {
+    let shift = (sh >> 3) & 0x07;
+    let value = ((sh & 0x07) as u32) << 14 | ((sm & 0x7F) as u32) << 7 | (sl & 0x7F) as u32;
+    let speed = value as f64 / (1u32 << (14 - shift)) as f64;
+    if sh & 0x40 != 0 {
+        -speed
+    } else {
+        speed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::timecode::FrameRate;
+
+    #[test]
+    fn commands_round_trip() {
+        let mut tracks = MmcTracks::new();
+        tracks.set(MmcTrack::TimeCode, true);
+        tracks.set(MmcTrack::Audio(10), true);
+        let message = MmcMessage::Command {
+            device_id: 0x10,
+            commands: vec![
+                MmcCommand::Stop,
+                MmcCommand::Locate(SmpteTime {
+                    subframes: 50,
+                    ..SmpteTime::new(1, 2, 3, 4, FrameRate::Fps30Drop)
+                }),
+                MmcCommand::DeferredPlay,
+                MmcCommand::Shuttle(-2.5),
+                MmcCommand::RecordReady(tracks),
+                MmcCommand::arm_track(MmcTrack::Audio(2), true),
+                MmcCommand::Read(vec![SELECTED_TIME_CODE]),
+                MmcCommand::Other {
+                    command: 0x4C,
+                    data: vec![0x01, 0x02],
+                },
+            ],
+        };
+        let bytes = message.encode();
+        assert_eq!(
+            &bytes[5..13],
+            &[0x44, 0x06, 0x01, 0x41, 0x02, 0x03, 0x04, 50]
+        );
+        assert_eq!(MmcMessage::decode(&bytes), Ok(message));
+    }
+
+    #[test]
+    fn responses_round_trip() {
+        let message = MmcMessage::Response {
+            device_id: 0x01,
+            responses: vec![
+                MmcResponse::SelectedTimeCode(SmpteTime::new(0, 59, 59, 23, FrameRate::Fps24)),
+                MmcResponse::Other {
+                    field: 0x21,
+                    data: vec![0x01, 0x02],
+                },
+                MmcResponse::RecordReady(MmcTracks::from_bytes(&[0x60])),
+            ],
+        };
+        assert_eq!(MmcMessage::decode(&message.encode()), Ok(message));
+    }
+
+    #[test]
+    fn invalid_messages() {
+        let truncated = [0xF0, 0x7F, 0x7F, 0x06, 0x44, 0x06, 0x01, 0xF7];
+        assert_eq!(
+            MmcMessage::decode(&truncated),
+            Err(MmcError::InvalidMessage)
+        );
+        let identity = [0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7];
+        assert_eq!(MmcMessage::decode(&identity), Err(MmcError::InvalidMessage));
+    }
+
+    #[test]
+    fn shuttle_speeds() {
+        for speed in [0.0, 1.0, -1.0, 0.5, 7.75, 8.0, 100.25, -1000.0] {
+            let [sh, sm, sl] = encode_speed(speed);
+            assert_eq!(decode_speed(sh, sm, sl), speed);
+        }
+        assert_eq!(encode_speed(1.0), [0x01, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn track_bitmap() {
+        let mut tracks = MmcTracks::new();
+        tracks.set(MmcTrack::Audio(24), true);
+        assert_eq!(tracks.bytes(), &[0x00, 0x00, 0x00, 0x00, 0x01]);
+        assert!(tracks.contains(MmcTrack::Audio(24)));
+        tracks.set(MmcTrack::Audio(24), false);
+        assert!(tracks.is_empty());
+
+        tracks.set(MmcTrack::Audio(0), true);
+        tracks.set(MmcTrack::Audio(MmcTracks::MAX_AUDIO_TRACK + 1), true);
+        tracks.set(MmcTrack::Audio(u16::MAX), true);
+        assert!(tracks.is_empty());
+        tracks.set(MmcTrack::AuxB, true);
+        assert!(!tracks.contains(MmcTrack::Audio(0)));
+        tracks.set(MmcTrack::AuxB, false);
+        assert_eq!(
+            MmcCommand::arm_track(MmcTrack::Audio(u16::MAX), true),
+            MmcCommand::MaskedWrite {
+                register: RECORD_READY,
+                byte: 124,
+                mask: 0,
+                data: 0
+            }
+        );
+
+        tracks.set(MmcTrack::Audio(MmcTracks::MAX_AUDIO_TRACK), true);
+        assert_eq!(tracks.bytes().len(), 125);
+        let message = MmcMessage::Command {
+            device_id: 0x7F,
+            commands: vec![MmcCommand::RecordReady(tracks)],
+        };
+        let bytes = message.encode();
+        assert_eq!(&bytes[4..7], &[WRITE, 127, RECORD_READY]);
+        assert!(bytes[1..bytes.len() - 1].iter().all(|byte| *byte < 0x80));
+        assert_eq!(MmcMessage::decode(&bytes), Ok(message));
+        assert_eq!(MmcTracks::from_bytes(&[0x01; 200]).bytes().len(), 125);
+    }
+
+    #[test]
+    fn transport_follows_mmc_and_mtc() {
+        let mut transport = MmcTransport::new(0x01);
+        let time = SmpteTime::new(1, 0, 0, 0, FrameRate::Fps25);
+        transport.handle_message(&MmcMessage::command(0x02, MmcCommand::Play));
+        assert_eq!(transport.state(), TransportState::Stopped);
+
+        let mut commands = MmcMessage::Command {
+            device_id: 0x01,
+            commands: vec![
+                MmcCommand::Locate(time),
+                MmcCommand::arm_track(MmcTrack::Audio(1), true),
+            ],
+        }
+        .to_packet_buffer(Timestamp::Now);
+        commands.push_data(
+            Timestamp::Now,
+            &MmcMessage::command(0x7F, MmcCommand::Play).encode(),
+        );
+        transport.handle_packet_list(&commands);
+        assert_eq!(transport.state(), TransportState::Playing);
+        assert_eq!(transport.position(), Some(time));
+        assert!(transport.armed_tracks().contains(MmcTrack::Audio(1)));
+
+        let mut mtc = PacketBuffer::new(Timestamp::Now, &MtcMessage::FullFrame(time).encode());
+        for piece in 0..8 {
+            mtc.push_data(
+                Timestamp::Now,
+                &MtcMessage::quarter_frame(&time.add_frames(10), piece).encode(),
+            );
+        }
+        transport.handle_packet_list(&mtc);
+        assert_eq!(transport.position(), Some(time.add_frames(12)));
+
+        transport.handle_message(&MmcMessage::command(0x01, MmcCommand::Stop));
+        assert_eq!(transport.state(), TransportState::Stopped);
+    }
+}
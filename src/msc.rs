@@ -0,0 +1,571 @@
+use std::fmt;
+
+use crate::events::{EventBuffer, EventList, Timestamp};
+use crate::packets::{PacketBuffer, PacketList};
+use crate::protocol::Protocol;
+use crate::sysex::{SysExAssembler, SYSEX_END, SYSEX_START};
+use crate::timecode::SmpteTime;
+
+const REAL_TIME: u8 = 0x7F;
+const MSC: u8 = 0x02;
+
+const GO: u8 = 0x01;
+const STOP: u8 = 0x02;
+const RESUME: u8 = 0x03;
+const TIMED_GO: u8 = 0x04;
+const LOAD: u8 = 0x05;
+const SET: u8 = 0x06;
+const FIRE: u8 = 0x07;
+const ALL_OFF: u8 = 0x08;
+const RESTORE: u8 = 0x09;
+const RESET: u8 = 0x0A;
+const GO_OFF: u8 = 0x0B;
+
+/// The maximum length of a MIDI Show Control message, including `F0` and `F7`.
+pub const MSC_MAX_MESSAGE_SIZE: usize = 128;
+
+/// The device addressed by a MIDI Show Control message.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MscDeviceId {
+    /// A single device, from `0x00` to `0x6F`.
+    Device(u8),
+    /// A group of devices, from 1 to 15.
+    Group(u8),
+    /// All the devices.
+    AllCall,
+}
+
+impl MscDeviceId {
+    /// Get the device ID from its byte.
+    ///
+    pub fn from_byte(byte: u8) -> Self {
+        match byte & 0x7F {
+            0x7F => Self::AllCall,
+            byte @ 0x70..=0x7E => Self::Group(byte - 0x6F),
+            byte => Self::Device(byte),
+        }
+    }
+
+    /// Get the byte for the device ID, or an error when the device or group is out of range.
+    ///
+    pub fn byte(&self) -> Result<u8, MscError> {
+        match *self {
+            Self::Device(id @ 0x00..=0x6F) => Ok(id),
+            Self::Group(group @ 1..=15) => Ok(0x6F + group),
+            Self::AllCall => Ok(0x7F),
+            _ => Err(MscError::InvalidDeviceId),
+        }
+    }
+}
+
+/// The kind of equipment a MIDI Show Control command is intended for.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MscCommandFormat(pub u8);
+
+impl MscCommandFormat {
+    pub const LIGHTING: Self = Self(0x01);
+    pub const MOVING_LIGHTS: Self = Self(0x02);
+    pub const SOUND: Self = Self(0x10);
+    pub const MUSIC: Self = Self(0x11);
+    pub const MACHINERY: Self = Self(0x20);
+    pub const VIDEO: Self = Self(0x30);
+    pub const PROJECTION: Self = Self(0x40);
+    pub const PROCESS_CONTROL: Self = Self(0x50);
+    pub const PYRO: Self = Self(0x60);
+    pub const ALL_TYPES: Self = Self(0x7F);
+}
+
+/// A cue, given by its number and optionally the cue list and the cue path where it belongs.
+///
+/// The fields are ASCII numbers, with digits and decimal points.
+///
+/// ```
+/// use coremidi::MscCue;
+/// let cue = MscCue::new("23.5").unwrap().with_list("2").unwrap();
+/// assert_eq!(cue.number(), "23.5");
+/// assert_eq!(cue.list(), Some("2"));
+/// assert!(MscCue::new("23a").is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MscCue {
+    number: String,
+    list: Option<String>,
+    path: Option<String>,
+}
+
+impl MscCue {
+    /// Create a cue from its number.
+    ///
+    pub fn new(number: &str) -> Result<Self, MscError> {
+        Ok(Self {
+            number: validate_cue_field(number.as_bytes())?,
+            list: None,
+            path: None,
+        })
+    }
+
+    /// Set the cue list of the cue.
+    ///
+    pub fn with_list(mut self, list: &str) -> Result<Self, MscError> {
+        self.list = Some(validate_cue_field(list.as_bytes())?);
+        Ok(self)
+    }
+
+    /// Set the cue path of the cue. It can only be set together with a cue list.
+    ///
+    pub fn with_path(mut self, path: &str) -> Result<Self, MscError> {
+        if self.list.is_none() {
+            return Err(MscError::InvalidCue);
+        }
+        self.path = Some(validate_cue_field(path.as_bytes())?);
+        Ok(self)
+    }
+
+    /// Get the cue number.
+    ///
+    pub fn number(&self) -> &str {
+        &self.number
+    }
+
+    /// Get the cue list.
+    ///
+    pub fn list(&self) -> Option<&str> {
+        self.list.as_deref()
+    }
+
+    /// Get the cue path.
+    ///
+    pub fn path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        bytes.extend(self.number.as_bytes());
+        for field in self.list.iter().chain(self.path.iter()) {
+            bytes.push(0x00);
+            bytes.extend(field.as_bytes());
+        }
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Option<Self>, MscError> {
+        if bytes.is_empty() {
+            return Ok(None);
+        }
+        let mut fields = bytes.split(|b| *b == 0x00);
+        let number = validate_cue_field(fields.next().unwrap_or_default())?;
+        let list = fields.next().map(validate_cue_field).transpose()?;
+        let path = fields.next().map(validate_cue_field).transpose()?;
+        if fields.next().is_some() {
+            return Err(MscError::InvalidCue);
+        }
+        Ok(Some(Self { number, list, path }))
+    }
+}
+
+impl fmt::Display for MscCue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.number)?;
+        if let Some(list) = &self.list {
+            write!(f, " list {}", list)?;
+        }
+        if let Some(path) = &self.path {
+            write!(f, " path {}", path)?;
+        }
+        Ok(())
+    }
+}
+
+/// A MIDI Show Control command.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum MscCommand {
+    /// Start a transition to a cue, or to the next one.
+    Go(Option<MscCue>),
+    /// Stop a running transition, or all of them.
+    Stop(Option<MscCue>),
+    /// Resume a stopped transition, or all of them.
+    Resume(Option<MscCue>),
+    /// Start a transition at a time.
+    TimedGo(SmpteTime, Option<MscCue>),
+    /// Get ready to go to a cue.
+    Load(MscCue),
+    /// Set a generic 14-bit control to a 14-bit value, optionally at a time.
+    Set {
+        control: u16,
+        value: u16,
+        time: Option<SmpteTime>,
+    },
+    /// Trigger a macro.
+    Fire(u8),
+    AllOff,
+    Restore,
+    Reset,
+    /// Stop a cue by going to its off state.
+    GoOff(Option<MscCue>),
+    /// Any other command, with its data.
+    Other {
+        command: u8,
+        data: Vec<u8>,
+    },
+}
+
+/// A MIDI Show Control message.
+///
+/// It can be sent as SysEx in a [PacketBuffer] for MIDI 1.0 destinations, or as
+/// SysEx7 packets in an [EventBuffer] for UMP destinations.
+///
+/// ```
+/// use coremidi::{MscCommand, MscCommandFormat, MscCue, MscDeviceId, MscMessage, Timestamp};
+/// let message = MscMessage::new(
+///     MscDeviceId::Device(1),
+///     MscCommandFormat::LIGHTING,
+///     MscCommand::Go(Some(MscCue::new("12.5").unwrap())),
+/// );
+/// let buffer = message.to_packet_buffer(Timestamp::Now).unwrap();
+/// let data = buffer.iter().next().unwrap().data();
+/// assert_eq!(data, &[0xf0, 0x7f, 0x01, 0x02, 0x01, 0x01, b'1', b'2', b'.', b'5', 0xf7]);
+/// assert_eq!(MscMessage::decode(data), Ok(message));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct MscMessage {
+    pub device_id: MscDeviceId,
+    pub command_format: MscCommandFormat,
+    pub command: MscCommand,
+}
+
+impl MscMessage {
+    /// Create a message.
+    ///
+    pub fn new(
+        device_id: MscDeviceId,
+        command_format: MscCommandFormat,
+        command: MscCommand,
+    ) -> Self {
+        Self {
+            device_id,
+            command_format,
+            command,
+        }
+    }
+
+    /// Encode the message as SysEx bytes, or return an error when the device ID is out of range,
+    /// the data of another command is not 7-bit, or the message is longer than
+    /// [MSC_MAX_MESSAGE_SIZE].
+    ///
+    pub fn encode(&self) -> Result<Vec<u8>, MscError> {
+        let mut bytes = vec![
+            SYSEX_START,
+            REAL_TIME,
+            self.device_id.byte()?,
+            MSC,
+            self.command_format.0 & 0x7F,
+        ];
+        match &self.command {
+            MscCommand::Go(cue) => encode_cue(&mut bytes, GO, cue.as_ref()),
+            MscCommand::Stop(cue) => encode_cue(&mut bytes, STOP, cue.as_ref()),
+            MscCommand::Resume(cue) => encode_cue(&mut bytes, RESUME, cue.as_ref()),
+            MscCommand::TimedGo(time, cue) => {
+                bytes.push(TIMED_GO);
+                bytes.extend(time.standard_bytes());
+                if let Some(cue) = cue {
+                    cue.encode(&mut bytes);
+                }
+            }
+            MscCommand::Load(cue) => encode_cue(&mut bytes, LOAD, Some(cue)),
+            MscCommand::Set {
+                control,
+                value,
+                time,
+            } => {
+                bytes.extend([
+                    SET,
+                    (control & 0x7F) as u8,
+                    ((control >> 7) & 0x7F) as u8,
+                    (value & 0x7F) as u8,
+                    ((value >> 7) & 0x7F) as u8,
+                ]);
+                if let Some(time) = time {
+                    bytes.extend(time.standard_bytes());
+                }
+            }
+            MscCommand::Fire(macro_number) => bytes.extend([FIRE, macro_number & 0x7F]),
+            MscCommand::AllOff => bytes.push(ALL_OFF),
+            MscCommand::Restore => bytes.push(RESTORE),
+            MscCommand::Reset => bytes.push(RESET),
+            MscCommand::GoOff(cue) => encode_cue(&mut bytes, GO_OFF, cue.as_ref()),
+            MscCommand::Other { command, data } => {
+                if *command > 0x7F || data.iter().any(|b| *b > 0x7F) {
+                    return Err(MscError::InvalidMessage);
+                }
+                bytes.push(*command);
+                bytes.extend(data);
+            }
+        }
+        bytes.push(SYSEX_END);
+        if bytes.len() > MSC_MAX_MESSAGE_SIZE {
+            return Err(MscError::TooLong);
+        }
+        Ok(bytes)
+    }
+
+    /// Decode a complete SysEx message, including its `F0` and `F7` bytes.
+    ///
+    pub fn decode(bytes: &[u8]) -> Result<Self, MscError> {
+        let (device_id, command_format, command, data) = match bytes {
+            [SYSEX_START, REAL_TIME, device_id, MSC, format, command, data @ .., SYSEX_END] => {
+                (*device_id, *format, *command, data)
+            }
+            _ => return Err(MscError::InvalidMessage),
+        };
+        if bytes.len() > MSC_MAX_MESSAGE_SIZE || bytes[1..bytes.len() - 1].iter().any(|b| *b > 0x7F)
+        {
+            return Err(MscError::InvalidMessage);
+        }
+        let command = match (command, data) {
+            (GO, cue) => MscCommand::Go(MscCue::decode(cue)?),
+            (STOP, cue) => MscCommand::Stop(MscCue::decode(cue)?),
+            (RESUME, cue) => MscCommand::Resume(MscCue::decode(cue)?),
+            (TIMED_GO, [hr, mn, sc, fr, ff, cue @ ..]) => MscCommand::TimedGo(
+                SmpteTime::from_standard_bytes(*hr, *mn, *sc, *fr, *ff),
+                MscCue::decode(cue)?,
+            ),
+            (LOAD, cue) => MscCommand::Load(MscCue::decode(cue)?.ok_or(MscError::InvalidCue)?),
+            (SET, [c0, c1, v0, v1, time @ ..]) => MscCommand::Set {
+                control: (*c1 as u16) << 7 | *c0 as u16,
+                value: (*v1 as u16) << 7 | *v0 as u16,
+                time: match time {
+                    [] => None,
+                    [hr, mn, sc, fr, ff] => {
+                        Some(SmpteTime::from_standard_bytes(*hr, *mn, *sc, *fr, *ff))
+                    }
+                    _ => return Err(MscError::InvalidMessage),
+                },
+            },
+            (FIRE, [macro_number]) => MscCommand::Fire(*macro_number),
+            (ALL_OFF, []) => MscCommand::AllOff,
+            (RESTORE, []) => MscCommand::Restore,
+            (RESET, []) => MscCommand::Reset,
+            (GO_OFF, cue) => MscCommand::GoOff(MscCue::decode(cue)?),
+            (TIMED_GO | SET | FIRE | ALL_OFF | RESTORE | RESET, _) => {
+                return Err(MscError::InvalidMessage)
+            }
+            (command, data) => MscCommand::Other {
+                command,
+                data: data.to_vec(),
+            },
+        };
+        Ok(Self {
+            device_id: MscDeviceId::from_byte(device_id),
+            command_format: MscCommandFormat(command_format),
+            command,
+        })
+    }
+
+    /// Create a `PacketBuffer` with the message, for MIDI 1.0 destinations.
+    /// See [MscMessage::encode] for the errors.
+    ///
+    pub fn to_packet_buffer(&self, timestamp: Timestamp) -> Result<PacketBuffer, MscError> {
+        Ok(PacketBuffer::new(timestamp, &self.encode()?))
+    }
+
+    /// Create an `EventBuffer` with the message as SysEx7 packets in the given group,
+    /// for UMP destinations. See [MscMessage::encode] for the errors.
+    ///
+    pub fn to_event_buffer(
+        &self,
+        protocol: Protocol,
+        timestamp: Timestamp,
+        group: u8,
+    ) -> Result<EventBuffer, MscError> {
+        let mut buffer = EventBuffer::new(protocol);
+        buffer.push_sysex7(timestamp, group, &self.encode()?);
+        Ok(buffer)
+    }
+
+    /// Get the MSC messages from a packet list, ignoring any other message.
+    ///
+    pub fn from_packet_list(packet_list: &PacketList) -> Vec<Self> {
+        let mut messages = Vec::new();
+        SysExAssembler::new().push_packet_list(packet_list, |bytes| {
+            messages.extend(Self::decode(bytes).ok());
+        });
+        messages
+    }
+
+    /// Get the MSC messages sent as SysEx7 packets in an event list, ignoring any other message.
+    ///
+    pub fn from_event_list(event_list: &EventList) -> Vec<Self> {
+        let mut messages = Vec::new();
+        SysExAssembler::new().push_event_list(event_list, |bytes| {
+            messages.extend(Self::decode(bytes).ok());
+        });
+        messages
+    }
+}
+
+/// An error building or decoding a MIDI Show Control message.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MscError {
+    /// The message is not a well formed MIDI Show Control message.
+    InvalidMessage,
+    /// A cue field has characters other than ASCII digits and decimal points,
+    /// or a cue path is given without a cue list.
+    InvalidCue,
+    /// The device is above `0x6F`, or the group is not from 1 to 15.
+    InvalidDeviceId,
+    /// The message is longer than [MSC_MAX_MESSAGE_SIZE].
+    TooLong,
+}
+
+impl fmt::Display for MscError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidMessage => write!(f, "invalid MIDI show control message"),
+            Self::InvalidCue => write!(f, "invalid MIDI show control cue"),
+            Self::InvalidDeviceId => write!(f, "invalid MIDI show control device id"),
+            Self::TooLong => write!(f, "the MIDI show control message is too long"),
+        }
+    }
+}
+
+impl std::error::Error for MscError {}
+
+fn validate_cue_field(field: &[u8]) -> Result<String, MscError> {
+    if field.is_empty() || !field.iter().all(|b| b.is_ascii_digit() || *b == b'.') {
+        return Err(MscError::InvalidCue);
+    }
+    Ok(String::from_utf8_lossy(field).into_owned())
+}
+
+fn encode_cue(bytes: &mut Vec<u8>, command: u8, cue: Option<&MscCue>) {
+    bytes.push(command);
+    if let Some(cue) = cue {
+        cue.encode(bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::timecode::FrameRate;
+
+    fn round_trip(command: MscCommand) {
+        let message = MscMessage::new(MscDeviceId::Group(3), MscCommandFormat::SOUND, command);
+        assert_eq!(MscMessage::decode(&message.encode().unwrap()), Ok(message));
+    }
+
+    #[test]
+    fn commands_round_trip() {
+        let cue = MscCue::new("1")
+            .and_then(|cue| cue.with_list("2.5"))
+            .and_then(|cue| cue.with_path("30"))
+            .unwrap();
+        let time = SmpteTime {
+            subframes: 12,
+            ..SmpteTime::new(1, 2, 3, 4, FrameRate::Fps25)
+        };
+        round_trip(MscCommand::Go(None));
+        round_trip(MscCommand::Go(Some(cue.clone())));
+        round_trip(MscCommand::Stop(Some(MscCue::new("7").unwrap())));
+        round_trip(MscCommand::Resume(None));
+        round_trip(MscCommand::TimedGo(time, Some(cue.clone())));
+        round_trip(MscCommand::TimedGo(time, None));
+        round_trip(MscCommand::Load(cue));
+        round_trip(MscCommand::Set {
+            control: 0x1234,
+            value: 0x3FFF,
+            time: None,
+        });
+        round_trip(MscCommand::Set {
+            control: 1,
+            value: 2,
+            time: Some(time),
+        });
+        round_trip(MscCommand::Fire(99));
+        round_trip(MscCommand::AllOff);
+        round_trip(MscCommand::Restore);
+        round_trip(MscCommand::Reset);
+        round_trip(MscCommand::GoOff(None));
+        round_trip(MscCommand::Other {
+            command: 0x11,
+            data: vec![],
+        });
+    }
+
+    #[test]
+    fn device_ids() {
+        assert_eq!(MscDeviceId::from_byte(0x6F), MscDeviceId::Device(0x6F));
+        assert_eq!(MscDeviceId::from_byte(0x70), MscDeviceId::Group(1));
+        assert_eq!(MscDeviceId::from_byte(0x7E), MscDeviceId::Group(15));
+        assert_eq!(MscDeviceId::Group(15).byte(), Ok(0x7E));
+        assert_eq!(MscDeviceId::AllCall.byte(), Ok(0x7F));
+        assert_eq!(
+            MscDeviceId::Device(0x70).byte(),
+            Err(MscError::InvalidDeviceId)
+        );
+        assert_eq!(MscDeviceId::Group(0).byte(), Err(MscError::InvalidDeviceId));
+        assert_eq!(
+            MscDeviceId::Group(16).byte(),
+            Err(MscError::InvalidDeviceId)
+        );
+        let message = MscMessage::new(
+            MscDeviceId::Device(0x70),
+            MscCommandFormat::SOUND,
+            MscCommand::AllOff,
+        );
+        assert_eq!(message.encode(), Err(MscError::InvalidDeviceId));
+    }
+
+    #[test]
+    fn message_size_limit() {
+        let message = |digits: usize| {
+            let cue = MscCue::new(&"1".repeat(digits)).unwrap();
+            MscMessage::new(
+                MscDeviceId::AllCall,
+                MscCommandFormat::ALL_TYPES,
+                MscCommand::Go(Some(cue)),
+            )
+        };
+        assert_eq!(message(121).encode().unwrap().len(), MSC_MAX_MESSAGE_SIZE);
+        assert_eq!(message(122).encode(), Err(MscError::TooLong));
+        assert_eq!(
+            message(122).to_packet_buffer(Timestamp::Now).err(),
+            Some(MscError::TooLong)
+        );
+    }
+
+    #[test]
+    fn invalid_cues() {
+        assert_eq!(MscCue::new(""), Err(MscError::InvalidCue));
+        assert_eq!(MscCue::new("1,5"), Err(MscError::InvalidCue));
+        assert_eq!(
+            MscCue::new("1").and_then(|cue| cue.with_path("2")),
+            Err(MscError::InvalidCue)
+        );
+        let go = [0xF0, 0x7F, 0x01, 0x02, 0x01, 0x01, b'1', b'A', 0xF7];
+        assert_eq!(MscMessage::decode(&go), Err(MscError::InvalidCue));
+        let go = [
+            0xF0, 0x7F, 0x01, 0x02, 0x01, 0x01, b'1', 0x00, 0x00, b'2', 0xF7,
+        ];
+        assert_eq!(MscMessage::decode(&go), Err(MscError::InvalidCue));
+        let load = [0xF0, 0x7F, 0x01, 0x02, 0x01, 0x05, 0xF7];
+        assert_eq!(MscMessage::decode(&load), Err(MscError::InvalidCue));
+    }
+
+    #[test]
+    fn sysex7_event_list() {
+        let message = MscMessage::new(
+            MscDeviceId::AllCall,
+            MscCommandFormat::ALL_TYPES,
+            MscCommand::Go(Some(MscCue::new("100.25").unwrap().with_list("3").unwrap())),
+        );
+        let buffer = message
+            .to_event_buffer(Protocol::Midi20, Timestamp::Now, 2)
+            .unwrap();
+        assert_eq!(MscMessage::from_event_list(&buffer), vec![message]);
+    }
+}
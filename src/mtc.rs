@@ -0,0 +1,418 @@
+use std::time::Duration;
+
+use crate::events::Timestamp;
+use crate::host_time::{nanos_to_ticks, ticks_to_nanos, HostClock, SystemClock};
+use crate::packets::{PacketBuffer, PacketList};
+use crate::sysex::SysExAssembler;
+use crate::timecode::{FrameRate, MtcMessage, QuarterFrames, SmpteTime};
+
+const NANOS_PER_SECOND: u128 = 1_000_000_000;
+
+/// Generates the MIDI Time Code of a running transport as timestamped packets.
+///
+/// A full frame message is sent first, then the quarter frame messages, four per frame,
+/// with timestamps computed from the frame rate without accumulating rounding errors.
+/// The packets are generated a window at a time, so they can be scheduled ahead of time
+/// with [crate::OutputPort::send].
+///
+/// ```
+/// use coremidi::{FrameRate, MtcGenerator, PacketBuffer, SmpteTime, Timestamp};
+/// let start = Timestamp::from_host_time(1000);
+/// let mut generator = MtcGenerator::new(SmpteTime::new(1, 0, 0, 0, FrameRate::Fps25), start);
+/// let mut buffer = PacketBuffer::with_capacity(256);
+/// assert_eq!(generator.fill(&mut buffer, Timestamp::from_host_time(1001)), 2);
+/// assert_eq!(buffer.iter().next().unwrap().data()[..2], [0xf0, 0x7f]);
+/// for _ in 1..8 {
+///     let until = Timestamp::from_host_time(generator.next_timestamp().host_time() + 1);
+///     generator.fill(&mut buffer, until);
+/// }
+/// assert_eq!(generator.position(), SmpteTime::new(1, 0, 0, 2, FrameRate::Fps25));
+/// ```
+#[derive(Debug, Clone)]
+pub struct MtcGenerator {
+    start: SmpteTime,
+    start_timestamp: Timestamp,
+    quarter_frames: u64,
+    full_frame_pending: bool,
+}
+
+impl MtcGenerator {
+    /// Create a generator starting at a time code and a host timestamp.
+    /// The frame rate is the one of the start time code, and [Timestamp::Now] is taken
+    /// as the current host time.
+    ///
+    pub fn new(start: SmpteTime, timestamp: Timestamp) -> Self {
+        Self {
+            start: SmpteTime {
+                subframes: 0,
+                ..start
+            },
+            start_timestamp: SystemClock.resolve(timestamp),
+            quarter_frames: 0,
+            full_frame_pending: true,
+        }
+    }
+
+    /// Restart the time code at another time and timestamp, sending a full frame message first.
+    ///
+    pub fn locate(&mut self, start: SmpteTime, timestamp: Timestamp) {
+        *self = Self::new(start, timestamp);
+    }
+
+    /// Get the frame rate of the time code.
+    ///
+    pub fn rate(&self) -> FrameRate {
+        self.start.rate
+    }
+
+    /// Get the time code of the frame of the next quarter frame message.
+    ///
+    pub fn position(&self) -> SmpteTime {
+        self.start.add_frames((self.quarter_frames / 4) as i64)
+    }
+
+    /// Get the timestamp of the next message.
+    ///
+    pub fn next_timestamp(&self) -> Timestamp {
+        self.timestamp(self.quarter_frames)
+    }
+
+    /// Add the messages with a timestamp before `until` to a buffer,
+    /// and return the number of messages added.
+    ///
+    pub fn fill(&mut self, buffer: &mut PacketBuffer, until: Timestamp) -> usize {
+        let mut count = 0;
+        if self.full_frame_pending && self.start_timestamp < until {
+            let message = MtcMessage::FullFrame(self.start);
+            buffer.push_data(self.start_timestamp, &message.encode());
+            self.full_frame_pending = false;
+            count += 1;
+        }
+        loop {
+            let timestamp = self.next_timestamp();
+            if timestamp >= until {
+                break;
+            }
+            let piece = (self.quarter_frames % 8) as u8;
+            let time = self.start.add_frames((self.quarter_frames / 8 * 2) as i64);
+            let message = MtcMessage::quarter_frame(&time, piece);
+            buffer.push_data(timestamp, &message.encode());
+            self.quarter_frames += 1;
+            count += 1;
+        }
+        count
+    }
+
+    fn timestamp(&self, quarter_frames: u64) -> Timestamp {
+        let nanos = match self.start.rate {
+            FrameRate::Fps30Drop => quarter_frames as u128 * 1001 * NANOS_PER_SECOND / 120_000,
+            rate => quarter_frames as u128 * NANOS_PER_SECOND / (4 * rate.nominal_fps() as u128),
+        };
+        Timestamp::from_host_time(self.start_timestamp.host_time() + nanos_to_ticks(nanos as u64))
+    }
+}
+
+/// The direction of a running time code.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MtcDirection {
+    Forward,
+    Reverse,
+}
+
+/// Reads the MIDI Time Code received from an input port into a SMPTE position.
+///
+/// Quarter frames are reassembled in both directions, and the position is updated every
+/// complete sequence of 8 quarter frames (2 frames), or when a full frame message arrives.
+/// When no quarter frame arrives for longer than the dropout timeout, the time code is
+/// considered stopped, and the sequence being assembled is discarded.
+///
+/// ```
+/// use coremidi::{FrameRate, MtcGenerator, MtcReader, PacketBuffer, SmpteTime, Timestamp};
+/// let start = SmpteTime::new(0, 10, 0, 0, FrameRate::Fps30);
+/// let mut generator = MtcGenerator::new(start, Timestamp::Now);
+/// let mut buffer = PacketBuffer::with_capacity(256);
+/// while generator.position().frames < 2 {
+///     let until = Timestamp::from_host_time(generator.next_timestamp().host_time() + 1);
+///     generator.fill(&mut buffer, until);
+/// }
+///
+/// let mut reader = MtcReader::new();
+/// reader.handle_packet_list(&buffer);
+/// assert!(reader.is_running(generator.next_timestamp()));
+/// assert_eq!(reader.position(), Some(SmpteTime::new(0, 10, 0, 2, FrameRate::Fps30)));
+/// ```
+#[derive(Debug)]
+pub struct MtcReader {
+    quarter_frames: QuarterFrames,
+    last_piece: Option<u8>,
+    last_timestamp: Option<Timestamp>,
+    direction: Option<MtcDirection>,
+    anchor: Option<(SmpteTime, Timestamp)>,
+    running: bool,
+    dropout_timeout: Duration,
+    dropouts: u64,
+    assembler: SysExAssembler,
+}
+
+impl MtcReader {
+    /// The default time without quarter frames after which the time code is considered stopped.
+    pub const DEFAULT_DROPOUT_TIMEOUT: Duration = Duration::from_millis(100);
+
+    /// Create a reader with the default dropout timeout.
+    ///
+    pub fn new() -> Self {
+        Self::with_dropout_timeout(Self::DEFAULT_DROPOUT_TIMEOUT)
+    }
+
+    /// Create a reader with a custom dropout timeout.
+    ///
+    pub fn with_dropout_timeout(dropout_timeout: Duration) -> Self {
+        Self {
+            quarter_frames: QuarterFrames::default(),
+            last_piece: None,
+            last_timestamp: None,
+            direction: None,
+            anchor: None,
+            running: false,
+            dropout_timeout,
+            dropouts: 0,
+            assembler: SysExAssembler::new(),
+        }
+    }
+
+    /// Get the last decoded position.
+    ///
+    pub fn position(&self) -> Option<SmpteTime> {
+        self.anchor.map(|(time, _)| time)
+    }
+
+    /// Get the position at a host timestamp, extrapolated from the last decoded one
+    /// while the time code is running.
+    ///
+    pub fn position_at(&self, timestamp: Timestamp) -> Option<SmpteTime> {
+        let (time, anchor_timestamp) = self.anchor?;
+        if !self.is_running(timestamp) {
+            return Some(time);
+        }
+        let elapsed = ticks_to_nanos(
+            timestamp
+                .host_time()
+                .saturating_sub(anchor_timestamp.host_time()),
+        );
+        let frames = (elapsed as f64 * time.rate.fps() / 1e9) as i64;
+        Some(match self.direction {
+            Some(MtcDirection::Reverse) => time.add_frames(-frames),
+            _ => time.add_frames(frames),
+        })
+    }
+
+    /// Get the direction of the time code, once known.
+    ///
+    pub fn direction(&self) -> Option<MtcDirection> {
+        self.direction
+    }
+
+    /// Check whether the time code is running at a host timestamp,
+    /// that is, quarter frames keep arriving within the dropout timeout.
+    ///
+    pub fn is_running(&self, timestamp: Timestamp) -> bool {
+        self.running
+            && self
+                .last_timestamp
+                .map_or(false, |last| !self.is_dropout(last, timestamp))
+    }
+
+    /// Get the number of times the quarter frames stopped arriving while running.
+    ///
+    pub fn dropouts(&self) -> u64 {
+        self.dropouts
+    }
+
+    /// Update the position from the MTC messages in a packet list, using their timestamps.
+    ///
+    pub fn handle_packet_list(&mut self, packet_list: &PacketList) {
+        for packet in packet_list.iter() {
+            let timestamp = packet.timestamp();
+            let mut full_frames = Vec::new();
+            self.assembler.push(packet.data(), |bytes| {
+                full_frames.extend(MtcMessage::decode(bytes));
+            });
+            for message in full_frames {
+                self.handle_message(&message, timestamp);
+            }
+            for message in MtcMessage::quarter_frames(packet.data()) {
+                self.handle_message(&message, timestamp);
+            }
+        }
+    }
+
+    /// Update the position from a MTC message received at a host timestamp.
+    ///
+    pub fn handle_message(&mut self, message: &MtcMessage, timestamp: Timestamp) {
+        match *message {
+            MtcMessage::FullFrame(time) => {
+                self.reset();
+                self.running = false;
+                self.anchor = Some((time, timestamp));
+            }
+            MtcMessage::QuarterFrame { piece, value } => {
+                self.handle_quarter_frame(piece & 0x07, value, timestamp)
+            }
+        }
+    }
+
+    fn handle_quarter_frame(&mut self, piece: u8, value: u8, timestamp: Timestamp) {
+        if let Some(last) = self.last_timestamp {
+            if self.is_dropout(last, timestamp) {
+                if self.running {
+                    self.dropouts += 1;
+                }
+                self.running = false;
+                self.reset();
+            }
+        }
+        self.last_timestamp = Some(timestamp);
+
+        let direction = match self.last_piece {
+            Some(last) if piece == (last + 1) % 8 => Some(MtcDirection::Forward),
+            Some(last) if piece == (last + 7) % 8 => Some(MtcDirection::Reverse),
+            _ => None,
+        };
+        if direction.is_none() || (self.direction.is_some() && direction != self.direction) {
+            self.quarter_frames.reset();
+        }
+        self.direction = direction;
+        self.last_piece = Some(piece);
+
+        let reverse = direction == Some(MtcDirection::Reverse);
+        if let Some(time) = self.quarter_frames.push_directed(piece, value, reverse) {
+            // The sequence was sent along 2 frames, since the frame of its first piece.
+            let frames = if reverse { -2 } else { 2 };
+            self.anchor = Some((time.add_frames(frames), timestamp));
+            self.running = true;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.quarter_frames.reset();
+        self.last_piece = None;
+        self.direction = None;
+    }
+
+    fn is_dropout(&self, last: Timestamp, timestamp: Timestamp) -> bool {
+        ticks_to_nanos(timestamp.host_time().saturating_sub(last.host_time()))
+            > self.dropout_timeout.as_nanos() as u64
+    }
+}
+
+impl Default for MtcReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const START_HOST_TIME: u64 = 1_000_000;
+
+    fn ms(ms: u64) -> Timestamp {
+        Timestamp::from_host_time(START_HOST_TIME + nanos_to_ticks(ms * 1_000_000))
+    }
+
+    #[test]
+    fn generator_timestamps_do_not_drift() {
+        let start = SmpteTime::new(0, 0, 0, 0, FrameRate::Fps30Drop);
+        let mut generator = MtcGenerator::new(start, ms(0));
+        let mut buffer = PacketBuffer::with_capacity(1024);
+        // Generate one hour in chunks of 10 ms
+        for window in 1..=360_000 {
+            buffer.clear();
+            generator.fill(&mut buffer, ms(window * 10));
+        }
+        // 29.97 fps for one hour is 107892 frames, exactly 01:00:00;00
+        assert_eq!(
+            generator.position(),
+            SmpteTime::new(1, 0, 0, 0, FrameRate::Fps30Drop)
+        );
+        let quarter_frame = Duration::from_nanos(1001 * 1_000_000_000 / 120_000);
+        let late = generator
+            .next_timestamp()
+            .checked_sub(quarter_frame)
+            .unwrap();
+        assert!(late < ms(3600 * 1000));
+    }
+
+    #[test]
+    fn generator_starts_now() {
+        let before = SystemClock.now();
+        let start = SmpteTime::new(0, 0, 0, 0, FrameRate::Fps25);
+        let mut generator = MtcGenerator::new(start, Timestamp::Now);
+        let mut buffer = PacketBuffer::with_capacity(1024);
+        assert!(generator.next_timestamp() >= before);
+        generator.fill(&mut buffer, SystemClock.after(Duration::from_millis(100)));
+        assert!(buffer.iter().count() > 1);
+        assert!(buffer.iter().all(|packet| packet.timestamp() >= before));
+    }
+
+    #[test]
+    fn reader_follows_generator() {
+        let start = SmpteTime::new(23, 59, 59, 20, FrameRate::Fps24);
+        let mut generator = MtcGenerator::new(start, ms(0));
+        let mut reader = MtcReader::new();
+        let mut buffer = PacketBuffer::with_capacity(1024);
+        generator.fill(&mut buffer, ms(500));
+        reader.handle_packet_list(&buffer);
+
+        assert_eq!(reader.direction(), Some(MtcDirection::Forward));
+        assert_eq!(reader.position(), Some(start.add_frames(12)));
+        assert_eq!(
+            reader.position_at(ms(500)),
+            Some(SmpteTime::new(0, 0, 0, 8, FrameRate::Fps24))
+        );
+        assert!(reader.is_running(ms(500)));
+    }
+
+    #[test]
+    fn reader_detects_reverse() {
+        let time = SmpteTime::new(0, 0, 10, 0, FrameRate::Fps25);
+        let mut reader = MtcReader::new();
+        for (index, piece) in (0..8).rev().chain((0..8).rev()).enumerate() {
+            let timestamp = ms(index as u64 * 10);
+            reader.handle_message(&MtcMessage::quarter_frame(&time, piece), timestamp);
+        }
+        assert_eq!(reader.direction(), Some(MtcDirection::Reverse));
+        assert_eq!(reader.position(), Some(time.add_frames(-2)));
+    }
+
+    #[test]
+    fn reader_dropout() {
+        let time = SmpteTime::new(0, 0, 10, 0, FrameRate::Fps25);
+        let mut reader = MtcReader::new();
+        for piece in 0..8 {
+            reader.handle_message(
+                &MtcMessage::quarter_frame(&time, piece),
+                ms(piece as u64 * 10),
+            );
+        }
+        assert!(reader.is_running(ms(80)));
+        assert!(!reader.is_running(ms(200)));
+        assert_eq!(reader.position_at(ms(500)), Some(time.add_frames(2)));
+
+        // A sequence interrupted by a dropout is discarded
+        for piece in 0..4 {
+            reader.handle_message(&MtcMessage::quarter_frame(&time, piece), ms(500));
+        }
+        for piece in 4..8 {
+            reader.handle_message(&MtcMessage::quarter_frame(&time, piece), ms(1000));
+        }
+        assert_eq!(reader.dropouts(), 1);
+        assert!(!reader.is_running(ms(1000)));
+
+        reader.handle_message(&MtcMessage::FullFrame(time), ms(1000));
+        assert_eq!(reader.position(), Some(time));
+        assert_eq!(reader.direction(), None);
+    }
+}
@@ -11,24 +11,58 @@ use coremidi_sys::{
 use crate::device::Device;
 use crate::object::{Object, ObjectType};
 
+/// The objects of a notification are given with the unique ids they had when it was received,
+/// if any, which the objects removed usually don't have anymore.
+///
+/// With the `serde` feature, only the unique ids are serialized, as object references are only
+/// meaningful within the process that obtained them. The objects of a deserialized notification
+/// are null, and can be found again with [Object::find_by_unique_id] while they exist.
+///
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AddedRemovedInfo {
+    #[cfg_attr(
+        feature = "serde",
+        serde(skip, default = "crate::serialization::null_object")
+    )]
     pub parent: Object,
     pub parent_type: ObjectType,
+    pub parent_unique_id: Option<u32>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(skip, default = "crate::serialization::null_object")
+    )]
     pub child: Object,
     pub child_type: ObjectType,
+    pub child_unique_id: Option<u32>,
 }
 
+/// See [AddedRemovedInfo] about the unique id of the object.
+///
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PropertyChangedInfo {
+    #[cfg_attr(
+        feature = "serde",
+        serde(skip, default = "crate::serialization::null_object")
+    )]
     pub object: Object,
     pub object_type: ObjectType,
+    pub object_unique_id: Option<u32>,
     pub property_name: String,
 }
 
+/// See [AddedRemovedInfo] about the unique id of the device.
+///
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct IoErrorInfo {
+    #[cfg_attr(
+        feature = "serde",
+        serde(skip, default = "crate::serialization::null_object")
+    )]
     pub driver_device: Device,
+    pub driver_device_unique_id: Option<u32>,
     pub error_code: OSStatus,
 }
 
@@ -36,6 +70,7 @@ pub struct IoErrorInfo {
 /// See [MIDINotification](https://developer.apple.com/documentation/coremidi/midinotification).
 ///
 #[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Notification {
     SetupChanged,
     ObjectAdded(AddedRemovedInfo),
@@ -56,10 +91,14 @@ impl Notification {
         let child_type = ObjectType::try_from(add_remove_notification.childType);
         match (parent_type, child_type) {
             (Ok(parent_type), Ok(child_type)) => {
+                let parent = Object(add_remove_notification.parent);
+                let child = Object(add_remove_notification.child);
                 let add_remove_info = AddedRemovedInfo {
-                    parent: Object(add_remove_notification.parent),
+                    parent_unique_id: parent.unique_id(),
+                    parent,
                     parent_type,
-                    child: Object(add_remove_notification.child),
+                    child_unique_id: child.unique_id(),
+                    child,
                     child_type,
                 };
                 match notification.messageID as ::std::os::raw::c_uint {
@@ -86,8 +125,10 @@ impl Notification {
                     let name: CFString = unsafe { TCFType::wrap_under_get_rule(name_ref) };
                     name.to_string()
                 };
+                let object = Object(property_changed_notification.object);
                 let property_changed_info = PropertyChangedInfo {
-                    object: Object(property_changed_notification.object),
+                    object_unique_id: object.unique_id(),
+                    object,
                     object_type,
                     property_name,
                 };
@@ -100,10 +141,12 @@ impl Notification {
     fn from_io_error(notification: &MIDINotification) -> Notification {
         let io_error_notification =
             unsafe { &*(notification as *const _ as *const MIDIIOErrorNotification) };
+        let driver_device = Device {
+            object: Object(io_error_notification.driverDevice),
+        };
         let io_error_info = IoErrorInfo {
-            driver_device: Device {
-                object: Object(io_error_notification.driverDevice),
-            },
+            driver_device_unique_id: driver_device.unique_id(),
+            driver_device,
             error_code: io_error_notification.errorCode,
         };
         Notification::IoError(io_error_info)
@@ -193,8 +236,10 @@ mod tests {
         let info = AddedRemovedInfo {
             parent: Object(1),
             parent_type: ObjectType::Device,
+            parent_unique_id: None,
             child: Object(2),
             child_type: ObjectType::Other,
+            child_unique_id: None,
         };
 
         assert_eq!(notification.unwrap(), Notification::ObjectAdded(info));
@@ -220,8 +265,10 @@ mod tests {
         let info = AddedRemovedInfo {
             parent: Object(1),
             parent_type: ObjectType::Device,
+            parent_unique_id: None,
             child: Object(2),
             child_type: ObjectType::Other,
+            child_unique_id: None,
         };
 
         assert_eq!(notification.unwrap(), Notification::ObjectRemoved(info));
@@ -288,6 +335,7 @@ mod tests {
         let info = PropertyChangedInfo {
             object: Object(1),
             object_type: ObjectType::Device,
+            object_unique_id: None,
             property_name: "name".to_string(),
         };
 
@@ -359,6 +407,7 @@ mod tests {
 
         let info = IoErrorInfo {
             driver_device: Device { object: Object(1) },
+            driver_device_unique_id: None,
             error_code: 123 as OSStatus,
         };
 
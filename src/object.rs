@@ -1,13 +1,14 @@
 use core_foundation_sys::base::OSStatus;
 use std::fmt;
 
-use coremidi_sys::{MIDIObjectRef, SInt32};
+use coremidi_sys::{MIDIObjectFindByUniqueID, MIDIObjectRef, MIDIObjectType, SInt32};
 
 use crate::properties::{
     BooleanProperty, IntegerProperty, Properties, PropertyGetter, PropertySetter, StringProperty,
 };
 
 #[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ObjectType {
     Other,
     Device,
@@ -49,6 +50,21 @@ impl TryFrom<i32> for ObjectType {
 pub struct Object(pub(crate) MIDIObjectRef);
 
 impl Object {
+    /// Find an object (and its type) from its unique id.
+    /// See [MIDIObjectFindByUniqueID](https://developer.apple.com/documentation/coremidi/1495207-midiobjectfindbyuniqueid).
+    ///
+    pub fn find_by_unique_id(unique_id: u32) -> Option<(Object, ObjectType)> {
+        let mut object_ref: MIDIObjectRef = 0;
+        let mut object_type: MIDIObjectType = 0;
+        let status = unsafe {
+            MIDIObjectFindByUniqueID(unique_id as SInt32, &mut object_ref, &mut object_type)
+        };
+        match (status, ObjectType::try_from(object_type)) {
+            (0, Ok(object_type)) if object_ref != 0 => Some((Object(object_ref), object_type)),
+            _ => None,
+        }
+    }
+
     /// Get the name for the object.
     ///
     pub fn name(&self) -> Option<String> {
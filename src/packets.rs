@@ -1,3 +1,4 @@
+use std::borrow::Borrow;
 use std::fmt;
 use std::marker::PhantomData;
 use std::ops::Deref;
@@ -7,7 +8,8 @@ use coremidi_sys::{
     MIDIPacket, MIDIPacketList, MIDIPacketListAdd, MIDIPacketListInit, MIDIPacketNext,
 };
 
-use crate::events::Storage;
+use crate::decode::write_midi1_messages;
+use crate::events::{Storage, TimestampOrderError};
 
 pub use crate::events::Timestamp;
 
@@ -45,8 +47,45 @@ impl PacketList {
             _phantom: PhantomData,
         }
     }
+
+    /// Get the number of bytes used by the list, and the offset of its last packet.
+    fn layout(&self) -> (usize, usize) {
+        let start = self as *const PacketList as usize;
+        let mut packet_ptr = std::ptr::addr_of!(self.0.packet) as *const MIDIPacket;
+        let mut layout = (packet_ptr as usize - start, packet_ptr as usize - start);
+        for _ in 0..self.len() {
+            let packet = unsafe { &*packet_ptr };
+            let end = packet.data.as_ptr() as usize + packet.length as usize;
+            layout = (end - start, packet_ptr as usize - start);
+            packet_ptr = unsafe { MIDIPacketNext(packet_ptr) };
+        }
+        layout
+    }
 }
 
+/// Copying a `PacketList` gives a [PacketBuffer] with the same packets, that is not bound
+/// to the lifetime of the original list, as the one given to an input callback.
+///
+/// ```
+/// use coremidi::{PacketBuffer, PacketList, Timestamp};
+///
+/// let buffer = PacketBuffer::new(Timestamp::from_host_time(42), &[0x90, 0x3c, 0x7f]);
+/// let packet_list: &PacketList = &buffer;
+/// let owned: PacketBuffer = packet_list.to_owned();
+/// assert_eq!(owned.iter().next().unwrap().data(), &[0x90, 0x3c, 0x7f]);
+/// ```
+impl ToOwned for PacketList {
+    type Owned = PacketBuffer;
+
+    fn to_owned(&self) -> PacketBuffer {
+        let mut buffer = PacketBuffer::with_capacity(self.layout().0);
+        buffer.copy_from(self);
+        buffer
+    }
+}
+
+/// With the alternate flag, as in `{:#?}`, the messages of the packets are decoded.
+///
 impl fmt::Debug for PacketList {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let result = write!(f, "PacketList(ptr={:x}, packets=[", unsafe {
@@ -58,13 +97,19 @@ impl fmt::Debug for PacketList {
                 Err(err) => Err(err),
                 Ok(()) => {
                     let sep = if i != 0 { ", " } else { "" };
-                    write!(f, "{}{:?}", sep, packet)
+                    if f.alternate() {
+                        write!(f, "{}{:#?}", sep, packet)
+                    } else {
+                        write!(f, "{}{:?}", sep, packet)
+                    }
                 }
             })
             .and_then(|_| write!(f, "])"))
     }
 }
 
+/// With the alternate flag, as in `{:#}`, the messages of the packets are decoded.
+///
 impl fmt::Display for PacketList {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let num_packets = self.len();
@@ -72,6 +117,7 @@ impl fmt::Display for PacketList {
         self.iter()
             .fold(result, |prev_result, packet| match prev_result {
                 Err(err) => Err(err),
+                Ok(()) if f.alternate() => write!(f, "\n  {:#}", packet),
                 Ok(()) => write!(f, "\n  {}", packet),
             })
     }
@@ -107,14 +153,14 @@ impl Packet {
     /// Get the packet timestamp.
     ///
     pub fn timestamp(&self) -> Timestamp {
-        self.0.timeStamp as Timestamp
+        Timestamp::from_host_time(self.0.timeStamp)
     }
 
     /// Get the packet data. This method just gives raw MIDI bytes. You would need another
     /// library to decode them and work with higher level events.
     ///
     /// ```
-    /// let packet_list = &coremidi::PacketBuffer::new(0, &[0x90, 0x40, 0x7f]);
+    /// let packet_list = &coremidi::PacketBuffer::new(coremidi::Timestamp::Now, &[0x90, 0x40, 0x7f]);
     /// let data: Vec<u8> = packet_list.iter().map(|packet| packet.data().to_vec()).flatten().collect();
     /// assert_eq!(data, vec![0x90, 0x40, 0x7f])
     /// ```
@@ -125,13 +171,25 @@ impl Packet {
     }
 }
 
+/// With the alternate flag, as in `{:#?}`, the messages are decoded.
+///
 impl fmt::Debug for Packet {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if f.alternate() {
+            write!(
+                f,
+                "Packet(ptr={:x}, ts={:016x}, messages=[",
+                self as *const _ as usize,
+                self.timestamp().host_time()
+            )?;
+            write_midi1_messages(f, self.data())?;
+            return write!(f, "])");
+        }
         let result = write!(
             f,
             "Packet(ptr={:x}, ts={:016x}, data=[",
             self as *const _ as usize,
-            self.timestamp() as u64
+            self.timestamp().host_time()
         );
         let result = self
             .data()
@@ -148,9 +206,23 @@ impl fmt::Debug for Packet {
     }
 }
 
+/// The data is shown in hex, or decoded into messages with the alternate flag, as in `{:#}`.
+/// Incomplete messages and unexpected data bytes are shown in hex, and long
+/// System Exclusive messages are truncated.
+///
+/// ```
+/// let buffer = coremidi::PacketBuffer::new(coremidi::Timestamp::from_host_time(42), &[0x90, 0x3c, 0x7f, 0xf8]);
+/// let packet = buffer.iter().next().unwrap();
+/// assert_eq!(format!("{}", packet), "000000000000002a: 90 3c 7f f8");
+/// assert_eq!(format!("{:#}", packet), "000000000000002a: NoteOn ch=1 note=60(C4) vel=127; TimingClock");
+/// ```
 impl fmt::Display for Packet {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let result = write!(f, "{:016x}:", self.timestamp());
+        if f.alternate() {
+            write!(f, "{:016x}: ", self.timestamp().host_time())?;
+            return write_midi1_messages(f, self.data());
+        }
+        let result = write!(f, "{:016x}:", self.timestamp().host_time());
         self.data()
             .iter()
             .fold(result, |prev_result, b| match prev_result {
@@ -166,6 +238,7 @@ impl fmt::Display for Packet {
 /// while a `PacketBuffer` is a mutable structure that allows to build a `PacketList` by adding packets.
 /// It dereferences to a `PacketList`, so it can be used whenever a `PacketList` is needed.
 ///
+#[derive(Clone)]
 pub struct PacketBuffer {
     storage: Storage,
     current_packet_offset: usize,
@@ -185,8 +258,8 @@ impl PacketBuffer {
     /// Example on how to create a `PacketBuffer` with a single packet for a MIDI note on for C-5:
     ///
     /// ```
-    /// use coremidi::PacketBuffer;
-    /// let buffer = PacketBuffer::new(0, &[0x90, 0x3c, 0x7f]);
+    /// use coremidi::{PacketBuffer, Timestamp};
+    /// let buffer = PacketBuffer::new(Timestamp::Now, &[0x90, 0x3c, 0x7f]);
     /// assert_eq!(buffer.len(), 1);
     /// assert_eq!(buffer.iter().next().map(|packet| packet.data().to_vec()), Some(vec![0x90, 0x3c, 0x7f]))
     /// ```
@@ -200,7 +273,7 @@ impl PacketBuffer {
                 packet_list_ptr,
                 storage.capacity() as u64,
                 current_packet_ptr,
-                timestamp,
+                timestamp.host_time(),
                 data.len() as u64,
                 data.as_ptr(),
             )
@@ -251,13 +324,13 @@ impl PacketBuffer {
     /// The timestamp applies to the first MIDI byte in the packet.
     ///
     /// An event must not have a timestamp that is smaller than that of a previous event
-    /// in the same `PacketList`
+    /// in the same `PacketList`, which [PacketBuffer::try_push_data] checks.
     ///
     /// Example:
     ///
     /// ```
-    /// let mut chord = coremidi::PacketBuffer::new(0, &[0x90, 0x3c, 0x7f]);
-    /// chord.push_data(0, &[0x90, 0x40, 0x7f]);
+    /// let mut chord = coremidi::PacketBuffer::new(coremidi::Timestamp::Now, &[0x90, 0x3c, 0x7f]);
+    /// chord.push_data(coremidi::Timestamp::Now, &[0x90, 0x40, 0x7f]);
     /// assert_eq!(chord.len(), 1);
     /// let repr = format!("{}", &chord as &coremidi::PacketList);
     /// assert_eq!(repr, "PacketList(len=1)\n  0000000000000000: 90 3c 7f 90 40 7f");
@@ -275,7 +348,7 @@ impl PacketBuffer {
                 packet_list_ptr,
                 self.storage.capacity() as u64,
                 current_packet_ptr,
-                timestamp,
+                timestamp.host_time(),
                 data.len() as u64,
                 data.as_ptr(),
             )
@@ -288,6 +361,56 @@ impl PacketBuffer {
         self
     }
 
+    /// Add a new event like [PacketBuffer::push_data], but rejecting a timestamp that is
+    /// earlier than the one of the last packet in the buffer.
+    ///
+    /// Example:
+    ///
+    /// ```
+    /// use coremidi::{PacketBuffer, Timestamp};
+    ///
+    /// let mut buffer = PacketBuffer::new(Timestamp::from_host_time(20), &[0x90, 0x3c, 0x7f]);
+    /// assert!(buffer.try_push_data(Timestamp::from_host_time(10), &[0x80, 0x3c, 0x00]).is_err());
+    /// assert!(buffer.try_push_data(Timestamp::from_host_time(30), &[0x80, 0x3c, 0x00]).is_ok());
+    /// assert_eq!(buffer.len(), 2);
+    /// ```
+    pub fn try_push_data(
+        &mut self,
+        timestamp: Timestamp,
+        data: &[u8],
+    ) -> Result<&mut Self, TimestampOrderError> {
+        match self.last_timestamp() {
+            Some(previous) if timestamp < previous => Err(TimestampOrderError {
+                previous,
+                timestamp,
+            }),
+            _ => Ok(self.push_data(timestamp, data)),
+        }
+    }
+
+    /// Replace the content of the buffer with a copy of a `PacketList`.
+    ///
+    /// It reuses the current storage, so it doesn't allocate when the list fits in [PacketBuffer::capacity],
+    /// which makes it suitable for copying the lists received in input callbacks into preallocated buffers.
+    ///
+    /// ```
+    /// use coremidi::{PacketBuffer, Timestamp};
+    ///
+    /// let received = PacketBuffer::new(Timestamp::Now, &[0x90, 0x3c, 0x7f]);
+    /// let mut buffer = PacketBuffer::with_capacity(1024);
+    /// buffer.copy_from(&received);
+    /// assert_eq!(buffer.capacity(), 1024);
+    /// assert_eq!(buffer.iter().next().unwrap().data(), &[0x90, 0x3c, 0x7f]);
+    /// ```
+    pub fn copy_from(&mut self, packet_list: &PacketList) {
+        let (len, last_packet_offset) = packet_list.layout();
+        unsafe {
+            self.storage
+                .copy_from_ptr(packet_list as *const PacketList as *const u8, len);
+        }
+        self.current_packet_offset = last_packet_offset;
+    }
+
     /// Clears the buffer, removing all packets.
     /// Note that this method has no effect on the allocated capacity of the buffer.
     pub fn clear(&mut self) {
@@ -298,6 +421,17 @@ impl PacketBuffer {
         };
     }
 
+    fn last_timestamp(&self) -> Option<Timestamp> {
+        if self.as_ref().is_empty() {
+            None
+        } else {
+            let current_packet = unsafe {
+                &*(self.storage.as_ptr::<u8>().add(self.current_packet_offset) as *const Packet)
+            };
+            Some(current_packet.timestamp())
+        }
+    }
+
     fn ensure_capacity(&mut self, data_len: usize) {
         let next_capacity = self.aligned_bytes_len() + Self::PACKET_HEADER_SIZE + data_len;
 
@@ -325,6 +459,12 @@ impl PacketBuffer {
     }
 }
 
+impl Borrow<PacketList> for PacketBuffer {
+    fn borrow(&self) -> &PacketList {
+        self.as_ref()
+    }
+}
+
 impl AsRef<PacketList> for PacketBuffer {
     #[inline]
     fn as_ref(&self) -> &PacketList {
@@ -348,7 +488,7 @@ mod tests {
 
     #[test]
     pub fn single_packet_alloc_inline() {
-        let packet_buf = PacketBuffer::new(42, &[0x90u8, 0x40, 0x7f]);
+        let packet_buf = PacketBuffer::new(Timestamp::from_host_time(42), &[0x90u8, 0x40, 0x7f]);
         if let Storage::External(_) = packet_buf.storage {
             panic!("A single 3-byte message must not be allocated externally")
         }
@@ -356,7 +496,7 @@ mod tests {
 
     #[test]
     fn packet_buffer_deref() {
-        let packet_buf = PacketBuffer::new(42, &[0x90u8, 0x40, 0x7f]);
+        let packet_buf = PacketBuffer::new(Timestamp::from_host_time(42), &[0x90u8, 0x40, 0x7f]);
         let packet_list: &PacketList = &packet_buf;
         assert_eq!(
             unsafe { packet_list.as_ptr() as *const MIDIPacketList },
@@ -367,10 +507,11 @@ mod tests {
     // FIXME
     #[test]
     fn packet_list_length() {
-        let mut packet_buf = PacketBuffer::new(42, &[0x90u8, 0x40, 0x7f]);
-        packet_buf.push_data(43, &[0x91u8, 0x40, 0x7f]);
-        packet_buf.push_data(44, &[0x80u8, 0x40, 0x7f]);
-        packet_buf.push_data(45, &[0x81u8, 0x40, 0x7f]);
+        let mut packet_buf =
+            PacketBuffer::new(Timestamp::from_host_time(42), &[0x90u8, 0x40, 0x7f]);
+        packet_buf.push_data(Timestamp::from_host_time(43), &[0x91u8, 0x40, 0x7f]);
+        packet_buf.push_data(Timestamp::from_host_time(44), &[0x80u8, 0x40, 0x7f]);
+        packet_buf.push_data(Timestamp::from_host_time(45), &[0x81u8, 0x40, 0x7f]);
         assert_eq!(packet_buf.len(), 4);
     }
 
@@ -391,9 +532,9 @@ mod tests {
     #[test]
     fn packet_buffer_with_capacity() {
         let mut packet_buf = PacketBuffer::with_capacity(128);
-        packet_buf.push_data(43, &[0x91u8, 0x40, 0x7f]);
-        packet_buf.push_data(44, &[0x80u8, 0x40, 0x7f]);
-        packet_buf.push_data(45, &[0x81u8, 0x40, 0x7f]);
+        packet_buf.push_data(Timestamp::from_host_time(43), &[0x91u8, 0x40, 0x7f]);
+        packet_buf.push_data(Timestamp::from_host_time(44), &[0x80u8, 0x40, 0x7f]);
+        packet_buf.push_data(Timestamp::from_host_time(45), &[0x81u8, 0x40, 0x7f]);
         assert_eq!(packet_buf.capacity(), 128);
         assert_eq!(packet_buf.len(), 3);
     }
@@ -401,15 +542,43 @@ mod tests {
     // FIXME
     #[test]
     fn packet_buffer_clear() {
-        let mut packet_buf = PacketBuffer::new(42, &[0x90u8, 0x40, 0x7f]);
-        packet_buf.push_data(43, &[0x91u8, 0x40, 0x7f]);
-        packet_buf.push_data(44, &[0x80u8, 0x40, 0x7f]);
-        packet_buf.push_data(45, &[0x81u8, 0x40, 0x7f]);
+        let mut packet_buf =
+            PacketBuffer::new(Timestamp::from_host_time(42), &[0x90u8, 0x40, 0x7f]);
+        packet_buf.push_data(Timestamp::from_host_time(43), &[0x91u8, 0x40, 0x7f]);
+        packet_buf.push_data(Timestamp::from_host_time(44), &[0x80u8, 0x40, 0x7f]);
+        packet_buf.push_data(Timestamp::from_host_time(45), &[0x81u8, 0x40, 0x7f]);
         assert_eq!(packet_buf.len(), 4);
         packet_buf.clear();
         assert_eq!(packet_buf.len(), 0);
     }
 
+    #[test]
+    fn packet_buffer_try_push_data_in_order() {
+        let mut packet_buf = PacketBuffer::with_capacity(256);
+        assert!(packet_buf
+            .try_push_data(Timestamp::Now, &[0x90u8, 0x40, 0x7f])
+            .is_ok());
+        assert!(packet_buf
+            .try_push_data(Timestamp::from_host_time(42), &[0x91u8, 0x40, 0x7f])
+            .is_ok());
+        assert!(packet_buf
+            .try_push_data(Timestamp::from_host_time(42), &[0x92u8, 0x40, 0x7f])
+            .is_ok());
+        assert_eq!(
+            packet_buf
+                .try_push_data(Timestamp::Now, &[0x80u8, 0x40, 0x7f])
+                .err(),
+            Some(TimestampOrderError {
+                previous: Timestamp::from_host_time(42),
+                timestamp: Timestamp::Now,
+            })
+        );
+        assert_eq!(
+            packet_buf.iter().last().map(|packet| packet.timestamp()),
+            Some(Timestamp::from_host_time(42))
+        );
+    }
+
     #[test]
     fn compare_equal_timestamps() {
         unsafe {
@@ -521,9 +690,9 @@ mod tests {
         let list_native = &*(pkt_list_ptr as *const _ as *const PacketList);
 
         // build the PacketBuffer, containing the same packets
-        let mut packet_buf = PacketBuffer::new(packets[0].0, &packets[0].1);
+        let mut packet_buf = PacketBuffer::new(packets[0].0.into(), &packets[0].1);
         for pkt in &packets[1..] {
-            packet_buf.push_data(pkt.0, &pkt.1);
+            packet_buf.push_data(pkt.0.into(), &pkt.1);
         }
 
         // print buffer contents for debugging purposes
@@ -557,4 +726,50 @@ mod tests {
             assert_eq!(n.data(), p.data());
         }
     }
+
+    fn packets(list: &PacketList) -> Vec<(Timestamp, Vec<u8>)> {
+        list.iter()
+            .map(|packet| (packet.timestamp(), packet.data().to_vec()))
+            .collect()
+    }
+
+    #[test]
+    fn copy_from_reuses_storage() {
+        let mut source = PacketBuffer::new(Timestamp::from_host_time(1), &[0x90, 0x40, 0x7f]);
+        source.push_data(
+            Timestamp::from_host_time(2),
+            &[0xf0, 0x7e, 0x7f, 0x06, 0x01, 0xf7],
+        );
+
+        let mut buffer = PacketBuffer::with_capacity(256);
+        let storage_ptr = unsafe { buffer.storage.as_ptr::<u8>() };
+        buffer.copy_from(&source);
+        assert_eq!(unsafe { buffer.storage.as_ptr::<u8>() }, storage_ptr);
+        assert_eq!(packets(&buffer), packets(&source));
+
+        buffer.push_data(Timestamp::from_host_time(3), &[0x80, 0x40, 0x00]);
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.last_timestamp(), Some(Timestamp::from_host_time(3)));
+
+        buffer.copy_from(&PacketBuffer::with_capacity(0));
+        assert!(buffer.is_empty());
+        buffer.push_data(Timestamp::Now, &[0xf8]);
+        assert_eq!(packets(&buffer), vec![(Timestamp::Now, vec![0xf8])]);
+    }
+
+    #[test]
+    fn to_owned_and_clone() {
+        let mut source = PacketBuffer::with_capacity(0);
+        for i in 0..32u8 {
+            source.push_data(Timestamp::from_host_time(i as u64 + 1), &[0x90, i, 0x7f]);
+        }
+        let list: &PacketList = &source;
+        let owned = list.to_owned();
+        assert_eq!(packets(&owned), packets(&source));
+
+        let mut cloned = owned.clone();
+        cloned.push_data(Timestamp::from_host_time(100), &[0xfc]);
+        assert_eq!(cloned.len(), 33);
+        assert_eq!(owned.len(), 32);
+    }
 }
@@ -0,0 +1,184 @@
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+use std::sync::Arc;
+
+/// What to do when a callback panics while being called by CoreMIDI.
+///
+/// Unwinding into CoreMIDI is undefined behaviour, so panics in callbacks are always caught,
+/// and then handled according to the policy of the [crate::Client] that created the callback.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PanicPolicy {
+    /// Report the panic to stderr and keep calling the callback for later events.
+    LogAndContinue,
+    /// Report the panic to stderr and stop calling the callback, dropping any later events.
+    Poison,
+    /// Report the panic to stderr and abort the process.
+    Abort,
+}
+
+impl PanicPolicy {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => Self::Poison,
+            2 => Self::Abort,
+            _ => Self::LogAndContinue,
+        }
+    }
+
+    fn as_u8(self) -> u8 {
+        match self {
+            Self::LogAndContinue => 0,
+            Self::Poison => 1,
+            Self::Abort => 2,
+        }
+    }
+}
+
+impl Default for PanicPolicy {
+    fn default() -> Self {
+        Self::LogAndContinue
+    }
+}
+
+/// The panic policy of a client and the number of panics in all its callbacks.
+#[derive(Debug, Default)]
+pub(crate) struct ClientPanics {
+    policy: AtomicU8,
+    count: AtomicU64,
+}
+
+impl ClientPanics {
+    pub(crate) fn policy(&self) -> PanicPolicy {
+        PanicPolicy::from_u8(self.policy.load(Ordering::Relaxed))
+    }
+
+    pub(crate) fn set_policy(&self, policy: PanicPolicy) {
+        self.policy.store(policy.as_u8(), Ordering::Relaxed);
+    }
+
+    pub(crate) fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+}
+
+/// Catches the panics of one callback, following the policy of its client.
+#[derive(Debug)]
+pub(crate) struct PanicGuard {
+    name: &'static str,
+    client: Arc<ClientPanics>,
+    count: AtomicU64,
+    poisoned: AtomicBool,
+}
+
+impl PanicGuard {
+    pub(crate) fn new(name: &'static str, client: Arc<ClientPanics>) -> Self {
+        Self {
+            name,
+            client,
+            count: AtomicU64::new(0),
+            poisoned: AtomicBool::new(false),
+        }
+    }
+
+    /// Call `f` unless the callback is poisoned, catching any panic.
+    pub(crate) fn call<F: FnOnce()>(&self, f: F) {
+        if self.is_poisoned() {
+            return;
+        }
+        if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(f)) {
+            self.count.fetch_add(1, Ordering::Relaxed);
+            self.client.count.fetch_add(1, Ordering::Relaxed);
+            let message = panic_message(payload.as_ref());
+            match self.client.policy() {
+                PanicPolicy::LogAndContinue => {
+                    eprintln!("coremidi: {} callback panicked: {}", self.name, message)
+                }
+                PanicPolicy::Poison => {
+                    self.poisoned.store(true, Ordering::Relaxed);
+                    eprintln!(
+                        "coremidi: {} callback panicked, dropping later events: {}",
+                        self.name, message
+                    );
+                }
+                PanicPolicy::Abort => {
+                    eprintln!(
+                        "coremidi: {} callback panicked, aborting: {}",
+                        self.name, message
+                    );
+                    std::process::abort();
+                }
+            }
+        }
+    }
+
+    pub(crate) fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn is_poisoned(&self) -> bool {
+        self.poisoned.load(Ordering::Relaxed)
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic payload"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn guard(policy: PanicPolicy) -> (Arc<ClientPanics>, PanicGuard) {
+        let client = Arc::new(ClientPanics::default());
+        client.set_policy(policy);
+        (client.clone(), PanicGuard::new("test", client))
+    }
+
+    #[test]
+    fn log_and_continue() {
+        let (client, guard) = guard(PanicPolicy::LogAndContinue);
+        let mut calls = 0;
+        guard.call(|| panic!("first"));
+        guard.call(|| calls += 1);
+        guard.call(|| panic!("{}", String::from("second")));
+        assert_eq!(calls, 1);
+        assert_eq!(guard.count(), 2);
+        assert_eq!(client.count(), 2);
+        assert!(!guard.is_poisoned());
+    }
+
+    #[test]
+    fn poison() {
+        let (client, guard) = guard(PanicPolicy::Poison);
+        let mut calls = 0;
+        guard.call(|| calls += 1);
+        guard.call(|| panic!("poisoned"));
+        guard.call(|| calls += 1);
+        assert_eq!(calls, 1);
+        assert_eq!(guard.count(), 1);
+        assert_eq!(client.count(), 1);
+        assert!(guard.is_poisoned());
+    }
+
+    #[test]
+    fn policy_round_trip() {
+        let client = ClientPanics::default();
+        assert_eq!(client.policy(), PanicPolicy::LogAndContinue);
+        for policy in [
+            PanicPolicy::Poison,
+            PanicPolicy::Abort,
+            PanicPolicy::LogAndContinue,
+        ] {
+            client.set_policy(policy);
+            assert_eq!(client.policy(), policy);
+        }
+    }
+}
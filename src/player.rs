@@ -0,0 +1,793 @@
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::time::Duration;
+
+use coremidi_sys::OSStatus;
+
+use crate::endpoints::destinations::Destination;
+use crate::events::Timestamp;
+use crate::host_time::{nanos_to_ticks, ticks_to_nanos, HostClock, SystemClock};
+use crate::packets::PacketBuffer;
+use crate::ports::OutputPort;
+use crate::smf::Smf;
+
+const NOTE_OFF: u8 = 0x80;
+const NOTE_ON: u8 = 0x90;
+const CONTROL_CHANGE: u8 = 0xB0;
+const PROGRAM_CHANGE: u8 = 0xC0;
+const PITCH_BEND: u8 = 0xE0;
+
+const BANK_SELECT_MSB: u8 = 0;
+const BANK_SELECT_LSB: u8 = 32;
+const DATA_ENTRY_MSB: u8 = 6;
+const DATA_ENTRY_LSB: u8 = 38;
+const DATA_INCREMENT: u8 = 96;
+const DATA_DECREMENT: u8 = 97;
+const NRPN_LSB: u8 = 98;
+const NRPN_MSB: u8 = 99;
+const RPN_LSB: u8 = 100;
+const RPN_MSB: u8 = 101;
+const PARAMETER_CONTROLLERS: [u8; 8] = [
+    DATA_ENTRY_MSB,
+    DATA_ENTRY_LSB,
+    DATA_INCREMENT,
+    DATA_DECREMENT,
+    NRPN_LSB,
+    NRPN_MSB,
+    RPN_LSB,
+    RPN_MSB,
+];
+const RPN_NULL: u8 = 127;
+const FIRST_CHANNEL_MODE_CONTROLLER: u8 = 120;
+const PITCH_BEND_CENTER: [u8; 2] = [0x00, 0x40];
+
+/// Plays a [Smf] by scheduling its messages ahead of time.
+///
+/// Like the [crate::ClockMaster], the messages due within a window of time are added
+/// to a buffer with their future timestamps, so they can be sent with a single call to
+/// [OutputPort::send] and played on time by CoreMIDI. The transport can be started,
+/// paused, moved and looped, and the playback speed scaled, each change taking effect
+/// at a host timestamp, where [Timestamp::Now] is taken as the current host time.
+///
+/// When moving to another position, the program changes, controllers and pitch bends
+/// in effect at that position are sent again, the ones only set after it are reset
+/// when moving back, and the notes still held are released whenever playback stops or
+/// jumps. The value of each registered and non-registered parameter is sent again right
+/// after selecting it, but data increments and decrements are not replayed. Messages
+/// already sent ahead of the change are not recalled, so the destination should be
+/// flushed with [crate::Endpoint::flush] first.
+///
+/// ```
+/// use std::time::Duration;
+/// use coremidi::{PacketBuffer, Smf, SmfDivision, SmfEvent, SmfEventKind, SmfFormat, SmfPlayer, SmfTrack, Timestamp};
+/// let events = vec![
+///     SmfEvent { ticks: 0, kind: SmfEventKind::Midi(vec![0x90, 60, 100]) },
+///     SmfEvent { ticks: 96, kind: SmfEventKind::Midi(vec![0x80, 60, 0]) },
+/// ];
+/// let smf = Smf {
+///     format: SmfFormat::SingleTrack,
+///     division: SmfDivision::TicksPerQuarter(96),
+///     tracks: vec![SmfTrack { events }],
+/// };
+/// let mut player = SmfPlayer::new(&smf);
+/// player.play(Timestamp::from_host_time(1000));
+/// let mut buffer = PacketBuffer::with_capacity(256);
+/// assert_eq!(player.fill(&mut buffer, Timestamp::from_host_time(1001)), 1);
+/// assert_eq!(buffer.iter().next().unwrap().data(), &[0x90, 60, 100]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct SmfPlayer {
+    events: Vec<(Duration, Vec<u8>)>,
+    duration: Duration,
+    position: Duration,
+    anchor: Timestamp,
+    speed: f64,
+    playing: bool,
+    loop_range: Option<Range<Duration>>,
+    next_event: usize,
+    held_notes: [u128; 16],
+    pending: Vec<(Timestamp, Vec<u8>)>,
+}
+
+impl SmfPlayer {
+    /// Create a stopped player positioned at the beginning of a file.
+    ///
+    pub fn new(smf: &Smf) -> Self {
+        let events = smf
+            .timeline()
+            .into_iter()
+            .filter_map(|timed| {
+                let data = timed.event.midi_data()?;
+                Some((timed.time, data.to_vec()))
+            })
+            .collect();
+        Self {
+            events,
+            duration: smf.duration(),
+            position: Duration::ZERO,
+            anchor: Timestamp::Now,
+            speed: 1.0,
+            playing: false,
+            loop_range: None,
+            next_event: 0,
+            held_notes: [0; 16],
+            pending: Vec::new(),
+        }
+    }
+
+    /// Get the duration of the file at its original tempo.
+    ///
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// Check whether the player is playing, as of the last generated message.
+    ///
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    /// Get the playback speed, where 1.0 is the tempo of the file.
+    ///
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    /// Get the loop range, if any.
+    ///
+    pub fn loop_range(&self) -> Option<Range<Duration>> {
+        self.loop_range.clone()
+    }
+
+    /// Get the position in the file, from its beginning, at a host timestamp.
+    ///
+    pub fn position_at(&self, timestamp: Timestamp) -> Duration {
+        if !self.playing {
+            return self.position;
+        }
+        let (now, anchor) = (timestamp.host_time(), self.anchor.host_time());
+        if now >= anchor {
+            return self.position + self.scaled(now - anchor);
+        }
+        // The anchor was moved ahead by a jump to the start of the loop.
+        let elapsed = self.scaled(anchor - now);
+        match &self.loop_range {
+            Some(range) if self.position == range.start && elapsed <= range.end - range.start => {
+                range.end - elapsed
+            }
+            _ => self.position.saturating_sub(elapsed),
+        }
+    }
+
+    /// Start playing from the current position at a host timestamp.
+    ///
+    pub fn play(&mut self, timestamp: Timestamp) {
+        if self.playing {
+            return;
+        }
+        self.anchor = SystemClock.resolve(timestamp);
+        self.next_event = self.event_index(self.position);
+        self.playing = true;
+    }
+
+    /// Pause at a host timestamp, keeping the position and releasing the held notes.
+    ///
+    pub fn pause(&mut self, timestamp: Timestamp) {
+        let timestamp = SystemClock.resolve(timestamp);
+        self.position = self.position_at(timestamp);
+        self.playing = false;
+        self.release_notes(timestamp);
+    }
+
+    /// Stop at a host timestamp, releasing the held notes and going back to the beginning.
+    ///
+    pub fn stop(&mut self, timestamp: Timestamp) {
+        self.pause(timestamp);
+        self.position = Duration::ZERO;
+        self.next_event = 0;
+    }
+
+    /// Move to a position in the file at a host timestamp, releasing the held notes
+    /// and chasing the program changes, controllers and pitch bends in effect there.
+    ///
+    pub fn seek(&mut self, position: Duration, timestamp: Timestamp) {
+        let timestamp = SystemClock.resolve(timestamp);
+        self.release_notes(timestamp);
+        self.jump(position.min(self.duration), timestamp);
+    }
+
+    /// Set a range of the file to play repeatedly, or `None` to play to the end.
+    /// Empty ranges are ignored.
+    ///
+    pub fn set_loop(&mut self, range: Option<Range<Duration>>) {
+        self.loop_range = range.filter(|range| range.start < range.end);
+    }
+
+    /// Scale the tempo of the file from a host timestamp, where 2.0 plays twice as fast.
+    /// Speeds that are not positive are ignored.
+    ///
+    pub fn set_speed(&mut self, speed: f64, timestamp: Timestamp) {
+        if !(speed > 0.0 && speed.is_finite()) {
+            return;
+        }
+        let timestamp = SystemClock.resolve(timestamp);
+        if self.playing {
+            self.position = self.position_at(timestamp);
+            self.anchor = timestamp;
+        }
+        self.speed = speed;
+    }
+
+    /// Add the messages with a timestamp before `until` to a buffer, including the ones
+    /// sent by the transport changes, and return the number of messages added.
+    ///
+    pub fn fill(&mut self, buffer: &mut PacketBuffer, until: Timestamp) -> usize {
+        let until = SystemClock.resolve(until);
+        let mut count = self.flush_pending(buffer);
+        while self.playing {
+            let end = match &self.loop_range {
+                Some(range) if self.position <= range.end => range.end,
+                _ => Duration::MAX,
+            };
+            match self.events.get(self.next_event) {
+                Some((time, _)) if *time < end => {
+                    let timestamp = self.timestamp_at(*time);
+                    if timestamp >= until {
+                        break;
+                    }
+                    let message = self.events[self.next_event].1.clone();
+                    self.track_note(&message);
+                    buffer.push_data(timestamp, &message);
+                    self.next_event += 1;
+                    count += 1;
+                }
+                _ => {
+                    let range = match &self.loop_range {
+                        Some(range) if end == range.end => range.clone(),
+                        _ => {
+                            let timestamp = self.timestamp_at(self.duration);
+                            if timestamp >= until {
+                                break;
+                            }
+                            self.pause(timestamp);
+                            self.position = self.duration;
+                            count += self.flush_pending(buffer);
+                            break;
+                        }
+                    };
+                    let timestamp = self.timestamp_at(range.end);
+                    if timestamp >= until {
+                        break;
+                    }
+                    self.release_notes(timestamp);
+                    self.jump(range.start, timestamp);
+                    count += self.flush_pending(buffer);
+                }
+            }
+        }
+        count
+    }
+
+    /// Send the messages with a timestamp before `until` to a destination,
+    /// and return the number of messages sent.
+    ///
+    pub fn send_until(
+        &mut self,
+        output_port: &OutputPort,
+        destination: &Destination,
+        until: Timestamp,
+    ) -> Result<usize, OSStatus> {
+        let mut buffer = PacketBuffer::with_capacity(1024);
+        let count = self.fill(&mut buffer, until);
+        if count > 0 {
+            output_port.send(destination, &buffer)?;
+        }
+        Ok(count)
+    }
+
+    fn jump(&mut self, position: Duration, timestamp: Timestamp) {
+        let previous_event = self.next_event;
+        self.position = position;
+        self.anchor = timestamp;
+        self.next_event = self.event_index(position);
+        self.chase(previous_event, timestamp);
+    }
+
+    /// Send the state in effect before the next event, and reset the state that was
+    /// only set by the events between it and the previous one, when moving back.
+    fn chase(&mut self, previous_event: usize, timestamp: Timestamp) {
+        let mut channels = vec![ChannelState::new(); 16];
+        for (_, message) in &self.events[..self.next_event] {
+            if let Some((channel, change)) = state_change(message) {
+                channels[channel].apply(change);
+            }
+        }
+        let skipped = self.next_event..previous_event.max(self.next_event);
+        for (_, message) in &self.events[skipped] {
+            if let Some((channel, change)) = state_change(message) {
+                channels[channel].reset(change);
+            }
+        }
+        for (channel, state) in channels.iter().enumerate() {
+            self.pending.extend(
+                state
+                    .messages(channel as u8)
+                    .into_iter()
+                    .map(|message| (timestamp, message)),
+            );
+        }
+    }
+
+    fn release_notes(&mut self, timestamp: Timestamp) {
+        for (channel, notes) in self.held_notes.iter_mut().enumerate() {
+            for note in (0..128u8).filter(|note| *notes & (1 << note) != 0) {
+                self.pending
+                    .push((timestamp, vec![NOTE_OFF | channel as u8, note, 0]));
+            }
+            *notes = 0;
+        }
+    }
+
+    fn track_note(&mut self, message: &[u8]) {
+        if let [status, note, velocity, ..] = *message {
+            let (kind, channel) = (status & 0xF0, (status & 0x0F) as usize);
+            let bit = 1u128 << (note & 0x7F);
+            match kind {
+                NOTE_ON if velocity > 0 => self.held_notes[channel] |= bit,
+                NOTE_ON | NOTE_OFF => self.held_notes[channel] &= !bit,
+                _ => {}
+            }
+        }
+    }
+
+    fn flush_pending(&mut self, buffer: &mut PacketBuffer) -> usize {
+        let count = self.pending.len();
+        for (timestamp, message) in self.pending.drain(..) {
+            buffer.push_data(timestamp, &message);
+        }
+        count
+    }
+
+    fn event_index(&self, position: Duration) -> usize {
+        self.events.partition_point(|(time, _)| *time < position)
+    }
+
+    fn scaled(&self, ticks: u64) -> Duration {
+        Duration::from_nanos((ticks_to_nanos(ticks) as f64 * self.speed).round() as u64)
+    }
+
+    fn timestamp_at(&self, time: Duration) -> Timestamp {
+        let nanos = time.saturating_sub(self.position).as_nanos() as f64 / self.speed;
+        Timestamp::from_host_time(self.anchor.host_time() + nanos_to_ticks(nanos.round() as u64))
+    }
+}
+
+/// A change to the state of a channel that is chased when moving to another position.
+enum StateChange {
+    Controller(u8, u8),
+    Program(u8),
+    PitchBend([u8; 2]),
+}
+
+fn state_change(message: &[u8]) -> Option<(usize, StateChange)> {
+    let (status, data) = message.split_first()?;
+    let channel = (status & 0x0F) as usize;
+    match (status & 0xF0, data) {
+        (CONTROL_CHANGE, [controller, value, ..])
+            if *controller < FIRST_CHANNEL_MODE_CONTROLLER =>
+        {
+            Some((channel, StateChange::Controller(*controller, *value)))
+        }
+        (PROGRAM_CHANGE, [program, ..]) => Some((channel, StateChange::Program(*program))),
+        (PITCH_BEND, [lsb, msb, ..]) => Some((channel, StateChange::PitchBend([*lsb, *msb]))),
+        _ => None,
+    }
+}
+
+/// The value of a controller after a General MIDI reset.
+fn controller_default(controller: u8) -> u8 {
+    match controller {
+        7 => 100,
+        8 | 10 => 64,
+        11 => 127,
+        _ => 0,
+    }
+}
+
+/// The state of a channel in effect at a position, to be sent again when moving there.
+#[derive(Clone)]
+struct ChannelState {
+    program: Option<u8>,
+    pitch_bend: Option<[u8; 2]>,
+    controllers: [Option<u8>; FIRST_CHANNEL_MODE_CONTROLLER as usize],
+    /// The data entry MSB and LSB of each parameter, by its selection MSB controller
+    /// and its number.
+    parameters: BTreeMap<(u8, [u8; 2]), [Option<u8>; 2]>,
+    /// The selection MSB controller of the kind of parameter selected last.
+    selected: Option<u8>,
+}
+
+impl ChannelState {
+    fn new() -> Self {
+        Self {
+            program: None,
+            pitch_bend: None,
+            controllers: [None; FIRST_CHANNEL_MODE_CONTROLLER as usize],
+            parameters: BTreeMap::new(),
+            selected: None,
+        }
+    }
+
+    fn apply(&mut self, change: StateChange) {
+        match change {
+            StateChange::Controller(
+                controller @ (NRPN_LSB | NRPN_MSB | RPN_LSB | RPN_MSB),
+                value,
+            ) => {
+                self.controllers[controller as usize] = Some(value);
+                // The MSB controllers are odd, right after their LSB.
+                self.selected = Some(controller | 1);
+            }
+            StateChange::Controller(controller @ (DATA_ENTRY_MSB | DATA_ENTRY_LSB), value) => {
+                if let Some(parameter) = self.selected_parameter() {
+                    let data = self.parameters.entry(parameter).or_default();
+                    data[(controller == DATA_ENTRY_LSB) as usize] = Some(value);
+                }
+            }
+            StateChange::Controller(DATA_INCREMENT | DATA_DECREMENT, _) => {}
+            StateChange::Controller(controller, value) => {
+                self.controllers[controller as usize] = Some(value)
+            }
+            StateChange::Program(program) => self.program = Some(program),
+            StateChange::PitchBend(value) => self.pitch_bend = Some(value),
+        }
+    }
+
+    /// Reset what a change after the position sets, unless it is already set at the position.
+    /// The defaults of parameters are unknown, so only their selection is reset.
+    fn reset(&mut self, change: StateChange) {
+        match change {
+            StateChange::Controller(NRPN_MSB | NRPN_LSB | RPN_MSB | RPN_LSB, _) => {
+                if self.selected.is_none() {
+                    self.controllers[RPN_MSB as usize] = Some(RPN_NULL);
+                    self.controllers[RPN_LSB as usize] = Some(RPN_NULL);
+                    self.selected = Some(RPN_MSB);
+                }
+            }
+            StateChange::Controller(
+                DATA_ENTRY_MSB | DATA_ENTRY_LSB | DATA_INCREMENT | DATA_DECREMENT,
+                _,
+            ) => {}
+            StateChange::Controller(controller, _) => {
+                self.controllers[controller as usize].get_or_insert(controller_default(controller));
+            }
+            StateChange::Program(_) => {
+                self.program.get_or_insert(0);
+            }
+            StateChange::PitchBend(_) => {
+                self.pitch_bend.get_or_insert(PITCH_BEND_CENTER);
+            }
+        }
+    }
+
+    fn selected_parameter(&self) -> Option<(u8, [u8; 2])> {
+        let selection = self.selected?;
+        let number = [
+            self.controllers[selection as usize]?,
+            self.controllers[selection as usize - 1]?,
+        ];
+        if number == [RPN_NULL, RPN_NULL] {
+            None
+        } else {
+            Some((selection, number))
+        }
+    }
+
+    /// The messages restoring this state: the bank and program first, then the controllers,
+    /// each parameter right after selecting it, the parameter selected last and the pitch bend.
+    fn messages(&self, channel: u8) -> Vec<Vec<u8>> {
+        let control = |number: u8, value: u8| vec![CONTROL_CHANGE | channel, number, value];
+        let controller =
+            |number: u8| self.controllers[number as usize].map(|value| control(number, value));
+        let bank = [BANK_SELECT_MSB, BANK_SELECT_LSB];
+        let mut messages: Vec<_> = bank
+            .iter()
+            .filter_map(|number| controller(*number))
+            .collect();
+        messages.extend(
+            self.program
+                .map(|program| vec![PROGRAM_CHANGE | channel, program]),
+        );
+        messages.extend(
+            (0..FIRST_CHANNEL_MODE_CONTROLLER)
+                .filter(|number| !bank.contains(number) && !PARAMETER_CONTROLLERS.contains(number))
+                .filter_map(controller),
+        );
+        for ((selection, [msb, lsb]), [data_msb, data_lsb]) in &self.parameters {
+            messages.push(control(*selection, *msb));
+            messages.push(control(selection - 1, *lsb));
+            messages.extend(data_msb.map(|value| control(DATA_ENTRY_MSB, value)));
+            messages.extend(data_lsb.map(|value| control(DATA_ENTRY_LSB, value)));
+        }
+        if let Some(selection) = self.selected {
+            messages.extend(controller(selection));
+            messages.extend(controller(selection - 1));
+        }
+        messages.extend(
+            self.pitch_bend
+                .map(|[lsb, msb]| vec![PITCH_BEND | channel, lsb, msb]),
+        );
+        messages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::smf::{SmfDivision, SmfEvent, SmfEventKind, SmfFormat, SmfTrack};
+
+    fn at(millis: u64) -> Timestamp {
+        Timestamp::from_host_time(1 + nanos_to_ticks(millis * 1_000_000))
+    }
+
+    fn smf(events: &[(u64, &[u8])]) -> Smf {
+        // 96 ticks per quarter at 120 bpm, so 1 tick is 1/192 seconds.
+        let events = events
+            .iter()
+            .map(|(ticks, data)| SmfEvent {
+                ticks: *ticks,
+                kind: SmfEventKind::Midi(data.to_vec()),
+            })
+            .collect();
+        Smf {
+            format: SmfFormat::SingleTrack,
+            division: SmfDivision::TicksPerQuarter(96),
+            tracks: vec![SmfTrack { events }],
+        }
+    }
+
+    fn messages(player: &mut SmfPlayer, until: Timestamp) -> Vec<(Timestamp, Vec<u8>)> {
+        let mut buffer = PacketBuffer::with_capacity(1024);
+        let count = player.fill(&mut buffer, until);
+        // Messages with the same timestamp can share a packet.
+        let mut messages: Vec<(Timestamp, Vec<u8>)> = Vec::new();
+        for packet in buffer.iter() {
+            for byte in packet.data() {
+                match messages.last_mut() {
+                    Some((_, message)) if *byte < 0x80 => message.push(*byte),
+                    _ => messages.push((packet.timestamp(), vec![*byte])),
+                }
+            }
+        }
+        assert_eq!(count, messages.len());
+        messages
+    }
+
+    fn song() -> Smf {
+        smf(&[
+            (0, &[0xB0, 7, 100]),
+            (0, &[0xC0, 5]),
+            (0, &[0x90, 60, 100]),
+            (96, &[0x80, 60, 0]),
+            (96, &[0xE0, 0, 0x50]),
+            (96, &[0x91, 64, 90]),
+            (192, &[0x81, 64, 0]),
+        ])
+    }
+
+    #[test]
+    fn play_ahead() {
+        let mut player = SmfPlayer::new(&song());
+        assert_eq!(player.duration(), Duration::from_secs(1));
+        player.play(at(0));
+        let first = messages(&mut player, at(400));
+        assert_eq!(first.len(), 3);
+        assert!(first.iter().all(|(timestamp, _)| *timestamp == at(0)));
+        let second = messages(&mut player, at(2000));
+        assert_eq!(
+            second,
+            vec![
+                (at(500), vec![0x80, 60, 0]),
+                (at(500), vec![0xE0, 0, 0x50]),
+                (at(500), vec![0x91, 64, 90]),
+                (at(1000), vec![0x81, 64, 0]),
+            ]
+        );
+        assert!(!player.is_playing());
+        assert_eq!(player.position_at(at(3000)), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn speed() {
+        let mut player = SmfPlayer::new(&song());
+        player.set_speed(2.0, at(0));
+        player.play(at(0));
+        let sent = messages(&mut player, at(2000));
+        assert_eq!(sent[3].0, at(250));
+        assert_eq!(sent[6].0, at(500));
+
+        let mut player = SmfPlayer::new(&song());
+        player.play(at(0));
+        messages(&mut player, at(100));
+        player.set_speed(0.5, at(250));
+        assert_eq!(player.position_at(at(250)), Duration::from_millis(250));
+        let sent = messages(&mut player, at(2000));
+        assert_eq!(sent[0].0, at(750));
+        assert_eq!(sent[3].0, at(1750));
+    }
+
+    #[test]
+    fn pause_releases_notes() {
+        let mut player = SmfPlayer::new(&song());
+        player.play(at(0));
+        messages(&mut player, at(100));
+        player.pause(at(200));
+        assert_eq!(player.position_at(at(1000)), Duration::from_millis(200));
+        assert_eq!(
+            messages(&mut player, at(2000)),
+            vec![(at(200), vec![0x80, 60, 0])]
+        );
+        player.play(at(1000));
+        let sent = messages(&mut player, at(2000));
+        assert_eq!(sent[0], (at(1300), vec![0x80, 60, 0]));
+    }
+
+    #[test]
+    fn seek_chases_state() {
+        let smf = smf(&[
+            (0, &[0xB0, 0, 1]),
+            (0, &[0xB0, 32, 2]),
+            (0, &[0xC0, 5]),
+            (0, &[0xB0, 7, 100]),
+            (0, &[0x90, 60, 100]),
+            (48, &[0xB0, 7, 80]),
+            (48, &[0xB0, 121, 0]),
+            (48, &[0xE1, 0, 0x50]),
+            (96, &[0x80, 60, 0]),
+        ]);
+        let mut player = SmfPlayer::new(&smf);
+        player.play(at(0));
+        messages(&mut player, at(100));
+        player.seek(Duration::from_millis(300), at(100));
+        assert_eq!(
+            messages(&mut player, at(101)),
+            vec![
+                (at(100), vec![0x80, 60, 0]),
+                (at(100), vec![0xB0, 0, 1]),
+                (at(100), vec![0xB0, 32, 2]),
+                (at(100), vec![0xC0, 5]),
+                (at(100), vec![0xB0, 7, 80]),
+                (at(100), vec![0xE1, 0, 0x50]),
+            ]
+        );
+        assert_eq!(player.position_at(at(150)), Duration::from_millis(350));
+        assert_eq!(
+            messages(&mut player, at(1000)),
+            vec![(at(300), vec![0x80, 60, 0])]
+        );
+    }
+
+    #[test]
+    fn seek_chases_parameters() {
+        let smf = smf(&[
+            (0, &[0xB0, 101, 0]),
+            (0, &[0xB0, 100, 0]),
+            (0, &[0xB0, 6, 12]),
+            (0, &[0xB0, 38, 0]),
+            (0, &[0xB0, 99, 1]),
+            (0, &[0xB0, 98, 8]),
+            (0, &[0xB0, 6, 64]),
+            (0, &[0xB0, 96, 0]),
+            (0, &[0xB0, 7, 90]),
+            (0, &[0x90, 60, 100]),
+            (96, &[0x80, 60, 0]),
+        ]);
+        let mut player = SmfPlayer::new(&smf);
+        player.seek(Duration::from_millis(100), at(0));
+        assert_eq!(
+            messages(&mut player, at(1)),
+            vec![
+                (at(0), vec![0xB0, 7, 90]),
+                (at(0), vec![0xB0, 99, 1]),
+                (at(0), vec![0xB0, 98, 8]),
+                (at(0), vec![0xB0, 6, 64]),
+                (at(0), vec![0xB0, 101, 0]),
+                (at(0), vec![0xB0, 100, 0]),
+                (at(0), vec![0xB0, 6, 12]),
+                (at(0), vec![0xB0, 38, 0]),
+                (at(0), vec![0xB0, 99, 1]),
+                (at(0), vec![0xB0, 98, 8]),
+            ]
+        );
+    }
+
+    #[test]
+    fn stop_rewinds() {
+        let mut player = SmfPlayer::new(&song());
+        player.play(at(0));
+        messages(&mut player, at(600));
+        player.stop(at(600));
+        assert!(!player.is_playing());
+        assert_eq!(player.position_at(at(600)), Duration::ZERO);
+        assert_eq!(
+            messages(&mut player, at(1000)),
+            vec![(at(600), vec![0x81, 64, 0])]
+        );
+        player.play(at(1000));
+        assert_eq!(messages(&mut player, at(1001)).len(), 3);
+    }
+
+    #[test]
+    fn loop_range() {
+        let mut player = SmfPlayer::new(&song());
+        player.set_loop(Some(Duration::from_millis(250)..Duration::from_millis(750)));
+        player.play(at(0));
+        let sent = messages(&mut player, at(1500));
+        assert_eq!(
+            sent[3..],
+            [
+                (at(500), vec![0x80, 60, 0]),
+                (at(500), vec![0xE0, 0, 0x50]),
+                (at(500), vec![0x91, 64, 90]),
+                (at(750), vec![0x81, 64, 0]),
+                (at(750), vec![0xC0, 5]),
+                (at(750), vec![0xB0, 7, 100]),
+                (at(750), vec![0xE0, 0x00, 0x40]),
+                (at(1000), vec![0x80, 60, 0]),
+                (at(1000), vec![0xE0, 0, 0x50]),
+                (at(1000), vec![0x91, 64, 90]),
+                (at(1250), vec![0x81, 64, 0]),
+                (at(1250), vec![0xC0, 5]),
+                (at(1250), vec![0xB0, 7, 100]),
+                (at(1250), vec![0xE0, 0x00, 0x40]),
+            ]
+        );
+        assert!(player.is_playing());
+        assert_eq!(player.position_at(at(1200)), Duration::from_millis(700));
+        assert_eq!(player.position_at(at(1300)), Duration::from_millis(300));
+
+        player.set_loop(Some(Duration::from_secs(1)..Duration::from_secs(1)));
+        assert_eq!(player.loop_range(), None);
+    }
+
+    #[test]
+    fn loop_resets_state_set_inside_it() {
+        let smf = smf(&[
+            (0, &[0xB0, 7, 90]),
+            (48, &[0xB2, 1, 64]),
+            (48, &[0xB2, 7, 50]),
+            (48, &[0xB2, 10, 20]),
+            (48, &[0xC2, 9]),
+            (48, &[0xE2, 0, 0x60]),
+            (96, &[0xB0, 7, 120]),
+            (144, &[0x90, 60, 100]),
+        ]);
+        let mut player = SmfPlayer::new(&smf);
+        player.set_loop(Some(Duration::from_millis(100)..Duration::from_millis(600)));
+        player.play(at(0));
+        let sent = messages(&mut player, at(601));
+        assert_eq!(
+            sent[7..],
+            [
+                (at(600), vec![0xB0, 7, 90]),
+                (at(600), vec![0xC2, 0]),
+                (at(600), vec![0xB2, 1, 0]),
+                (at(600), vec![0xB2, 7, 100]),
+                (at(600), vec![0xB2, 10, 64]),
+                (at(600), vec![0xE2, 0x00, 0x40]),
+            ]
+        );
+    }
+
+    #[test]
+    fn play_now() {
+        let mut player = SmfPlayer::new(&song());
+        let before = SystemClock.now();
+        player.play(Timestamp::Now);
+        let sent = messages(&mut player, SystemClock.after(Duration::from_secs(2)));
+        assert_eq!(sent.len(), 7);
+        assert!(sent.iter().all(|(timestamp, _)| *timestamp >= before));
+        let elapsed = SystemClock.duration_between(sent[0].0, sent[6].0);
+        assert!(elapsed.as_millis() == 1000 || elapsed.as_millis() == 999);
+    }
+}
@@ -1,8 +1,12 @@
 use core_foundation::base::OSStatus;
+use std::cell::UnsafeCell;
 use std::collections::HashMap;
 use std::ffi::c_void;
+use std::fmt;
 use std::ops::Deref;
 use std::ptr;
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
 
 use coremidi_sys::{
     MIDIObjectRef, MIDIPortConnectSource, MIDIPortDisconnectSource, MIDIPortDispose, MIDIPortRef,
@@ -13,6 +17,7 @@ use crate::endpoints::destinations::Destination;
 use crate::endpoints::sources::Source;
 use crate::object::Object;
 use crate::packets::PacketList;
+use crate::panics::PanicGuard;
 use crate::{EventBuffer, EventList, PacketBuffer};
 
 pub enum Packets<'a> {
@@ -88,11 +93,11 @@ impl Drop for Port {
 /// A simple example to create an output port and send a MIDI event:
 ///
 /// ```rust,no_run
-/// use coremidi::{Client, Destination, EventBuffer, Protocol};
+/// use coremidi::{Client, Destination, EventBuffer, Protocol, Timestamp};
 /// let client = Client::new("example-client").unwrap();
 /// let output_port = client.output_port("example-port").unwrap();
 /// let destination = Destination::from_index(0).unwrap();
-/// let events = EventBuffer::new(Protocol::Midi10).with_packet(0, &[0x2090407f]);
+/// let events = EventBuffer::new(Protocol::Midi10).with_packet(Timestamp::Now, &[0x2090407f]);
 /// output_port.send(&destination, &events).unwrap();
 /// ```
 #[derive(Debug)]
@@ -157,15 +162,30 @@ impl Deref for OutputPort {
 #[derive(Debug)]
 pub struct InputPort {
     pub(crate) port: Port,
+    pub(crate) panic_guard: Arc<PanicGuard>,
 }
 
 impl InputPort {
-    pub(crate) fn new(port_ref: MIDIPortRef) -> Self {
+    pub(crate) fn new(port_ref: MIDIPortRef, panic_guard: Arc<PanicGuard>) -> Self {
         Self {
             port: Port::new(port_ref),
+            panic_guard,
         }
     }
 
+    /// Get the number of panics caught in the callback of this port.
+    ///
+    pub fn panic_count(&self) -> u64 {
+        self.panic_guard.count()
+    }
+
+    /// Check whether the callback of this port panicked under [crate::PanicPolicy::Poison],
+    /// so that the events received are being dropped.
+    ///
+    pub fn is_poisoned(&self) -> bool {
+        self.panic_guard.is_poisoned()
+    }
+
     pub fn connect_source(&self, source: &Source) -> Result<(), OSStatus> {
         let status =
             unsafe { MIDIPortConnectSource(self.object.0, source.object.0, ptr::null_mut()) };
@@ -194,8 +214,260 @@ impl Deref for InputPort {
     }
 }
 
+/// An input port created by [crate::Client::input_port_with_queue], whose sources are connected
+/// with the tag of the messages received from them.
+///
+/// The tag is given to CoreMIDI as the connection reference of the source, and given back
+/// with every event list received, so the callback doesn't need to look anything up.
+///
+#[derive(Debug)]
+pub struct TaggedInputPort {
+    pub(crate) port: Port,
+    pub(crate) panic_guard: Arc<PanicGuard>,
+}
+
+impl TaggedInputPort {
+    pub(crate) fn new(port_ref: MIDIPortRef, panic_guard: Arc<PanicGuard>) -> Self {
+        Self {
+            port: Port::new(port_ref),
+            panic_guard,
+        }
+    }
+
+    /// Get the number of panics caught in the callback of this port.
+    ///
+    pub fn panic_count(&self) -> u64 {
+        self.panic_guard.count()
+    }
+
+    /// Check whether the callback of this port panicked under [crate::PanicPolicy::Poison],
+    /// so that the events received are being dropped.
+    ///
+    pub fn is_poisoned(&self) -> bool {
+        self.panic_guard.is_poisoned()
+    }
+
+    /// Connect a source, with the tag of the messages received from it, for example its unique id.
+    /// Connecting a source again replaces its tag.
+    ///
+    pub fn connect_source(&self, source: &Source, tag: u32) -> Result<(), OSStatus> {
+        let status = unsafe {
+            MIDIPortConnectSource(self.object.0, source.object.0, tag as usize as *mut c_void)
+        };
+        if status == 0 {
+            Ok(())
+        } else {
+            Err(status)
+        }
+    }
+
+    pub fn disconnect_source(&self, source: &Source) -> Result<(), OSStatus> {
+        let status = unsafe { MIDIPortDisconnectSource(self.object.0, source.object.0) };
+        if status == 0 {
+            Ok(())
+        } else {
+            Err(status)
+        }
+    }
+}
+
+impl Deref for TaggedInputPort {
+    type Target = Port;
+
+    fn deref(&self) -> &Port {
+        &self.port
+    }
+}
+
+/// The contexts of the sources connected to an [InputPortWithContext], shared with its callback.
+///
+/// CoreMIDI is only given the key of a context, never a pointer to it, and the callback holds
+/// its own reference to the context while using it. So disconnecting a source, or dropping the port,
+/// while a callback is in flight only releases the context once that callback is done with it.
+///
+/// Looking up a key doesn't lock, so that the callback is never delayed by the port connecting or
+/// disconnecting sources. The contexts are kept in slots that are never moved nor freed before
+/// the contexts themselves, and a key holds the generation of its slot besides its index,
+/// so that the key of a disconnected source never finds the context of a later connection.
+///
+pub(crate) struct Contexts<T> {
+    chunks: [AtomicPtr<Slot<T>>; CHUNKS],
+    slots: Mutex<Slots>,
+}
+
+/// The slots are allocated in chunks of doubling lengths, starting with this one.
+const FIRST_CHUNK_LEN: usize = 8;
+const CHUNKS: usize = 24;
+/// The low half of a key is the index of its slot plus one, so that keys are never zero
+/// (a null `srcConnRefCon`), and the high half is the generation of the slot.
+const INDEX_BITS: u32 = usize::BITS / 2;
+const INDEX_MASK: usize = (1 << INDEX_BITS) - 1;
+
+struct Slot<T> {
+    /// The key of the context in the slot, or zero when it is empty.
+    key: AtomicUsize,
+    /// The number of callbacks looking into the slot.
+    readers: AtomicUsize,
+    context: UnsafeCell<Option<Arc<Mutex<T>>>>,
+}
+
+impl<T> Default for Slot<T> {
+    fn default() -> Self {
+        Self {
+            key: AtomicUsize::new(0),
+            readers: AtomicUsize::new(0),
+            context: UnsafeCell::new(None),
+        }
+    }
+}
+
+/// The bookkeeping of the slots, only used when inserting and removing contexts.
+#[derive(Default)]
+struct Slots {
+    generations: Vec<usize>,
+    free: Vec<usize>,
+}
+
+// A slot is only written while its key is zero and no callback is looking into it,
+// and the contexts are only shared through `Arc<Mutex<T>>`.
+unsafe impl<T: Send> Send for Contexts<T> {}
+unsafe impl<T: Send> Sync for Contexts<T> {}
+
+impl<T> Contexts<T> {
+    pub(crate) fn new() -> Self {
+        Self {
+            chunks: Default::default(),
+            slots: Mutex::new(Slots::default()),
+        }
+    }
+
+    /// Register a context, returning its key.
+    fn insert(&self, context: T) -> usize {
+        let mut slots = self.slots.lock().unwrap_or_else(PoisonError::into_inner);
+        let index = match slots.free.pop() {
+            Some(index) => index,
+            None => {
+                let index = slots.generations.len();
+                let (chunk, offset) = locate(index).expect("too many input port contexts");
+                if offset == 0 {
+                    let chunk_slots: Box<[Slot<T>]> =
+                        (0..chunk_len(chunk)).map(|_| Slot::default()).collect();
+                    let chunk_slots = Box::into_raw(chunk_slots) as *mut Slot<T>;
+                    self.chunks[chunk].store(chunk_slots, Ordering::Release);
+                }
+                slots.generations.push(0);
+                index
+            }
+        };
+        let key = (slots.generations[index] << INDEX_BITS) | (index + 1);
+        let slot = self.slot(index).unwrap();
+        unsafe { *slot.context.get() = Some(Arc::new(Mutex::new(context))) };
+        slot.key.store(key, Ordering::SeqCst);
+        key
+    }
+
+    fn remove(&self, key: usize) -> Option<Arc<Mutex<T>>> {
+        let mut slots = self.slots.lock().unwrap_or_else(PoisonError::into_inner);
+        let index = (key & INDEX_MASK).checked_sub(1)?;
+        let slot = self.slot(index)?;
+        slot.key
+            .compare_exchange(key, 0, Ordering::SeqCst, Ordering::SeqCst)
+            .ok()?;
+        // Callbacks that saw the key are only cloning the context, so this never waits for long.
+        while slot.readers.load(Ordering::SeqCst) != 0 {
+            std::hint::spin_loop();
+        }
+        let context = unsafe { (*slot.context.get()).take() };
+        slots.generations[index] = (slots.generations[index] + 1) & (usize::MAX >> INDEX_BITS);
+        slots.free.push(index);
+        context
+    }
+
+    fn get(&self, key: usize) -> Option<Arc<Mutex<T>>> {
+        let index = (key & INDEX_MASK).checked_sub(1)?;
+        let slot = self.slot(index)?;
+        slot.readers.fetch_add(1, Ordering::SeqCst);
+        let context = if slot.key.load(Ordering::SeqCst) == key {
+            unsafe { (*slot.context.get()).clone() }
+        } else {
+            None
+        };
+        slot.readers.fetch_sub(1, Ordering::SeqCst);
+        context
+    }
+
+    fn slot(&self, index: usize) -> Option<&Slot<T>> {
+        let (chunk, offset) = locate(index)?;
+        let slots = self.chunks[chunk].load(Ordering::Acquire);
+        if slots.is_null() {
+            None
+        } else {
+            Some(unsafe { &*slots.add(offset) })
+        }
+    }
+}
+
+impl<T> Drop for Contexts<T> {
+    fn drop(&mut self) {
+        for (chunk, slots) in self.chunks.iter_mut().enumerate() {
+            let slots = *slots.get_mut();
+            if !slots.is_null() {
+                let slots = ptr::slice_from_raw_parts_mut(slots, chunk_len(chunk));
+                drop(unsafe { Box::from_raw(slots) });
+            }
+        }
+    }
+}
+
+fn chunk_len(chunk: usize) -> usize {
+    FIRST_CHUNK_LEN << chunk
+}
+
+/// Get the chunk of the slot at an index, and the offset of the slot in it.
+fn locate(index: usize) -> Option<(usize, usize)> {
+    let chunk = (usize::BITS - 1 - (index / FIRST_CHUNK_LEN + 1).leading_zeros()) as usize;
+    let offset = index - FIRST_CHUNK_LEN * ((1 << chunk) - 1);
+    if chunk < CHUNKS && index < INDEX_MASK {
+        Some((chunk, offset))
+    } else {
+        None
+    }
+}
+
+impl<T> fmt::Debug for Contexts<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let len = self
+            .slots
+            .lock()
+            .map(|slots| slots.generations.len() - slots.free.len())
+            .unwrap_or(0);
+        write!(f, "Contexts(len={})", len)
+    }
+}
+
+/// Adapt a callback taking a context into one taking the `srcConnRefCon` given by CoreMIDI,
+/// which is the key of the context. Events for keys that are not registered (anymore) are ignored.
+pub(crate) fn context_callback<T, F>(
+    contexts: Arc<Contexts<T>>,
+    mut callback: F,
+) -> impl FnMut(&EventList, *mut c_void)
+where
+    F: FnMut(&EventList, &mut T),
+{
+    move |event_list, src_conn_ref_con| {
+        if let Some(context) = contexts.get(src_conn_ref_con as usize) {
+            let mut context = context.lock().unwrap_or_else(PoisonError::into_inner);
+            callback(event_list, &mut context);
+        }
+    }
+}
+
 /// An input [MIDI port](https://developer.apple.com/documentation/coremidi/midiportref) owned by a client.
 ///
+/// Every connected source has a context, which is given to the callback for the events received from it.
+/// The contexts are owned by the port and shared with the callback, so they need to be `Send`.
+/// A context is released once its source is disconnected, or the port dropped, and no callback is using it.
+///
 /// A simple example to create an input port:
 ///
 /// ```rust,no_run
@@ -209,40 +481,81 @@ impl Deref for InputPort {
 #[derive(Debug)]
 pub struct InputPortWithContext<T> {
     pub(crate) port: Port,
-    pub(crate) contexts: HashMap<MIDIObjectRef, Box<T>>,
+    pub(crate) contexts: Arc<Contexts<T>>,
+    pub(crate) keys: HashMap<MIDIObjectRef, usize>,
+    pub(crate) panic_guard: Arc<PanicGuard>,
 }
 
 impl<T> InputPortWithContext<T> {
-    pub(crate) fn new(port_ref: MIDIPortRef) -> Self {
+    pub(crate) fn new(
+        port_ref: MIDIPortRef,
+        contexts: Arc<Contexts<T>>,
+        panic_guard: Arc<PanicGuard>,
+    ) -> Self {
         Self {
             port: Port::new(port_ref),
-            contexts: HashMap::new(),
+            contexts,
+            keys: HashMap::new(),
+            panic_guard,
         }
     }
 
+    /// Get the number of panics caught in the callback of this port.
+    ///
+    pub fn panic_count(&self) -> u64 {
+        self.panic_guard.count()
+    }
+
+    /// Check whether the callback of this port panicked under [crate::PanicPolicy::Poison],
+    /// so that the events received are being dropped.
+    ///
+    pub fn is_poisoned(&self) -> bool {
+        self.panic_guard.is_poisoned()
+    }
+
+    /// Connect a source, with the context to give to the callback for its events.
+    /// Connecting a source again replaces its context.
+    ///
     pub fn connect_source(&mut self, source: &Source, context: T) -> Result<(), OSStatus> {
-        let mut context = Box::new(context);
-        let context_ptr = context.as_mut() as *mut T;
-        let status = unsafe {
-            MIDIPortConnectSource(self.object.0, source.object.0, context_ptr as *mut c_void)
-        };
+        let key = self.contexts.insert(context);
+        let status =
+            unsafe { MIDIPortConnectSource(self.object.0, source.object.0, key as *mut c_void) };
         if status == 0 {
-            self.contexts.insert(source.object.0, context);
+            if let Some(previous_key) = self.keys.insert(source.object.0, key) {
+                self.contexts.remove(previous_key);
+            }
             Ok(())
         } else {
+            self.contexts.remove(key);
             Err(status)
         }
     }
 
+    /// Disconnect a source, releasing its context as soon as no callback is using it.
+    ///
     pub fn disconnect_source(&mut self, source: &Source) -> Result<(), OSStatus> {
         let status = unsafe { MIDIPortDisconnectSource(self.object.0, source.object.0) };
         if status == 0 {
-            self.contexts.remove(&source.object.0);
+            if let Some(key) = self.keys.remove(&source.object.0) {
+                self.contexts.remove(key);
+            }
             Ok(())
         } else {
             Err(status)
         }
     }
+
+    /// Access the context of a connected source, waiting for any callback using it to finish.
+    ///
+    pub fn with_context<R, F>(&self, source: &Source, f: F) -> Option<R>
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        let key = self.keys.get(&source.object.0)?;
+        let context = self.contexts.get(*key)?;
+        let mut context = context.lock().unwrap_or_else(PoisonError::into_inner);
+        Some(f(&mut context))
+    }
 }
 
 impl<T> Deref for InputPortWithContext<T> {
@@ -252,3 +565,251 @@ impl<T> Deref for InputPortWithContext<T> {
         &self.port
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Client;
+    use crate::events::Timestamp;
+    use crate::panics::ClientPanics;
+    use crate::protocol::Protocol;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::{Barrier, RwLock};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    /// A context that records its release in a flag outliving it, and counts how many are alive.
+    struct Tracked {
+        alive: Arc<AtomicUsize>,
+        released: Arc<AtomicBool>,
+        events: u64,
+    }
+
+    impl Tracked {
+        fn new(alive: &Arc<AtomicUsize>) -> Self {
+            alive.fetch_add(1, Ordering::SeqCst);
+            Self {
+                alive: alive.clone(),
+                released: Arc::new(AtomicBool::new(false)),
+                events: 0,
+            }
+        }
+
+        fn touch(&mut self) {
+            assert!(
+                !self.released.load(Ordering::SeqCst),
+                "context used after release"
+            );
+            self.events += 1;
+        }
+    }
+
+    impl Drop for Tracked {
+        fn drop(&mut self) {
+            assert!(
+                !self.released.swap(true, Ordering::SeqCst),
+                "context released twice"
+            );
+            self.alive.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    fn events() -> EventBuffer {
+        EventBuffer::new(Protocol::Midi10).with_packet(Timestamp::Now, &[0x20f80000])
+    }
+
+    fn deliver(callback: &mut impl FnMut(&EventList, *mut c_void), key: usize) {
+        callback(&events(), key as *mut c_void);
+    }
+
+    fn panic_guard() -> Arc<PanicGuard> {
+        Arc::new(PanicGuard::new("test", Arc::new(ClientPanics::default())))
+    }
+
+    #[test]
+    fn removal_is_deferred_while_in_use() {
+        let alive = Arc::new(AtomicUsize::new(0));
+        let contexts = Arc::new(Contexts::new());
+        let key = contexts.insert(Tracked::new(&alive));
+
+        let barrier = Arc::new(Barrier::new(2));
+        let callback_barrier = barrier.clone();
+        let mut callback = context_callback(contexts.clone(), move |_, context: &mut Tracked| {
+            callback_barrier.wait();
+            callback_barrier.wait();
+            context.touch();
+        });
+        let delivery = thread::spawn(move || deliver(&mut callback, key));
+
+        barrier.wait();
+        assert!(contexts.remove(key).is_some());
+        assert_eq!(alive.load(Ordering::SeqCst), 1);
+        barrier.wait();
+        delivery.join().unwrap();
+        assert_eq!(alive.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn unknown_keys_are_ignored() {
+        let contexts = Arc::new(Contexts::<u32>::new());
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let mut callback = context_callback(contexts.clone(), move |_, _| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        deliver(&mut callback, 0);
+        deliver(&mut callback, 42);
+        let key = contexts.insert(7);
+        deliver(&mut callback, key);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn stale_keys_do_not_find_reused_slots() {
+        let contexts = Contexts::new();
+        let keys = (0..20)
+            .map(|context| contexts.insert(context))
+            .collect::<Vec<_>>();
+        assert!(contexts.remove(keys[3]).is_some());
+        assert!(contexts.remove(keys[3]).is_none());
+        let key = contexts.insert(42);
+        assert_ne!(key, keys[3]);
+        assert!(contexts.get(keys[3]).is_none());
+        assert_eq!(*contexts.get(key).unwrap().lock().unwrap(), 42);
+        assert_eq!(*contexts.get(keys[19]).unwrap().lock().unwrap(), 19);
+        assert!(contexts.get(usize::MAX).is_none());
+    }
+
+    #[test]
+    fn concurrent_delivery_and_disconnection() {
+        const KEYS: usize = 8;
+        const ROUNDS: usize = 2_000;
+        const THREADS: usize = 4;
+
+        let alive = Arc::new(AtomicUsize::new(0));
+        let contexts = Arc::new(Contexts::new());
+        let live_keys = Arc::new(RwLock::new(Vec::new()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let delivered = Arc::new(AtomicUsize::new(0));
+
+        // Every thread calls its own receive handler, as CoreMIDI would from several threads,
+        // all of them looking up the same contexts.
+        let guard = panic_guard();
+        let deliveries = (0..THREADS)
+            .map(|_| {
+                let counter = delivered.clone();
+                let callback =
+                    context_callback(contexts.clone(), move |_, context: &mut Tracked| {
+                        context.touch();
+                        thread::yield_now();
+                        context.touch();
+                        counter.fetch_add(1, Ordering::SeqCst);
+                    });
+                let mut handler = Client::receive_handler(callback, guard.clone());
+                let live_keys = live_keys.clone();
+                let stop = stop.clone();
+                thread::spawn(move || {
+                    let events = events();
+                    let evtlist = unsafe { events.as_ptr() };
+                    while !stop.load(Ordering::Relaxed) {
+                        let keys = live_keys.read().unwrap().clone();
+                        for key in keys {
+                            handler(evtlist, key as *mut c_void);
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        // Keys are removed before being taken out of the snapshot, so deliveries race with removals.
+        let mut keys = Vec::new();
+        for round in 0..ROUNDS {
+            keys.push(contexts.insert(Tracked::new(&alive)));
+            if keys.len() > KEYS || round % 3 == 0 {
+                let key = keys.remove(round % keys.len());
+                contexts.remove(key);
+            }
+            *live_keys.write().unwrap() = keys.clone();
+            thread::yield_now();
+        }
+        stop.store(true, Ordering::Relaxed);
+        for delivery in deliveries {
+            delivery.join().unwrap();
+        }
+
+        assert_eq!(guard.count(), 0);
+        assert!(delivered.load(Ordering::SeqCst) > 0);
+        assert_eq!(alive.load(Ordering::SeqCst), keys.len());
+        drop(contexts);
+        assert_eq!(alive.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn connect_and_disconnect_while_receiving() {
+        const ROUNDS: usize = 500;
+
+        let client = Client::new("Test Client").unwrap();
+        let virtual_source = client.virtual_source("Test Source").unwrap();
+        let source = Source::new(virtual_source.endpoint.object.0);
+        let alive = Arc::new(AtomicUsize::new(0));
+        let mut input_port = client
+            .input_port_with_protocol("Test Port", Protocol::Midi10, |_, context: &mut Tracked| {
+                context.touch()
+            })
+            .unwrap();
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let sender_stop = stop.clone();
+        let sender = thread::spawn(move || {
+            let events = events();
+            while !sender_stop.load(Ordering::Relaxed) {
+                virtual_source.received(&events).unwrap();
+                thread::yield_now();
+            }
+            virtual_source
+        });
+
+        for round in 0..ROUNDS {
+            input_port
+                .connect_source(&source, Tracked::new(&alive))
+                .unwrap();
+            if round % 2 == 0 {
+                input_port.disconnect_source(&source).unwrap();
+            }
+        }
+        stop.store(true, Ordering::Relaxed);
+        let virtual_source = sender.join().unwrap();
+
+        assert_eq!(input_port.panic_count(), 0);
+        assert!(alive.load(Ordering::SeqCst) <= 1);
+        drop(input_port);
+        drop(virtual_source);
+        drop(client);
+
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while alive.load(Ordering::SeqCst) > 0 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(alive.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn queue_receives_tagged_messages() {
+        let client = Client::new("Test Client").unwrap();
+        let virtual_source = client.virtual_source("Test Source").unwrap();
+        let source = Source::new(virtual_source.endpoint.object.0);
+        let (input_port, mut receiver) = client
+            .input_port_with_queue("Test Port", Protocol::Midi10, 16)
+            .unwrap();
+        input_port.connect_source(&source, 0xcafe).unwrap();
+        virtual_source.received(&events()).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while receiver.is_empty() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        let message = receiver.try_recv().unwrap();
+        assert_eq!(message.source(), 0xcafe);
+        assert_eq!(message.words(), &[0x20f80000]);
+    }
+}
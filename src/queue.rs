@@ -0,0 +1,342 @@
+use std::cell::UnsafeCell;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::events::{ump_word_count, EventList, Timestamp};
+
+/// A single Universal MIDI Packet received from a source, as delivered by a [UmpReceiver].
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UmpMessage {
+    source: u32,
+    timestamp: Timestamp,
+    words: [u32; 4],
+    len: u8,
+}
+
+impl UmpMessage {
+    /// Create a message from up to 4 words, ignoring any extra ones.
+    ///
+    pub fn new(source: u32, timestamp: Timestamp, words: &[u32]) -> Self {
+        let len = words.len().min(4);
+        let mut message = Self {
+            source,
+            timestamp,
+            words: [0; 4],
+            len: len as u8,
+        };
+        message.words[..len].copy_from_slice(&words[..len]);
+        message
+    }
+
+    /// Get the tag of the source the message was received from,
+    /// which is the one given to [crate::TaggedInputPort::connect_source].
+    ///
+    pub fn source(&self) -> u32 {
+        self.source
+    }
+
+    /// Get the timestamp of the packet the message was received in.
+    ///
+    pub fn timestamp(&self) -> Timestamp {
+        self.timestamp
+    }
+
+    /// Get the words of the message.
+    ///
+    pub fn words(&self) -> &[u32] {
+        &self.words[..self.len as usize]
+    }
+}
+
+impl Default for UmpMessage {
+    fn default() -> Self {
+        Self::new(0, Timestamp::Now, &[])
+    }
+}
+
+/// A fixed-capacity single-producer single-consumer ring of messages.
+struct Ring {
+    slots: Box<[UnsafeCell<UmpMessage>]>,
+    mask: usize,
+    /// The index of the next slot to read, only written by the consumer.
+    head: AtomicUsize,
+    /// The index of the next slot to write, only written by the producer.
+    tail: AtomicUsize,
+    dropped: AtomicU64,
+    overflows: AtomicU64,
+}
+
+// The slots are only written by the single producer before publishing them through `tail`,
+// and only read by the single consumer before releasing them through `head`.
+unsafe impl Sync for Ring {}
+unsafe impl Send for Ring {}
+
+/// Create a lock-free queue of [UmpMessage]s with room for at least `capacity` messages
+/// (rounded up to a power of two), returning its two ends.
+///
+/// Neither end allocates nor locks once created, so the [UmpSender] can be used from
+/// the CoreMIDI thread. See [crate::Client::input_port_with_queue] for the common case.
+///
+/// ```
+/// use coremidi::{ump_queue, EventBuffer, Protocol, Timestamp};
+///
+/// let (mut sender, mut receiver) = ump_queue(2);
+/// let events = EventBuffer::new(Protocol::Midi10)
+///     .with_packet(Timestamp::Now, &[0x2090407f, 0x2080407f, 0x20b00100]);
+/// assert_eq!(sender.push_event_list(7, &events), 2);
+/// assert_eq!(receiver.overflow_count(), 1);
+/// assert_eq!(receiver.dropped_count(), 1);
+///
+/// let message = receiver.try_recv().unwrap();
+/// assert_eq!((message.source(), message.words()), (7, &[0x2090407f][..]));
+/// assert_eq!(receiver.try_iter().count(), 1);
+/// ```
+pub fn ump_queue(capacity: usize) -> (UmpSender, UmpReceiver) {
+    let capacity = capacity.max(1).next_power_of_two();
+    let ring = Arc::new(Ring {
+        slots: (0..capacity)
+            .map(|_| UnsafeCell::new(UmpMessage::default()))
+            .collect(),
+        mask: capacity - 1,
+        head: AtomicUsize::new(0),
+        tail: AtomicUsize::new(0),
+        dropped: AtomicU64::new(0),
+        overflows: AtomicU64::new(0),
+    });
+    (UmpSender { ring: ring.clone() }, UmpReceiver { ring })
+}
+
+/// The producing end of a [ump_queue].
+///
+pub struct UmpSender {
+    ring: Arc<Ring>,
+}
+
+impl UmpSender {
+    /// Add a message to the queue, or drop it and return `false` if the queue is full.
+    ///
+    pub fn push(&mut self, message: UmpMessage) -> bool {
+        let pushed = self.try_push(message);
+        if !pushed {
+            self.ring.overflows.fetch_add(1, Ordering::Relaxed);
+        }
+        pushed
+    }
+
+    /// Add the Universal MIDI Packets of an [EventList] received from the source tagged with `source`,
+    /// dropping the ones that don't fit. It returns how many were added.
+    ///
+    pub fn push_event_list(&mut self, source: u32, event_list: &EventList) -> usize {
+        let mut pushed = 0;
+        let mut overflowed = false;
+        for packet in event_list.iter() {
+            let mut words = packet.data();
+            while !words.is_empty() {
+                let len = ump_word_count(words[0]).min(words.len());
+                let message = UmpMessage::new(source, packet.timestamp(), &words[..len]);
+                if self.try_push(message) {
+                    pushed += 1;
+                } else {
+                    overflowed = true;
+                }
+                words = &words[len..];
+            }
+        }
+        if overflowed {
+            self.ring.overflows.fetch_add(1, Ordering::Relaxed);
+        }
+        pushed
+    }
+
+    fn try_push(&mut self, message: UmpMessage) -> bool {
+        let ring = &*self.ring;
+        let tail = ring.tail.load(Ordering::Relaxed);
+        let head = ring.head.load(Ordering::Acquire);
+        if tail.wrapping_sub(head) > ring.mask {
+            ring.dropped.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        unsafe { *ring.slots[tail & ring.mask].get() = message };
+        ring.tail.store(tail.wrapping_add(1), Ordering::Release);
+        true
+    }
+}
+
+impl std::fmt::Debug for UmpSender {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "UmpSender(capacity={})", self.ring.mask + 1)
+    }
+}
+
+/// The consuming end of a [ump_queue].
+///
+pub struct UmpReceiver {
+    ring: Arc<Ring>,
+}
+
+impl UmpReceiver {
+    /// Take the oldest message in the queue, if any, without blocking.
+    ///
+    pub fn try_recv(&mut self) -> Option<UmpMessage> {
+        let ring = &*self.ring;
+        let head = ring.head.load(Ordering::Relaxed);
+        let tail = ring.tail.load(Ordering::Acquire);
+        if head == tail {
+            return None;
+        }
+        let message = unsafe { *ring.slots[head & ring.mask].get() };
+        ring.head.store(head.wrapping_add(1), Ordering::Release);
+        Some(message)
+    }
+
+    /// Get an iterator taking the messages currently in the queue.
+    ///
+    pub fn try_iter(&mut self) -> UmpTryIter {
+        UmpTryIter { receiver: self }
+    }
+
+    /// Get the number of messages waiting in the queue.
+    ///
+    pub fn len(&self) -> usize {
+        let tail = self.ring.tail.load(Ordering::Acquire);
+        tail.wrapping_sub(self.ring.head.load(Ordering::Relaxed))
+    }
+
+    /// Check whether there are no messages waiting in the queue.
+    ///
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the maximum number of messages the queue can hold.
+    ///
+    pub fn capacity(&self) -> usize {
+        self.ring.mask + 1
+    }
+
+    /// Get the number of messages dropped because the queue was full.
+    ///
+    pub fn dropped_count(&self) -> u64 {
+        self.ring.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Get the number of times the queue overflowed, counting once
+    /// for every [EventList] that could not be added completely.
+    ///
+    pub fn overflow_count(&self) -> u64 {
+        self.ring.overflows.load(Ordering::Relaxed)
+    }
+}
+
+impl std::fmt::Debug for UmpReceiver {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "UmpReceiver(len={}, capacity={})",
+            self.len(),
+            self.capacity()
+        )
+    }
+}
+
+/// An iterator taking the messages currently in a [UmpReceiver].
+///
+pub struct UmpTryIter<'a> {
+    receiver: &'a mut UmpReceiver,
+}
+
+impl<'a> Iterator for UmpTryIter<'a> {
+    type Item = UmpMessage;
+
+    fn next(&mut self) -> Option<UmpMessage> {
+        self.receiver.try_recv()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::EventBuffer;
+    use crate::protocol::Protocol;
+    use std::thread;
+
+    #[test]
+    fn capacity_is_a_power_of_two() {
+        assert_eq!(ump_queue(0).1.capacity(), 1);
+        assert_eq!(ump_queue(100).1.capacity(), 128);
+        assert_eq!(ump_queue(128).1.capacity(), 128);
+    }
+
+    #[test]
+    fn splits_event_lists_into_messages() {
+        let (mut sender, mut receiver) = ump_queue(16);
+        let mut events = EventBuffer::new(Protocol::Midi20);
+        events.push(
+            Timestamp::from_host_time(10),
+            &[0x40903c00, 0xffff0000, 0x10f80000],
+        );
+        events.push(Timestamp::from_host_time(20), &[0x30047e7f, 0x06010000]);
+        assert_eq!(sender.push_event_list(3, &events), 3);
+
+        let messages = receiver.try_iter().collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec![
+                UmpMessage::new(3, Timestamp::from_host_time(10), &[0x40903c00, 0xffff0000]),
+                UmpMessage::new(3, Timestamp::from_host_time(10), &[0x10f80000]),
+                UmpMessage::new(3, Timestamp::from_host_time(20), &[0x30047e7f, 0x06010000]),
+            ]
+        );
+        assert!(receiver.is_empty());
+    }
+
+    #[test]
+    fn counts_overflows() {
+        let (mut sender, mut receiver) = ump_queue(2);
+        assert!(sender.push(UmpMessage::new(0, Timestamp::Now, &[1])));
+        assert!(sender.push(UmpMessage::new(0, Timestamp::Now, &[2])));
+        assert!(!sender.push(UmpMessage::new(0, Timestamp::Now, &[3])));
+        assert_eq!(receiver.len(), 2);
+        assert_eq!(receiver.dropped_count(), 1);
+        assert_eq!(receiver.overflow_count(), 1);
+
+        assert_eq!(receiver.try_recv().map(|m| m.words()[0]), Some(1));
+        assert!(sender.push(UmpMessage::new(0, Timestamp::Now, &[4])));
+        assert_eq!(
+            receiver
+                .try_iter()
+                .map(|m| m.words()[0])
+                .collect::<Vec<_>>(),
+            vec![2, 4]
+        );
+    }
+
+    #[test]
+    fn concurrent_producer_and_consumer() {
+        const COUNT: u32 = 100_000;
+        let (mut sender, mut receiver) = ump_queue(64);
+        let producer = thread::spawn(move || {
+            let mut next = 0;
+            while next < COUNT {
+                if sender.try_push(UmpMessage::new(1, Timestamp::Now, &[next])) {
+                    next += 1;
+                } else {
+                    thread::yield_now();
+                }
+            }
+        });
+        let mut expected = 0;
+        while expected < COUNT {
+            match receiver.try_recv() {
+                Some(message) => {
+                    assert_eq!(message.words(), &[expected]);
+                    expected += 1;
+                }
+                None => thread::yield_now(),
+            }
+        }
+        producer.join().unwrap();
+        assert!(receiver.is_empty());
+    }
+}
@@ -0,0 +1,421 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use coremidi_sys::OSStatus;
+
+use crate::capture::{Capture, CaptureData, CaptureSource};
+use crate::client::Client;
+use crate::endpoints::sources::VirtualSource;
+use crate::events::{EventBuffer, Timestamp};
+use crate::host_time::{nanos_to_ticks, HostClock, SystemClock};
+use crate::packets::PacketBuffer;
+
+const MAX_WAIT: Duration = Duration::from_millis(10);
+
+/// Replays a [Capture] with the relative timing of its packets.
+///
+/// The packets are given new host timestamps from the time the replay starts, scaled by
+/// the replay speed, and can be received from one [VirtualSource] per captured source,
+/// so an application sees the same input stream as when it was captured:
+///
+/// ```rust,no_run
+/// use std::sync::atomic::AtomicBool;
+/// use coremidi::{Capture, CaptureReplay, Client, SystemClock};
+/// let client = Client::new("example-client").unwrap();
+/// let file = std::fs::File::open("session.midicapt").unwrap();
+/// let mut replay = CaptureReplay::new(Capture::read_from(file).unwrap());
+/// replay.set_speed(2.0);
+/// let sources = replay.virtual_sources(&client).unwrap();
+/// replay.run(&SystemClock, &sources, &AtomicBool::new(false)).unwrap();
+/// ```
+///
+/// It can also be driven by hand, taking the packets due before a host timestamp:
+///
+/// ```
+/// use coremidi::{Capture, CaptureReplay, CaptureSource, PacketBuffer, Timestamp};
+/// let mut capture = Capture::new();
+/// let mut packets = PacketBuffer::new(Timestamp::from_host_time(5000), &[0x90, 60, 100]);
+/// packets.push_data(Timestamp::from_host_time(7000), &[0x80, 60, 0]);
+/// capture.record_packet_list(&CaptureSource::new(1, "Keyboard"), &packets);
+///
+/// let mut replay = CaptureReplay::new(capture);
+/// replay.start(Timestamp::from_host_time(100));
+/// let mut timestamps = Vec::new();
+/// replay.replay_until(Timestamp::from_host_time(10_000), |_, timestamp, _| timestamps.push(timestamp));
+/// assert_eq!(timestamps.len(), 2);
+/// assert_eq!(timestamps[0], Timestamp::from_host_time(100));
+/// assert!(replay.is_finished());
+/// ```
+#[derive(Debug, Clone)]
+pub struct CaptureReplay {
+    capture: Capture,
+    times: Vec<u64>,
+    filter: Option<Vec<u32>>,
+    records: Vec<(u64, usize)>,
+    span: u64,
+    speed: f64,
+    looping: bool,
+    anchor: Timestamp,
+    position: u64,
+    pass: u64,
+    next: usize,
+    playing: bool,
+}
+
+impl CaptureReplay {
+    /// Create a replay of all the sources of a capture, at its original speed.
+    ///
+    pub fn new(capture: Capture) -> Self {
+        // Packets to be sent immediately keep the time of the previous packet,
+        // and packets arrived out of order are replayed in order of arrival.
+        let mut previous = 0;
+        let mut times: Vec<u64> = capture
+            .records()
+            .iter()
+            .map(|record| {
+                if !record.timestamp.is_now() {
+                    previous = previous.max(capture.nanos(record.timestamp));
+                }
+                previous
+            })
+            .collect();
+        let origin = times.iter().copied().find(|nanos| *nanos > 0).unwrap_or(0);
+        for nanos in times.iter_mut() {
+            *nanos = nanos.saturating_sub(origin);
+        }
+        let mut replay = Self {
+            capture,
+            times,
+            filter: None,
+            records: Vec::new(),
+            span: 0,
+            speed: 1.0,
+            looping: false,
+            anchor: Timestamp::Now,
+            position: 0,
+            pass: 0,
+            next: 0,
+            playing: false,
+        };
+        replay.select_records();
+        replay
+    }
+
+    /// Get the captured sources being replayed.
+    ///
+    pub fn sources(&self) -> Vec<&CaptureSource> {
+        self.capture
+            .sources()
+            .iter()
+            .filter(|source| self.is_selected(source.unique_id))
+            .collect()
+    }
+
+    /// Replay only the sources with the given unique IDs, or all of them with `None`.
+    ///
+    pub fn set_source_filter(&mut self, unique_ids: Option<&[u32]>) {
+        self.filter = unique_ids.map(|unique_ids| unique_ids.to_vec());
+        let elapsed = self.records.get(self.next).map(|(time, _)| *time);
+        self.select_records();
+        self.next = match elapsed {
+            Some(elapsed) => self.records.partition_point(|(time, _)| *time < elapsed),
+            None => self.records.len(),
+        };
+    }
+
+    /// Get the replay speed, where 1.0 is the original speed.
+    ///
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    /// Scale the replay speed from the next packet, where 2.0 replays twice as fast.
+    /// Speeds that are not positive are ignored.
+    ///
+    pub fn set_speed(&mut self, speed: f64) {
+        if !(speed > 0.0 && speed.is_finite()) {
+            return;
+        }
+        if self.playing {
+            if let Some(next) = self.next_elapsed() {
+                self.anchor = self.timestamp_at(next);
+                self.position = next;
+            }
+        }
+        self.speed = speed;
+    }
+
+    /// Set whether the capture starts over after its last packet, which is also replayed
+    /// as the first packet of the next pass. Captures lasting no time are replayed once.
+    ///
+    pub fn set_looping(&mut self, looping: bool) {
+        self.looping = looping;
+    }
+
+    /// Start replaying from the first packet of the replayed sources, at a host timestamp,
+    /// where [Timestamp::Now] is taken as the current host time.
+    ///
+    pub fn start(&mut self, timestamp: Timestamp) {
+        self.anchor = SystemClock.resolve(timestamp);
+        self.position = self.first_elapsed();
+        self.pass = 0;
+        self.next = 0;
+        self.playing = true;
+    }
+
+    /// Check whether every packet was replayed, which never happens while looping.
+    ///
+    pub fn is_finished(&self) -> bool {
+        self.next_elapsed().is_none()
+    }
+
+    /// Get the host timestamp of the next packet to replay.
+    ///
+    pub fn next_timestamp(&self) -> Option<Timestamp> {
+        if !self.playing {
+            return None;
+        }
+        self.next_elapsed().map(|next| self.timestamp_at(next))
+    }
+
+    /// Call `f` with the unique ID of the source, the new host timestamp and the data
+    /// of each packet due before `until`, and return the number of packets replayed.
+    ///
+    pub fn replay_until<F>(&mut self, until: Timestamp, mut f: F) -> usize
+    where
+        F: FnMut(u32, Timestamp, &CaptureData),
+    {
+        let mut count = 0;
+        while let Some(timestamp) = self.next_timestamp() {
+            if timestamp >= until {
+                break;
+            }
+            let record = &self.capture.records()[self.records[self.next].1];
+            f(record.source, timestamp, &record.data);
+            count += 1;
+            self.next += 1;
+            if self.next == self.records.len() && self.looping && self.span > 0 {
+                self.next = 0;
+                self.pass += 1;
+            }
+        }
+        count
+    }
+
+    /// Create a virtual source for each captured source being replayed, named after it,
+    /// and return them by the unique ID of the captured source.
+    ///
+    pub fn virtual_sources(
+        &self,
+        client: &Client,
+    ) -> Result<HashMap<u32, VirtualSource>, OSStatus> {
+        let mut virtual_sources = HashMap::new();
+        for source in self.sources() {
+            let name = if source.name.is_empty() {
+                format!("Source {}", source.unique_id)
+            } else {
+                source.name.clone()
+            };
+            virtual_sources.insert(source.unique_id, client.virtual_source(&name)?);
+        }
+        Ok(virtual_sources)
+    }
+
+    /// Replay the capture from now through virtual sources, waiting for each packet to be due,
+    /// until it finishes or `stop` is set. Packets from sources without a virtual source
+    /// are skipped.
+    ///
+    pub fn run<C: HostClock>(
+        &mut self,
+        clock: &C,
+        virtual_sources: &HashMap<u32, VirtualSource>,
+        stop: &AtomicBool,
+    ) -> Result<(), OSStatus> {
+        self.start(clock.now());
+        while !stop.load(Ordering::Relaxed) {
+            let next = match self.next_timestamp() {
+                Some(next) => next,
+                None => return Ok(()),
+            };
+            let now = clock.now();
+            if next > now {
+                thread::sleep(clock.duration_between(now, next).min(MAX_WAIT));
+                continue;
+            }
+            let mut result = Ok(());
+            let until = Timestamp::from_host_time(now.host_time() + 1);
+            self.replay_until(until, |source, timestamp, data| {
+                let virtual_source = match virtual_sources.get(&source) {
+                    Some(virtual_source) if result.is_ok() => virtual_source,
+                    _ => return,
+                };
+                result = match data {
+                    CaptureData::Packet(data) => {
+                        virtual_source.received(&PacketBuffer::new(timestamp, data))
+                    }
+                    CaptureData::Event { protocol, words } => virtual_source
+                        .received(&EventBuffer::new(*protocol).with_packet(timestamp, words)),
+                };
+            });
+            result?;
+        }
+        Ok(())
+    }
+
+    fn is_selected(&self, unique_id: u32) -> bool {
+        match &self.filter {
+            Some(unique_ids) => unique_ids.contains(&unique_id),
+            None => true,
+        }
+    }
+
+    fn select_records(&mut self) {
+        let records: Vec<_> = self
+            .capture
+            .records()
+            .iter()
+            .zip(&self.times)
+            .enumerate()
+            .filter(|(_, (record, _))| self.is_selected(record.source))
+            .map(|(index, (_, nanos))| (*nanos, index))
+            .collect();
+        self.span = match (records.first(), records.last()) {
+            (Some((first, _)), Some((last, _))) => last - first,
+            _ => 0,
+        };
+        self.records = records;
+    }
+
+    fn first_elapsed(&self) -> u64 {
+        self.records.first().map(|(nanos, _)| *nanos).unwrap_or(0)
+    }
+
+    fn next_elapsed(&self) -> Option<u64> {
+        self.records
+            .get(self.next)
+            .map(|(nanos, _)| self.pass * self.span + nanos)
+    }
+
+    fn timestamp_at(&self, elapsed: u64) -> Timestamp {
+        let nanos = elapsed.saturating_sub(self.position) as f64 / self.speed;
+        Timestamp::from_host_time(self.anchor.host_time() + nanos_to_ticks(nanos.round() as u64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::host_time::ManualClock;
+
+    fn at(millis: u64) -> Timestamp {
+        Timestamp::from_host_time(1 + nanos_to_ticks(millis * 1_000_000))
+    }
+
+    fn capture() -> Capture {
+        let mut capture = Capture::new();
+        let keyboard = CaptureSource::new(1, "Keyboard");
+        let pads = CaptureSource::new(2, "Pads");
+        let mut packets = PacketBuffer::new(at(1000), &[0x90, 60, 100]);
+        packets.push_data(at(1500), &[0x80, 60, 0]);
+        capture.record_packet_list(&keyboard, &packets);
+        let events = EventBuffer::new(crate::Protocol::Midi20)
+            .with_packet(at(1200), &[0x40903c00, 0xffff0000])
+            .with_packet(Timestamp::Now, &[0x40803c00, 0x00000000]);
+        capture.record_event_list(&pads, &events);
+        capture.record_packet_list(&keyboard, &PacketBuffer::new(at(2000), &[0xFC]));
+        capture
+    }
+
+    fn replayed(replay: &mut CaptureReplay, until: Timestamp) -> Vec<(u32, Timestamp)> {
+        let mut replayed = Vec::new();
+        let count = replay.replay_until(until, |source, timestamp, _| {
+            replayed.push((source, timestamp));
+        });
+        assert_eq!(count, replayed.len());
+        replayed
+    }
+
+    #[test]
+    fn relative_timing() {
+        let mut replay = CaptureReplay::new(capture());
+        assert_eq!(replay.next_timestamp(), None);
+        replay.start(at(10_000));
+        assert_eq!(replay.next_timestamp(), Some(at(10_000)));
+        assert_eq!(replayed(&mut replay, at(10_400)), vec![(1, at(10_000))]);
+        assert_eq!(
+            replayed(&mut replay, at(20_000)),
+            vec![
+                (1, at(10_500)),
+                (2, at(10_500)),
+                (2, at(10_500)),
+                (1, at(11_000))
+            ]
+        );
+        assert!(replay.is_finished());
+    }
+
+    #[test]
+    fn start_now() {
+        let before = SystemClock.now();
+        let mut replay = CaptureReplay::new(capture());
+        replay.start(Timestamp::Now);
+        let sent = replayed(&mut replay, SystemClock.after(Duration::from_secs(2)));
+        assert_eq!(sent.len(), 5);
+        assert!(sent.iter().all(|(_, timestamp)| *timestamp >= before));
+        assert!(sent[4].1 >= before.checked_add(Duration::from_secs(1)).unwrap());
+    }
+
+    #[test]
+    fn speed_and_filter() {
+        let mut replay = CaptureReplay::new(capture());
+        replay.set_source_filter(Some(&[2]));
+        assert_eq!(replay.sources(), vec![&CaptureSource::new(2, "Pads")]);
+        replay.set_speed(2.0);
+        replay.start(at(0));
+        assert_eq!(
+            replayed(&mut replay, at(20_000)),
+            vec![(2, at(0)), (2, at(0))]
+        );
+
+        let mut replay = CaptureReplay::new(capture());
+        replay.start(at(0));
+        replayed(&mut replay, at(1));
+        replay.set_speed(0.5);
+        assert_eq!(
+            replayed(&mut replay, at(20_000)),
+            vec![(1, at(500)), (2, at(500)), (2, at(500)), (1, at(1500))]
+        );
+    }
+
+    #[test]
+    fn looping() {
+        let mut replay = CaptureReplay::new(capture());
+        replay.set_looping(true);
+        replay.start(at(0));
+        let sent = replayed(&mut replay, at(2500));
+        assert_eq!(sent.len(), 11);
+        assert_eq!(sent[4], (1, at(1000)));
+        assert_eq!(sent[5], (1, at(1000)));
+        assert_eq!(sent[10], (1, at(2000)));
+        assert!(!replay.is_finished());
+    }
+
+    #[test]
+    fn run_until_finished() {
+        let mut replay = CaptureReplay::new(capture());
+        replay.set_speed(100.0);
+        replay
+            .run(&SystemClock, &HashMap::new(), &AtomicBool::new(false))
+            .unwrap();
+        assert!(replay.is_finished());
+
+        let clock = ManualClock::new(at(0));
+        replay.set_looping(true);
+        replay
+            .run(&clock, &HashMap::new(), &AtomicBool::new(true))
+            .unwrap();
+        assert_eq!(replay.next_timestamp(), Some(at(0)));
+    }
+}
@@ -0,0 +1,1231 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::sysex::{SysExLink, SysExLinkError, SYSEX_END, SYSEX_START};
+
+const NON_REAL_TIME: u8 = 0x7E;
+const ALL_CALL: u8 = 0x7F;
+
+const DUMP_HEADER: u8 = 0x01;
+const DATA_PACKET: u8 = 0x02;
+const DUMP_REQUEST: u8 = 0x03;
+const CANCEL: u8 = 0x7D;
+const WAIT: u8 = 0x7C;
+const NAK: u8 = 0x7E;
+const ACK: u8 = 0x7F;
+
+/// The number of data bytes carried by every Sample Dump data packet.
+pub const SAMPLE_DUMP_PACKET_DATA_SIZE: usize = 120;
+
+/// How a sample loops, as declared in the [SampleDumpHeader].
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoopType {
+    Forward,
+    Alternating,
+    Off,
+}
+
+impl LoopType {
+    fn to_byte(self) -> u8 {
+        match self {
+            Self::Forward => 0x00,
+            Self::Alternating => 0x01,
+            Self::Off => 0x7F,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Result<Self, SampleDumpError> {
+        match byte {
+            0x00 => Ok(Self::Forward),
+            0x01 => Ok(Self::Alternating),
+            0x7F => Ok(Self::Off),
+            _ => Err(SampleDumpError::InvalidMessage),
+        }
+    }
+}
+
+/// The description of a sample, sent before its data.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SampleDumpHeader {
+    /// The device ID (or channel) of the dump.
+    pub device_id: u8,
+    /// The 14-bit sample number.
+    pub sample_number: u16,
+    /// The significant bits per sample word, from 8 to 28.
+    pub bits_per_sample: u8,
+    /// The sample period in nanoseconds (21-bit).
+    pub sample_period_ns: u32,
+    /// The length of the sample in words (21-bit).
+    pub length: u32,
+    /// The first word of the loop (21-bit).
+    pub loop_start: u32,
+    /// The last word of the loop (21-bit).
+    pub loop_end: u32,
+    pub loop_type: LoopType,
+}
+
+impl SampleDumpHeader {
+    /// The number of 7-bit bytes used to transmit every sample word.
+    ///
+    pub fn bytes_per_word(&self) -> usize {
+        (self.bits_per_sample as usize + 6) / 7
+    }
+
+    /// The total number of data bytes needed to transmit the sample.
+    ///
+    pub fn data_len(&self) -> usize {
+        self.length as usize * self.bytes_per_word()
+    }
+
+    /// The number of data packets needed to transmit the sample.
+    ///
+    pub fn packet_count(&self) -> usize {
+        (self.data_len() + SAMPLE_DUMP_PACKET_DATA_SIZE - 1) / SAMPLE_DUMP_PACKET_DATA_SIZE
+    }
+
+    fn validate(&self) -> Result<(), SampleDumpError> {
+        if !(8..=28).contains(&self.bits_per_sample)
+            || self.device_id > 0x7F
+            || self.sample_number > 0x3FFF
+            || self.sample_period_ns > 0x1F_FFFF
+            || self.length > 0x1F_FFFF
+            || self.loop_start > 0x1F_FFFF
+            || self.loop_end > 0x1F_FFFF
+        {
+            Err(SampleDumpError::InvalidHeader)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// A message of the MIDI Sample Dump Standard.
+///
+/// ```
+/// use coremidi::SampleDumpMessage;
+/// let ack = SampleDumpMessage::Ack { device_id: 1, packet_number: 5 };
+/// assert_eq!(ack.encode(), vec![0xf0, 0x7e, 0x01, 0x7f, 0x05, 0xf7]);
+/// assert_eq!(SampleDumpMessage::decode(&ack.encode()), Ok(ack));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SampleDumpMessage {
+    Header(SampleDumpHeader),
+    Request {
+        device_id: u8,
+        sample_number: u16,
+    },
+    DataPacket {
+        device_id: u8,
+        packet_number: u8,
+        data: Vec<u8>,
+        checksum: u8,
+    },
+    Ack {
+        device_id: u8,
+        packet_number: u8,
+    },
+    Nak {
+        device_id: u8,
+        packet_number: u8,
+    },
+    Wait {
+        device_id: u8,
+        packet_number: u8,
+    },
+    Cancel {
+        device_id: u8,
+        packet_number: u8,
+    },
+}
+
+impl SampleDumpMessage {
+    /// Create a data packet, padding the data with zeros up to 120 bytes and computing its checksum.
+    ///
+    pub fn data_packet(device_id: u8, packet_number: u8, data: &[u8]) -> Self {
+        let mut data = data.to_vec();
+        data.resize(SAMPLE_DUMP_PACKET_DATA_SIZE, 0);
+        let checksum = checksum(device_id, packet_number & 0x7F, &data);
+        Self::DataPacket {
+            device_id,
+            packet_number: packet_number & 0x7F,
+            data,
+            checksum,
+        }
+    }
+
+    /// Check whether the checksum of a data packet matches its contents.
+    /// It is always true for any other message.
+    ///
+    pub fn is_checksum_valid(&self) -> bool {
+        match self {
+            Self::DataPacket {
+                device_id,
+                packet_number,
+                data,
+                checksum: expected,
+            } => checksum(*device_id, *packet_number, data) == *expected,
+            _ => true,
+        }
+    }
+
+    /// Get the device ID addressed by the message.
+    ///
+    pub fn device_id(&self) -> u8 {
+        match self {
+            Self::Header(header) => header.device_id,
+            Self::Request { device_id, .. }
+            | Self::DataPacket { device_id, .. }
+            | Self::Ack { device_id, .. }
+            | Self::Nak { device_id, .. }
+            | Self::Wait { device_id, .. }
+            | Self::Cancel { device_id, .. } => *device_id,
+        }
+    }
+
+    /// Encode the message as SysEx bytes.
+    ///
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = vec![SYSEX_START, NON_REAL_TIME, self.device_id() & 0x7F];
+        match self {
+            Self::Header(header) => {
+                bytes.push(DUMP_HEADER);
+                push_u14(&mut bytes, header.sample_number);
+                bytes.push(header.bits_per_sample);
+                push_u21(&mut bytes, header.sample_period_ns);
+                push_u21(&mut bytes, header.length);
+                push_u21(&mut bytes, header.loop_start);
+                push_u21(&mut bytes, header.loop_end);
+                bytes.push(header.loop_type.to_byte());
+            }
+            Self::Request { sample_number, .. } => {
+                bytes.push(DUMP_REQUEST);
+                push_u14(&mut bytes, *sample_number);
+            }
+            Self::DataPacket {
+                packet_number,
+                data,
+                checksum,
+                ..
+            } => {
+                bytes.push(DATA_PACKET);
+                bytes.push(packet_number & 0x7F);
+                bytes.extend(data.iter().map(|b| b & 0x7F));
+                bytes.push(checksum & 0x7F);
+            }
+            Self::Ack { packet_number, .. } => bytes.extend([ACK, packet_number & 0x7F]),
+            Self::Nak { packet_number, .. } => bytes.extend([NAK, packet_number & 0x7F]),
+            Self::Wait { packet_number, .. } => bytes.extend([WAIT, packet_number & 0x7F]),
+            Self::Cancel { packet_number, .. } => bytes.extend([CANCEL, packet_number & 0x7F]),
+        }
+        bytes.push(SYSEX_END);
+        bytes
+    }
+
+    /// Decode a complete SysEx message, including its `F0` and `F7` bytes.
+    ///
+    pub fn decode(bytes: &[u8]) -> Result<Self, SampleDumpError> {
+        let body = match bytes {
+            [SYSEX_START, NON_REAL_TIME, body @ .., SYSEX_END] if body.len() >= 2 => body,
+            _ => return Err(SampleDumpError::InvalidMessage),
+        };
+        if body.iter().any(|b| *b > 0x7F) {
+            return Err(SampleDumpError::InvalidMessage);
+        }
+        let device_id = body[0];
+        match (body[1], &body[2..]) {
+            (
+                DUMP_HEADER,
+                [s0, s1, bits, p0, p1, p2, l0, l1, l2, ls0, ls1, ls2, le0, le1, le2, loop_type],
+            ) => {
+                let header = SampleDumpHeader {
+                    device_id,
+                    sample_number: u14(*s0, *s1),
+                    bits_per_sample: *bits,
+                    sample_period_ns: u21(*p0, *p1, *p2),
+                    length: u21(*l0, *l1, *l2),
+                    loop_start: u21(*ls0, *ls1, *ls2),
+                    loop_end: u21(*le0, *le1, *le2),
+                    loop_type: LoopType::from_byte(*loop_type)?,
+                };
+                header.validate()?;
+                Ok(Self::Header(header))
+            }
+            (DUMP_REQUEST, [s0, s1]) => Ok(Self::Request {
+                device_id,
+                sample_number: u14(*s0, *s1),
+            }),
+            (DATA_PACKET, [packet_number, rest @ ..])
+                if rest.len() == SAMPLE_DUMP_PACKET_DATA_SIZE + 1 =>
+            {
+                Ok(Self::DataPacket {
+                    device_id,
+                    packet_number: *packet_number,
+                    data: rest[..SAMPLE_DUMP_PACKET_DATA_SIZE].to_vec(),
+                    checksum: rest[SAMPLE_DUMP_PACKET_DATA_SIZE],
+                })
+            }
+            (ACK, [packet_number]) => Ok(Self::Ack {
+                device_id,
+                packet_number: *packet_number,
+            }),
+            (NAK, [packet_number]) => Ok(Self::Nak {
+                device_id,
+                packet_number: *packet_number,
+            }),
+            (WAIT, [packet_number]) => Ok(Self::Wait {
+                device_id,
+                packet_number: *packet_number,
+            }),
+            (CANCEL, [packet_number]) => Ok(Self::Cancel {
+                device_id,
+                packet_number: *packet_number,
+            }),
+            _ => Err(SampleDumpError::InvalidMessage),
+        }
+    }
+}
+
+/// Encode signed samples into the 7-bit, left-justified, offset binary format used by the
+/// Sample Dump Standard, using `bits_per_sample` significant bits for every sample.
+///
+/// ```
+/// let data = coremidi::encode_samples(16, &[0, -32768, 32767]);
+/// assert_eq!(data, vec![0x40, 0x00, 0x00, 0x00, 0x00, 0x00, 0x7f, 0x7f, 0x60]);
+/// assert_eq!(coremidi::decode_samples(16, &data), vec![0, -32768, 32767]);
+/// ```
+///
+/// # Panics
+///
+/// Panics if `bits_per_sample` is not between 8 and 28.
+///
+pub fn encode_samples(bits_per_sample: u8, samples: &[i32]) -> Vec<u8> {
+    assert_bits_per_sample(bits_per_sample);
+    let bits = bits_per_sample as u32;
+    let bytes_per_word = (bits + 6) / 7;
+    let mut data = Vec::with_capacity(samples.len() * bytes_per_word as usize);
+    for sample in samples {
+        let offset = (*sample as i64 + (1i64 << (bits - 1))) as u64 & ((1u64 << bits) - 1);
+        let justified = offset << (bytes_per_word * 7 - bits);
+        for i in (0..bytes_per_word).rev() {
+            data.push(((justified >> (i * 7)) & 0x7F) as u8);
+        }
+    }
+    data
+}
+
+/// Decode data in the Sample Dump Standard format into signed samples.
+/// Any trailing bytes that don't complete a sample word are ignored.
+///
+/// # Panics
+///
+/// Panics if `bits_per_sample` is not between 8 and 28.
+///
+pub fn decode_samples(bits_per_sample: u8, data: &[u8]) -> Vec<i32> {
+    assert_bits_per_sample(bits_per_sample);
+    let bits = bits_per_sample as u32;
+    let bytes_per_word = (bits + 6) / 7;
+    data.chunks_exact(bytes_per_word as usize)
+        .map(|word| {
+            let justified = word
+                .iter()
+                .fold(0u64, |acc, byte| (acc << 7) | (*byte & 0x7F) as u64);
+            let offset = justified >> (bytes_per_word * 7 - bits);
+            (offset as i64 - (1i64 << (bits - 1))) as i32
+        })
+        .collect()
+}
+
+fn assert_bits_per_sample(bits_per_sample: u8) {
+    assert!(
+        (8..=28).contains(&bits_per_sample),
+        "invalid bits per sample: {}",
+        bits_per_sample
+    );
+}
+
+/// An error in a Sample Dump transfer.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SampleDumpError {
+    /// The message is not a well formed Sample Dump message.
+    InvalidMessage,
+    /// The header contains values out of range.
+    InvalidHeader,
+    /// The other side cancelled the transfer.
+    Cancelled,
+    /// No message arrived in time.
+    Timeout,
+    /// A packet was rejected too many times.
+    TooManyRetries { packet_number: u8 },
+    /// The transport failed.
+    Link(SysExLinkError),
+}
+
+impl fmt::Display for SampleDumpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidMessage => write!(f, "invalid sample dump message"),
+            Self::InvalidHeader => write!(f, "invalid sample dump header"),
+            Self::Cancelled => write!(f, "the sample dump was cancelled"),
+            Self::Timeout => write!(f, "timeout waiting for the sample dump"),
+            Self::TooManyRetries { packet_number } => {
+                write!(f, "too many retries for packet {}", packet_number)
+            }
+            Self::Link(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for SampleDumpError {}
+
+impl From<SysExLinkError> for SampleDumpError {
+    fn from(err: SysExLinkError) -> Self {
+        Self::Link(err)
+    }
+}
+
+/// The timing and retry policy of a Sample Dump transfer.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SampleDumpConfig {
+    /// How long the sender waits for a reply to the header before assuming an open loop.
+    pub header_timeout: Duration,
+    /// How long the sender waits for a reply to a packet before assuming an open loop.
+    pub packet_timeout: Duration,
+    /// How long the receiver waits for the next message before giving up.
+    pub receive_timeout: Duration,
+    /// How many times the same packet can be rejected before giving up.
+    pub max_retries: u32,
+}
+
+impl Default for SampleDumpConfig {
+    fn default() -> Self {
+        Self {
+            header_timeout: Duration::from_secs(2),
+            packet_timeout: Duration::from_millis(20),
+            receive_timeout: Duration::from_secs(2),
+            max_retries: 3,
+        }
+    }
+}
+
+/// The progress of a Sample Dump transfer.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SampleDumpState {
+    InProgress,
+    Completed,
+    Failed(SampleDumpError),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SenderState {
+    SendHeader,
+    AwaitHeaderReply { deadline: Instant },
+    SendPacket { index: usize },
+    AwaitPacketReply { index: usize, deadline: Instant },
+    Waiting { index: Option<usize> },
+    Finished(SampleDumpState),
+}
+
+/// The sending side of a Sample Dump transfer.
+///
+/// It is a state machine that doesn't perform any I/O by itself: received messages are fed
+/// with [SampleDumpSender::handle], and the messages to send are taken from [SampleDumpSender::poll].
+/// The [SampleDumpSender::run] method drives it through a [SysExLink] until it finishes.
+///
+/// ```
+/// use coremidi::{LoopType, QueueLink, SampleDumpHeader, SampleDumpReceiver, SampleDumpSender};
+/// use std::thread;
+///
+/// let header = SampleDumpHeader {
+///     device_id: 0, sample_number: 1, bits_per_sample: 16, sample_period_ns: 22675,
+///     length: 4, loop_start: 0, loop_end: 3, loop_type: LoopType::Off,
+/// };
+/// let (mut a, mut b) = QueueLink::pair();
+/// let receiver = thread::spawn(move || {
+///     let mut receiver = SampleDumpReceiver::new(0);
+///     receiver.run(&mut b).map(|_| receiver.samples())
+/// });
+/// let mut sender = SampleDumpSender::new(header, &[0, 100, -100, 0]).unwrap();
+/// sender.run(&mut a).unwrap();
+/// assert_eq!(receiver.join().unwrap(), Ok(vec![0, 100, -100, 0]));
+/// ```
+#[derive(Debug, Clone)]
+pub struct SampleDumpSender {
+    header: SampleDumpHeader,
+    data: Vec<u8>,
+    config: SampleDumpConfig,
+    state: SenderState,
+    retries: u32,
+    outgoing: VecDeque<Vec<u8>>,
+}
+
+impl SampleDumpSender {
+    /// Create a sender for the samples described by the header, using the default configuration.
+    /// The header length is taken from the number of samples.
+    ///
+    pub fn new(header: SampleDumpHeader, samples: &[i32]) -> Result<Self, SampleDumpError> {
+        Self::with_config(header, samples, SampleDumpConfig::default())
+    }
+
+    /// Create a sender with a custom timing and retry configuration.
+    /// The header length is taken from the number of samples, whatever it was.
+    ///
+    pub fn with_config(
+        mut header: SampleDumpHeader,
+        samples: &[i32],
+        config: SampleDumpConfig,
+    ) -> Result<Self, SampleDumpError> {
+        header.length = u32::try_from(samples.len()).unwrap_or(u32::MAX);
+        header.validate()?;
+        let data = encode_samples(header.bits_per_sample, samples);
+        Ok(Self {
+            header,
+            data,
+            config,
+            state: SenderState::SendHeader,
+            retries: 0,
+            outgoing: VecDeque::new(),
+        })
+    }
+
+    /// Get the header being sent.
+    ///
+    pub fn header(&self) -> &SampleDumpHeader {
+        &self.header
+    }
+
+    /// Get the current state of the transfer.
+    ///
+    pub fn state(&self) -> SampleDumpState {
+        match self.state {
+            SenderState::Finished(state) => state,
+            _ => SampleDumpState::InProgress,
+        }
+    }
+
+    /// Get the time at which [SampleDumpSender::poll] has to be called again if no message arrives,
+    /// or `None` when it is waiting for a message indefinitely or it has finished.
+    ///
+    pub fn deadline(&self) -> Option<Instant> {
+        match self.state {
+            SenderState::AwaitHeaderReply { deadline }
+            | SenderState::AwaitPacketReply { deadline, .. } => Some(deadline),
+            _ => None,
+        }
+    }
+
+    /// Abort the transfer, telling the receiver.
+    ///
+    pub fn cancel(&mut self) {
+        if self.state() == SampleDumpState::InProgress {
+            self.outgoing.push_back(
+                SampleDumpMessage::Cancel {
+                    device_id: self.header.device_id,
+                    packet_number: self.current_packet_number(),
+                }
+                .encode(),
+            );
+            self.finish(SampleDumpState::Failed(SampleDumpError::Cancelled));
+        }
+    }
+
+    /// Get the next message to send at time `now`, if any.
+    ///
+    pub fn poll(&mut self, now: Instant) -> Option<Vec<u8>> {
+        if let Some(message) = self.outgoing.pop_front() {
+            return Some(message);
+        }
+        match self.state {
+            SenderState::SendHeader => {
+                self.state = SenderState::AwaitHeaderReply {
+                    deadline: now + self.config.header_timeout,
+                };
+                Some(SampleDumpMessage::Header(self.header.clone()).encode())
+            }
+            SenderState::AwaitHeaderReply { deadline } if now >= deadline => {
+                self.next_packet(0);
+                self.poll(now)
+            }
+            SenderState::SendPacket { index } => {
+                self.state = SenderState::AwaitPacketReply {
+                    index,
+                    deadline: now + self.config.packet_timeout,
+                };
+                Some(self.packet(index).encode())
+            }
+            SenderState::AwaitPacketReply { index, deadline } if now >= deadline => {
+                self.next_packet(index + 1);
+                self.poll(now)
+            }
+            _ => None,
+        }
+    }
+
+    /// Handle a message received from the other side at time `now`.
+    /// Messages that are not Sample Dump handshakes for this device are ignored.
+    ///
+    pub fn handle(&mut self, message: &[u8], _now: Instant) {
+        let message = match SampleDumpMessage::decode(message) {
+            Ok(message) if self.accepts(message.device_id()) => message,
+            _ => return,
+        };
+        let index = match self.state {
+            SenderState::AwaitHeaderReply { .. } => None,
+            SenderState::AwaitPacketReply { index, .. } => Some(index),
+            SenderState::Waiting { index } => index,
+            _ => return,
+        };
+        match (message, index) {
+            (SampleDumpMessage::Ack { .. }, None) => self.next_packet(0),
+            (SampleDumpMessage::Ack { packet_number, .. }, Some(index))
+                if packet_number == (index & 0x7F) as u8 =>
+            {
+                self.next_packet(index + 1)
+            }
+            (SampleDumpMessage::Nak { .. }, None) => self.retry(SenderState::SendHeader, 0),
+            (SampleDumpMessage::Nak { packet_number, .. }, Some(index))
+                if packet_number == (index & 0x7F) as u8 =>
+            {
+                self.retry(SenderState::SendPacket { index }, packet_number)
+            }
+            (SampleDumpMessage::Wait { .. }, index) => {
+                self.state = SenderState::Waiting { index };
+            }
+            (SampleDumpMessage::Cancel { .. }, _) => {
+                self.finish(SampleDumpState::Failed(SampleDumpError::Cancelled))
+            }
+            _ => {}
+        }
+    }
+
+    /// Run the transfer through a link until it completes or fails.
+    ///
+    pub fn run<L: SysExLink>(&mut self, link: &mut L) -> Result<(), SampleDumpError> {
+        loop {
+            while let Some(message) = self.poll(Instant::now()) {
+                link.send(&message)?;
+            }
+            match self.state() {
+                SampleDumpState::InProgress => {}
+                SampleDumpState::Completed => return Ok(()),
+                SampleDumpState::Failed(err) => return Err(err),
+            }
+            let timeout = self
+                .deadline()
+                .map(|deadline| deadline.saturating_duration_since(Instant::now()));
+            if let Some(message) = link.receive(timeout)? {
+                self.handle(&message, Instant::now());
+            }
+        }
+    }
+
+    fn accepts(&self, device_id: u8) -> bool {
+        device_id == self.header.device_id || device_id == ALL_CALL
+    }
+
+    fn current_packet_number(&self) -> u8 {
+        match self.state {
+            SenderState::SendPacket { index }
+            | SenderState::AwaitPacketReply { index, .. }
+            | SenderState::Waiting { index: Some(index) } => (index & 0x7F) as u8,
+            _ => 0,
+        }
+    }
+
+    fn packet(&self, index: usize) -> SampleDumpMessage {
+        let start = index * SAMPLE_DUMP_PACKET_DATA_SIZE;
+        let end = (start + SAMPLE_DUMP_PACKET_DATA_SIZE).min(self.data.len());
+        SampleDumpMessage::data_packet(
+            self.header.device_id,
+            (index & 0x7F) as u8,
+            &self.data[start..end],
+        )
+    }
+
+    fn next_packet(&mut self, index: usize) {
+        self.retries = 0;
+        if index < self.header.packet_count() {
+            self.state = SenderState::SendPacket { index };
+        } else {
+            self.finish(SampleDumpState::Completed);
+        }
+    }
+
+    fn retry(&mut self, state: SenderState, packet_number: u8) {
+        self.retries += 1;
+        if self.retries > self.config.max_retries {
+            self.finish(SampleDumpState::Failed(SampleDumpError::TooManyRetries {
+                packet_number,
+            }));
+        } else {
+            self.state = state;
+        }
+    }
+
+    fn finish(&mut self, state: SampleDumpState) {
+        self.state = SenderState::Finished(state);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ReceiverState {
+    Idle,
+    AwaitHeader { deadline: Option<Instant> },
+    Receiving { deadline: Instant },
+    Finished(SampleDumpState),
+}
+
+/// The receiving side of a Sample Dump transfer.
+///
+/// Like [SampleDumpSender], it is a state machine fed with [SampleDumpReceiver::handle] and
+/// polled with [SampleDumpReceiver::poll], that can be driven with [SampleDumpReceiver::run].
+/// It acknowledges every valid packet, and rejects the ones with a wrong checksum so that the
+/// sender retries them.
+///
+#[derive(Debug, Clone)]
+pub struct SampleDumpReceiver {
+    device_id: u8,
+    config: SampleDumpConfig,
+    state: ReceiverState,
+    header: Option<SampleDumpHeader>,
+    data: Vec<u8>,
+    next_packet: usize,
+    retries: u32,
+    outgoing: VecDeque<Vec<u8>>,
+}
+
+impl SampleDumpReceiver {
+    /// Create a receiver that waits for a dump addressed to the device ID.
+    ///
+    pub fn new(device_id: u8) -> Self {
+        Self::with_config(device_id, SampleDumpConfig::default())
+    }
+
+    /// Create a receiver with a custom timing and retry configuration.
+    ///
+    pub fn with_config(device_id: u8, config: SampleDumpConfig) -> Self {
+        Self {
+            device_id,
+            config,
+            state: ReceiverState::Idle,
+            header: None,
+            data: Vec::new(),
+            next_packet: 0,
+            retries: 0,
+            outgoing: VecDeque::new(),
+        }
+    }
+
+    /// Ask the device to send the given sample, instead of waiting for it to start the dump.
+    ///
+    pub fn request(&mut self, sample_number: u16) {
+        self.outgoing.push_back(
+            SampleDumpMessage::Request {
+                device_id: self.device_id,
+                sample_number,
+            }
+            .encode(),
+        );
+    }
+
+    /// Get the header of the dump, once received.
+    ///
+    pub fn header(&self) -> Option<&SampleDumpHeader> {
+        self.header.as_ref()
+    }
+
+    /// Get the current state of the transfer.
+    ///
+    pub fn state(&self) -> SampleDumpState {
+        match self.state {
+            ReceiverState::Finished(state) => state,
+            _ => SampleDumpState::InProgress,
+        }
+    }
+
+    /// Get the time at which [SampleDumpReceiver::poll] has to be called again if no message arrives.
+    ///
+    pub fn deadline(&self) -> Option<Instant> {
+        match self.state {
+            ReceiverState::AwaitHeader { deadline } => deadline,
+            ReceiverState::Receiving { deadline } => Some(deadline),
+            _ => None,
+        }
+    }
+
+    /// Get the samples received so far.
+    ///
+    pub fn samples(&self) -> Vec<i32> {
+        match &self.header {
+            Some(header) => {
+                let len = self.data.len().min(header.data_len());
+                decode_samples(header.bits_per_sample, &self.data[..len])
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// Ask the sender to pause until the next handshake message.
+    ///
+    pub fn wait(&mut self) {
+        if self.state() == SampleDumpState::InProgress {
+            let packet_number = self.last_packet_number();
+            self.outgoing.push_back(
+                SampleDumpMessage::Wait {
+                    device_id: self.device_id,
+                    packet_number,
+                }
+                .encode(),
+            );
+        }
+    }
+
+    /// Resume a transfer paused with [SampleDumpReceiver::wait].
+    ///
+    pub fn resume(&mut self) {
+        if self.state() == SampleDumpState::InProgress {
+            let packet_number = self.last_packet_number();
+            self.send_ack(packet_number);
+        }
+    }
+
+    /// Abort the transfer, telling the sender.
+    ///
+    pub fn cancel(&mut self) {
+        if self.state() == SampleDumpState::InProgress {
+            let packet_number = self.last_packet_number();
+            self.outgoing.push_back(
+                SampleDumpMessage::Cancel {
+                    device_id: self.device_id,
+                    packet_number,
+                }
+                .encode(),
+            );
+            self.state =
+                ReceiverState::Finished(SampleDumpState::Failed(SampleDumpError::Cancelled));
+        }
+    }
+
+    /// Get the next message to send at time `now`, if any.
+    ///
+    pub fn poll(&mut self, now: Instant) -> Option<Vec<u8>> {
+        match self.state {
+            ReceiverState::Idle => {
+                let deadline = if self.outgoing.is_empty() {
+                    None
+                } else {
+                    Some(now + self.config.receive_timeout)
+                };
+                self.state = ReceiverState::AwaitHeader { deadline };
+            }
+            ReceiverState::AwaitHeader {
+                deadline: Some(deadline),
+            }
+            | ReceiverState::Receiving { deadline }
+                if now >= deadline =>
+            {
+                self.state =
+                    ReceiverState::Finished(SampleDumpState::Failed(SampleDumpError::Timeout));
+            }
+            _ => {}
+        }
+        self.outgoing.pop_front()
+    }
+
+    /// Handle a message received from the other side at time `now`.
+    ///
+    pub fn handle(&mut self, message: &[u8], now: Instant) {
+        let message = match SampleDumpMessage::decode(message) {
+            Ok(message) if self.accepts(message.device_id()) => message,
+            _ => return,
+        };
+        match (self.state, message) {
+            (ReceiverState::Idle, SampleDumpMessage::Header(header))
+            | (ReceiverState::AwaitHeader { .. }, SampleDumpMessage::Header(header)) => {
+                if header.validate().is_err() {
+                    self.send_nak(0);
+                    return;
+                }
+                self.data = Vec::with_capacity(header.data_len());
+                self.next_packet = 0;
+                self.send_ack(0);
+                if header.packet_count() == 0 {
+                    // An empty sample has no data packets, like for the sender
+                    self.state = ReceiverState::Finished(SampleDumpState::Completed);
+                } else {
+                    self.received(now);
+                }
+                self.header = Some(header);
+            }
+            (ReceiverState::Receiving { .. }, message @ SampleDumpMessage::DataPacket { .. }) => {
+                self.handle_data_packet(message, now)
+            }
+            (ReceiverState::Receiving { .. }, SampleDumpMessage::Cancel { .. })
+            | (ReceiverState::AwaitHeader { .. }, SampleDumpMessage::Cancel { .. }) => {
+                self.state =
+                    ReceiverState::Finished(SampleDumpState::Failed(SampleDumpError::Cancelled));
+            }
+            _ => {}
+        }
+    }
+
+    /// Run the transfer through a link until it completes or fails.
+    ///
+    pub fn run<L: SysExLink>(&mut self, link: &mut L) -> Result<(), SampleDumpError> {
+        loop {
+            while let Some(message) = self.poll(Instant::now()) {
+                link.send(&message)?;
+            }
+            match self.state() {
+                SampleDumpState::InProgress => {}
+                SampleDumpState::Completed => return Ok(()),
+                SampleDumpState::Failed(err) => return Err(err),
+            }
+            let timeout = self
+                .deadline()
+                .map(|deadline| deadline.saturating_duration_since(Instant::now()));
+            if let Some(message) = link.receive(timeout)? {
+                self.handle(&message, Instant::now());
+            }
+        }
+    }
+
+    fn handle_data_packet(&mut self, message: SampleDumpMessage, now: Instant) {
+        let valid = message.is_checksum_valid();
+        let (packet_number, data) = match message {
+            SampleDumpMessage::DataPacket {
+                packet_number,
+                data,
+                ..
+            } => (packet_number, data),
+            _ => return,
+        };
+        let expected = (self.next_packet & 0x7F) as u8;
+        let previous = (self.next_packet.wrapping_sub(1) & 0x7F) as u8;
+        self.received(now);
+        if packet_number == expected && valid {
+            self.data.extend_from_slice(&data);
+            self.next_packet += 1;
+            self.retries = 0;
+            self.send_ack(packet_number);
+            let data_len = self.header.as_ref().map_or(0, |header| header.data_len());
+            if self.data.len() >= data_len {
+                self.data.truncate(data_len);
+                self.state = ReceiverState::Finished(SampleDumpState::Completed);
+            }
+        } else if packet_number == previous && self.next_packet > 0 && valid {
+            // The sender missed our acknowledgement and is repeating the previous packet
+            self.send_ack(packet_number);
+        } else {
+            self.retries += 1;
+            if self.retries > self.config.max_retries {
+                self.cancel();
+                self.state = ReceiverState::Finished(SampleDumpState::Failed(
+                    SampleDumpError::TooManyRetries { packet_number },
+                ));
+            } else {
+                self.send_nak(expected);
+            }
+        }
+    }
+
+    fn accepts(&self, device_id: u8) -> bool {
+        device_id == self.device_id || device_id == ALL_CALL || self.device_id == ALL_CALL
+    }
+
+    fn last_packet_number(&self) -> u8 {
+        (self.next_packet.saturating_sub(1) & 0x7F) as u8
+    }
+
+    fn received(&mut self, now: Instant) {
+        self.state = ReceiverState::Receiving {
+            deadline: now + self.config.receive_timeout,
+        };
+    }
+
+    fn send_ack(&mut self, packet_number: u8) {
+        self.outgoing.push_back(
+            SampleDumpMessage::Ack {
+                device_id: self.device_id,
+                packet_number,
+            }
+            .encode(),
+        );
+    }
+
+    fn send_nak(&mut self, packet_number: u8) {
+        self.outgoing.push_back(
+            SampleDumpMessage::Nak {
+                device_id: self.device_id,
+                packet_number,
+            }
+            .encode(),
+        );
+    }
+}
+
+fn checksum(device_id: u8, packet_number: u8, data: &[u8]) -> u8 {
+    data.iter().fold(
+        NON_REAL_TIME ^ device_id ^ DATA_PACKET ^ packet_number,
+        |acc, b| acc ^ b,
+    ) & 0x7F
+}
+
+fn push_u14(bytes: &mut Vec<u8>, value: u16) {
+    bytes.extend([(value & 0x7F) as u8, ((value >> 7) & 0x7F) as u8]);
+}
+
+fn push_u21(bytes: &mut Vec<u8>, value: u32) {
+    bytes.extend([
+        (value & 0x7F) as u8,
+        ((value >> 7) & 0x7F) as u8,
+        ((value >> 14) & 0x7F) as u8,
+    ]);
+}
+
+fn u14(lsb: u8, msb: u8) -> u16 {
+    lsb as u16 | (msb as u16) << 7
+}
+
+fn u21(b0: u8, b1: u8, b2: u8) -> u32 {
+    b0 as u32 | (b1 as u32) << 7 | (b2 as u32) << 14
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sysex::QueueLink;
+    use std::thread;
+
+    fn header(length: u32) -> SampleDumpHeader {
+        SampleDumpHeader {
+            device_id: 3,
+            sample_number: 0x1234,
+            bits_per_sample: 16,
+            sample_period_ns: 22675,
+            length,
+            loop_start: 0,
+            loop_end: length.saturating_sub(1),
+            loop_type: LoopType::Forward,
+        }
+    }
+
+    fn ramp(len: usize) -> Vec<i32> {
+        (0..len as i32).map(|i| i * 31 - 15000).collect()
+    }
+
+    #[test]
+    fn header_round_trip() {
+        let message = SampleDumpMessage::Header(header(1000));
+        let bytes = message.encode();
+        assert_eq!(bytes.len(), 21);
+        assert_eq!(SampleDumpMessage::decode(&bytes), Ok(message));
+    }
+
+    #[test]
+    fn data_packet_checksum() {
+        let message = SampleDumpMessage::data_packet(3, 130, &[1, 2, 3]);
+        let mut bytes = message.encode();
+        assert_eq!(bytes.len(), 127);
+        assert_eq!(bytes[4], 2);
+        assert!(SampleDumpMessage::decode(&bytes)
+            .unwrap()
+            .is_checksum_valid());
+        bytes[10] ^= 0x01;
+        assert!(!SampleDumpMessage::decode(&bytes)
+            .unwrap()
+            .is_checksum_valid());
+    }
+
+    #[test]
+    fn decode_invalid() {
+        assert_eq!(
+            SampleDumpMessage::decode(&[0xf0, 0x7e, 0x00, 0x7f, 0xf7]),
+            Err(SampleDumpError::InvalidMessage)
+        );
+        assert_eq!(
+            SampleDumpMessage::decode(&[0xf0, 0x7f, 0x00, 0x7f, 0x00, 0xf7]),
+            Err(SampleDumpError::InvalidMessage)
+        );
+        for bits in [0, 7, 29, 0x7f] {
+            let mut bytes = SampleDumpMessage::Header(header(1000)).encode();
+            bytes[6] = bits;
+            assert_eq!(
+                SampleDumpMessage::decode(&bytes),
+                Err(SampleDumpError::InvalidHeader)
+            );
+        }
+    }
+
+    #[test]
+    fn samples_round_trip() {
+        for bits in [8u8, 12, 14, 16, 20, 24, 28] {
+            let max = (1i32 << (bits - 1)) - 1;
+            let samples = vec![0, -max - 1, max, 1, -1];
+            let data = encode_samples(bits, &samples);
+            assert_eq!(data.len(), samples.len() * ((bits as usize + 6) / 7));
+            assert_eq!(decode_samples(bits, &data), samples);
+        }
+    }
+
+    #[test]
+    fn sender_open_loop() {
+        let samples = ramp(100);
+        let mut sender = SampleDumpSender::new(header(0), &samples).unwrap();
+        let start = Instant::now();
+        assert!(sender.poll(start).is_some());
+        assert_eq!(sender.poll(start), None);
+        let after_header = start + Duration::from_secs(2);
+        let packets = (0..3)
+            .map(|i| sender.poll(after_header + Duration::from_millis(20 * i)))
+            .collect::<Vec<_>>();
+        assert!(packets.iter().all(|packet| packet.is_some()));
+        assert_eq!(sender.state(), SampleDumpState::InProgress);
+        assert_eq!(sender.poll(after_header + Duration::from_millis(60)), None);
+        assert_eq!(sender.state(), SampleDumpState::Completed);
+    }
+
+    #[test]
+    fn sender_retries_on_nak() {
+        let mut sender = SampleDumpSender::new(header(0), &ramp(10)).unwrap();
+        let now = Instant::now();
+        sender.poll(now).unwrap();
+        sender.handle(
+            &SampleDumpMessage::Ack {
+                device_id: 3,
+                packet_number: 0,
+            }
+            .encode(),
+            now,
+        );
+        let packet = sender.poll(now).unwrap();
+        for _ in 0..3 {
+            sender.handle(
+                &SampleDumpMessage::Nak {
+                    device_id: 3,
+                    packet_number: 0,
+                }
+                .encode(),
+                now,
+            );
+            assert_eq!(sender.poll(now), Some(packet.clone()));
+        }
+        sender.handle(
+            &SampleDumpMessage::Nak {
+                device_id: 3,
+                packet_number: 0,
+            }
+            .encode(),
+            now,
+        );
+        assert_eq!(
+            sender.state(),
+            SampleDumpState::Failed(SampleDumpError::TooManyRetries { packet_number: 0 })
+        );
+    }
+
+    #[test]
+    fn sender_waits_and_cancels() {
+        let mut sender = SampleDumpSender::new(header(0), &ramp(100)).unwrap();
+        let now = Instant::now();
+        sender.poll(now).unwrap();
+        sender.handle(
+            &SampleDumpMessage::Wait {
+                device_id: 3,
+                packet_number: 0,
+            }
+            .encode(),
+            now,
+        );
+        assert_eq!(sender.deadline(), None);
+        assert_eq!(sender.poll(now + Duration::from_secs(10)), None);
+        sender.handle(
+            &SampleDumpMessage::Cancel {
+                device_id: 3,
+                packet_number: 0,
+            }
+            .encode(),
+            now,
+        );
+        assert_eq!(
+            sender.state(),
+            SampleDumpState::Failed(SampleDumpError::Cancelled)
+        );
+    }
+
+    #[test]
+    fn receiver_naks_bad_checksum() {
+        let mut receiver = SampleDumpReceiver::new(3);
+        let now = Instant::now();
+        assert_eq!(receiver.poll(now), None);
+        receiver.handle(&SampleDumpMessage::Header(header(60)).encode(), now);
+        assert_eq!(
+            receiver.poll(now),
+            Some(
+                SampleDumpMessage::Ack {
+                    device_id: 3,
+                    packet_number: 0
+                }
+                .encode()
+            )
+        );
+        let mut packet =
+            SampleDumpMessage::data_packet(3, 0, &encode_samples(16, &ramp(40))).encode();
+        packet[20] ^= 0x01;
+        receiver.handle(&packet, now);
+        assert_eq!(
+            receiver.poll(now),
+            Some(
+                SampleDumpMessage::Nak {
+                    device_id: 3,
+                    packet_number: 0
+                }
+                .encode()
+            )
+        );
+        assert_eq!(receiver.poll(now + Duration::from_secs(3)), None);
+        assert_eq!(
+            receiver.state(),
+            SampleDumpState::Failed(SampleDumpError::Timeout)
+        );
+    }
+
+    #[test]
+    fn empty_sample_completes() {
+        let mut sender = SampleDumpSender::new(header(0), &[]).unwrap();
+        let mut receiver = SampleDumpReceiver::new(3);
+        let now = Instant::now();
+        assert_eq!(receiver.poll(now), None);
+        let header_message = sender.poll(now).unwrap();
+        receiver.handle(&header_message, now);
+        let ack = receiver.poll(now).unwrap();
+        assert_eq!(receiver.state(), SampleDumpState::Completed);
+        sender.handle(&ack, now);
+        assert_eq!(sender.poll(now), None);
+        assert_eq!(sender.state(), SampleDumpState::Completed);
+        assert_eq!(receiver.poll(now + Duration::from_secs(3)), None);
+        assert_eq!(receiver.state(), SampleDumpState::Completed);
+    }
+
+    #[test]
+    fn transfer_through_queue_link() {
+        let samples = ramp(1000);
+        let (mut a, mut b) = QueueLink::pair();
+        let receiver = thread::spawn(move || {
+            let mut receiver = SampleDumpReceiver::new(3);
+            receiver
+                .run(&mut b)
+                .map(|_| (receiver.header().cloned(), receiver.samples()))
+        });
+        let mut sender = SampleDumpSender::new(header(1000), &samples).unwrap();
+        sender.run(&mut a).unwrap();
+        let (received_header, received_samples) = receiver.join().unwrap().unwrap();
+        assert_eq!(received_header, Some(header(1000)));
+        assert_eq!(received_samples, samples);
+    }
+}
@@ -0,0 +1,452 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::events::Timestamp;
+use crate::packets::PacketBuffer;
+use crate::tuning::{NoteTuning, TuningMessage, TuningTable};
+
+/// A scale parsed from a Scala `.scl` file.
+///
+/// The pitches are given in cents above the first degree of the scale (which is always `1/1`
+/// and not included), and the last one is the period of the scale (usually `2/1`).
+///
+/// A scale is mapped to the MIDI notes with a [ScalaKeyboardMapping] to get a frequency table,
+/// or directly a MIDI Tuning Standard bulk dump ready to be sent:
+///
+/// ```
+/// use coremidi::{ScalaKeyboardMapping, ScalaScale, Timestamp};
+/// let scale = ScalaScale::parse("! just.scl\nJust intonation\n 12\n!\n16/15\n9/8\n6/5\n5/4\n4/3\n45/32\n3/2\n8/5\n5/3\n9/5\n15/8\n2/1\n").unwrap();
+/// let mapping = ScalaKeyboardMapping::default();
+/// let frequencies = scale.frequencies(&mapping).unwrap();
+/// assert_eq!(frequencies[69], Some(440.0));
+/// assert!((frequencies[76].unwrap() - 660.0).abs() < 1e-9);
+///
+/// let buffer = scale.to_packet_buffer(&mapping, Timestamp::Now, 0x7f, 0).unwrap();
+/// assert_eq!(buffer.iter().next().unwrap().data().len(), 408);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScalaScale {
+    /// The description line of the file.
+    pub description: String,
+    /// The pitches of the degrees after `1/1`, in cents.
+    pub pitches: Vec<f64>,
+}
+
+impl ScalaScale {
+    /// Parse the contents of a `.scl` file.
+    ///
+    pub fn parse(text: &str) -> Result<Self, ScalaError> {
+        let mut lines = Lines::new(text);
+        let (_, description) = lines.next()?;
+        let (line, count) = lines.next()?;
+        let count: usize = parse_value(line, count)?;
+        let mut pitches = Vec::with_capacity(count);
+        for _ in 0..count {
+            let (line, pitch) = lines.next()?;
+            pitches.push(parse_pitch(line, pitch)?);
+        }
+        Ok(Self {
+            description: description.to_string(),
+            pitches,
+        })
+    }
+
+    /// Get the pitch in cents of any degree of the scale, repeating it every period.
+    ///
+    pub fn cents(&self, degree: i32) -> f64 {
+        let count = self.pitches.len() as i32;
+        if count == 0 {
+            return 0.0;
+        }
+        let period = degree.div_euclid(count);
+        let index = degree.rem_euclid(count) as usize;
+        let pitch = if index == 0 {
+            0.0
+        } else {
+            self.pitches[index - 1]
+        };
+        period as f64 * self.pitches[count as usize - 1] + pitch
+    }
+
+    /// Get the frequencies in Hz of the 128 MIDI notes, or `None` for the unmapped ones.
+    ///
+    pub fn frequencies(
+        &self,
+        mapping: &ScalaKeyboardMapping,
+    ) -> Result<Vec<Option<f64>>, ScalaError> {
+        let reference = self
+            .key_cents(mapping, mapping.reference_note)
+            .ok_or(ScalaError::UnmappedReference)?;
+        Ok((0..128u8)
+            .map(|key| {
+                if key < mapping.first_note || key > mapping.last_note {
+                    return None;
+                }
+                self.key_cents(mapping, key).map(|cents| {
+                    mapping.reference_frequency * ((cents - reference) / 1200.0).exp2()
+                })
+            })
+            .collect())
+    }
+
+    /// Create a tuning table for the mapped notes, leaving the rest equal tempered.
+    ///
+    pub fn tuning_table(&self, mapping: &ScalaKeyboardMapping) -> Result<TuningTable, ScalaError> {
+        let mut table = TuningTable::equal_temperament();
+        table.set_name(&self.description);
+        for (key, frequency) in self.frequencies(mapping)?.into_iter().enumerate() {
+            if let Some(frequency) = frequency {
+                table.set_note(key as u8, NoteTuning::from_frequency(frequency));
+            }
+        }
+        Ok(table)
+    }
+
+    /// Create the MIDI Tuning Standard bulk dump of the scale.
+    /// The unmapped notes are sent as [NoteTuning::NO_CHANGE].
+    ///
+    pub fn bulk_dump(
+        &self,
+        mapping: &ScalaKeyboardMapping,
+        device_id: u8,
+        program: u8,
+    ) -> Result<TuningMessage, ScalaError> {
+        let notes = self
+            .frequencies(mapping)?
+            .into_iter()
+            .map(|frequency| frequency.map_or(NoteTuning::NO_CHANGE, NoteTuning::from_frequency))
+            .collect();
+        Ok(TuningMessage::BulkDump {
+            device_id,
+            program,
+            name: self.description.clone(),
+            notes,
+        })
+    }
+
+    /// Create a `PacketBuffer` with the bulk dump of the scale, ready to be sent with [crate::OutputPort::send].
+    ///
+    pub fn to_packet_buffer(
+        &self,
+        mapping: &ScalaKeyboardMapping,
+        timestamp: Timestamp,
+        device_id: u8,
+        program: u8,
+    ) -> Result<PacketBuffer, ScalaError> {
+        Ok(self
+            .bulk_dump(mapping, device_id, program)?
+            .to_packet_buffer(timestamp))
+    }
+
+    fn key_cents(&self, mapping: &ScalaKeyboardMapping, key: u8) -> Option<f64> {
+        let offset = key as i32 - mapping.middle_note as i32;
+        if mapping.map.is_empty() {
+            return Some(self.cents(offset));
+        }
+        let size = mapping.map.len() as i32;
+        let degree = mapping.map[offset.rem_euclid(size) as usize]?;
+        let octave_degree = match mapping.octave_degree {
+            0 => self.pitches.len() as i32,
+            degree => degree as i32,
+        };
+        Some(offset.div_euclid(size) as f64 * self.cents(octave_degree) + self.cents(degree as i32))
+    }
+}
+
+impl FromStr for ScalaScale {
+    type Err = ScalaError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+/// A keyboard mapping parsed from a Scala `.kbm` file.
+///
+/// The default mapping is linear over all the MIDI notes, with the first degree of the scale
+/// at note 60 and note 69 tuned to 440 Hz.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScalaKeyboardMapping {
+    /// The first MIDI note to retune.
+    pub first_note: u8,
+    /// The last MIDI note to retune.
+    pub last_note: u8,
+    /// The MIDI note where the first entry of the mapping is placed.
+    pub middle_note: u8,
+    /// The MIDI note with a fixed frequency.
+    pub reference_note: u8,
+    /// The frequency in Hz of the reference note.
+    pub reference_frequency: f64,
+    /// The scale degree used as the formal octave, repeated every `map.len()` notes.
+    /// It is zero when the last degree of the scale is used.
+    pub octave_degree: u32,
+    /// The scale degree of the notes from the middle note on, or `None` for the unmapped ones.
+    /// An empty map means a linear mapping.
+    pub map: Vec<Option<u32>>,
+}
+
+impl ScalaKeyboardMapping {
+    /// Parse the contents of a `.kbm` file.
+    ///
+    pub fn parse(text: &str) -> Result<Self, ScalaError> {
+        let mut lines = Lines::new(text);
+        let (line, size) = lines.next()?;
+        let size: usize = parse_value(line, size)?;
+        let (line, first_note) = lines.next()?;
+        let first_note = parse_note(line, first_note)?;
+        let (line, last_note) = lines.next()?;
+        let last_note = parse_note(line, last_note)?;
+        let (line, middle_note) = lines.next()?;
+        let middle_note = parse_note(line, middle_note)?;
+        let (line, reference_note) = lines.next()?;
+        let reference_note = parse_note(line, reference_note)?;
+        let (line, reference_frequency) = lines.next()?;
+        let reference_frequency: f64 = parse_value(line, reference_frequency)?;
+        if !(reference_frequency.is_finite() && reference_frequency > 0.0) {
+            return Err(invalid_value(line, reference_frequency.to_string()));
+        }
+        let (line, octave_degree) = lines.next()?;
+        let octave_degree = parse_value(line, octave_degree)?;
+        let mut map = Vec::with_capacity(size);
+        for _ in 0..size {
+            let (line, entry) = lines.next()?;
+            map.push(match first_token(entry) {
+                "x" | "X" => None,
+                _ => Some(parse_value(line, entry)?),
+            });
+        }
+        Ok(Self {
+            first_note,
+            last_note,
+            middle_note,
+            reference_note,
+            reference_frequency,
+            octave_degree,
+            map,
+        })
+    }
+}
+
+impl Default for ScalaKeyboardMapping {
+    fn default() -> Self {
+        Self {
+            first_note: 0,
+            last_note: 127,
+            middle_note: 60,
+            reference_note: 69,
+            reference_frequency: 440.0,
+            octave_degree: 0,
+            map: Vec::new(),
+        }
+    }
+}
+
+impl FromStr for ScalaKeyboardMapping {
+    type Err = ScalaError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+/// An error parsing Scala files or mapping a scale.
+/// Line numbers start at 1.
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScalaError {
+    /// A pitch is neither a ratio nor a value in cents.
+    InvalidPitch { line: usize, text: String },
+    /// A number is invalid or out of range.
+    InvalidValue { line: usize, text: String },
+    /// The file ended before all the expected lines were read.
+    UnexpectedEnd { line: usize },
+    /// The reference note of the keyboard mapping is not mapped to any degree.
+    UnmappedReference,
+}
+
+impl fmt::Display for ScalaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidPitch { line, text } => {
+                write!(f, "line {}: invalid pitch `{}`", line, text)
+            }
+            Self::InvalidValue { line, text } => {
+                write!(f, "line {}: invalid value `{}`", line, text)
+            }
+            Self::UnexpectedEnd { line } => write!(f, "line {}: unexpected end of file", line),
+            Self::UnmappedReference => write!(f, "the reference note is not mapped"),
+        }
+    }
+}
+
+impl std::error::Error for ScalaError {}
+
+/// The lines of a Scala file that are not comments, with their line numbers.
+struct Lines<'a> {
+    lines: std::iter::Enumerate<std::str::Lines<'a>>,
+    line: usize,
+}
+
+impl<'a> Lines<'a> {
+    fn new(text: &'a str) -> Self {
+        Self {
+            lines: text.lines().enumerate(),
+            line: 0,
+        }
+    }
+
+    fn next(&mut self) -> Result<(usize, &'a str), ScalaError> {
+        for (index, text) in &mut self.lines {
+            self.line = index + 1;
+            if !text.starts_with('!') {
+                return Ok((self.line, text.trim()));
+            }
+        }
+        Err(ScalaError::UnexpectedEnd {
+            line: self.line + 1,
+        })
+    }
+}
+
+fn first_token(text: &str) -> &str {
+    text.split_whitespace().next().unwrap_or("")
+}
+
+fn invalid_value(line: usize, text: String) -> ScalaError {
+    ScalaError::InvalidValue { line, text }
+}
+
+fn parse_value<T: FromStr>(line: usize, text: &str) -> Result<T, ScalaError> {
+    let token = first_token(text);
+    token
+        .parse()
+        .map_err(|_| invalid_value(line, token.to_string()))
+}
+
+fn parse_note(line: usize, text: &str) -> Result<u8, ScalaError> {
+    match parse_value(line, text)? {
+        note @ 0..=127 => Ok(note),
+        note => Err(invalid_value(line, note.to_string())),
+    }
+}
+
+fn parse_pitch(line: usize, text: &str) -> Result<f64, ScalaError> {
+    let token = first_token(text);
+    let invalid = || ScalaError::InvalidPitch {
+        line,
+        text: token.to_string(),
+    };
+    if token.contains('.') {
+        return token
+            .parse::<f64>()
+            .ok()
+            .filter(|cents| cents.is_finite())
+            .ok_or_else(invalid);
+    }
+    let (numerator, denominator) = token.split_once('/').unwrap_or((token, "1"));
+    let numerator: u64 = numerator.parse().map_err(|_| invalid())?;
+    let denominator: u64 = denominator.parse().map_err(|_| invalid())?;
+    if numerator == 0 || denominator == 0 {
+        return Err(invalid());
+    }
+    Ok(1200.0 * (numerator as f64 / denominator as f64).log2())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EQUAL_12: &str = "! 12-tet.scl\n!\n12-tone equal temperament\n12\n!\n\
+        100.0\n200.\n300.0\n400.0\n500.0\n600.0\n700.0\n800.0\n900.0\n1000.0\n1100.0\n2/1\n";
+
+    #[test]
+    fn parse_scale() {
+        let scale = ScalaScale::parse(EQUAL_12).unwrap();
+        assert_eq!(scale.description, "12-tone equal temperament");
+        assert_eq!(scale.pitches.len(), 12);
+        assert_eq!(scale.pitches[11], 1200.0);
+        assert_eq!(scale.cents(-1), -100.0);
+        assert_eq!(scale.cents(25), 2500.0);
+
+        let frequencies = scale.frequencies(&ScalaKeyboardMapping::default()).unwrap();
+        for (key, frequency) in frequencies.into_iter().enumerate() {
+            let expected = NoteTuning::equal_tempered(key as u8).frequency();
+            assert!((frequency.unwrap() - expected).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn parse_pitches() {
+        assert_eq!(parse_pitch(1, "3/2 fifth"), Ok(1200.0 * 1.5f64.log2()));
+        assert_eq!(parse_pitch(1, "2"), Ok(1200.0));
+        assert_eq!(parse_pitch(1, "-5.5"), Ok(-5.5));
+        assert_eq!(
+            parse_pitch(7, "3/0"),
+            Err(ScalaError::InvalidPitch {
+                line: 7,
+                text: "3/0".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn scale_errors_have_line_numbers() {
+        let err = ScalaScale::parse("! bad.scl\nBad\n3\n9/8\n!\nfive/4\n2/1\n").unwrap_err();
+        assert_eq!(
+            err,
+            ScalaError::InvalidPitch {
+                line: 6,
+                text: "five/4".to_string()
+            }
+        );
+        assert_eq!(err.to_string(), "line 6: invalid pitch `five/4`");
+
+        let err = ScalaScale::parse("Short\n3\n9/8\n").unwrap_err();
+        assert_eq!(err, ScalaError::UnexpectedEnd { line: 4 });
+    }
+
+    #[test]
+    fn keyboard_mapping() {
+        // A 7 note white keys mapping of a 7 note scale, tuning the C below A4 to 264 Hz.
+        let mapping = ScalaKeyboardMapping::parse(
+            "! white.kbm\n12\n48\n72\n60\n60\n264.0\n7\n\
+            0\nx\n1\nx\n2\n3\nx\n4\nx\n5\nx\n6\n",
+        )
+        .unwrap();
+        assert_eq!(mapping.map[1], None);
+        assert_eq!(mapping.map[11], Some(6));
+
+        let scale =
+            ScalaScale::parse("Just major\n7\n9/8\n5/4\n4/3\n3/2\n5/3\n15/8\n2/1\n").unwrap();
+        let frequencies = scale.frequencies(&mapping).unwrap();
+        assert_eq!(frequencies[47], None);
+        assert_eq!(frequencies[61], None);
+        assert_eq!(frequencies[60], Some(264.0));
+        assert!((frequencies[69].unwrap() - 440.0).abs() < 1e-9);
+        assert!((frequencies[48].unwrap() - 132.0).abs() < 1e-9);
+        assert!((frequencies[71].unwrap() - 495.0).abs() < 1e-9);
+
+        match scale.bulk_dump(&mapping, 0x7F, 1).unwrap() {
+            TuningMessage::BulkDump { notes, name, .. } => {
+                assert_eq!(name, "Just major");
+                assert_eq!(notes[61], NoteTuning::NO_CHANGE);
+                assert_eq!(notes[69], NoteTuning::from_frequency(440.0));
+            }
+            _ => panic!("Expected a bulk dump"),
+        }
+    }
+
+    #[test]
+    fn keyboard_mapping_errors() {
+        let err = ScalaKeyboardMapping::parse("0\n0\n127\n60\n128\n440.0\n0\n").unwrap_err();
+        assert_eq!(err.to_string(), "line 5: invalid value `128`");
+
+        let mapping = ScalaKeyboardMapping::parse("1\n0\n127\n60\n61\n440.0\n1\nx\n").unwrap();
+        let scale = ScalaScale::parse(EQUAL_12).unwrap();
+        assert_eq!(
+            scale.frequencies(&mapping),
+            Err(ScalaError::UnmappedReference)
+        );
+    }
+}
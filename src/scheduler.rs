@@ -0,0 +1,548 @@
+use core_foundation::base::OSStatus;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap};
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::{Arc, Condvar, Mutex, Once, Weak};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use coremidi_sys::MIDIEndpointRef;
+
+use crate::endpoints::destinations::Destination;
+use crate::events::{EventBuffer, EventList, Timestamp};
+use crate::host_time::{HostClock, SystemClock};
+use crate::packets::{PacketBuffer, PacketList};
+use crate::ports::{OutputPort, Packets};
+use crate::properties::{Properties, PropertyGetter};
+use crate::protocol::Protocol;
+
+/// The longest time the dispatch thread waits before reading the clock again.
+const MAX_WAIT: Duration = Duration::from_millis(10);
+
+/// How long the advance schedule time read from a destination is used before reading it again.
+const ADVANCE_REFRESH: Duration = Duration::from_secs(1);
+
+/// Where a [SendScheduler] hands the packets over once they are due.
+///
+/// The output is called with the queue of the scheduler locked, so that flushing can't race with it,
+/// which means it must not call back into the scheduler.
+///
+pub trait ScheduledOutput: Send + 'static {
+    /// Send a list of packets to a destination.
+    ///
+    fn send_packet_list(
+        &mut self,
+        destination: &Destination,
+        packet_list: &PacketList,
+    ) -> Result<(), OSStatus>;
+
+    /// Send a list of events to a destination.
+    ///
+    fn send_event_list(
+        &mut self,
+        destination: &Destination,
+        event_list: &EventList,
+    ) -> Result<(), OSStatus>;
+}
+
+impl ScheduledOutput for OutputPort {
+    fn send_packet_list(
+        &mut self,
+        destination: &Destination,
+        packet_list: &PacketList,
+    ) -> Result<(), OSStatus> {
+        self.send(destination, packet_list)
+    }
+
+    fn send_event_list(
+        &mut self,
+        destination: &Destination,
+        event_list: &EventList,
+    ) -> Result<(), OSStatus> {
+        self.send(destination, event_list)
+    }
+}
+
+/// Holds packets with a future timestamp and sends them on time from a dedicated thread,
+/// for the drivers and virtual destinations that deliver packets as soon as they are sent.
+///
+/// Packets are handed to the output ahead of their timestamp by the destination's
+/// [Properties::advance_schedule_time_musec], as read when they are queued (at most once
+/// a second for every destination), keeping their original timestamps, and packets with
+/// [Timestamp::Now] are sent right away.
+/// Both [crate::Endpoint::flush] and [crate::flush] cancel the packets that are still queued.
+///
+/// ```rust,no_run
+/// use coremidi::{Client, Destination, HostClock, PacketBuffer, SendScheduler, SystemClock};
+/// use std::time::Duration;
+/// let client = Client::new("example-client").unwrap();
+/// let scheduler = SendScheduler::new(client.output_port("example-port").unwrap());
+/// let destination = Destination::from_index(0).unwrap();
+/// let later = SystemClock.after(Duration::from_millis(500));
+/// scheduler.send(&destination, &PacketBuffer::new(later, &[0x90, 0x3c, 0x7f]));
+/// ```
+pub struct SendScheduler {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl SendScheduler {
+    /// Create a scheduler sending through an output, usually an [OutputPort],
+    /// following the system clock.
+    ///
+    pub fn new<O: ScheduledOutput>(output: O) -> Self {
+        Self::with_clock(output, SystemClock)
+    }
+
+    /// Create a scheduler sending through an output and following a given clock.
+    ///
+    pub fn with_clock<O, C>(output: O, clock: C) -> Self
+    where
+        O: ScheduledOutput,
+        C: HostClock + Send + 'static,
+    {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                queue: BinaryHeap::new(),
+                advance: HashMap::new(),
+                clock: Box::new(clock),
+                next_sequence: 0,
+                running: true,
+            }),
+            condvar: Condvar::new(),
+            advance_times: Mutex::new(HashMap::new()),
+            failed_sends: AtomicUsize::new(0),
+        });
+        register(&shared);
+        let thread_shared = shared.clone();
+        let thread = thread::spawn(move || dispatch(thread_shared, output));
+        Self {
+            shared,
+            thread: Some(thread),
+        }
+    }
+
+    /// Queue a list of packets to be sent to a destination at their timestamps.
+    ///
+    pub fn send<'a, P>(&self, destination: &Destination, packets: P)
+    where
+        P: Into<Packets<'a>>,
+    {
+        let destination_ref = destination.endpoint.object.0;
+        let advance_time = self.shared.advance_time(destination);
+        let mut state = self.shared.state.lock().unwrap();
+        let advance = match state.advance.get(&destination_ref) {
+            Some(advance) => *advance,
+            None => advance_time,
+        };
+        match packets.into() {
+            Packets::BorrowedPacketList(packet_list) => {
+                for packet in packet_list.iter() {
+                    let payload = Payload::Bytes(packet.data().to_vec());
+                    state.push(packet.timestamp(), advance, destination_ref, payload);
+                }
+            }
+            Packets::BorrowedEventList(event_list) => {
+                state.push_event_list(destination_ref, advance, event_list)
+            }
+            Packets::OwnedEventBuffer(event_buffer) => {
+                state.push_event_list(destination_ref, advance, &event_buffer)
+            }
+        }
+        self.shared.condvar.notify_one();
+    }
+
+    /// Override how long before their timestamp the packets for a destination are sent,
+    /// instead of using its [Properties::advance_schedule_time_musec].
+    /// It also applies to the packets already queued for the destination.
+    ///
+    pub fn set_advance_time(&self, destination: &Destination, advance: Duration) {
+        let destination_ref = destination.endpoint.object.0;
+        let mut state = self.shared.state.lock().unwrap();
+        state.advance.insert(destination_ref, advance);
+        state.reschedule(destination_ref, advance);
+        self.shared.condvar.notify_one();
+    }
+
+    /// Cancel the packets queued for a destination.
+    ///
+    pub fn flush_destination(&self, destination: &Destination) {
+        self.shared.cancel(Some(destination.endpoint.object.0));
+    }
+
+    /// Cancel all the queued packets.
+    ///
+    pub fn flush(&self) {
+        self.shared.cancel(None);
+    }
+
+    /// Get the number of packets waiting to be sent.
+    ///
+    pub fn pending(&self) -> usize {
+        self.shared.state.lock().unwrap().queue.len()
+    }
+
+    /// Get the number of packets that the output failed to send.
+    ///
+    pub fn failed_sends(&self) -> usize {
+        self.shared.failed_sends.load(AtomicOrdering::Relaxed)
+    }
+}
+
+impl Drop for SendScheduler {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().running = false;
+        self.shared.condvar.notify_one();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Cancel the packets queued by every scheduler for a destination, or for all of them.
+pub(crate) fn cancel_scheduled(destination: Option<MIDIEndpointRef>) {
+    let mut schedulers = registry().lock().unwrap();
+    schedulers.retain(|shared| match shared.upgrade() {
+        Some(shared) => {
+            shared.cancel(destination);
+            true
+        }
+        None => false,
+    });
+}
+
+fn registry() -> &'static Mutex<Vec<Weak<Shared>>> {
+    static INIT: Once = Once::new();
+    static mut REGISTRY: Option<Mutex<Vec<Weak<Shared>>>> = None;
+
+    INIT.call_once(|| unsafe { REGISTRY = Some(Mutex::new(Vec::new())) });
+    unsafe { REGISTRY.as_ref().unwrap() }
+}
+
+fn register(shared: &Arc<Shared>) {
+    let mut schedulers = registry().lock().unwrap();
+    schedulers.retain(|shared| shared.strong_count() > 0);
+    schedulers.push(Arc::downgrade(shared));
+}
+
+fn advance_schedule_time(destination: &Destination) -> Duration {
+    let musec: Result<i32, OSStatus> =
+        Properties::advance_schedule_time_musec().value_from(destination);
+    Duration::from_micros(musec.unwrap_or(0).max(0) as u64)
+}
+
+fn dispatch<O: ScheduledOutput>(shared: Arc<Shared>, mut output: O) {
+    let mut state = shared.state.lock().unwrap();
+    while state.running {
+        let now = state.clock.now();
+        let wait = match state.queue.peek() {
+            Some(Reverse(entry)) if entry.send_at.is_now() || entry.send_at <= now => None,
+            Some(Reverse(entry)) => {
+                Some(MAX_WAIT.min(state.clock.duration_between(now, entry.send_at)))
+            }
+            None => Some(MAX_WAIT),
+        };
+        match wait {
+            // Sending with the state locked, so that a flush can't miss a packet being sent.
+            None => {
+                if let Some(Reverse(entry)) = state.queue.pop() {
+                    if entry.send(&mut output).is_err() {
+                        shared.failed_sends.fetch_add(1, AtomicOrdering::Relaxed);
+                    }
+                }
+            }
+            Some(wait) => state = shared.condvar.wait_timeout(state, wait).unwrap().0,
+        }
+    }
+}
+
+struct Shared {
+    state: Mutex<State>,
+    condvar: Condvar,
+    /// The advance schedule times read from the destinations, and when they were read.
+    advance_times: Mutex<HashMap<MIDIEndpointRef, (Duration, Instant)>>,
+    failed_sends: AtomicUsize,
+}
+
+impl Shared {
+    /// Get the advance schedule time of a destination, reading it again
+    /// without holding any lock when it is not known or was read too long ago.
+    fn advance_time(&self, destination: &Destination) -> Duration {
+        let destination_ref = destination.endpoint.object.0;
+        if let Some((advance, read_at)) = self.advance_times.lock().unwrap().get(&destination_ref) {
+            if read_at.elapsed() < ADVANCE_REFRESH {
+                return *advance;
+            }
+        }
+        let advance = advance_schedule_time(destination);
+        let mut advance_times = self.advance_times.lock().unwrap();
+        advance_times.insert(destination_ref, (advance, Instant::now()));
+        advance
+    }
+
+    fn cancel(&self, destination: Option<MIDIEndpointRef>) {
+        let mut state = self.state.lock().unwrap();
+        state.queue = std::mem::take(&mut state.queue)
+            .into_iter()
+            .filter(|Reverse(entry)| destination.map_or(false, |d| d != entry.destination))
+            .collect();
+    }
+}
+
+struct State {
+    queue: BinaryHeap<Reverse<Entry>>,
+    /// The advance times set through [SendScheduler::set_advance_time].
+    advance: HashMap<MIDIEndpointRef, Duration>,
+    clock: Box<dyn HostClock + Send>,
+    next_sequence: u64,
+    running: bool,
+}
+
+impl State {
+    fn push(
+        &mut self,
+        timestamp: Timestamp,
+        advance: Duration,
+        destination: MIDIEndpointRef,
+        payload: Payload,
+    ) {
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        self.queue.push(Reverse(Entry {
+            timestamp,
+            send_at: self.send_at(timestamp, advance),
+            sequence,
+            destination,
+            payload,
+        }));
+    }
+
+    fn push_event_list(
+        &mut self,
+        destination: MIDIEndpointRef,
+        advance: Duration,
+        event_list: &EventList,
+    ) {
+        let protocol = event_list.protocol();
+        for packet in event_list.iter() {
+            let payload = Payload::Words(protocol, packet.data().to_vec());
+            self.push(packet.timestamp(), advance, destination, payload);
+        }
+    }
+
+    /// Recompute when the packets queued for a destination are sent.
+    fn reschedule(&mut self, destination: MIDIEndpointRef, advance: Duration) {
+        let mut queue = std::mem::take(&mut self.queue).into_vec();
+        for Reverse(entry) in queue.iter_mut() {
+            if entry.destination == destination {
+                entry.send_at = self.send_at(entry.timestamp, advance);
+            }
+        }
+        self.queue = queue.into();
+    }
+
+    fn send_at(&self, timestamp: Timestamp, advance: Duration) -> Timestamp {
+        if timestamp.is_now() {
+            timestamp
+        } else {
+            self.clock.sub(timestamp, advance)
+        }
+    }
+}
+
+enum Payload {
+    Bytes(Vec<u8>),
+    Words(Protocol, Vec<u32>),
+}
+
+/// A queued packet, ordered by when it has to be sent and then by the order in which it was queued.
+struct Entry {
+    timestamp: Timestamp,
+    send_at: Timestamp,
+    sequence: u64,
+    destination: MIDIEndpointRef,
+    payload: Payload,
+}
+
+impl Entry {
+    fn send<O: ScheduledOutput>(&self, output: &mut O) -> Result<(), OSStatus> {
+        let destination = Destination::new(self.destination);
+        match &self.payload {
+            Payload::Bytes(data) => {
+                output.send_packet_list(&destination, &PacketBuffer::new(self.timestamp, data))
+            }
+            Payload::Words(protocol, words) => output.send_event_list(
+                &destination,
+                &EventBuffer::new(*protocol).with_packet(self.timestamp, words),
+            ),
+        }
+    }
+
+    fn key(&self) -> (Timestamp, u64) {
+        (self.send_at, self.sequence)
+    }
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Entry {}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::host_time::ManualClock;
+    use std::time::Instant;
+
+    type Sent = Arc<Mutex<Vec<(MIDIEndpointRef, Timestamp, Vec<u32>)>>>;
+
+    struct Recorder(Sent);
+
+    impl ScheduledOutput for Recorder {
+        fn send_packet_list(
+            &mut self,
+            destination: &Destination,
+            packet_list: &PacketList,
+        ) -> Result<(), OSStatus> {
+            let mut sent = self.0.lock().unwrap();
+            for packet in packet_list.iter() {
+                let data = packet.data().iter().map(|byte| *byte as u32).collect();
+                sent.push((destination.endpoint.object.0, packet.timestamp(), data));
+            }
+            Ok(())
+        }
+
+        fn send_event_list(
+            &mut self,
+            destination: &Destination,
+            event_list: &EventList,
+        ) -> Result<(), OSStatus> {
+            let mut sent = self.0.lock().unwrap();
+            for packet in event_list.iter() {
+                let data = packet.data().to_vec();
+                sent.push((destination.endpoint.object.0, packet.timestamp(), data));
+            }
+            Ok(())
+        }
+    }
+
+    fn wait_for(sent: &Sent, count: usize) -> usize {
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while sent.lock().unwrap().len() < count && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1));
+        }
+        sent.lock().unwrap().len()
+    }
+
+    fn at(clock: &ManualClock, millis: u64) -> Timestamp {
+        Timestamp::from_host_time(clock.duration_to_ticks(Duration::from_millis(millis)))
+    }
+
+    #[test]
+    fn dispatches_in_timestamp_order() {
+        let clock = Arc::new(ManualClock::new(Timestamp::from_host_time(1)));
+        let sent = Sent::default();
+        let scheduler = SendScheduler::with_clock(Recorder(sent.clone()), clock.clone());
+        let destination = Destination::new(7);
+        scheduler.set_advance_time(&destination, Duration::ZERO);
+
+        let mut packets = PacketBuffer::new(at(&clock, 20), &[0x90, 0x3c, 0x7f]);
+        packets.push_data(at(&clock, 30), &[0x80, 0x3c, 0x00]);
+        scheduler.send(&destination, &packets);
+        scheduler.send(
+            &destination,
+            &EventBuffer::new(Protocol::Midi20).with_packet(at(&clock, 10), &[0x40903c00, 0]),
+        );
+        scheduler.send(&destination, &PacketBuffer::new(Timestamp::Now, &[0xf8]));
+
+        assert_eq!(wait_for(&sent, 1), 1);
+        assert_eq!(scheduler.pending(), 3);
+
+        clock.advance(Duration::from_millis(25));
+        assert_eq!(wait_for(&sent, 3), 3);
+        clock.advance(Duration::from_millis(5));
+        assert_eq!(wait_for(&sent, 4), 4);
+        assert_eq!(
+            sent.lock().unwrap().clone(),
+            vec![
+                (7, Timestamp::Now, vec![0xf8]),
+                (7, at(&clock, 10), vec![0x40903c00, 0]),
+                (7, at(&clock, 20), vec![0x90, 0x3c, 0x7f]),
+                (7, at(&clock, 30), vec![0x80, 0x3c, 0x00]),
+            ]
+        );
+    }
+
+    #[test]
+    fn advance_time_and_flush() {
+        let clock = Arc::new(ManualClock::new(Timestamp::from_host_time(1)));
+        let sent = Sent::default();
+        let scheduler = SendScheduler::with_clock(Recorder(sent.clone()), clock.clone());
+        let (early, late) = (Destination::new(101), Destination::new(102));
+        scheduler.set_advance_time(&early, Duration::from_millis(50));
+        scheduler.set_advance_time(&late, Duration::ZERO);
+
+        scheduler.send(&early, &PacketBuffer::new(at(&clock, 40), &[0xfa]));
+        scheduler.send(&early, &PacketBuffer::new(at(&clock, 500), &[0xfc]));
+        scheduler.send(&late, &PacketBuffer::new(at(&clock, 40), &[0xfa]));
+        assert_eq!(wait_for(&sent, 1), 1);
+        assert_eq!(sent.lock().unwrap()[0].0, 101);
+
+        scheduler.flush_destination(&early);
+        assert_eq!(scheduler.pending(), 1);
+        cancel_scheduled(Some(102));
+        assert_eq!(scheduler.pending(), 0);
+        clock.advance(Duration::from_secs(1));
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(sent.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn advance_time_is_per_destination() {
+        let clock = Arc::new(ManualClock::new(Timestamp::from_host_time(1)));
+        clock.advance(Duration::from_millis(100));
+        let sent = Sent::default();
+        let scheduler = SendScheduler::with_clock(Recorder(sent.clone()), clock.clone());
+        let (early, late) = (Destination::new(101), Destination::new(102));
+        scheduler.set_advance_time(&early, Duration::from_millis(50));
+        scheduler.set_advance_time(&late, Duration::ZERO);
+
+        scheduler.send(&late, &PacketBuffer::new(at(&clock, 110), &[0xfa]));
+        scheduler.send(&early, &PacketBuffer::new(at(&clock, 150), &[0xfc]));
+        assert_eq!(wait_for(&sent, 1), 1);
+        assert_eq!(sent.lock().unwrap()[0], (101, at(&clock, 150), vec![0xfc]));
+        assert_eq!(scheduler.pending(), 1);
+
+        scheduler.set_advance_time(&late, Duration::from_millis(20));
+        assert_eq!(wait_for(&sent, 2), 2);
+        assert_eq!(sent.lock().unwrap()[1], (102, at(&clock, 110), vec![0xfa]));
+    }
+
+    #[test]
+    fn advance_time_is_cached() {
+        let scheduler = SendScheduler::new(Recorder(Sent::default()));
+        let destination = Destination::new(7);
+        let read_at =
+            |scheduler: &SendScheduler| scheduler.shared.advance_times.lock().unwrap()[&7].1;
+        assert_eq!(scheduler.shared.advance_time(&destination), Duration::ZERO);
+        let first_read = read_at(&scheduler);
+        scheduler.send(&destination, &PacketBuffer::new(Timestamp::Now, &[0xf8]));
+        assert_eq!(read_at(&scheduler), first_read);
+    }
+}
@@ -0,0 +1,406 @@
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::{self, Serialize, SerializeStruct, Serializer};
+
+use crate::device::Device;
+use crate::endpoints::destinations::Destination;
+use crate::endpoints::endpoint::Endpoint;
+use crate::endpoints::sources::Source;
+use crate::entity::Entity;
+use crate::events::{EventBuffer, EventList, Timestamp};
+use crate::object::{Object, ObjectType};
+use crate::packets::{PacketBuffer, PacketList};
+use crate::protocol::Protocol;
+
+/// The maximum number of bytes in a `MIDIPacket` (its length is a `UInt16`).
+const MAX_PACKET_DATA: usize = u16::MAX as usize;
+
+/// The maximum number of words in a `MIDIEventPacket`.
+const MAX_EVENT_PACKET_WORDS: usize = 64;
+
+/// Timestamps are serialized as the raw host time, where zero means "now".
+///
+impl Serialize for Timestamp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(self.host_time())
+    }
+}
+
+impl<'de> Deserialize<'de> for Timestamp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        u64::deserialize(deserializer).map(Timestamp::from_host_time)
+    }
+}
+
+#[derive(serde::Serialize)]
+struct PacketRef<'a> {
+    timestamp: Timestamp,
+    data: &'a [u8],
+}
+
+#[derive(serde::Deserialize)]
+struct PacketData {
+    timestamp: Timestamp,
+    data: Vec<u8>,
+}
+
+/// A packet list is serialized as a sequence of `{ timestamp, data }` packets.
+///
+impl Serialize for PacketList {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter().map(|packet| PacketRef {
+            timestamp: packet.timestamp(),
+            data: packet.data(),
+        }))
+    }
+}
+
+impl Serialize for PacketBuffer {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (**self).serialize(serializer)
+    }
+}
+
+/// Deserializing rebuilds the buffer packet by packet, rejecting packets that are too long
+/// or whose timestamps go backwards.
+///
+impl<'de> Deserialize<'de> for PacketBuffer {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let packets = Vec::<PacketData>::deserialize(deserializer)?;
+        let mut buffer = PacketBuffer::with_capacity(0);
+        for packet in packets {
+            if packet.data.len() > MAX_PACKET_DATA {
+                return Err(de::Error::invalid_length(
+                    packet.data.len(),
+                    &"at most 65535 bytes per packet",
+                ));
+            }
+            buffer
+                .try_push_data(packet.timestamp, &packet.data)
+                .map_err(de::Error::custom)?;
+        }
+        Ok(buffer)
+    }
+}
+
+struct EventPackets<'a>(&'a EventList);
+
+#[derive(serde::Serialize)]
+struct EventPacketRef<'a> {
+    timestamp: Timestamp,
+    words: &'a [u32],
+}
+
+impl<'a> Serialize for EventPackets<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.0.iter().map(|packet| EventPacketRef {
+            timestamp: packet.timestamp(),
+            words: packet.data(),
+        }))
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct EventPacketData {
+    timestamp: Timestamp,
+    words: Vec<u32>,
+}
+
+#[derive(serde::Deserialize)]
+struct EventBufferData {
+    protocol: Protocol,
+    packets: Vec<EventPacketData>,
+}
+
+/// An event list is serialized as its protocol and a sequence of `{ timestamp, words }` packets.
+///
+impl Serialize for EventList {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("EventList", 2)?;
+        state.serialize_field("protocol", &self.protocol())?;
+        state.serialize_field("packets", &EventPackets(self))?;
+        state.end()
+    }
+}
+
+impl Serialize for EventBuffer {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (**self).serialize(serializer)
+    }
+}
+
+/// Deserializing rebuilds the buffer packet by packet, rejecting packets that are too long
+/// or whose timestamps go backwards.
+///
+impl<'de> Deserialize<'de> for EventBuffer {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let data = EventBufferData::deserialize(deserializer)?;
+        let mut buffer = EventBuffer::new(data.protocol);
+        for packet in data.packets {
+            if packet.words.len() > MAX_EVENT_PACKET_WORDS {
+                return Err(de::Error::invalid_length(
+                    packet.words.len(),
+                    &"at most 64 words per packet",
+                ));
+            }
+            buffer
+                .try_push(packet.timestamp, &packet.words)
+                .map_err(de::Error::custom)?;
+        }
+        Ok(buffer)
+    }
+}
+
+/// Objects are serialized by their unique id, as object references are only
+/// meaningful within the process that obtained them.
+///
+impl Serialize for Object {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.unique_id() {
+            Some(unique_id) => serializer.serialize_u32(unique_id),
+            None => Err(ser::Error::custom("the MIDI object has no unique id")),
+        }
+    }
+}
+
+/// Deserializing looks the object up by its unique id, which fails if it doesn't exist
+/// (anymore) in this system.
+///
+impl<'de> Deserialize<'de> for Object {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_object(deserializer, |_| true)
+    }
+}
+
+fn deserialize_object<'de, D, F>(deserializer: D, accepts: F) -> Result<Object, D::Error>
+where
+    D: Deserializer<'de>,
+    F: Fn(ObjectType) -> bool,
+{
+    let unique_id = u32::deserialize(deserializer)?;
+    match Object::find_by_unique_id(unique_id) {
+        Some((object, object_type)) if accepts(object_type) => Ok(object),
+        Some((_, object_type)) => Err(de::Error::custom(format!(
+            "the MIDI object with unique id {} is of an unexpected type {:?}",
+            unique_id, object_type
+        ))),
+        None => Err(de::Error::custom(format!(
+            "no MIDI object found with unique id {}",
+            unique_id
+        ))),
+    }
+}
+
+/// The null object standing for the objects of deserialized notifications,
+/// which only carry their unique ids.
+pub(crate) fn null_object<T: From<Object>>() -> T {
+    T::from(Object(0))
+}
+
+impl Serialize for Device {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.object.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Device {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_object(deserializer, |object_type| {
+            matches!(object_type, ObjectType::Device | ObjectType::ExternalDevice)
+        })
+        .map(Device::from)
+    }
+}
+
+impl Serialize for Entity {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.object.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Entity {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_object(deserializer, |object_type| {
+            matches!(object_type, ObjectType::Entity | ObjectType::ExternalEntity)
+        })
+        .map(Entity::from)
+    }
+}
+
+impl Serialize for Endpoint {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.object.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Endpoint {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_object(deserializer, |object_type| {
+            matches!(
+                object_type,
+                ObjectType::Source
+                    | ObjectType::ExternalSource
+                    | ObjectType::Destination
+                    | ObjectType::ExternalDestination
+            )
+        })
+        .map(|object| Endpoint::new(object.0))
+    }
+}
+
+impl Serialize for Source {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.endpoint.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Source {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_object(deserializer, |object_type| {
+            matches!(object_type, ObjectType::Source | ObjectType::ExternalSource)
+        })
+        .map(Source::from)
+    }
+}
+
+impl Serialize for Destination {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.endpoint.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Destination {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_object(deserializer, |object_type| {
+            matches!(
+                object_type,
+                ObjectType::Destination | ObjectType::ExternalDestination
+            )
+        })
+        .map(Destination::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::events::{EventBuffer, Timestamp};
+    use crate::notifications::{AddedRemovedInfo, Notification};
+    use crate::object::{Object, ObjectType};
+    use crate::packets::PacketBuffer;
+    use crate::protocol::Protocol;
+
+    #[test]
+    fn timestamp_as_host_time() {
+        assert_eq!(serde_json::to_string(&Timestamp::Now).unwrap(), "0");
+        assert_eq!(
+            serde_json::from_str::<Timestamp>("1234").unwrap(),
+            Timestamp::from_host_time(1234)
+        );
+    }
+
+    #[test]
+    fn packet_buffer_round_trip() {
+        let mut buffer = PacketBuffer::new(Timestamp::from_host_time(10), &[0x90, 0x3c, 0x7f]);
+        buffer.push_data(Timestamp::from_host_time(20), &[0x80, 0x3c, 0x00]);
+
+        let json = serde_json::to_string(&buffer).unwrap();
+        assert_eq!(
+            json,
+            r#"[{"timestamp":10,"data":[144,60,127]},{"timestamp":20,"data":[128,60,0]}]"#
+        );
+
+        let decoded: PacketBuffer = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(
+            decoded
+                .iter()
+                .map(|packet| (packet.timestamp(), packet.data().to_vec()))
+                .collect::<Vec<_>>(),
+            buffer
+                .iter()
+                .map(|packet| (packet.timestamp(), packet.data().to_vec()))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn packet_buffer_rejects_unordered_packets() {
+        let json = r#"[{"timestamp":20,"data":[144,60,127]},{"timestamp":10,"data":[128,60,0]}]"#;
+        assert!(serde_json::from_str::<PacketBuffer>(json).is_err());
+    }
+
+    #[test]
+    fn event_buffer_round_trip() {
+        let buffer = EventBuffer::new(Protocol::Midi20)
+            .with_packet(Timestamp::from_host_time(10), &[0x40903c00, 0xffff0000]);
+
+        let json = serde_json::to_string(&buffer).unwrap();
+        assert_eq!(
+            json,
+            r#"{"protocol":"Midi20","packets":[{"timestamp":10,"words":[1083194368,4294901760]}]}"#
+        );
+
+        let decoded: EventBuffer = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.protocol(), Protocol::Midi20);
+        assert_eq!(decoded.len(), 1);
+        let packet = decoded.iter().next().unwrap();
+        assert_eq!(packet.timestamp(), Timestamp::from_host_time(10));
+        assert_eq!(packet.data(), &[0x40903c00, 0xffff0000]);
+    }
+
+    #[test]
+    fn event_buffer_rejects_oversized_packets() {
+        let words = vec![0u32; 65];
+        let json = format!(
+            r#"{{"protocol":"Midi10","packets":[{{"timestamp":0,"words":{:?}}}]}}"#,
+            words
+        );
+        assert!(serde_json::from_str::<EventBuffer>(&json).is_err());
+    }
+
+    #[test]
+    fn object_type_and_protocol() {
+        let json = serde_json::to_string(&ObjectType::ExternalSource).unwrap();
+        assert_eq!(json, r#""ExternalSource""#);
+        assert_eq!(
+            serde_json::from_str::<ObjectType>(&json).unwrap(),
+            ObjectType::ExternalSource
+        );
+        assert_eq!(
+            serde_json::from_str::<Protocol>(r#"{"Unknown":3}"#).unwrap(),
+            Protocol::Unknown(3)
+        );
+    }
+
+    #[test]
+    fn object_without_unique_id_is_not_serialized() {
+        assert!(serde_json::to_string(&Object(0)).is_err());
+    }
+
+    #[test]
+    fn notification_as_unique_ids() {
+        let notification = Notification::ObjectRemoved(AddedRemovedInfo {
+            parent: Object(1),
+            parent_type: ObjectType::Device,
+            parent_unique_id: Some(5),
+            child: Object(2),
+            child_type: ObjectType::Entity,
+            child_unique_id: None,
+        });
+        let json = serde_json::to_string(&notification).unwrap();
+        assert_eq!(
+            json,
+            r#"{"ObjectRemoved":{"parent_type":"Device","parent_unique_id":5,"child_type":"Entity","child_unique_id":null}}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<Notification>(&json).unwrap(),
+            Notification::ObjectRemoved(AddedRemovedInfo {
+                parent: Object(0),
+                parent_type: ObjectType::Device,
+                parent_unique_id: Some(5),
+                child: Object(0),
+                child_type: ObjectType::Entity,
+                child_unique_id: None,
+            })
+        );
+    }
+}
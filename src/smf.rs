@@ -0,0 +1,1431 @@
+use std::fmt;
+use std::time::Duration;
+
+use crate::events::{midi1_message_words, EventBuffer, Timestamp};
+use crate::host_time::{nanos_to_ticks, ticks_to_nanos, HostClock, SystemClock};
+use crate::packets::{PacketBuffer, PacketList};
+use crate::protocol::Protocol;
+use crate::timecode::SmpteTime;
+
+/// The tempo of a file until its first tempo meta event, 120 bpm.
+const DEFAULT_MICROS_PER_QUARTER: u32 = 500_000;
+
+/// The organisation of the tracks of a Standard MIDI File.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmfFormat {
+    /// Format 0, a single multi-channel track.
+    SingleTrack,
+    /// Format 1, simultaneous tracks sharing the tempo map.
+    MultiTrack,
+    /// Format 2, independent single-track patterns, each one with its own tempo map.
+    MultiSequence,
+}
+
+impl SmfFormat {
+    /// Get the format from its number in the file header.
+    ///
+    pub fn from_code(code: u16) -> Option<Self> {
+        match code {
+            0 => Some(Self::SingleTrack),
+            1 => Some(Self::MultiTrack),
+            2 => Some(Self::MultiSequence),
+            _ => None,
+        }
+    }
+
+    /// Get the number of the format in the file header.
+    ///
+    pub fn code(&self) -> u16 {
+        match self {
+            Self::SingleTrack => 0,
+            Self::MultiTrack => 1,
+            Self::MultiSequence => 2,
+        }
+    }
+}
+
+/// The meaning of the delta times of a Standard MIDI File.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmfDivision {
+    /// Ticks per quarter note, with the tempo given by the tempo meta events.
+    TicksPerQuarter(u16),
+    /// Ticks per frame of a time code, where 29 frames per second means 29.97 drop frame.
+    Smpte {
+        frames_per_second: u8,
+        ticks_per_frame: u8,
+    },
+}
+
+impl SmfDivision {
+    /// Get the division from the word in the file header.
+    ///
+    pub fn from_word(word: u16) -> Option<Self> {
+        if word & 0x8000 == 0 {
+            Some(Self::TicksPerQuarter(word)).filter(|_| word != 0)
+        } else {
+            let frames_per_second = ((word >> 8) as u8 as i8).unsigned_abs();
+            let ticks_per_frame = word as u8;
+            match frames_per_second {
+                24 | 25 | 29 | 30 if ticks_per_frame != 0 => Some(Self::Smpte {
+                    frames_per_second,
+                    ticks_per_frame,
+                }),
+                _ => None,
+            }
+        }
+    }
+
+    /// Get the word of the division in the file header.
+    ///
+    pub fn word(&self) -> u16 {
+        match *self {
+            Self::TicksPerQuarter(ticks) => ticks & 0x7FFF,
+            Self::Smpte {
+                frames_per_second,
+                ticks_per_frame,
+            } => ((-(frames_per_second as i8)) as u8 as u16) << 8 | ticks_per_frame as u16,
+        }
+    }
+}
+
+/// A meta event of a Standard MIDI File.
+///
+/// Texts are decoded as UTF-8, replacing invalid sequences.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum SmfMeta {
+    SequenceNumber(u16),
+    Text(String),
+    Copyright(String),
+    TrackName(String),
+    InstrumentName(String),
+    Lyric(String),
+    Marker(String),
+    CuePoint(String),
+    ChannelPrefix(u8),
+    Port(u8),
+    EndOfTrack,
+    /// The tempo in microseconds per quarter note.
+    Tempo(u32),
+    SmpteOffset(SmpteTime),
+    /// A time signature, where the denominator is given as a power of two.
+    TimeSignature {
+        numerator: u8,
+        denominator_power: u8,
+        clocks_per_click: u8,
+        thirty_seconds_per_quarter: u8,
+    },
+    /// A key signature, with the number of sharps (or flats when negative).
+    KeySignature {
+        sharps: i8,
+        minor: bool,
+    },
+    SequencerSpecific(Vec<u8>),
+    /// Any other meta event, with its type and data.
+    Other {
+        kind: u8,
+        data: Vec<u8>,
+    },
+}
+
+impl SmfMeta {
+    /// Decode a meta event from its type and data.
+    ///
+    pub fn decode(kind: u8, data: &[u8]) -> Self {
+        let text = || String::from_utf8_lossy(data).into_owned();
+        match (kind, data) {
+            (0x00, [high, low]) => Self::SequenceNumber(u16::from_be_bytes([*high, *low])),
+            (0x01, _) => Self::Text(text()),
+            (0x02, _) => Self::Copyright(text()),
+            (0x03, _) => Self::TrackName(text()),
+            (0x04, _) => Self::InstrumentName(text()),
+            (0x05, _) => Self::Lyric(text()),
+            (0x06, _) => Self::Marker(text()),
+            (0x07, _) => Self::CuePoint(text()),
+            (0x20, [channel]) => Self::ChannelPrefix(*channel),
+            (0x21, [port]) => Self::Port(*port),
+            (0x2F, []) => Self::EndOfTrack,
+            (0x51, [a, b, c]) => Self::Tempo(u32::from_be_bytes([0, *a, *b, *c])),
+            (0x54, [hr, mn, sc, fr, ff]) => {
+                Self::SmpteOffset(SmpteTime::from_standard_bytes(*hr, *mn, *sc, *fr, *ff))
+            }
+            (0x58, [nn, dd, cc, bb]) => Self::TimeSignature {
+                numerator: *nn,
+                denominator_power: *dd,
+                clocks_per_click: *cc,
+                thirty_seconds_per_quarter: *bb,
+            },
+            (0x59, [sf, mi]) => Self::KeySignature {
+                sharps: *sf as i8,
+                minor: *mi == 1,
+            },
+            (0x7F, _) => Self::SequencerSpecific(data.to_vec()),
+            _ => Self::Other {
+                kind,
+                data: data.to_vec(),
+            },
+        }
+    }
+
+    /// Encode a meta event into its type and data.
+    ///
+    pub fn encode(&self) -> (u8, Vec<u8>) {
+        match self {
+            Self::SequenceNumber(number) => (0x00, number.to_be_bytes().to_vec()),
+            Self::Text(text) => (0x01, text.as_bytes().to_vec()),
+            Self::Copyright(text) => (0x02, text.as_bytes().to_vec()),
+            Self::TrackName(text) => (0x03, text.as_bytes().to_vec()),
+            Self::InstrumentName(text) => (0x04, text.as_bytes().to_vec()),
+            Self::Lyric(text) => (0x05, text.as_bytes().to_vec()),
+            Self::Marker(text) => (0x06, text.as_bytes().to_vec()),
+            Self::CuePoint(text) => (0x07, text.as_bytes().to_vec()),
+            Self::ChannelPrefix(channel) => (0x20, vec![*channel]),
+            Self::Port(port) => (0x21, vec![*port]),
+            Self::EndOfTrack => (0x2F, vec![]),
+            Self::Tempo(micros_per_quarter) => (
+                0x51,
+                micros_per_quarter.min(&0xFFFFFF).to_be_bytes()[1..].to_vec(),
+            ),
+            Self::SmpteOffset(time) => (0x54, time.standard_bytes().to_vec()),
+            Self::TimeSignature {
+                numerator,
+                denominator_power,
+                clocks_per_click,
+                thirty_seconds_per_quarter,
+            } => (
+                0x58,
+                vec![
+                    *numerator,
+                    *denominator_power,
+                    *clocks_per_click,
+                    *thirty_seconds_per_quarter,
+                ],
+            ),
+            Self::KeySignature { sharps, minor } => (0x59, vec![*sharps as u8, *minor as u8]),
+            Self::SequencerSpecific(data) => (0x7F, data.clone()),
+            Self::Other { kind, data } => (*kind, data.clone()),
+        }
+    }
+}
+
+/// The contents of an event of a Standard MIDI File.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum SmfEventKind {
+    /// A channel message including its status byte, also when the file used running status.
+    Midi(Vec<u8>),
+    /// A System Exclusive message starting with `F0`. When it does not end with `F7`,
+    /// the rest of the message follows in [SmfEventKind::Escape] events.
+    SysEx(Vec<u8>),
+    /// Bytes to be sent as they are, like the continuation of a SysEx message or real time messages.
+    Escape(Vec<u8>),
+    Meta(SmfMeta),
+}
+
+/// An event of a track, at an absolute position in ticks from the start of the track.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct SmfEvent {
+    pub ticks: u64,
+    pub kind: SmfEventKind,
+}
+
+impl SmfEvent {
+    /// Get the bytes to send for the event, or `None` for meta events.
+    ///
+    pub fn midi_data(&self) -> Option<&[u8]> {
+        match &self.kind {
+            SmfEventKind::Midi(data) | SmfEventKind::SysEx(data) | SmfEventKind::Escape(data) => {
+                Some(data)
+            }
+            SmfEventKind::Meta(_) => None,
+        }
+    }
+}
+
+/// A track of a Standard MIDI File, with its events in order.
+///
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SmfTrack {
+    pub events: Vec<SmfEvent>,
+}
+
+impl SmfTrack {
+    /// Get the name of the track from its first track name meta event.
+    ///
+    pub fn name(&self) -> Option<&str> {
+        self.events.iter().find_map(|event| match &event.kind {
+            SmfEventKind::Meta(SmfMeta::TrackName(name)) => Some(name.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Get the position in ticks of the last event.
+    ///
+    pub fn end_ticks(&self) -> u64 {
+        self.events.last().map_or(0, |event| event.ticks)
+    }
+
+    fn tempos(&self) -> impl Iterator<Item = (u64, u32)> + '_ {
+        self.events.iter().filter_map(|event| match event.kind {
+            SmfEventKind::Meta(SmfMeta::Tempo(micros_per_quarter)) => {
+                Some((event.ticks, micros_per_quarter))
+            }
+            _ => None,
+        })
+    }
+}
+
+/// An event placed in time by the tempo map of its file.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SmfTimedEvent<'a> {
+    /// The time from the start of the file.
+    pub time: Duration,
+    /// The index of the track of the event.
+    pub track: usize,
+    pub event: &'a SmfEvent,
+}
+
+/// A Standard MIDI File.
+///
+/// Once parsed, the events of all the tracks can be rendered into timestamped buffers
+/// ready to be sent, in chunks of a given duration:
+///
+/// ```
+/// use coremidi::{HostClock, Smf, SystemClock};
+/// use std::time::Duration;
+/// let data = [
+///     b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 0, 0, 1, 0, 96,
+///     b'M', b'T', b'r', b'k', 0, 0, 0, 18,
+///     0x00, 0x90, 0x3c, 0x64, // Note On for Middle C
+///     0x60, 0x3c, 0x00, // a quarter note later, Note Off with running status
+///     0x00, 0xff, 0x51, 0x03, 0x0f, 0x42, 0x40, // 60 bpm
+///     0x00, 0xff, 0x2f, 0x00, // End of track
+/// ];
+/// let smf = Smf::parse(&data).unwrap();
+/// assert_eq!(smf.tracks[0].events.len(), 4);
+/// assert_eq!(smf.duration(), Duration::from_millis(500));
+///
+/// let start = SystemClock.after(Duration::from_millis(100));
+/// let buffers = smf.to_packet_buffers(start, Duration::from_millis(100));
+/// assert_eq!(buffers.len(), 2);
+/// assert_eq!(buffers[1].iter().next().unwrap().data(), &[0x90, 0x3c, 0x00]);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Smf {
+    pub format: SmfFormat,
+    pub division: SmfDivision,
+    pub tracks: Vec<SmfTrack>,
+}
+
+impl Smf {
+    /// Parse the contents of a `.mid` file.
+    ///
+    /// Chunks other than tracks are skipped, and running status is also accepted
+    /// after SysEx and meta events.
+    ///
+    pub fn parse(data: &[u8]) -> Result<Self, SmfError> {
+        let mut reader = Reader::new(data);
+        if reader.bytes(4)? != b"MThd" {
+            return Err(SmfError::InvalidHeader);
+        }
+        let length = reader.u32()? as usize;
+        let header = reader.bytes(length)?;
+        let (format, track_count, division) = match header {
+            [f0, f1, t0, t1, d0, d1, ..] => (
+                u16::from_be_bytes([*f0, *f1]),
+                u16::from_be_bytes([*t0, *t1]),
+                u16::from_be_bytes([*d0, *d1]),
+            ),
+            _ => return Err(SmfError::InvalidHeader),
+        };
+        let format = SmfFormat::from_code(format).ok_or(SmfError::InvalidHeader)?;
+        let division = SmfDivision::from_word(division).ok_or(SmfError::InvalidHeader)?;
+
+        let mut tracks = Vec::with_capacity(track_count as usize);
+        while tracks.len() < track_count as usize {
+            let kind = reader.bytes(4)?;
+            let length = reader.u32()? as usize;
+            let chunk = reader.bytes(length)?;
+            if kind == b"MTrk" {
+                tracks.push(parse_track(tracks.len(), chunk)?);
+            }
+        }
+
+        Ok(Self {
+            format,
+            division,
+            tracks,
+        })
+    }
+
+    /// Get the tempo map of the file, or of its first pattern for format 2 files.
+    ///
+    pub fn tempo_map(&self) -> SmfTempoMap {
+        match self.format {
+            SmfFormat::MultiSequence => self.track_tempo_map(0),
+            _ => SmfTempoMap::new(
+                self.division,
+                self.tracks.iter().flat_map(|track| track.tempos()),
+            ),
+        }
+    }
+
+    /// Get the tempo map that applies to a track, which is its own one for format 2 files.
+    ///
+    pub fn track_tempo_map(&self, track: usize) -> SmfTempoMap {
+        match (self.format, self.tracks.get(track)) {
+            (SmfFormat::MultiSequence, Some(track)) => {
+                SmfTempoMap::new(self.division, track.tempos())
+            }
+            (SmfFormat::MultiSequence, None) => SmfTempoMap::new(self.division, None),
+            _ => self.tempo_map(),
+        }
+    }
+
+    /// Get the events of all the tracks in time order.
+    ///
+    /// The tracks of format 0 and 1 files are merged, with the events at the same time
+    /// ordered by track. The patterns of format 2 files are played one after the other.
+    ///
+    pub fn timeline(&self) -> Vec<SmfTimedEvent<'_>> {
+        let timed_events = |offset: Duration, index: usize, tempo_map: &SmfTempoMap| {
+            let tempo_map = tempo_map.clone();
+            self.tracks[index]
+                .events
+                .iter()
+                .map(move |event| SmfTimedEvent {
+                    time: offset + tempo_map.time_at(event.ticks),
+                    track: index,
+                    event,
+                })
+        };
+        match self.format {
+            SmfFormat::MultiSequence => {
+                let mut timeline = Vec::new();
+                let mut offset = Duration::ZERO;
+                for (index, track) in self.tracks.iter().enumerate() {
+                    let tempo_map = self.track_tempo_map(index);
+                    timeline.extend(timed_events(offset, index, &tempo_map));
+                    offset += tempo_map.time_at(track.end_ticks());
+                }
+                timeline
+            }
+            _ => {
+                let tempo_map = self.tempo_map();
+                let mut timeline: Vec<SmfTimedEvent> = (0..self.tracks.len())
+                    .flat_map(|index| timed_events(Duration::ZERO, index, &tempo_map))
+                    .collect();
+                timeline.sort_by_key(|timed_event| timed_event.event.ticks);
+                timeline
+            }
+        }
+    }
+
+    /// Get the time of the last event.
+    ///
+    pub fn duration(&self) -> Duration {
+        self.timeline()
+            .last()
+            .map_or(Duration::ZERO, |timed_event| timed_event.time)
+    }
+
+    /// Render the MIDI events into packet buffers, each one holding the events of a chunk
+    /// of time, timestamped relative to a start time. Chunks without events are skipped,
+    /// and a zero chunk duration renders everything into a single buffer.
+    /// A [Timestamp::Now] start is taken as the current host time.
+    ///
+    pub fn to_packet_buffers(&self, start: Timestamp, chunk: Duration) -> Vec<PacketBuffer> {
+        let start = SystemClock.resolve(start);
+        let mut buffers: Vec<(u128, PacketBuffer)> = Vec::new();
+        for timed_event in self.timeline() {
+            if let Some(data) = timed_event.event.midi_data() {
+                let index = chunk_index(timed_event.time, chunk);
+                if buffers.last().map(|(last, _)| *last) != Some(index) {
+                    buffers.push((index, PacketBuffer::with_capacity(0)));
+                }
+                let timestamp = timestamp_at(start, timed_event.time);
+                buffers.last_mut().unwrap().1.push_data(timestamp, data);
+            }
+        }
+        buffers.into_iter().map(|(_, buffer)| buffer).collect()
+    }
+
+    /// Render the MIDI events into MIDI 1.0 Universal MIDI Packets for a group, like
+    /// [Smf::to_packet_buffers]. SysEx messages split into several events are joined at the
+    /// time of their first part, and escaped data not starting with a status byte is skipped.
+    ///
+    pub fn to_event_buffers(
+        &self,
+        start: Timestamp,
+        chunk: Duration,
+        group: u8,
+    ) -> Vec<EventBuffer> {
+        let start = SystemClock.resolve(start);
+        let mut buffers: Vec<(u128, EventBuffer)> = Vec::new();
+        let mut sysex: Option<(Duration, Vec<u8>)> = None;
+        for timed_event in self.timeline() {
+            let (time, message) = match (&timed_event.event.kind, sysex.take()) {
+                (SmfEventKind::Meta(_), pending) => {
+                    sysex = pending;
+                    continue;
+                }
+                (SmfEventKind::Escape(data), Some((time, mut message))) => {
+                    message.extend_from_slice(data);
+                    (time, message)
+                }
+                (SmfEventKind::Escape(data), None) if data.first() < Some(&0x80) => continue,
+                (SmfEventKind::Midi(data), _)
+                | (SmfEventKind::SysEx(data), _)
+                | (SmfEventKind::Escape(data), None) => (timed_event.time, data.clone()),
+            };
+            if message.first() == Some(&0xF0) && message.last() != Some(&0xF7) {
+                sysex = Some((time, message));
+                continue;
+            }
+            let index = chunk_index(time, chunk);
+            if buffers.last().map(|(last, _)| *last) != Some(index) {
+                buffers.push((index, EventBuffer::new(Protocol::Midi10)));
+            }
+            let timestamp = timestamp_at(start, time);
+            let buffer = &mut buffers.last_mut().unwrap().1;
+            let words = midi1_message_words(group, &message);
+            if message.first() == Some(&0xF0) {
+                for packet in words.chunks(2) {
+                    buffer.push(timestamp, packet);
+                }
+            } else {
+                buffer.push(timestamp, &words);
+            }
+        }
+        buffers.into_iter().map(|(_, buffer)| buffer).collect()
+    }
+
+    /// Encode the file into the contents of a `.mid` file, optionally using running status.
+    ///
+    /// The events of each track must be in order. Every track ends with a single end of
+    /// track event, at the position of the last event or of its existing end of track.
+    ///
+    pub fn encode(&self, running_status: bool) -> Vec<u8> {
+        let mut data = b"MThd".to_vec();
+        data.extend_from_slice(&6u32.to_be_bytes());
+        data.extend_from_slice(&self.format.code().to_be_bytes());
+        data.extend_from_slice(&(self.tracks.len() as u16).to_be_bytes());
+        data.extend_from_slice(&self.division.word().to_be_bytes());
+        for track in &self.tracks {
+            let track = encode_track(track, running_status);
+            data.extend_from_slice(b"MTrk");
+            data.extend_from_slice(&(track.len() as u32).to_be_bytes());
+            data.extend(track);
+        }
+        data
+    }
+}
+
+/// Records timestamped MIDI 1.0 messages into the tracks of a Standard MIDI File,
+/// converting their host timestamps into ticks with a tempo map.
+///
+/// It can be fed from the callback of an input port and saved once the take is over:
+///
+/// ```rust,no_run
+/// use coremidi::{Client, HostClock, SmfDivision, SmfFormat, SmfTempoMap, SmfWriter, Source, SystemClock};
+/// use std::sync::{Arc, Mutex};
+/// let tempo_map = SmfTempoMap::new(SmfDivision::TicksPerQuarter(480), vec![(0, 500_000)]);
+/// let writer = Arc::new(Mutex::new(SmfWriter::new(tempo_map, SystemClock.now())));
+/// let recorder = writer.clone();
+/// let client = Client::new("example-client").unwrap();
+/// let input_port = client
+///     .input_port("example-port", move |packet_list| {
+///         recorder.lock().unwrap().push_packet_list(0, packet_list);
+///     })
+///     .unwrap();
+/// input_port.connect_source(&Source::from_index(0).unwrap()).unwrap();
+/// // ... play for a while ...
+/// let data = writer.lock().unwrap().encode(SmfFormat::SingleTrack, true);
+/// std::fs::write("take.mid", data).unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct SmfWriter {
+    tempo_map: SmfTempoMap,
+    start: Timestamp,
+    tracks: Vec<(SmfTrack, MessageSplitter)>,
+}
+
+impl SmfWriter {
+    /// Create a writer whose tick zero is at a start time,
+    /// where [Timestamp::Now] is taken as the current host time.
+    ///
+    pub fn new(tempo_map: SmfTempoMap, start: Timestamp) -> Self {
+        Self {
+            tempo_map,
+            start: SystemClock.resolve(start),
+            tracks: Vec::new(),
+        }
+    }
+
+    /// Get the tempo map used to convert timestamps into ticks.
+    ///
+    pub fn tempo_map(&self) -> &SmfTempoMap {
+        &self.tempo_map
+    }
+
+    /// Get the position in ticks of a timestamp, where [Timestamp::Now] and timestamps
+    /// before the start are at tick zero.
+    ///
+    pub fn ticks_at(&self, timestamp: Timestamp) -> u64 {
+        let ticks = timestamp.host_time().saturating_sub(self.start.host_time());
+        let elapsed = Duration::from_nanos(ticks_to_nanos(ticks));
+        self.tempo_map.ticks_at(elapsed)
+    }
+
+    /// Add a complete MIDI 1.0 message to a track.
+    ///
+    /// Channel messages are stored as they are, SysEx messages must include the `F0` and `F7`
+    /// framing bytes, and system common and real time messages are stored as escaped data.
+    ///
+    pub fn push_message(&mut self, track: usize, timestamp: Timestamp, message: &[u8]) {
+        let ticks = self.ticks_at(timestamp);
+        let kind = match message.first() {
+            Some(0x80..=0xEF) => SmfEventKind::Midi(message.to_vec()),
+            Some(0xF0) => SmfEventKind::SysEx(message.to_vec()),
+            Some(0xF1..=0xFF) => SmfEventKind::Escape(message.to_vec()),
+            _ => return,
+        };
+        self.track(track).0.events.push(SmfEvent { ticks, kind });
+    }
+
+    /// Add the messages of a list of packets to a track, using their timestamps.
+    ///
+    /// Running status is handled, SysEx messages split across packets are placed at the
+    /// timestamp of their last packet, and data bytes without a status are dropped.
+    ///
+    pub fn push_packet_list(&mut self, track: usize, packet_list: &PacketList) {
+        for packet in packet_list.iter() {
+            let timestamp = packet.timestamp();
+            let mut messages = Vec::new();
+            self.track(track)
+                .1
+                .push(packet.data(), |message| messages.push(message.to_vec()));
+            for message in messages {
+                self.push_message(track, timestamp, &message);
+            }
+        }
+    }
+
+    /// Build a file with the recorded tracks and the tempo changes of the tempo map.
+    ///
+    /// Format 0 merges every track into one, format 1 adds a first track with the tempo
+    /// changes, and format 2 adds the tempo changes to every track.
+    ///
+    pub fn to_smf(&self, format: SmfFormat) -> Smf {
+        let tempos: Vec<SmfEvent> = self
+            .tempo_map
+            .changes()
+            .iter()
+            .map(|change| SmfEvent {
+                ticks: change.ticks,
+                kind: SmfEventKind::Meta(SmfMeta::Tempo(change.micros_per_quarter)),
+            })
+            .collect();
+        let sorted = |mut events: Vec<SmfEvent>| {
+            events.sort_by_key(|event| event.ticks);
+            SmfTrack { events }
+        };
+        let recorded = self.tracks.iter().map(|(track, _)| track.events.clone());
+        let tracks = match format {
+            SmfFormat::SingleTrack => {
+                vec![sorted(
+                    tempos.iter().cloned().chain(recorded.flatten()).collect(),
+                )]
+            }
+            SmfFormat::MultiTrack => std::iter::once(sorted(tempos))
+                .chain(recorded.map(sorted))
+                .collect(),
+            SmfFormat::MultiSequence => recorded
+                .map(|events| sorted(tempos.iter().cloned().chain(events).collect()))
+                .collect(),
+        };
+        Smf {
+            format,
+            division: self.tempo_map.division(),
+            tracks,
+        }
+    }
+
+    /// Encode the recorded tracks into the contents of a `.mid` file, see [SmfWriter::to_smf].
+    ///
+    pub fn encode(&self, format: SmfFormat, running_status: bool) -> Vec<u8> {
+        self.to_smf(format).encode(running_status)
+    }
+
+    fn track(&mut self, track: usize) -> &mut (SmfTrack, MessageSplitter) {
+        if self.tracks.len() <= track {
+            self.tracks.resize_with(track + 1, Default::default);
+        }
+        &mut self.tracks[track]
+    }
+}
+
+/// The times of the ticks of a Standard MIDI File, following its tempo changes.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct SmfTempoMap {
+    division: SmfDivision,
+    changes: Vec<SmfTempoChange>,
+}
+
+/// A change of tempo in a [SmfTempoMap].
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SmfTempoChange {
+    pub ticks: u64,
+    pub micros_per_quarter: u32,
+    /// The time of the change from the start of the file.
+    pub time: Duration,
+}
+
+impl SmfTempoMap {
+    /// Create a tempo map from the position in ticks and the microseconds per quarter note
+    /// of its changes. Until the first change the tempo is 120 bpm, tempos of zero are taken
+    /// as one microsecond per quarter note, and tempo changes are ignored for SMPTE divisions.
+    ///
+    pub fn new<I>(division: SmfDivision, tempos: I) -> Self
+    where
+        I: IntoIterator<Item = (u64, u32)>,
+    {
+        let mut tempos: Vec<(u64, u32)> = match division {
+            SmfDivision::TicksPerQuarter(_) => tempos.into_iter().collect(),
+            SmfDivision::Smpte { .. } => Vec::new(),
+        };
+        tempos.sort_by_key(|(ticks, _)| *ticks);
+
+        let mut tempo_map = Self {
+            division,
+            changes: vec![SmfTempoChange {
+                ticks: 0,
+                micros_per_quarter: DEFAULT_MICROS_PER_QUARTER,
+                time: Duration::ZERO,
+            }],
+        };
+        for (ticks, micros_per_quarter) in tempos {
+            let micros_per_quarter = micros_per_quarter.max(1);
+            let time = tempo_map.time_at(ticks);
+            let last = tempo_map.changes.last_mut().unwrap();
+            if last.ticks == ticks {
+                last.micros_per_quarter = micros_per_quarter;
+            } else {
+                tempo_map.changes.push(SmfTempoChange {
+                    ticks,
+                    micros_per_quarter,
+                    time,
+                });
+            }
+        }
+        tempo_map
+    }
+
+    /// Get the division of the ticks.
+    ///
+    pub fn division(&self) -> SmfDivision {
+        self.division
+    }
+
+    /// Get the tempo changes, starting with the tempo at the beginning.
+    ///
+    pub fn changes(&self) -> &[SmfTempoChange] {
+        &self.changes
+    }
+
+    /// Get the tempo in microseconds per quarter note at a position in ticks.
+    ///
+    pub fn micros_per_quarter_at(&self, ticks: u64) -> u32 {
+        self.change_at_ticks(ticks).micros_per_quarter
+    }
+
+    /// Get the time of a position in ticks.
+    ///
+    pub fn time_at(&self, ticks: u64) -> Duration {
+        let change = self.change_at_ticks(ticks);
+        let (numer, denom) = self.nanos_per_tick(change);
+        let nanos = (ticks - change.ticks) as u128 * numer / denom;
+        change.time + Duration::from_nanos(nanos as u64)
+    }
+
+    /// Get the position in ticks at a time, rounded to the nearest tick.
+    ///
+    pub fn ticks_at(&self, time: Duration) -> u64 {
+        let change = self
+            .changes
+            .iter()
+            .rev()
+            .find(|change| change.time <= time)
+            .unwrap_or(&self.changes[0]);
+        let (numer, denom) = self.nanos_per_tick(change);
+        let nanos = (time - change.time).as_nanos();
+        change.ticks + ((nanos * denom + numer / 2) / numer) as u64
+    }
+
+    fn change_at_ticks(&self, ticks: u64) -> &SmfTempoChange {
+        self.changes
+            .iter()
+            .rev()
+            .find(|change| change.ticks <= ticks)
+            .unwrap_or(&self.changes[0])
+    }
+
+    /// The duration of a tick in nanoseconds, as a fraction.
+    fn nanos_per_tick(&self, change: &SmfTempoChange) -> (u128, u128) {
+        match self.division {
+            SmfDivision::TicksPerQuarter(ticks) => (
+                change.micros_per_quarter as u128 * 1000,
+                ticks.max(1) as u128,
+            ),
+            SmfDivision::Smpte {
+                frames_per_second: 29,
+                ticks_per_frame,
+            } => (1_001_000_000_000, 30_000 * ticks_per_frame as u128),
+            SmfDivision::Smpte {
+                frames_per_second,
+                ticks_per_frame,
+            } => (
+                1_000_000_000,
+                frames_per_second as u128 * ticks_per_frame as u128,
+            ),
+        }
+    }
+}
+
+/// The errors that can happen parsing a Standard MIDI File.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmfError {
+    /// The file does not start with a valid `MThd` header.
+    InvalidHeader,
+    /// The file ended in the middle of a chunk or an event.
+    UnexpectedEnd,
+    /// A variable-length quantity is longer than four bytes.
+    InvalidLength,
+    /// A track contains a status byte that is not allowed in a file.
+    InvalidStatus { track: usize, status: u8 },
+    /// A track contains data bytes without any previous status byte.
+    MissingStatus { track: usize },
+}
+
+impl fmt::Display for SmfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidHeader => write!(f, "invalid MIDI file header"),
+            Self::UnexpectedEnd => write!(f, "unexpected end of MIDI file"),
+            Self::InvalidLength => write!(f, "invalid variable-length quantity"),
+            Self::InvalidStatus { track, status } => {
+                write!(f, "track {}: invalid status byte {:02x}", track, status)
+            }
+            Self::MissingStatus { track } => write!(f, "track {}: missing status byte", track),
+        }
+    }
+}
+
+impl std::error::Error for SmfError {}
+
+fn parse_track(index: usize, data: &[u8]) -> Result<SmfTrack, SmfError> {
+    let mut reader = Reader::new(data);
+    let mut events = Vec::new();
+    let mut ticks = 0u64;
+    let mut running_status = None;
+    while !reader.is_empty() {
+        ticks += reader.vlq()? as u64;
+        let kind = match reader.peek()? {
+            0xFF => {
+                reader.u8()?;
+                let kind = reader.u8()?;
+                let length = reader.vlq()? as usize;
+                SmfEventKind::Meta(SmfMeta::decode(kind, reader.bytes(length)?))
+            }
+            0xF0 => {
+                reader.u8()?;
+                let length = reader.vlq()? as usize;
+                let mut message = vec![0xF0];
+                message.extend_from_slice(reader.bytes(length)?);
+                SmfEventKind::SysEx(message)
+            }
+            0xF7 => {
+                reader.u8()?;
+                let length = reader.vlq()? as usize;
+                SmfEventKind::Escape(reader.bytes(length)?.to_vec())
+            }
+            status @ 0xF1..=0xFE => {
+                return Err(SmfError::InvalidStatus {
+                    track: index,
+                    status,
+                })
+            }
+            status @ 0x80..=0xEF => {
+                reader.u8()?;
+                running_status = Some(status);
+                SmfEventKind::Midi(channel_message(status, &mut reader)?)
+            }
+            _ => {
+                let status = running_status.ok_or(SmfError::MissingStatus { track: index })?;
+                SmfEventKind::Midi(channel_message(status, &mut reader)?)
+            }
+        };
+        let end_of_track = kind == SmfEventKind::Meta(SmfMeta::EndOfTrack);
+        events.push(SmfEvent { ticks, kind });
+        if end_of_track {
+            break;
+        }
+    }
+    Ok(SmfTrack { events })
+}
+
+fn channel_message(status: u8, reader: &mut Reader) -> Result<Vec<u8>, SmfError> {
+    let length = match status & 0xF0 {
+        0xC0 | 0xD0 => 1,
+        _ => 2,
+    };
+    let mut message = vec![status];
+    message.extend_from_slice(reader.bytes(length)?);
+    Ok(message)
+}
+
+fn encode_track(track: &SmfTrack, running_status: bool) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut ticks = 0;
+    let mut end = 0;
+    let mut status = None;
+    for event in &track.events {
+        end = end.max(event.ticks);
+        if event.kind == SmfEventKind::Meta(SmfMeta::EndOfTrack) {
+            continue;
+        }
+        write_vlq(&mut data, event.ticks.saturating_sub(ticks));
+        ticks = ticks.max(event.ticks);
+        match &event.kind {
+            SmfEventKind::Midi(message) => {
+                let skip = running_status && status == message.first().copied();
+                data.extend_from_slice(if skip { &message[1..] } else { message });
+                status = message.first().copied();
+                continue;
+            }
+            SmfEventKind::SysEx(message) => {
+                let message = message.strip_prefix(&[0xF0]).unwrap_or(message);
+                data.push(0xF0);
+                write_vlq(&mut data, message.len() as u64);
+                data.extend_from_slice(message);
+            }
+            SmfEventKind::Escape(bytes) => {
+                data.push(0xF7);
+                write_vlq(&mut data, bytes.len() as u64);
+                data.extend_from_slice(bytes);
+            }
+            SmfEventKind::Meta(meta) => {
+                let (kind, bytes) = meta.encode();
+                data.extend_from_slice(&[0xFF, kind]);
+                write_vlq(&mut data, bytes.len() as u64);
+                data.extend_from_slice(&bytes);
+            }
+        }
+        status = None;
+    }
+    write_vlq(&mut data, end - ticks);
+    data.extend_from_slice(&[0xFF, 0x2F, 0x00]);
+    data
+}
+
+fn write_vlq(data: &mut Vec<u8>, value: u64) {
+    let value = value.min(0x0FFF_FFFF) as u32;
+    let mut shift = 21;
+    while shift > 0 && value >> shift == 0 {
+        shift -= 7;
+    }
+    while shift > 0 {
+        data.push(0x80 | (value >> shift) as u8 & 0x7F);
+        shift -= 7;
+    }
+    data.push(value as u8 & 0x7F);
+}
+
+/// Splits a MIDI 1.0 byte stream into complete messages, keeping the running status
+/// and the SysEx message being received between calls.
+#[derive(Debug, Clone, Default)]
+struct MessageSplitter {
+    message: Vec<u8>,
+    running_status: Option<u8>,
+    sysex: bool,
+}
+
+impl MessageSplitter {
+    fn push<F: FnMut(&[u8])>(&mut self, data: &[u8], mut on_message: F) {
+        for &byte in data {
+            match byte {
+                0xF8..=0xFF => on_message(&[byte]),
+                0xF0 => {
+                    self.sysex = true;
+                    self.running_status = None;
+                    self.message.clear();
+                    self.message.push(byte);
+                }
+                0xF7 => {
+                    if self.sysex {
+                        self.message.push(byte);
+                        on_message(&self.message);
+                    }
+                    self.sysex = false;
+                    self.message.clear();
+                }
+                0x80..=0xF6 => {
+                    self.sysex = false;
+                    self.running_status = Some(byte).filter(|status| *status < 0xF0);
+                    self.message.clear();
+                    self.message.push(byte);
+                    self.complete(&mut on_message);
+                }
+                _ if self.sysex => self.message.push(byte),
+                _ => {
+                    if self.message.is_empty() {
+                        match self.running_status {
+                            Some(status) => self.message.push(status),
+                            None => continue,
+                        }
+                    }
+                    self.message.push(byte);
+                    self.complete(&mut on_message);
+                }
+            }
+        }
+    }
+
+    fn complete<F: FnMut(&[u8])>(&mut self, on_message: &mut F) {
+        let length = match self.message[0] {
+            0x80..=0xBF | 0xE0..=0xEF | 0xF2 => 3,
+            0xC0..=0xDF | 0xF1 | 0xF3 => 2,
+            _ => 1,
+        };
+        if self.message.len() >= length {
+            on_message(&self.message);
+            self.message.clear();
+        }
+    }
+}
+
+fn chunk_index(time: Duration, chunk: Duration) -> u128 {
+    if chunk.is_zero() {
+        0
+    } else {
+        time.as_nanos() / chunk.as_nanos()
+    }
+}
+
+fn timestamp_at(start: Timestamp, time: Duration) -> Timestamp {
+    Timestamp::from_host_time(start.host_time() + nanos_to_ticks(time.as_nanos() as u64))
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn peek(&self) -> Result<u8, SmfError> {
+        self.data.first().copied().ok_or(SmfError::UnexpectedEnd)
+    }
+
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8], SmfError> {
+        if length > self.data.len() {
+            return Err(SmfError::UnexpectedEnd);
+        }
+        let (bytes, rest) = self.data.split_at(length);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, SmfError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, SmfError> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn vlq(&mut self) -> Result<u32, SmfError> {
+        let mut value = 0u32;
+        for _ in 0..4 {
+            let byte = self.u8()?;
+            value = value << 7 | (byte & 0x7F) as u32;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(SmfError::InvalidLength)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::timecode::FrameRate;
+
+    fn chunk(kind: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut chunk = kind.to_vec();
+        chunk.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        chunk.extend_from_slice(data);
+        chunk
+    }
+
+    fn file(format: u16, division: u16, tracks: &[&[u8]]) -> Vec<u8> {
+        let mut header = format.to_be_bytes().to_vec();
+        header.extend_from_slice(&(tracks.len() as u16).to_be_bytes());
+        header.extend_from_slice(&division.to_be_bytes());
+        let mut file = chunk(b"MThd", &header);
+        file.extend(chunk(b"XFIH", &[1, 2, 3]));
+        for track in tracks {
+            file.extend(chunk(b"MTrk", track));
+        }
+        file
+    }
+
+    fn multi_track() -> Smf {
+        let conductor: &[u8] = &[
+            0x00, 0xFF, 0x03, 0x04, b'S', b'o', b'n', b'g', // track name
+            0x00, 0xFF, 0x58, 0x04, 0x03, 0x02, 0x18, 0x08, // 3/4
+            0x00, 0xFF, 0x59, 0x02, 0xFE, 0x01, // Bb minor
+            0x81, 0x40, 0xFF, 0x51, 0x03, 0x0F, 0x42, 0x40, // 60 bpm at 192
+            0x60, 0xFF, 0x06, 0x01, b'A', // marker at 288
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        let notes: &[u8] = &[
+            0x00, 0xC0, 0x05, // program change
+            0x00, 0x90, 0x3C, 0x64, // note on
+            0x81, 0x00, 0x3C, 0x00, // running status note off at 128
+            0x00, 0xFF, 0x05, 0x02, b'l', b'a', // lyric
+            0x40, 0x40, 0x00, // running status kept after meta, at 192
+            0x00, 0xF0, 0x03, 0x7E, 0x7F, 0x09, // SysEx without F7
+            0x10, 0xF7, 0x02, 0x01, 0xF7, // continuation at 208
+            0x00, 0xF7, 0x01, 0xFA, // escaped start
+            0x00, 0xFF, 0x2F, 0x00, 0x00, 0x90, 0x3C, 0x64, // ignored after end of track
+        ];
+        Smf::parse(&file(1, 96, &[conductor, notes])).unwrap()
+    }
+
+    #[test]
+    fn parse_multi_track() {
+        let smf = multi_track();
+        assert_eq!(smf.format, SmfFormat::MultiTrack);
+        assert_eq!(smf.division, SmfDivision::TicksPerQuarter(96));
+        assert_eq!(smf.tracks.len(), 2);
+        assert_eq!(smf.tracks[0].name(), Some("Song"));
+        assert_eq!(
+            smf.tracks[0].events[1].kind,
+            SmfEventKind::Meta(SmfMeta::TimeSignature {
+                numerator: 3,
+                denominator_power: 2,
+                clocks_per_click: 24,
+                thirty_seconds_per_quarter: 8,
+            })
+        );
+        assert_eq!(
+            smf.tracks[0].events[2].kind,
+            SmfEventKind::Meta(SmfMeta::KeySignature {
+                sharps: -2,
+                minor: true
+            })
+        );
+        assert_eq!(
+            smf.tracks[0].events[3],
+            SmfEvent {
+                ticks: 192,
+                kind: SmfEventKind::Meta(SmfMeta::Tempo(1_000_000))
+            }
+        );
+
+        let events: Vec<(u64, SmfEventKind)> = smf.tracks[1]
+            .events
+            .iter()
+            .map(|event| (event.ticks, event.kind.clone()))
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                (0, SmfEventKind::Midi(vec![0xC0, 0x05])),
+                (0, SmfEventKind::Midi(vec![0x90, 0x3C, 0x64])),
+                (128, SmfEventKind::Midi(vec![0x90, 0x3C, 0x00])),
+                (128, SmfEventKind::Meta(SmfMeta::Lyric("la".to_string()))),
+                (192, SmfEventKind::Midi(vec![0x90, 0x40, 0x00])),
+                (192, SmfEventKind::SysEx(vec![0xF0, 0x7E, 0x7F, 0x09])),
+                (208, SmfEventKind::Escape(vec![0x01, 0xF7])),
+                (208, SmfEventKind::Escape(vec![0xFA])),
+                (208, SmfEventKind::Meta(SmfMeta::EndOfTrack)),
+            ]
+        );
+    }
+
+    #[test]
+    fn tempo_map() {
+        let tempo_map = SmfTempoMap::new(
+            SmfDivision::TicksPerQuarter(480),
+            vec![(960, 1_000_000), (0, 250_000), (0, 600_000)],
+        );
+        assert_eq!(tempo_map.changes().len(), 2);
+        assert_eq!(tempo_map.micros_per_quarter_at(959), 600_000);
+        assert_eq!(tempo_map.time_at(960), Duration::from_millis(1200));
+        assert_eq!(tempo_map.time_at(1200), Duration::from_millis(1700));
+        assert_eq!(tempo_map.ticks_at(Duration::from_millis(1700)), 1200);
+        assert_eq!(tempo_map.ticks_at(Duration::from_millis(600)), 480);
+
+        let smpte = SmfTempoMap::new(
+            SmfDivision::from_word(0xE728).unwrap(),
+            vec![(0, 1_000_000)],
+        );
+        assert_eq!(
+            smpte.division(),
+            SmfDivision::Smpte {
+                frames_per_second: 25,
+                ticks_per_frame: 40
+            }
+        );
+        assert_eq!(smpte.division().word(), 0xE728);
+        assert_eq!(smpte.time_at(1000), Duration::from_secs(1));
+        let drop_frame = SmfTempoMap::new(SmfDivision::from_word(0xE302).unwrap(), None);
+        assert_eq!(drop_frame.time_at(60), Duration::from_nanos(1_001_000_000));
+    }
+
+    #[test]
+    fn zero_tempo() {
+        let track: &[u8] = &[
+            0x00, 0xFF, 0x51, 0x03, 0x00, 0x00, 0x00, // 0 microseconds per quarter
+            0x60, 0x90, 0x3C, 0x64, 0x00, 0xFF, 0x2F, 0x00,
+        ];
+        let smf = Smf::parse(&file(0, 96, &[track])).unwrap();
+        let tempo_map = smf.tempo_map();
+        assert_eq!(tempo_map.micros_per_quarter_at(0), 1);
+        assert_eq!(tempo_map.time_at(96), Duration::from_micros(1));
+        assert_eq!(tempo_map.ticks_at(Duration::from_millis(1)), 96_000);
+
+        let writer = SmfWriter::new(tempo_map, Timestamp::from_host_time(1));
+        let later = Timestamp::from_host_time(1 + nanos_to_ticks(1_000));
+        assert_eq!(writer.ticks_at(later), 96);
+    }
+
+    #[test]
+    fn render_packet_buffers() {
+        let smf = multi_track();
+        assert_eq!(smf.duration(), Duration::from_secs(2));
+        let start = Timestamp::from_host_time(1000);
+        let buffers = smf.to_packet_buffers(start, Duration::from_secs(1));
+        assert_eq!(buffers.len(), 2);
+
+        let packets: Vec<(u64, Vec<u8>)> = buffers
+            .iter()
+            .flat_map(|buffer| {
+                buffer
+                    .iter()
+                    .map(|packet| {
+                        let ticks = packet.timestamp().host_time() - start.host_time();
+                        (ticks_to_nanos(ticks) / 1_000_000, packet.data().to_vec())
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        let data: Vec<u8> = packets.iter().flat_map(|(_, data)| data.clone()).collect();
+        assert_eq!(
+            data,
+            vec![
+                0xC0, 0x05, 0x90, 0x3C, 0x64, 0x90, 0x3C, 0x00, 0x90, 0x40, 0x00, 0xF0, 0x7E, 0x7F,
+                0x09, 0x01, 0xF7, 0xFA
+            ]
+        );
+        assert_eq!(packets.last().unwrap().0, 1166);
+    }
+
+    #[test]
+    fn render_event_buffers() {
+        let smf = multi_track();
+        let before = SystemClock.now();
+        let buffers = smf.to_event_buffers(Timestamp::Now, Duration::ZERO, 1);
+        assert!(buffers[0].iter().all(|packet| packet.timestamp() >= before));
+        assert_eq!(buffers.len(), 1);
+        let words: Vec<u32> = buffers[0]
+            .iter()
+            .flat_map(|packet| packet.data().to_vec())
+            .collect();
+        assert_eq!(
+            words,
+            vec![
+                0x21C00500, 0x21903C64, 0x21903C00, 0x21904000, 0x31047E7F, 0x09010000, 0x11FA0000
+            ]
+        );
+    }
+
+    #[test]
+    fn format_2_plays_patterns_in_sequence() {
+        let first: &[u8] = &[
+            0x00, 0xFF, 0x51, 0x03, 0x0F, 0x42, 0x40, // 60 bpm
+            0x60, 0xFF, 0x2F, 0x00,
+        ];
+        let second: &[u8] = &[0x30, 0x90, 0x3C, 0x64, 0x00, 0xFF, 0x2F, 0x00];
+        let smf = Smf::parse(&file(2, 96, &[first, second])).unwrap();
+        assert_eq!(smf.track_tempo_map(1).micros_per_quarter_at(0), 500_000);
+        let timeline = smf.timeline();
+        assert_eq!(timeline[2].track, 1);
+        assert_eq!(timeline[2].time, Duration::from_millis(1250));
+    }
+
+    #[test]
+    fn smpte_offset() {
+        let track: &[u8] = &[0x00, 0xFF, 0x54, 0x05, 0x61, 0x02, 0x03, 0x04, 0x05];
+        let smf = Smf::parse(&file(0, 96, &[track])).unwrap();
+        let mut time = SmpteTime::new(1, 2, 3, 4, FrameRate::Fps30);
+        time.subframes = 5;
+        assert_eq!(
+            smf.tracks[0].events[0].kind,
+            SmfEventKind::Meta(SmfMeta::SmpteOffset(time))
+        );
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        let smf = multi_track();
+        let mut encoded = smf.encode(true);
+        assert_eq!(Smf::parse(&encoded), Ok(smf.clone()));
+        assert!(encoded.len() < smf.encode(false).len());
+
+        // A missing end of track is added after the last event
+        let mut track = smf.tracks[1].clone();
+        track.events.pop();
+        let single = Smf {
+            format: SmfFormat::SingleTrack,
+            division: SmfDivision::TicksPerQuarter(96),
+            tracks: vec![track],
+        };
+        encoded = single.encode(false);
+        assert_eq!(&encoded[encoded.len() - 4..], &[0x00, 0xFF, 0x2F, 0x00]);
+        let parsed = Smf::parse(&encoded).unwrap();
+        assert_eq!(
+            parsed.tracks[0].events.last(),
+            Some(&SmfEvent {
+                ticks: 208,
+                kind: SmfEventKind::Meta(SmfMeta::EndOfTrack)
+            })
+        );
+    }
+
+    #[test]
+    fn variable_length_quantities() {
+        for value in [
+            0,
+            0x7F,
+            0x80,
+            0x2000,
+            0x3FFF,
+            0x4000,
+            0x1F_FFFF,
+            0x0FFF_FFFF,
+        ] {
+            let mut data = Vec::new();
+            write_vlq(&mut data, value);
+            assert_eq!(Reader::new(&data).vlq(), Ok(value as u32));
+        }
+        let mut data = Vec::new();
+        write_vlq(&mut data, 0x4000);
+        assert_eq!(data, vec![0x81, 0x80, 0x00]);
+    }
+
+    #[test]
+    fn writer_from_packet_lists() {
+        let ms = |ms: u64| Timestamp::from_host_time(1 + nanos_to_ticks(ms * 1_000_000));
+        let tempo_map = SmfTempoMap::new(
+            SmfDivision::TicksPerQuarter(480),
+            vec![(0, 500_000), (960, 1_000_000)],
+        );
+        let mut writer = SmfWriter::new(tempo_map.clone(), ms(0));
+        assert_eq!(writer.ticks_at(Timestamp::Now), 0);
+        assert_eq!(writer.ticks_at(ms(1500)), 1200);
+
+        let mut packets = PacketBuffer::new(ms(0), &[0x90, 0x3C, 0x64, 0x40, 0x64, 0xF8]);
+        packets.push_data(ms(250), &[0xF0, 0x7E, 0x7F]);
+        packets.push_data(ms(500), &[0x06, 0x01, 0xF7, 0x3C, 0x00, 0x80, 0x3C, 0x00]);
+        writer.push_packet_list(1, &packets);
+        writer.push_message(0, ms(1000), &[0xB0, 0x07, 0x64]);
+        writer.push_message(0, ms(1500), &[0x80, 0x40, 0x00]);
+
+        let smf = Smf::parse(&writer.encode(SmfFormat::MultiTrack, true)).unwrap();
+        assert_eq!(smf.format, SmfFormat::MultiTrack);
+        assert_eq!(smf.tracks.len(), 3);
+        assert_eq!(smf.tempo_map(), tempo_map);
+        let events = |track: usize| -> Vec<(u64, SmfEventKind)> {
+            smf.tracks[track]
+                .events
+                .iter()
+                .map(|event| (event.ticks, event.kind.clone()))
+                .collect()
+        };
+        assert_eq!(
+            events(1),
+            vec![
+                (960, SmfEventKind::Midi(vec![0xB0, 0x07, 0x64])),
+                (1200, SmfEventKind::Midi(vec![0x80, 0x40, 0x00])),
+                (1200, SmfEventKind::Meta(SmfMeta::EndOfTrack)),
+            ]
+        );
+        assert_eq!(
+            events(2),
+            vec![
+                (0, SmfEventKind::Midi(vec![0x90, 0x3C, 0x64])),
+                (0, SmfEventKind::Midi(vec![0x90, 0x40, 0x64])),
+                (0, SmfEventKind::Escape(vec![0xF8])),
+                (
+                    480,
+                    SmfEventKind::SysEx(vec![0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7])
+                ),
+                (480, SmfEventKind::Midi(vec![0x80, 0x3C, 0x00])),
+                (480, SmfEventKind::Meta(SmfMeta::EndOfTrack)),
+            ]
+        );
+
+        let single = writer.to_smf(SmfFormat::SingleTrack);
+        assert_eq!(single.tracks.len(), 1);
+        assert_eq!(single.tracks[0].events.len(), 9);
+        assert_eq!(single.duration(), Duration::from_millis(1500));
+    }
+
+    #[test]
+    fn invalid_files() {
+        assert_eq!(Smf::parse(b"RIFF"), Err(SmfError::InvalidHeader));
+        assert_eq!(Smf::parse(&file(3, 96, &[])), Err(SmfError::InvalidHeader));
+        assert_eq!(Smf::parse(&file(0, 0, &[])), Err(SmfError::InvalidHeader));
+        assert_eq!(
+            Smf::parse(&file(0, 96, &[&[0x00, 0x3C, 0x64]])),
+            Err(SmfError::MissingStatus { track: 0 })
+        );
+        assert_eq!(
+            Smf::parse(&file(0, 96, &[&[0x00, 0xF8]])),
+            Err(SmfError::InvalidStatus {
+                track: 0,
+                status: 0xF8
+            })
+        );
+        assert_eq!(
+            Smf::parse(&file(0, 96, &[&[0xFF, 0xFF, 0xFF, 0xFF, 0x00]])),
+            Err(SmfError::InvalidLength)
+        );
+        assert_eq!(
+            Smf::parse(&file(0, 96, &[&[0x00, 0x90, 0x3C]])),
+            Err(SmfError::UnexpectedEnd)
+        );
+        let mut truncated = file(1, 96, &[&[0x00, 0xFF, 0x2F, 0x00]]);
+        truncated[11] = 2;
+        assert_eq!(Smf::parse(&truncated), Err(SmfError::UnexpectedEnd));
+    }
+}
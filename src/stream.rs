@@ -0,0 +1,321 @@
+use core_foundation_sys::base::OSStatus;
+use futures_core::Stream;
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use crate::client::{Client, NotifyCallback};
+use crate::endpoints::destinations::VirtualDestination;
+use crate::events::EventBuffer;
+use crate::notifications::Notification;
+use crate::packets::PacketBuffer;
+use crate::ports::{InputPort, InputPortWithContext};
+use crate::protocol::Protocol;
+
+/// What a [MidiStream] does with a new item when its buffer is full.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Keep the buffered items and drop the new one.
+    DropNewest,
+    /// Drop the oldest buffered item to make room for the new one.
+    DropOldest,
+}
+
+struct Shared<T> {
+    items: VecDeque<T>,
+    capacity: usize,
+    overflow: OverflowPolicy,
+    dropped: u64,
+    closed: bool,
+    waker: Option<Waker>,
+}
+
+/// The end of a [MidiStream] used by the CoreMIDI callbacks.
+pub(crate) struct StreamSender<T> {
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+impl<T> StreamSender<T> {
+    pub(crate) fn send(&self, item: T) {
+        let mut shared = match self.shared.lock() {
+            Ok(shared) => shared,
+            Err(_) => return,
+        };
+        if shared.items.len() >= shared.capacity {
+            shared.dropped += 1;
+            match shared.overflow {
+                OverflowPolicy::DropNewest => return,
+                OverflowPolicy::DropOldest => {
+                    shared.items.pop_front();
+                }
+            }
+        }
+        shared.items.push_back(item);
+        if let Some(waker) = shared.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Drop for StreamSender<T> {
+    fn drop(&mut self) {
+        if let Ok(mut shared) = self.shared.lock() {
+            shared.closed = true;
+            if let Some(waker) = shared.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+pub(crate) fn stream_channel<T>(
+    capacity: usize,
+    overflow: OverflowPolicy,
+) -> (StreamSender<T>, MidiStream<T>) {
+    let capacity = capacity.max(1);
+    let shared = Arc::new(Mutex::new(Shared {
+        items: VecDeque::with_capacity(capacity),
+        capacity,
+        overflow,
+        dropped: 0,
+        closed: false,
+        waker: None,
+    }));
+    (
+        StreamSender {
+            shared: shared.clone(),
+        },
+        MidiStream { shared },
+    )
+}
+
+/// A bounded [Stream] of the items received by a CoreMIDI callback.
+///
+/// Items are buffered until polled, up to a capacity, after which the [OverflowPolicy] decides
+/// which ones are dropped. The stream ends once the port, destination or client feeding it is disposed.
+///
+/// Streams are created through [Client::new_with_notification_stream], [Client::input_port_stream],
+/// [Client::input_port_with_protocol_stream] and [Client::virtual_destination_with_protocol_stream].
+///
+pub struct MidiStream<T> {
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+impl<T> MidiStream<T> {
+    /// Get the number of items waiting to be polled.
+    ///
+    pub fn len(&self) -> usize {
+        self.shared
+            .lock()
+            .map(|shared| shared.items.len())
+            .unwrap_or(0)
+    }
+
+    /// Check whether there are no items waiting to be polled.
+    ///
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the number of items dropped because the buffer was full.
+    ///
+    pub fn dropped_count(&self) -> u64 {
+        self.shared.lock().map(|shared| shared.dropped).unwrap_or(0)
+    }
+}
+
+impl<T> Stream for MidiStream<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let mut shared = match self.shared.lock() {
+            Ok(shared) => shared,
+            Err(_) => return Poll::Ready(None),
+        };
+        if let Some(item) = shared.items.pop_front() {
+            Poll::Ready(Some(item))
+        } else if shared.closed {
+            Poll::Ready(None)
+        } else {
+            match &shared.waker {
+                Some(waker) if waker.will_wake(cx.waker()) => {}
+                _ => shared.waker = Some(cx.waker().clone()),
+            }
+            Poll::Pending
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len(), None)
+    }
+}
+
+impl<T> std::fmt::Debug for MidiStream<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "MidiStream(len={}, dropped={})",
+            self.len(),
+            self.dropped_count()
+        )
+    }
+}
+
+impl Client {
+    /// Creates a new CoreMIDI client like [Client::new_with_notifications],
+    /// with its notifications delivered through a [MidiStream].
+    ///
+    /// Notifications are still received on the run loop that was current when creating the client,
+    /// which needs to be running for the stream to get any.
+    ///
+    pub fn new_with_notification_stream(
+        name: &str,
+        capacity: usize,
+        overflow: OverflowPolicy,
+    ) -> Result<(Client, MidiStream<Notification>), OSStatus> {
+        let (sender, stream) = stream_channel(capacity, overflow);
+        let callback = NotifyCallback::by_ownership(move |notification| sender.send(notification));
+        let client = Client::new_with_notifications(name, callback)?;
+        Ok((client, stream))
+    }
+
+    /// Creates an input port like [Client::input_port], with the packets received delivered
+    /// as owned [PacketBuffer]s through a [MidiStream].
+    ///
+    /// ```rust,no_run
+    /// use coremidi::{Client, OverflowPolicy, Source};
+    /// let client = Client::new("example-client").unwrap();
+    /// let (input_port, packets) = client.input_port_stream("example-port", 256, OverflowPolicy::DropOldest).unwrap();
+    /// input_port.connect_source(&Source::from_index(0).unwrap()).unwrap();
+    /// // Poll `packets` from any async runtime.
+    /// ```
+    pub fn input_port_stream(
+        &self,
+        name: &str,
+        capacity: usize,
+        overflow: OverflowPolicy,
+    ) -> Result<(InputPort, MidiStream<PacketBuffer>), OSStatus> {
+        let (sender, stream) = stream_channel(capacity, overflow);
+        let input_port =
+            self.input_port(name, move |packet_list| sender.send(packet_list.to_owned()))?;
+        Ok((input_port, stream))
+    }
+
+    /// Creates an input port like [Client::input_port_with_protocol], with the events received
+    /// delivered through a [MidiStream] as owned [EventBuffer]s, together with the context
+    /// of the source they come from.
+    ///
+    #[allow(clippy::type_complexity)]
+    pub fn input_port_with_protocol_stream<T>(
+        &self,
+        name: &str,
+        protocol: Protocol,
+        capacity: usize,
+        overflow: OverflowPolicy,
+    ) -> Result<(InputPortWithContext<T>, MidiStream<(T, EventBuffer)>), OSStatus>
+    where
+        T: Clone + Send + 'static,
+    {
+        let (sender, stream) = stream_channel(capacity, overflow);
+        let input_port =
+            self.input_port_with_protocol(name, protocol, move |event_list, context: &mut T| {
+                sender.send((context.clone(), event_list.to_owned()))
+            })?;
+        Ok((input_port, stream))
+    }
+
+    /// Creates a virtual destination like [Client::virtual_destination_with_protocol],
+    /// with the events received delivered as owned [EventBuffer]s through a [MidiStream].
+    ///
+    pub fn virtual_destination_with_protocol_stream(
+        &self,
+        name: &str,
+        protocol: Protocol,
+        capacity: usize,
+        overflow: OverflowPolicy,
+    ) -> Result<(VirtualDestination, MidiStream<EventBuffer>), OSStatus> {
+        let (sender, stream) = stream_channel(capacity, overflow);
+        let virtual_destination =
+            self.virtual_destination_with_protocol(name, protocol, move |event_list| {
+                sender.send(event_list.to_owned())
+            })?;
+        Ok((virtual_destination, stream))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::task::Wake;
+
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn poll<T>(stream: &mut MidiStream<T>, waker: &Arc<CountingWaker>) -> Poll<Option<T>> {
+        let waker = Waker::from(waker.clone());
+        let mut cx = Context::from_waker(&waker);
+        Pin::new(stream).poll_next(&mut cx)
+    }
+
+    #[test]
+    fn wakes_on_items_and_close() {
+        let waker = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let (sender, mut stream) = stream_channel(4, OverflowPolicy::DropNewest);
+
+        assert_eq!(poll(&mut stream, &waker), Poll::Pending);
+        sender.send(1);
+        assert_eq!(waker.0.load(Ordering::SeqCst), 1);
+        assert_eq!(poll(&mut stream, &waker), Poll::Ready(Some(1)));
+        assert_eq!(poll(&mut stream, &waker), Poll::Pending);
+
+        sender.send(2);
+        drop(sender);
+        assert_eq!(waker.0.load(Ordering::SeqCst), 2);
+        assert_eq!(poll(&mut stream, &waker), Poll::Ready(Some(2)));
+        assert_eq!(poll(&mut stream, &waker), Poll::Ready(None));
+    }
+
+    #[test]
+    fn overflow_policies() {
+        let waker = Arc::new(CountingWaker(AtomicUsize::new(0)));
+
+        let (sender, mut stream) = stream_channel(2, OverflowPolicy::DropNewest);
+        (1..=4).for_each(|item| sender.send(item));
+        assert_eq!(stream.dropped_count(), 2);
+        assert_eq!(poll(&mut stream, &waker), Poll::Ready(Some(1)));
+        assert_eq!(poll(&mut stream, &waker), Poll::Ready(Some(2)));
+
+        let (sender, mut stream) = stream_channel(2, OverflowPolicy::DropOldest);
+        (1..=4).for_each(|item| sender.send(item));
+        assert_eq!(stream.len(), 2);
+        assert_eq!(stream.dropped_count(), 2);
+        assert_eq!(poll(&mut stream, &waker), Poll::Ready(Some(3)));
+        assert_eq!(poll(&mut stream, &waker), Poll::Ready(Some(4)));
+    }
+
+    #[test]
+    fn owned_buffers_outlive_the_sender() {
+        let waker = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let (sender, mut stream) = stream_channel(1, OverflowPolicy::DropOldest);
+        {
+            let received = PacketBuffer::new(crate::Timestamp::Now, &[0x90, 0x3c, 0x7f]);
+            let packet_list: &crate::PacketList = &received;
+            sender.send(packet_list.to_owned());
+        }
+        drop(sender);
+        match poll(&mut stream, &waker) {
+            Poll::Ready(Some(buffer)) => {
+                assert_eq!(buffer.iter().next().unwrap().data(), &[0x90, 0x3c, 0x7f])
+            }
+            _ => panic!("expected a buffer"),
+        }
+    }
+}
@@ -0,0 +1,498 @@
+use core_foundation_sys::base::OSStatus;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::time::Duration;
+
+use crate::endpoints::destinations::Destination;
+use crate::endpoints::sources::Source;
+use crate::events::{ump_word_count, EventList, Timestamp};
+use crate::packets::{PacketBuffer, PacketList};
+use crate::ports::{InputPort, OutputPort};
+use crate::Client;
+
+pub(crate) const SYSEX_START: u8 = 0xF0;
+pub(crate) const SYSEX_END: u8 = 0xF7;
+
+const UMP_SYSEX7: u32 = 0x3;
+const SYSEX7_COMPLETE: u32 = 0x0;
+const SYSEX7_START: u32 = 0x1;
+const SYSEX7_CONTINUE: u32 = 0x2;
+const SYSEX7_END: u32 = 0x3;
+
+/// Split a SysEx message into SysEx7 Universal MIDI Packets of up to 6 bytes each.
+/// The `F0` and `F7` framing bytes are dropped if present.
+pub(crate) fn sysex7_words(group: u8, message: &[u8]) -> Vec<u32> {
+    let message = match message {
+        [SYSEX_START, rest @ ..] => rest,
+        _ => message,
+    };
+    let message = match message {
+        [rest @ .., SYSEX_END] => rest,
+        _ => message,
+    };
+    let chunks = message.chunks(6).collect::<Vec<_>>();
+    let chunks = if chunks.is_empty() {
+        vec![&message[..0]]
+    } else {
+        chunks
+    };
+    let mut words = Vec::with_capacity(chunks.len() * 2);
+    for (i, chunk) in chunks.iter().enumerate() {
+        let status = match (i == 0, i == chunks.len() - 1) {
+            (true, true) => SYSEX7_COMPLETE,
+            (true, false) => SYSEX7_START,
+            (false, false) => SYSEX7_CONTINUE,
+            (false, true) => SYSEX7_END,
+        };
+        let mut bytes = [0u8; 6];
+        bytes[..chunk.len()].copy_from_slice(chunk);
+        words.push(
+            UMP_SYSEX7 << 28
+                | ((group & 0x0F) as u32) << 24
+                | status << 20
+                | (chunk.len() as u32) << 16
+                | (bytes[0] as u32) << 8
+                | bytes[1] as u32,
+        );
+        words.push(u32::from_be_bytes([bytes[2], bytes[3], bytes[4], bytes[5]]));
+    }
+    words
+}
+
+/// Reassembles System Exclusive messages from a stream of MIDI 1.0 bytes.
+///
+/// CoreMIDI may split a long SysEx message across several packets, and may interleave
+/// real-time messages with it. The assembler keeps the partial message between calls,
+/// ignores real-time bytes, and drops messages that are interrupted by a status byte
+/// or that grow beyond the maximum size.
+///
+/// ```
+/// let mut assembler = coremidi::SysExAssembler::new();
+/// let mut messages = Vec::new();
+/// assembler.push(&[0xf0, 0x7e, 0x00], |message| messages.push(message.to_vec()));
+/// assembler.push(&[0xf8, 0x01, 0xf7], |message| messages.push(message.to_vec()));
+/// assert_eq!(messages, vec![vec![0xf0, 0x7e, 0x00, 0x01, 0xf7]]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct SysExAssembler {
+    buffer: Vec<u8>,
+    in_sysex: bool,
+    max_size: usize,
+}
+
+impl SysExAssembler {
+    /// The default maximum size for a single message, including the framing bytes.
+    pub const DEFAULT_MAX_SIZE: usize = 64 * 1024;
+
+    /// Create an assembler with the default maximum message size.
+    ///
+    pub fn new() -> Self {
+        Self::with_max_size(Self::DEFAULT_MAX_SIZE)
+    }
+
+    /// Create an assembler that drops messages longer than `max_size` bytes.
+    ///
+    pub fn with_max_size(max_size: usize) -> Self {
+        Self {
+            buffer: Vec::new(),
+            in_sysex: false,
+            max_size,
+        }
+    }
+
+    /// Feed some MIDI bytes, calling `on_message` with every complete SysEx message found,
+    /// including its `F0` and `F7` framing bytes.
+    ///
+    pub fn push<F>(&mut self, data: &[u8], mut on_message: F)
+    where
+        F: FnMut(&[u8]),
+    {
+        for &byte in data {
+            match byte {
+                SYSEX_START => {
+                    self.buffer.clear();
+                    self.buffer.push(byte);
+                    self.in_sysex = true;
+                }
+                SYSEX_END if self.in_sysex => {
+                    self.buffer.push(byte);
+                    self.in_sysex = false;
+                    if self.buffer.len() <= self.max_size {
+                        on_message(&self.buffer);
+                    }
+                    self.buffer.clear();
+                }
+                0xF8..=0xFF => {}
+                0x80..=0xF7 => {
+                    self.in_sysex = false;
+                    self.buffer.clear();
+                }
+                _ if self.in_sysex => {
+                    if self.buffer.len() < self.max_size {
+                        self.buffer.push(byte);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Feed all the packets of a `PacketList`, in order.
+    ///
+    pub fn push_packet_list<F>(&mut self, packet_list: &PacketList, mut on_message: F)
+    where
+        F: FnMut(&[u8]),
+    {
+        for packet in packet_list.iter() {
+            self.push(packet.data(), &mut on_message);
+        }
+    }
+
+    /// Feed all the SysEx7 Universal MIDI Packets of an `EventList`, in order.
+    /// The messages are given with their `F0` and `F7` framing bytes, like for MIDI 1.0 bytes.
+    /// Any other kind of packet is ignored.
+    ///
+    pub fn push_event_list<F>(&mut self, event_list: &EventList, mut on_message: F)
+    where
+        F: FnMut(&[u8]),
+    {
+        for packet in event_list.iter() {
+            let mut words = packet.data();
+            while !words.is_empty() {
+                let len = ump_word_count(words[0]).min(words.len());
+                if words[0] >> 28 == UMP_SYSEX7 && len == 2 {
+                    self.push_sysex7(words[0], words[1], &mut on_message);
+                }
+                words = &words[len..];
+            }
+        }
+    }
+
+    fn push_sysex7<F>(&mut self, word0: u32, word1: u32, on_message: &mut F)
+    where
+        F: FnMut(&[u8]),
+    {
+        let status = (word0 >> 20) & 0x0F;
+        let count = (((word0 >> 16) & 0x0F) as usize).min(6);
+        let [_, _, b0, b1] = word0.to_be_bytes();
+        let [b2, b3, b4, b5] = word1.to_be_bytes();
+        let bytes = [b0, b1, b2, b3, b4, b5];
+        if status == SYSEX7_COMPLETE || status == SYSEX7_START {
+            self.push(&[SYSEX_START], &mut *on_message);
+        } else if !self.in_sysex {
+            return;
+        }
+        self.push(&bytes[..count], &mut *on_message);
+        if status == SYSEX7_COMPLETE || status == SYSEX7_END {
+            self.push(&[SYSEX_END], &mut *on_message);
+        }
+    }
+
+    /// Discard any partially received message.
+    ///
+    pub fn reset(&mut self) {
+        self.buffer.clear();
+        self.in_sysex = false;
+    }
+}
+
+impl Default for SysExAssembler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An error while exchanging messages through a [SysExLink].
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SysExLinkError {
+    /// CoreMIDI failed to send the message.
+    Send(OSStatus),
+    /// The other end of the link is gone.
+    Disconnected,
+}
+
+impl fmt::Display for SysExLinkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Send(status) => write!(f, "failed to send SysEx message (status {})", status),
+            Self::Disconnected => write!(f, "the SysEx link is disconnected"),
+        }
+    }
+}
+
+impl std::error::Error for SysExLinkError {}
+
+/// A bidirectional channel of complete SysEx messages.
+///
+/// It is the transport used by the handshaking protocols in this crate, so that they can run
+/// over CoreMIDI ports with a [PortLink], or in-process with a pair of [QueueLink].
+///
+pub trait SysExLink {
+    /// Send one complete SysEx message.
+    fn send(&mut self, message: &[u8]) -> Result<(), SysExLinkError>;
+
+    /// Wait for the next complete SysEx message, for at most `timeout`, or forever when `None`.
+    /// It returns `Ok(None)` when the timeout expires without any message.
+    fn receive(&mut self, timeout: Option<Duration>) -> Result<Option<Vec<u8>>, SysExLinkError>;
+}
+
+/// One end of an in-process [SysExLink], mostly useful for testing.
+///
+/// ```
+/// use coremidi::{QueueLink, SysExLink};
+/// let (mut a, mut b) = QueueLink::pair();
+/// a.send(&[0xf0, 0x7e, 0x00, 0x7f, 0x00, 0xf7]).unwrap();
+/// assert_eq!(b.receive(None).unwrap(), Some(vec![0xf0, 0x7e, 0x00, 0x7f, 0x00, 0xf7]));
+/// ```
+#[derive(Debug)]
+pub struct QueueLink {
+    outgoing: Sender<Vec<u8>>,
+    incoming: Receiver<Vec<u8>>,
+    pending: VecDeque<Vec<u8>>,
+    assembler: SysExAssembler,
+}
+
+impl QueueLink {
+    /// Create two connected ends, where what is sent through one is received by the other.
+    ///
+    pub fn pair() -> (QueueLink, QueueLink) {
+        let (a_tx, a_rx) = channel();
+        let (b_tx, b_rx) = channel();
+        (Self::new(a_tx, b_rx), Self::new(b_tx, a_rx))
+    }
+
+    fn new(outgoing: Sender<Vec<u8>>, incoming: Receiver<Vec<u8>>) -> Self {
+        Self {
+            outgoing,
+            incoming,
+            pending: VecDeque::new(),
+            assembler: SysExAssembler::new(),
+        }
+    }
+
+    /// Send raw bytes without any framing check, which may contain partial or several messages.
+    ///
+    pub fn send_bytes(&mut self, bytes: &[u8]) -> Result<(), SysExLinkError> {
+        self.outgoing
+            .send(bytes.to_vec())
+            .map_err(|_| SysExLinkError::Disconnected)
+    }
+
+    fn assemble(&mut self, bytes: &[u8]) {
+        let pending = &mut self.pending;
+        self.assembler
+            .push(bytes, |message| pending.push_back(message.to_vec()));
+    }
+}
+
+impl SysExLink for QueueLink {
+    fn send(&mut self, message: &[u8]) -> Result<(), SysExLinkError> {
+        self.send_bytes(message)
+    }
+
+    fn receive(&mut self, timeout: Option<Duration>) -> Result<Option<Vec<u8>>, SysExLinkError> {
+        loop {
+            if let Some(message) = self.pending.pop_front() {
+                return Ok(Some(message));
+            }
+            let bytes = match timeout {
+                Some(timeout) => match self.incoming.recv_timeout(timeout) {
+                    Ok(bytes) => bytes,
+                    Err(RecvTimeoutError::Timeout) => return Ok(None),
+                    Err(RecvTimeoutError::Disconnected) => {
+                        return Err(SysExLinkError::Disconnected)
+                    }
+                },
+                None => self
+                    .incoming
+                    .recv()
+                    .map_err(|_| SysExLinkError::Disconnected)?,
+            };
+            self.assemble(&bytes);
+        }
+    }
+}
+
+/// A [SysExLink] over CoreMIDI, receiving from a source and sending to a destination.
+///
+/// ```rust,no_run
+/// use coremidi::{Client, Destination, PortLink, Source, SysExLink};
+/// let client = Client::new("example-client").unwrap();
+/// let source = Source::from_index(0).unwrap();
+/// let destination = Destination::from_index(0).unwrap();
+/// let mut link = PortLink::new(&client, "example-link", &source, destination).unwrap();
+/// link.send(&[0xf0, 0x7e, 0x00, 0x03, 0x00, 0x00, 0xf7]).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct PortLink {
+    input_port: InputPort,
+    output_port: OutputPort,
+    destination: Destination,
+    incoming: Receiver<Vec<u8>>,
+}
+
+impl PortLink {
+    /// Create the input and output ports for the link, and connect the input to the source.
+    ///
+    pub fn new(
+        client: &Client,
+        name: &str,
+        source: &Source,
+        destination: Destination,
+    ) -> Result<Self, OSStatus> {
+        let (sender, incoming) = channel();
+        let mut assembler = SysExAssembler::new();
+        let input_port = client.input_port(name, move |packet_list| {
+            assembler.push_packet_list(packet_list, |message| {
+                let _ = sender.send(message.to_vec());
+            });
+        })?;
+        input_port.connect_source(source)?;
+        let output_port = client.output_port(name)?;
+        Ok(Self {
+            input_port,
+            output_port,
+            destination,
+            incoming,
+        })
+    }
+
+    /// Get the input port receiving the messages.
+    ///
+    pub fn input_port(&self) -> &InputPort {
+        &self.input_port
+    }
+
+    /// Get the output port sending the messages.
+    ///
+    pub fn output_port(&self) -> &OutputPort {
+        &self.output_port
+    }
+
+    /// Get the destination of the messages.
+    ///
+    pub fn destination(&self) -> &Destination {
+        &self.destination
+    }
+}
+
+impl SysExLink for PortLink {
+    fn send(&mut self, message: &[u8]) -> Result<(), SysExLinkError> {
+        let packet_buffer = PacketBuffer::new(Timestamp::Now, message);
+        self.output_port
+            .send(&self.destination, &packet_buffer)
+            .map_err(SysExLinkError::Send)
+    }
+
+    fn receive(&mut self, timeout: Option<Duration>) -> Result<Option<Vec<u8>>, SysExLinkError> {
+        match timeout {
+            Some(timeout) => match self.incoming.recv_timeout(timeout) {
+                Ok(message) => Ok(Some(message)),
+                Err(RecvTimeoutError::Timeout) => Ok(None),
+                Err(RecvTimeoutError::Disconnected) => Err(SysExLinkError::Disconnected),
+            },
+            None => self
+                .incoming
+                .recv()
+                .map(Some)
+                .map_err(|_| SysExLinkError::Disconnected),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assemble(chunks: &[&[u8]]) -> Vec<Vec<u8>> {
+        let mut assembler = SysExAssembler::new();
+        let mut messages = Vec::new();
+        for chunk in chunks {
+            assembler.push(chunk, |message| messages.push(message.to_vec()));
+        }
+        messages
+    }
+
+    #[test]
+    fn assembler_single_message() {
+        assert_eq!(
+            assemble(&[&[0x90, 0x40, 0x7f, 0xf0, 0x01, 0x02, 0xf7, 0x80, 0x40, 0x00]]),
+            vec![vec![0xf0, 0x01, 0x02, 0xf7]]
+        );
+    }
+
+    #[test]
+    fn assembler_split_with_realtime() {
+        assert_eq!(
+            assemble(&[&[0xf0, 0x01], &[0xf8, 0x02], &[0xfe, 0x03, 0xf7]]),
+            vec![vec![0xf0, 0x01, 0x02, 0x03, 0xf7]]
+        );
+    }
+
+    #[test]
+    fn assembler_interrupted_message() {
+        assert_eq!(
+            assemble(&[&[0xf0, 0x01, 0x90, 0x40, 0x7f, 0x02, 0xf7, 0xf0, 0x03, 0xf7]]),
+            vec![vec![0xf0, 0x03, 0xf7]]
+        );
+    }
+
+    #[test]
+    fn assembler_max_size() {
+        let mut assembler = SysExAssembler::with_max_size(4);
+        let mut messages = Vec::new();
+        assembler.push(
+            &[0xf0, 0x01, 0x02, 0x03, 0xf7, 0xf0, 0x01, 0xf7],
+            |message| messages.push(message.to_vec()),
+        );
+        assert_eq!(messages, vec![vec![0xf0, 0x01, 0xf7]]);
+    }
+
+    #[test]
+    fn sysex7_round_trip() {
+        let message = (0..20).collect::<Vec<u8>>();
+        let mut framed = vec![SYSEX_START];
+        framed.extend_from_slice(&message);
+        framed.push(SYSEX_END);
+
+        let words = sysex7_words(2, &framed);
+        assert_eq!(words.len(), 8);
+        assert_eq!(words[0], 0x32160001);
+        assert_eq!(words[6], 0x32321213);
+
+        let mut buffer = crate::EventBuffer::new(crate::Protocol::Midi20);
+        buffer.push(Timestamp::Now, &words);
+        let mut assembler = SysExAssembler::new();
+        let mut messages = Vec::new();
+        assembler.push_event_list(&buffer, |message| messages.push(message.to_vec()));
+        assert_eq!(messages, vec![framed]);
+    }
+
+    #[test]
+    fn sysex7_empty() {
+        assert_eq!(
+            sysex7_words(0, &[SYSEX_START, SYSEX_END]),
+            vec![0x30000000, 0]
+        );
+    }
+
+    #[test]
+    fn queue_link_reassembles_bytes() {
+        let (mut a, mut b) = QueueLink::pair();
+        a.send_bytes(&[0xf0, 0x01]).unwrap();
+        a.send_bytes(&[0x02, 0xf7, 0xf0, 0x03, 0xf7]).unwrap();
+        assert_eq!(b.receive(None).unwrap(), Some(vec![0xf0, 0x01, 0x02, 0xf7]));
+        assert_eq!(b.receive(None).unwrap(), Some(vec![0xf0, 0x03, 0xf7]));
+        assert_eq!(b.receive(Some(Duration::from_millis(1))).unwrap(), None);
+    }
+
+    #[test]
+    fn queue_link_disconnected() {
+        let (mut a, b) = QueueLink::pair();
+        drop(b);
+        assert_eq!(a.send(&[0xf0, 0xf7]), Err(SysExLinkError::Disconnected));
+        assert_eq!(a.receive(None), Err(SysExLinkError::Disconnected));
+    }
+}
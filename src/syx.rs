@@ -0,0 +1,511 @@
+use core_foundation_sys::base::OSStatus;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::endpoints::destinations::Destination;
+use crate::events::{EventBuffer, Timestamp};
+use crate::packets::PacketBuffer;
+use crate::ports::OutputPort;
+use crate::properties::{Properties, PropertyGetter};
+use crate::protocol::Protocol;
+use crate::sysex::{SYSEX_END, SYSEX_START};
+
+/// An error while reading or writing SysEx messages in the `.syx` format.
+///
+#[derive(Debug)]
+pub enum SyxError {
+    /// A byte outside of any message, where an `F0` was expected.
+    UnexpectedByte {
+        offset: usize,
+        byte: u8,
+    },
+    /// A byte within a message that is not 7-bit data.
+    InvalidData {
+        offset: usize,
+        byte: u8,
+    },
+    /// The message starting at the offset has no terminating `F7`.
+    Unterminated {
+        offset: usize,
+    },
+    Io(io::Error),
+}
+
+impl fmt::Display for SyxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnexpectedByte { offset, byte } => write!(
+                f,
+                "unexpected byte {:02x} at offset {}, expected the start of a SysEx message",
+                byte, offset
+            ),
+            Self::InvalidData { offset, byte } => write!(
+                f,
+                "invalid SysEx data byte {:02x} at offset {}",
+                byte, offset
+            ),
+            Self::Unterminated { offset } => write!(
+                f,
+                "the SysEx message at offset {} is not terminated",
+                offset
+            ),
+            Self::Io(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for SyxError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SyxError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+/// Check that `message` is a single SysEx message with 7-bit data,
+/// where `offset` is its position for error reporting.
+fn validate_message(message: &[u8], offset: usize) -> Result<(), SyxError> {
+    match message.first() {
+        Some(&SYSEX_START) => {}
+        Some(&byte) => return Err(SyxError::UnexpectedByte { offset, byte }),
+        None => return Err(SyxError::Unterminated { offset }),
+    }
+    for (index, &byte) in message.iter().enumerate().skip(1) {
+        match byte {
+            SYSEX_END if index == message.len() - 1 => return Ok(()),
+            SYSEX_END => {
+                return Err(SyxError::UnexpectedByte {
+                    offset: offset + index + 1,
+                    byte: message[index + 1],
+                })
+            }
+            0x80..=0xff => {
+                return Err(SyxError::InvalidData {
+                    offset: offset + index,
+                    byte,
+                })
+            }
+            _ => {}
+        }
+    }
+    Err(SyxError::Unterminated { offset })
+}
+
+/// The content of a `.syx` file: a sequence of complete System Exclusive messages.
+///
+/// Every message starts with `F0`, ends with `F7` and only contains 7-bit data in between,
+/// which is checked both when reading and when adding messages.
+///
+/// ```
+/// use coremidi::SyxFile;
+///
+/// let syx = SyxFile::from_bytes(&[0xf0, 0x43, 0x10, 0xf7, 0xf0, 0x7e, 0x7f, 0x06, 0x01, 0xf7]).unwrap();
+/// assert_eq!(syx.len(), 2);
+/// assert_eq!(syx.messages()[1], vec![0xf0, 0x7e, 0x7f, 0x06, 0x01, 0xf7]);
+///
+/// assert!(SyxFile::from_bytes(&[0xf0, 0x43, 0x90, 0xf7]).is_err());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SyxFile {
+    messages: Vec<Vec<u8>>,
+}
+
+impl SyxFile {
+    /// Create an empty file.
+    ///
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Split the bytes of a `.syx` file into its messages.
+    ///
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SyxError> {
+        let mut messages = Vec::new();
+        let mut offset = 0;
+        while offset < bytes.len() {
+            let end = bytes[offset..]
+                .iter()
+                .position(|&byte| byte == SYSEX_END)
+                .map_or(bytes.len(), |position| offset + position + 1);
+            validate_message(&bytes[offset..end], offset)?;
+            messages.push(bytes[offset..end].to_vec());
+            offset = end;
+        }
+        Ok(Self { messages })
+    }
+
+    /// Read a whole `.syx` file.
+    ///
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Self, SyxError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        Self::from_bytes(&bytes)
+    }
+
+    /// Write all the messages in the `.syx` format.
+    ///
+    pub fn write_to<W: Write>(&self, writer: W) -> Result<(), SyxError> {
+        let mut writer = SyxWriter::new(writer);
+        for message in &self.messages {
+            writer.write_message(message)?;
+        }
+        writer.into_inner()?;
+        Ok(())
+    }
+
+    /// Get the bytes of the file.
+    ///
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.messages.concat()
+    }
+
+    /// Add a message at the end of the file.
+    ///
+    pub fn push(&mut self, message: &[u8]) -> Result<(), SyxError> {
+        validate_message(message, 0)?;
+        self.messages.push(message.to_vec());
+        Ok(())
+    }
+
+    /// Get the messages, including their `F0` and `F7` framing bytes.
+    ///
+    pub fn messages(&self) -> &[Vec<u8>] {
+        &self.messages
+    }
+
+    /// Take the messages.
+    ///
+    pub fn into_messages(self) -> Vec<Vec<u8>> {
+        self.messages
+    }
+
+    /// Get the number of messages.
+    ///
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    /// Check whether there are no messages.
+    ///
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+}
+
+/// Writes SysEx messages in the `.syx` format as they arrive,
+/// for example from a [crate::SysExAssembler] receiving a dump.
+///
+/// ```
+/// use coremidi::{SysExAssembler, SyxWriter};
+///
+/// let mut writer = SyxWriter::new(Vec::new());
+/// let mut assembler = SysExAssembler::new();
+/// assembler.push(&[0xf0, 0x43, 0x00, 0x09, 0xf7], |message| writer.write_message(message).unwrap());
+/// assert_eq!(writer.messages_written(), 1);
+/// assert_eq!(writer.into_inner().unwrap(), vec![0xf0, 0x43, 0x00, 0x09, 0xf7]);
+/// ```
+#[derive(Debug)]
+pub struct SyxWriter<W: Write> {
+    writer: W,
+    messages_written: usize,
+}
+
+impl<W: Write> SyxWriter<W> {
+    /// Create a writer on top of another one.
+    ///
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            messages_written: 0,
+        }
+    }
+
+    /// Check and write a complete message, including its `F0` and `F7` framing bytes.
+    ///
+    pub fn write_message(&mut self, message: &[u8]) -> Result<(), SyxError> {
+        validate_message(message, 0)?;
+        self.writer.write_all(message)?;
+        self.messages_written += 1;
+        Ok(())
+    }
+
+    /// Get the number of messages written so far.
+    ///
+    pub fn messages_written(&self) -> usize {
+        self.messages_written
+    }
+
+    /// Flush and return the underlying writer.
+    ///
+    pub fn into_inner(mut self) -> Result<W, SyxError> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// How a [SysExSender] packs the messages it sends.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SysExFormat {
+    /// As MIDI 1.0 bytes in a [PacketBuffer].
+    Packets,
+    /// As SysEx7 Universal MIDI Packets in an [EventBuffer] of the given protocol and group.
+    SysEx7 { protocol: Protocol, group: u8 },
+}
+
+/// Sends a bulk of SysEx messages to a destination, pacing them so that the receiving
+/// device is not overrun.
+///
+/// Every message is followed by a pause of its transfer time at the configured speed
+/// (see [Properties::max_sysex_speed]), plus a configurable inter-message delay.
+///
+/// ```rust,no_run
+/// use coremidi::{Client, Destination, SysExSender, SyxFile};
+/// use std::time::Duration;
+///
+/// let client = Client::new("example-client").unwrap();
+/// let output_port = client.output_port("example-port").unwrap();
+/// let destination = Destination::from_index(0).unwrap();
+/// let syx = SyxFile::read_from(std::fs::File::open("patches.syx").unwrap()).unwrap();
+/// SysExSender::for_destination(&destination)
+///     .with_delay(Duration::from_millis(20))
+///     .send(&output_port, &destination, syx.messages())
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct SysExSender {
+    speed: u32,
+    delay: Duration,
+    format: SysExFormat,
+}
+
+impl SysExSender {
+    /// The speed of a MIDI 1.0 DIN connection, in bytes per second,
+    /// which is also the CoreMIDI default for [Properties::max_sysex_speed].
+    pub const DEFAULT_SPEED: u32 = 3125;
+
+    /// Create a sender of [SysExFormat::Packets] at the default speed and with no delay.
+    ///
+    pub fn new() -> Self {
+        Self {
+            speed: Self::DEFAULT_SPEED,
+            delay: Duration::ZERO,
+            format: SysExFormat::Packets,
+        }
+    }
+
+    /// Create a sender at the maximum SysEx speed of the destination,
+    /// or the default one when it is not available.
+    ///
+    pub fn for_destination(destination: &Destination) -> Self {
+        let speed: Option<i32> = Properties::max_sysex_speed().value_from(destination).ok();
+        match speed {
+            Some(speed) if speed > 0 => Self::new().with_speed(speed as u32),
+            _ => Self::new(),
+        }
+    }
+
+    /// Set the speed in bytes per second.
+    ///
+    pub fn with_speed(mut self, bytes_per_second: u32) -> Self {
+        self.speed = bytes_per_second.max(1);
+        self
+    }
+
+    /// Set the additional delay after every message.
+    ///
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Set how the messages are packed.
+    ///
+    pub fn with_format(mut self, format: SysExFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Get the speed in bytes per second.
+    ///
+    pub fn speed(&self) -> u32 {
+        self.speed
+    }
+
+    /// Get the additional delay after every message.
+    ///
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+
+    /// Get how the messages are packed.
+    ///
+    pub fn format(&self) -> SysExFormat {
+        self.format
+    }
+
+    /// Get the time it takes to send a message of a given length, including the delay after it.
+    ///
+    /// ```
+    /// use coremidi::SysExSender;
+    /// use std::time::Duration;
+    ///
+    /// let sender = SysExSender::new().with_speed(1000).with_delay(Duration::from_millis(5));
+    /// assert_eq!(sender.message_duration(250), Duration::from_millis(255));
+    /// ```
+    pub fn message_duration(&self, length: usize) -> Duration {
+        let nanos = length as u128 * 1_000_000_000 / self.speed as u128;
+        Duration::from_nanos(nanos as u64) + self.delay
+    }
+
+    /// Send the messages in order, blocking the current thread while pacing them.
+    /// It returns at the first error from CoreMIDI.
+    ///
+    pub fn send<M>(
+        &self,
+        port: &OutputPort,
+        destination: &Destination,
+        messages: &[M],
+    ) -> Result<(), OSStatus>
+    where
+        M: AsRef<[u8]>,
+    {
+        let mut deadline = Instant::now();
+        for (index, message) in messages.iter().enumerate() {
+            if index > 0 {
+                let now = Instant::now();
+                if deadline > now {
+                    thread::sleep(deadline - now);
+                }
+            }
+            let message = message.as_ref();
+            match self.format {
+                SysExFormat::Packets => port.send(destination, &self.packet_buffer(message))?,
+                SysExFormat::SysEx7 { .. } => {
+                    port.send(destination, &self.event_buffer(message))?
+                }
+            }
+            deadline = deadline.max(Instant::now()) + self.message_duration(message.len());
+        }
+        Ok(())
+    }
+
+    fn packet_buffer(&self, message: &[u8]) -> PacketBuffer {
+        PacketBuffer::new(Timestamp::Now, message)
+    }
+
+    fn event_buffer(&self, message: &[u8]) -> EventBuffer {
+        let (protocol, group) = match self.format {
+            SysExFormat::SysEx7 { protocol, group } => (protocol, group),
+            SysExFormat::Packets => (Protocol::Midi10, 0),
+        };
+        let mut buffer = EventBuffer::new(protocol);
+        buffer.push_sysex7(Timestamp::Now, group, message);
+        buffer
+    }
+}
+
+impl Default for SysExSender {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_multiple_messages() {
+        let bytes = [0xf0, 0x01, 0xf7, 0xf0, 0xf7, 0xf0, 0x02, 0x03, 0xf7];
+        let syx = SyxFile::read_from(&bytes[..]).unwrap();
+        assert_eq!(
+            syx.messages(),
+            &[
+                vec![0xf0, 0x01, 0xf7],
+                vec![0xf0, 0xf7],
+                vec![0xf0, 0x02, 0x03, 0xf7]
+            ]
+        );
+        assert_eq!(syx.to_bytes(), bytes.to_vec());
+        assert!(SyxFile::from_bytes(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn read_invalid_framing() {
+        assert!(matches!(
+            SyxFile::from_bytes(&[0xf0, 0x01, 0xf7, 0x02, 0xf7]),
+            Err(SyxError::UnexpectedByte {
+                offset: 3,
+                byte: 0x02
+            })
+        ));
+        assert!(matches!(
+            SyxFile::from_bytes(&[0xf0, 0x01, 0xf7, 0xf0, 0x02]),
+            Err(SyxError::Unterminated { offset: 3 })
+        ));
+        assert!(matches!(
+            SyxFile::from_bytes(&[0xf0, 0x01, 0xf0, 0x02, 0xf7]),
+            Err(SyxError::InvalidData {
+                offset: 2,
+                byte: 0xf0
+            })
+        ));
+    }
+
+    #[test]
+    fn push_validates_messages() {
+        let mut syx = SyxFile::new();
+        syx.push(&[0xf0, 0x7e, 0xf7]).unwrap();
+        assert!(syx.push(&[0xf0, 0x7e]).is_err());
+        assert!(syx.push(&[0xf0, 0x7e, 0xf7, 0xf0, 0xf7]).is_err());
+        assert!(syx.push(&[0x90, 0x3c, 0x7f]).is_err());
+        assert_eq!(syx.len(), 1);
+    }
+
+    #[test]
+    fn write_round_trip() {
+        let syx = SyxFile::from_bytes(&[0xf0, 0x43, 0x10, 0xf7, 0xf0, 0x41, 0xf7]).unwrap();
+        let mut bytes = Vec::new();
+        syx.write_to(&mut bytes).unwrap();
+        assert_eq!(SyxFile::from_bytes(&bytes).unwrap(), syx);
+    }
+
+    #[test]
+    fn sender_pacing() {
+        let sender = SysExSender::new();
+        assert_eq!(sender.message_duration(3125), Duration::from_secs(1));
+
+        let sender = sender.with_speed(0).with_delay(Duration::from_millis(1));
+        assert_eq!(sender.speed(), 1);
+        assert_eq!(sender.message_duration(2), Duration::from_millis(2001));
+    }
+
+    #[test]
+    fn sender_formats() {
+        let message = [0xf0, 0x7e, 0x7f, 0x06, 0x01, 0xf7];
+        let sender = SysExSender::new();
+        let packets = sender.packet_buffer(&message);
+        assert_eq!(packets.iter().next().unwrap().data(), &message);
+
+        let sender = sender.with_format(SysExFormat::SysEx7 {
+            protocol: Protocol::Midi20,
+            group: 2,
+        });
+        let events = sender.event_buffer(&message);
+        assert_eq!(events.protocol(), Protocol::Midi20);
+        assert_eq!(
+            events.iter().next().unwrap().data(),
+            &[0x32047e7f, 0x06010000]
+        );
+    }
+}
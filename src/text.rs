@@ -0,0 +1,834 @@
+use std::fmt::{self, Write};
+use std::str::FromStr;
+
+use coremidi_sys::MIDIProtocolID;
+
+use crate::events::{midi1_message_words, ump_word_count, EventBuffer, EventList, Timestamp};
+use crate::packets::{PacketBuffer, PacketList};
+use crate::protocol::Protocol;
+
+/// Parse the name of a note, like `C4`, `F#2`, `Bb-1`, or its number.
+fn parse_note_name(text: &str) -> Option<u8> {
+    if let Some(note) = parse_number(text) {
+        return Some(note).filter(|note| *note < 128).map(|note| note as u8);
+    }
+    let mut chars = text.chars();
+    let base: i32 = match chars.next()?.to_ascii_uppercase() {
+        'C' => 0,
+        'D' => 2,
+        'E' => 4,
+        'F' => 5,
+        'G' => 7,
+        'A' => 9,
+        'B' => 11,
+        _ => return None,
+    };
+    let rest = chars.as_str();
+    let (accidental, octave) = match rest.chars().next() {
+        Some('#') => (1, &rest[1..]),
+        Some('b') => (-1, &rest[1..]),
+        _ => (0, rest),
+    };
+    let octave: i32 = octave.parse().ok()?;
+    let note = (octave + 1) * 12 + base + accidental;
+    Some(note)
+        .filter(|note| (0..128).contains(note))
+        .map(|note| note as u8)
+}
+
+/// An error parsing the text form of a [PacketBuffer] or an [EventBuffer].
+///
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MidiTextError {
+    /// A line does not start with a valid timestamp followed by `:`.
+    InvalidTimestamp { line: usize, text: String },
+    /// The protocol is not `midi1` or `midi2`, or it is given for MIDI 1.0 packets.
+    InvalidProtocol { line: usize, text: String },
+    /// The text of an event list does not declare its protocol before the first packet.
+    MissingProtocol { line: usize },
+    /// A message is neither hexadecimal data nor a known symbolic message,
+    /// or it lacks a required value.
+    InvalidMessage { line: usize, text: String },
+    /// A number is invalid or out of range.
+    InvalidValue { line: usize, text: String },
+}
+
+impl fmt::Display for MidiTextError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidTimestamp { line, text } => {
+                write!(f, "line {}: invalid timestamp `{}`", line, text)
+            }
+            Self::InvalidProtocol { line, text } => {
+                write!(f, "line {}: invalid protocol `{}`", line, text)
+            }
+            Self::MissingProtocol { line } => write!(f, "line {}: missing protocol", line),
+            Self::InvalidMessage { line, text } => {
+                write!(f, "line {}: invalid message `{}`", line, text)
+            }
+            Self::InvalidValue { line, text } => {
+                write!(f, "line {}: invalid value `{}`", line, text)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MidiTextError {}
+
+impl PacketList {
+    /// Write the packets in the text form read by [PacketBuffer::from_text],
+    /// one packet per line.
+    ///
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for packet in self.iter() {
+            let _ = write!(text, "{}:", packet.timestamp());
+            for byte in packet.data() {
+                let _ = write!(text, " {:02x}", byte);
+            }
+            text.push('\n');
+        }
+        text
+    }
+}
+
+impl PacketBuffer {
+    /// Read packets from a text form, where each line holds the messages of a packet,
+    /// after its timestamp and a colon:
+    ///
+    /// ```text
+    /// # Comments start with `#` and empty lines are ignored.
+    /// 1000: 90 3c 64 80 3c 00
+    /// now: note_on ch1 C4 vel=100; control_change ch1 cc=7 val=90
+    /// 2000: sysex 7e 7f 06 01
+    /// ```
+    ///
+    /// The timestamp is either `now` or a host time in decimal. The messages of a line
+    /// are separated by `;`, each of them given as hexadecimal bytes or symbolically,
+    /// by its name followed by its values in any order:
+    ///
+    /// - `note_off` and `note_on`, with a note and `vel=` (optional for `note_off`),
+    /// - `poly_pressure`, with a note and `val=`,
+    /// - `control_change`, with `cc=` and `val=`,
+    /// - `program_change`, with `program=`,
+    /// - `channel_pressure`, with `val=`,
+    /// - `pitch_bend`, with `val=` from 0 to 16383, where 8192 is the center,
+    /// - `sysex`, with its data bytes in hexadecimal, with or without `f0` and `f7`,
+    /// - `mtc_quarter_frame`, `song_position` and `song_select`, with `val=`,
+    /// - `tune_request`, `timing_clock`, `start`, `continue`, `stop`,
+    ///   `active_sensing` and `reset`.
+    ///
+    /// Channel messages take their channel as `ch1` to `ch16`. Notes are given by number
+    /// or by name, with `C4` being 60 and `C-1` being 0, as a bare value or with `note=`.
+    /// Numbers can be decimal or hexadecimal with a `0x` prefix.
+    ///
+    /// ```
+    /// use coremidi::{PacketBuffer, Timestamp};
+    /// let buffer = PacketBuffer::from_text("1000: note_on ch1 C4 vel=100; pitch_bend ch2 val=8192").unwrap();
+    /// let packet = buffer.iter().next().unwrap();
+    /// assert_eq!(packet.timestamp(), Timestamp::from_host_time(1000));
+    /// assert_eq!(packet.data(), &[0x90, 60, 100, 0xe1, 0x00, 0x40]);
+    /// assert_eq!(buffer.to_text(), "1000: 90 3c 64 e1 00 40\n");
+    /// ```
+    pub fn from_text(text: &str) -> Result<Self, MidiTextError> {
+        let mut buffer = Self::with_capacity(256);
+        for item in Lines::new(text) {
+            let (line, content) = item?;
+            let (timestamp, content) = match content {
+                Content::Protocol(text) => {
+                    return Err(MidiTextError::InvalidProtocol {
+                        line,
+                        text: text.to_string(),
+                    })
+                }
+                Content::Packet(timestamp, content) => (timestamp, content),
+            };
+            let mut data = Vec::new();
+            for segment in segments(content) {
+                match parse_symbolic(line, segment, false)? {
+                    Some(message) => data.extend_from_slice(&message.bytes),
+                    None => data.extend(parse_hex::<u8>(line, segment, 2)?),
+                }
+            }
+            if !data.is_empty() {
+                buffer.push_data(timestamp, &data);
+            }
+        }
+        Ok(buffer)
+    }
+}
+
+impl FromStr for PacketBuffer {
+    type Err = MidiTextError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Self::from_text(text)
+    }
+}
+
+impl EventList {
+    /// Write the protocol and the packets in the text form read by [EventBuffer::from_text],
+    /// one packet per line.
+    ///
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        let _ = writeln!(text, "protocol {}", protocol_name(self.protocol()));
+        for packet in self.iter() {
+            let _ = write!(text, "{}:", packet.timestamp());
+            for word in packet.data() {
+                let _ = write!(text, " {:08x}", word);
+            }
+            text.push('\n');
+        }
+        text
+    }
+}
+
+impl EventBuffer {
+    /// Read Universal MIDI Packets from a text form like the one of
+    /// [PacketBuffer::from_text], starting with a line declaring the protocol,
+    /// and with hexadecimal data given as 32-bit words:
+    ///
+    /// ```text
+    /// protocol midi2
+    /// 1000: 40903c00 64000000
+    /// now: note_on ch1 C4 vel=0xffff g=2
+    /// ```
+    ///
+    /// The protocol is either `midi1`, `midi2`, or the number of any other protocol. Symbolic messages take their group as
+    /// `g=0` to `g=15`, which is 0 by default. With the MIDI 1.0 protocol they become
+    /// MIDI 1.0 Universal MIDI Packets, with the values of MIDI 1.0 messages. With the
+    /// MIDI 2.0 protocol, channel messages become MIDI 2.0 channel voice messages, with
+    /// 16-bit velocities and 32-bit values, and `program_change` also takes an optional
+    /// 14-bit `bank=`. System Exclusive messages become SysEx7 packets with both protocols.
+    ///
+    /// ```
+    /// use coremidi::{EventBuffer, Protocol};
+    /// let text = "protocol midi2\n5: control_change ch3 cc=74 val=0x80000000 g=1\n";
+    /// let buffer = EventBuffer::from_text(text).unwrap();
+    /// assert_eq!(buffer.protocol(), Protocol::Midi20);
+    /// assert_eq!(buffer.iter().next().unwrap().data(), &[0x41b24a00, 0x80000000]);
+    /// assert_eq!(buffer.to_text(), "protocol midi2\n5: 41b24a00 80000000\n");
+    /// ```
+    pub fn from_text(text: &str) -> Result<Self, MidiTextError> {
+        let mut buffer: Option<EventBuffer> = None;
+        for item in Lines::new(text) {
+            let (line, content) = item?;
+            match (content, &mut buffer) {
+                (Content::Protocol(name), None) => {
+                    let protocol =
+                        parse_protocol(name).ok_or_else(|| MidiTextError::InvalidProtocol {
+                            line,
+                            text: name.to_string(),
+                        })?;
+                    buffer = Some(EventBuffer::new(protocol));
+                }
+                (Content::Protocol(name), Some(_)) => {
+                    return Err(MidiTextError::InvalidProtocol {
+                        line,
+                        text: name.to_string(),
+                    })
+                }
+                (Content::Packet(..), None) => return Err(MidiTextError::MissingProtocol { line }),
+                (Content::Packet(timestamp, content), Some(buffer)) => {
+                    let midi2 = buffer.protocol() == Protocol::Midi20;
+                    let mut words = Vec::new();
+                    for segment in segments(content) {
+                        match parse_symbolic(line, segment, midi2)? {
+                            Some(Symbolic {
+                                midi2_words: Some(message),
+                                ..
+                            }) => words.extend_from_slice(&message),
+                            Some(Symbolic { group, bytes, .. }) => {
+                                words.extend(midi1_message_words(group, &bytes))
+                            }
+                            None => words.extend(parse_hex::<u32>(line, segment, 8)?),
+                        }
+                    }
+                    let mut rest = words.as_slice();
+                    while !rest.is_empty() {
+                        let (message, tail) =
+                            rest.split_at(ump_word_count(rest[0]).min(rest.len()));
+                        buffer.push(timestamp, message);
+                        rest = tail;
+                    }
+                }
+            }
+        }
+        buffer.ok_or(MidiTextError::MissingProtocol { line: 0 })
+    }
+}
+
+impl FromStr for EventBuffer {
+    type Err = MidiTextError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Self::from_text(text)
+    }
+}
+
+fn protocol_name(protocol: Protocol) -> String {
+    match protocol {
+        Protocol::Midi10 => "midi1".to_string(),
+        Protocol::Midi20 => "midi2".to_string(),
+        Protocol::Unknown(id) => id.to_string(),
+    }
+}
+
+fn parse_protocol(name: &str) -> Option<Protocol> {
+    match name {
+        "midi1" => Some(Protocol::Midi10),
+        "midi2" => Some(Protocol::Midi20),
+        id => id.parse::<MIDIProtocolID>().ok().map(Protocol::from),
+    }
+}
+
+enum Content<'a> {
+    Protocol(&'a str),
+    Packet(Timestamp, &'a str),
+}
+
+/// The lines of a text that are not comments, with their line numbers.
+struct Lines<'a> {
+    lines: std::iter::Enumerate<std::str::Lines<'a>>,
+}
+
+impl<'a> Lines<'a> {
+    fn new(text: &'a str) -> Self {
+        Self {
+            lines: text.lines().enumerate(),
+        }
+    }
+}
+
+impl<'a> Iterator for Lines<'a> {
+    type Item = Result<(usize, Content<'a>), MidiTextError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for (index, text) in &mut self.lines {
+            let line = index + 1;
+            let text = text.split('#').next().unwrap_or("").trim();
+            if text.is_empty() {
+                continue;
+            }
+            if let Some(name) = text.strip_prefix("protocol ") {
+                return Some(Ok((line, Content::Protocol(name.trim()))));
+            }
+            let invalid_timestamp = |text: &str| MidiTextError::InvalidTimestamp {
+                line,
+                text: text.to_string(),
+            };
+            let (timestamp, content) = match text.split_once(':') {
+                Some(parts) => parts,
+                None => return Some(Err(invalid_timestamp(text))),
+            };
+            let timestamp = match timestamp.trim() {
+                "now" => Timestamp::Now,
+                number => match number.parse() {
+                    Ok(host_time) => Timestamp::from_host_time(host_time),
+                    Err(_) => return Some(Err(invalid_timestamp(number))),
+                },
+            };
+            return Some(Ok((line, Content::Packet(timestamp, content))));
+        }
+        None
+    }
+}
+
+fn segments(content: &str) -> impl Iterator<Item = &str> {
+    content
+        .split(';')
+        .map(str::trim)
+        .filter(|segment| !segment.is_empty())
+}
+
+fn parse_hex<T: HexValue>(
+    line: usize,
+    segment: &str,
+    digits: usize,
+) -> Result<Vec<T>, MidiTextError> {
+    segment
+        .split_whitespace()
+        .map(|token| {
+            token
+                .strip_prefix("0x")
+                .or(Some(token))
+                .filter(|hex| hex.len() == digits)
+                .and_then(T::from_hex)
+                .ok_or_else(|| MidiTextError::InvalidMessage {
+                    line,
+                    text: token.to_string(),
+                })
+        })
+        .collect()
+}
+
+trait HexValue: Sized {
+    fn from_hex(text: &str) -> Option<Self>;
+}
+
+impl HexValue for u8 {
+    fn from_hex(text: &str) -> Option<Self> {
+        u8::from_str_radix(text, 16).ok()
+    }
+}
+
+impl HexValue for u32 {
+    fn from_hex(text: &str) -> Option<Self> {
+        u32::from_str_radix(text, 16).ok()
+    }
+}
+
+fn parse_number(text: &str) -> Option<u64> {
+    match text.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+/// A symbolic message, as MIDI 1.0 bytes, and as MIDI 2.0 channel voice words if requested.
+struct Symbolic {
+    group: u8,
+    bytes: Vec<u8>,
+    midi2_words: Option<[u32; 2]>,
+}
+
+struct Arguments<'a> {
+    line: usize,
+    segment: &'a str,
+    channel: Option<u8>,
+    group: u8,
+    note: Option<u8>,
+    values: Vec<(&'a str, u64)>,
+    data: Vec<u8>,
+}
+
+impl<'a> Arguments<'a> {
+    fn parse(
+        line: usize,
+        segment: &'a str,
+        tokens: &[&'a str],
+        sysex: bool,
+    ) -> Result<Self, MidiTextError> {
+        let invalid = |text: &str| MidiTextError::InvalidValue {
+            line,
+            text: text.to_string(),
+        };
+        let mut arguments = Self {
+            line,
+            segment,
+            channel: None,
+            group: 0,
+            note: None,
+            values: Vec::new(),
+            data: Vec::new(),
+        };
+        for token in tokens {
+            let (key, value) = match token.split_once('=') {
+                Some((key, value)) => (key, value),
+                None if token.starts_with("ch") => ("ch", &token[2..]),
+                None if sysex => {
+                    arguments.data.extend(parse_hex::<u8>(line, token, 2)?);
+                    continue;
+                }
+                None => ("note", *token),
+            };
+            match key {
+                "ch" => {
+                    let channel = parse_number(value)
+                        .filter(|channel| (1..=16).contains(channel))
+                        .ok_or_else(|| invalid(token))?;
+                    arguments.channel = Some(channel as u8 - 1);
+                }
+                "g" => {
+                    let group = parse_number(value)
+                        .filter(|group| *group < 16)
+                        .ok_or_else(|| invalid(token))?;
+                    arguments.group = group as u8;
+                }
+                "note" => {
+                    arguments.note = Some(parse_note_name(value).ok_or_else(|| invalid(token))?)
+                }
+                key => {
+                    let value = parse_number(value).ok_or_else(|| invalid(token))?;
+                    arguments.values.push((key, value));
+                }
+            }
+        }
+        Ok(arguments)
+    }
+
+    fn missing(&self) -> MidiTextError {
+        MidiTextError::InvalidMessage {
+            line: self.line,
+            text: self.segment.to_string(),
+        }
+    }
+
+    fn channel(&self) -> Result<u8, MidiTextError> {
+        self.channel.ok_or_else(|| self.missing())
+    }
+
+    fn note(&self) -> Result<u8, MidiTextError> {
+        self.note.ok_or_else(|| self.missing())
+    }
+
+    fn value(&self, key: &str, max: u64) -> Result<Option<u64>, MidiTextError> {
+        match self.values.iter().find(|(name, _)| *name == key) {
+            Some((_, value)) if *value > max => Err(MidiTextError::InvalidValue {
+                line: self.line,
+                text: format!("{}={}", key, value),
+            }),
+            Some((_, value)) => Ok(Some(*value)),
+            None => Ok(None),
+        }
+    }
+
+    fn required(&self, key: &str, max: u64) -> Result<u64, MidiTextError> {
+        self.value(key, max)?.ok_or_else(|| self.missing())
+    }
+}
+
+fn parse_symbolic(
+    line: usize,
+    segment: &str,
+    midi2: bool,
+) -> Result<Option<Symbolic>, MidiTextError> {
+    let tokens: Vec<&str> = segment.split_whitespace().collect();
+    let (name, tokens) = match tokens.split_first() {
+        Some((name, tokens)) => (*name, tokens),
+        None => return Ok(None),
+    };
+    let status = match name {
+        "note_off" => 0x80,
+        "note_on" => 0x90,
+        "poly_pressure" => 0xA0,
+        "control_change" => 0xB0,
+        "program_change" => 0xC0,
+        "channel_pressure" => 0xD0,
+        "pitch_bend" => 0xE0,
+        "sysex" => 0xF0,
+        "mtc_quarter_frame" => 0xF1,
+        "song_position" => 0xF2,
+        "song_select" => 0xF3,
+        "tune_request" => 0xF6,
+        "timing_clock" => 0xF8,
+        "start" => 0xFA,
+        "continue" => 0xFB,
+        "stop" => 0xFC,
+        "active_sensing" => 0xFE,
+        "reset" => 0xFF,
+        _ if name.starts_with(|c: char| c.is_ascii_hexdigit()) => return Ok(None),
+        _ => {
+            return Err(MidiTextError::InvalidMessage {
+                line,
+                text: segment.to_string(),
+            })
+        }
+    };
+    let arguments = Arguments::parse(line, segment, tokens, status == 0xF0)?;
+    let group = arguments.group;
+    if status >= 0xF0 {
+        let bytes = match status {
+            0xF0 => {
+                let data = arguments.data.as_slice();
+                let data = data.strip_prefix(&[0xF0]).unwrap_or(data);
+                let data = data.strip_suffix(&[0xF7]).unwrap_or(data);
+                if data.iter().any(|byte| *byte > 0x7F) {
+                    return Err(arguments.missing());
+                }
+                let mut bytes = vec![0xF0];
+                bytes.extend_from_slice(data);
+                bytes.push(0xF7);
+                bytes
+            }
+            0xF2 => {
+                let value = arguments.required("val", 0x3FFF)? as u16;
+                vec![status, (value & 0x7F) as u8, (value >> 7) as u8]
+            }
+            0xF1 | 0xF3 => vec![status, arguments.required("val", 0x7F)? as u8],
+            _ => vec![status],
+        };
+        return Ok(Some(Symbolic {
+            group,
+            bytes,
+            midi2_words: None,
+        }));
+    }
+
+    let status = status | arguments.channel()?;
+    let (bytes, words) = if midi2 {
+        let max = u32::MAX as u64;
+        let word = |index: u16, value: u64| {
+            let first = 0x4 << 28 | (group as u32) << 24 | (status as u32) << 16 | index as u32;
+            [first, value as u32]
+        };
+        let words = match status & 0xF0 {
+            0x80 | 0x90 => {
+                let velocity = match arguments.value("vel", 0xFFFF)? {
+                    Some(velocity) => velocity,
+                    None if status & 0xF0 == 0x80 => 0,
+                    None => return Err(arguments.missing()),
+                };
+                word((arguments.note()? as u16) << 8, velocity << 16)
+            }
+            0xA0 => word(
+                (arguments.note()? as u16) << 8,
+                arguments.required("val", max)?,
+            ),
+            0xB0 => word(
+                (arguments.required("cc", 0x7F)? as u16) << 8,
+                arguments.required("val", max)?,
+            ),
+            0xC0 => {
+                let program = arguments.required("program", 0x7F)?;
+                match arguments.value("bank", 0x3FFF)? {
+                    Some(bank) => word(1, program << 24 | (bank >> 7) << 8 | (bank & 0x7F)),
+                    None => word(0, program << 24),
+                }
+            }
+            0xD0 | 0xE0 => word(0, arguments.required("val", max)?),
+            _ => unreachable!(),
+        };
+        (Vec::new(), Some(words))
+    } else {
+        let bytes = match status & 0xF0 {
+            0x80 => {
+                let velocity = arguments.value("vel", 0x7F)?.unwrap_or(0);
+                vec![status, arguments.note()?, velocity as u8]
+            }
+            0x90 => vec![
+                status,
+                arguments.note()?,
+                arguments.required("vel", 0x7F)? as u8,
+            ],
+            0xA0 => vec![
+                status,
+                arguments.note()?,
+                arguments.required("val", 0x7F)? as u8,
+            ],
+            0xB0 => vec![
+                status,
+                arguments.required("cc", 0x7F)? as u8,
+                arguments.required("val", 0x7F)? as u8,
+            ],
+            0xC0 => vec![status, arguments.required("program", 0x7F)? as u8],
+            0xD0 => vec![status, arguments.required("val", 0x7F)? as u8],
+            0xE0 => {
+                let value = arguments.required("val", 0x3FFF)?;
+                vec![status, (value & 0x7F) as u8, (value >> 7) as u8]
+            }
+            _ => unreachable!(),
+        };
+        (bytes, None)
+    };
+    Ok(Some(Symbolic {
+        group,
+        bytes,
+        midi2_words: words,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode::note_name;
+
+    #[test]
+    fn note_names() {
+        assert_eq!(parse_note_name("C4"), Some(60));
+        assert_eq!(parse_note_name("Db4"), Some(61));
+        assert_eq!(parse_note_name("c-1"), Some(0));
+        assert_eq!(parse_note_name("G#9"), None);
+        assert_eq!(parse_note_name("64"), Some(64));
+        assert_eq!(parse_note_name("128"), None);
+        assert_eq!(parse_note_name("0x3c"), Some(60));
+        assert_eq!(parse_note_name("H2"), None);
+        for note in 0..128 {
+            assert_eq!(parse_note_name(&note_name(note)), Some(note));
+        }
+    }
+
+    #[test]
+    fn packet_round_trip() {
+        let text = "\
+            # A fixture\n\
+            now: 90 3c 7f\n\
+            \n\
+            1000: f0 7e 7f 06 01 f7 f8\n\
+            2000: b0 07 64   # volume\n";
+        let buffer = PacketBuffer::from_text(text).unwrap();
+        let printed = buffer.to_text();
+        assert_eq!(
+            printed,
+            "now: 90 3c 7f\n1000: f0 7e 7f 06 01 f7 f8\n2000: b0 07 64\n"
+        );
+        assert_eq!(
+            PacketBuffer::from_text(&printed).unwrap().to_text(),
+            printed
+        );
+    }
+
+    #[test]
+    fn symbolic_packets() {
+        let text = "\
+            10: note_on ch1 C4 vel=100; note_off ch16 note=0x3c\n\
+            20: poly_pressure ch2 E4 val=3; control_change ch1 cc=7 val=0x7f\n\
+            30: program_change ch10 program=5; channel_pressure ch1 val=9\n\
+            40: pitch_bend ch1 val=16383; sysex f0 7e 7f 06 01 f7; sysex 01 02\n\
+            50: mtc_quarter_frame val=0x21; song_position val=200; song_select val=3\n\
+            60: tune_request; timing_clock; start; continue; stop; active_sensing; reset\n";
+        let data: Vec<Vec<u8>> = PacketBuffer::from_text(text)
+            .unwrap()
+            .iter()
+            .map(|packet| packet.data().to_vec())
+            .collect();
+        assert_eq!(
+            data,
+            vec![
+                vec![0x90, 60, 100, 0x8F, 60, 0],
+                vec![0xA1, 64, 3, 0xB0, 7, 0x7F],
+                vec![0xC9, 5, 0xD0, 9],
+                vec![0xE0, 0x7F, 0x7F, 0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7, 0xF0, 1, 2, 0xF7],
+                vec![0xF1, 0x21, 0xF2, 0x48, 0x01, 0xF3, 3],
+                vec![0xF6, 0xF8, 0xFA, 0xFB, 0xFC, 0xFE, 0xFF],
+            ]
+        );
+    }
+
+    #[test]
+    fn event_round_trip() {
+        let mut buffer = EventBuffer::new(Protocol::Midi20);
+        buffer.push(Timestamp::Now, &[0x40903c00, 0xffff0000]);
+        buffer.push(Timestamp::from_host_time(7), &[0x10f80000]);
+        buffer.push_sysex7(
+            Timestamp::from_host_time(9),
+            3,
+            &[0xf0, 1, 2, 3, 4, 5, 6, 7, 0xf7],
+        );
+        let printed = buffer.to_text();
+        assert_eq!(
+            printed,
+            "protocol midi2\nnow: 40903c00 ffff0000\n7: 10f80000\n9: 33160102 03040506 33310700 00000000\n"
+        );
+        let parsed = EventBuffer::from_text(&printed).unwrap();
+        assert_eq!(parsed.to_text(), printed);
+
+        let buffer =
+            EventBuffer::new(Protocol::Unknown(3)).with_packet(Timestamp::Now, &[0x10f80000]);
+        let printed = buffer.to_text();
+        assert_eq!(printed, "protocol 3\nnow: 10f80000\n");
+        let parsed = EventBuffer::from_text(&printed).unwrap();
+        assert_eq!(parsed.protocol(), Protocol::Unknown(3));
+        assert_eq!(parsed.to_text(), printed);
+    }
+
+    #[test]
+    fn symbolic_events() {
+        let text = "\
+            protocol midi1\n\
+            1: note_on ch2 A4 vel=64 g=3; timing_clock g=1; sysex 7e 00\n";
+        let buffer = EventBuffer::from_text(text).unwrap();
+        assert_eq!(buffer.protocol(), Protocol::Midi10);
+        assert_eq!(
+            buffer.iter().next().unwrap().data(),
+            &[0x23914540, 0x11f80000, 0x30027e00, 0x00000000]
+        );
+
+        let text = "\
+            protocol midi2\n\
+            1: note_on ch1 C4 vel=0xffff; note_off ch1 C4\n\
+            2: program_change ch1 program=5 bank=130; pitch_bend ch1 val=0x80000000\n";
+        let buffer = EventBuffer::from_text(text).unwrap();
+        let words: Vec<u32> = buffer
+            .iter()
+            .flat_map(|packet| packet.data().to_vec())
+            .collect();
+        assert_eq!(
+            words,
+            vec![
+                0x40903c00, 0xffff0000, 0x40803c00, 0x00000000, 0x40c00001, 0x05000102, 0x40e00000,
+                0x80000000
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_text() {
+        let error = |text: &str| PacketBuffer::from_text(text).err().unwrap();
+        assert_eq!(
+            error("soon: 90 3c 7f"),
+            MidiTextError::InvalidTimestamp {
+                line: 1,
+                text: "soon".to_string()
+            }
+        );
+        assert_eq!(
+            error("\n90 3c 7f"),
+            MidiTextError::InvalidTimestamp {
+                line: 2,
+                text: "90 3c 7f".to_string()
+            }
+        );
+        assert_eq!(
+            error("1: 90 3c7f"),
+            MidiTextError::InvalidMessage {
+                line: 1,
+                text: "3c7f".to_string()
+            }
+        );
+        assert_eq!(
+            error("1: note_on C4 vel=1"),
+            MidiTextError::InvalidMessage {
+                line: 1,
+                text: "note_on C4 vel=1".to_string()
+            }
+        );
+        assert_eq!(
+            error("1: note_on ch1 C4 vel=128"),
+            MidiTextError::InvalidValue {
+                line: 1,
+                text: "vel=128".to_string()
+            }
+        );
+        assert_eq!(
+            error("1: note_on ch17 C4 vel=1"),
+            MidiTextError::InvalidValue {
+                line: 1,
+                text: "ch17".to_string()
+            }
+        );
+        assert_eq!(
+            error("1: pitchbend ch1 val=0"),
+            MidiTextError::InvalidMessage {
+                line: 1,
+                text: "pitchbend ch1 val=0".to_string()
+            }
+        );
+        assert!(matches!(
+            error("protocol midi1"),
+            MidiTextError::InvalidProtocol { line: 1, .. }
+        ));
+
+        let error = |text: &str| EventBuffer::from_text(text).err().unwrap();
+        assert_eq!(
+            error("1: 10f80000"),
+            MidiTextError::MissingProtocol { line: 1 }
+        );
+        assert_eq!(error(""), MidiTextError::MissingProtocol { line: 0 });
+        assert_eq!(
+            error("protocol midi3"),
+            MidiTextError::InvalidProtocol {
+                line: 1,
+                text: "midi3".to_string()
+            }
+        );
+        assert_eq!(
+            error("protocol midi2\n1: 10f800"),
+            MidiTextError::InvalidMessage {
+                line: 2,
+                text: "10f800".to_string()
+            }
+        );
+    }
+}
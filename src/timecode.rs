@@ -0,0 +1,388 @@
+use std::fmt;
+
+use crate::sysex::{SYSEX_END, SYSEX_START};
+
+pub(crate) const QUARTER_FRAME: u8 = 0xF1;
+
+const FRAMES_PER_DAY_30: u32 = 24 * 60 * 60 * 30;
+const DROP_FRAMES_PER_10_MINUTES: u32 = 17982;
+const DROP_FRAMES_PER_MINUTE: u32 = 1798;
+
+/// The frame rate of a SMPTE time code.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FrameRate {
+    Fps24,
+    Fps25,
+    /// 29.97 frames per second, counted as 30 frames per second with drop frame numbering.
+    Fps30Drop,
+    Fps30,
+}
+
+impl FrameRate {
+    /// Get the rate from its 2-bit MIDI time code representation.
+    ///
+    pub fn from_code(code: u8) -> Self {
+        match code & 0x03 {
+            0 => Self::Fps24,
+            1 => Self::Fps25,
+            2 => Self::Fps30Drop,
+            _ => Self::Fps30,
+        }
+    }
+
+    /// Get the 2-bit MIDI time code representation of the rate.
+    ///
+    pub fn code(&self) -> u8 {
+        match self {
+            Self::Fps24 => 0,
+            Self::Fps25 => 1,
+            Self::Fps30Drop => 2,
+            Self::Fps30 => 3,
+        }
+    }
+
+    /// Get the number of frames used to count a second.
+    ///
+    pub fn nominal_fps(&self) -> u8 {
+        match self {
+            Self::Fps24 => 24,
+            Self::Fps25 => 25,
+            Self::Fps30Drop | Self::Fps30 => 30,
+        }
+    }
+
+    /// Get the actual number of frames per second.
+    ///
+    pub fn fps(&self) -> f64 {
+        match self {
+            Self::Fps30Drop => 30000.0 / 1001.0,
+            rate => rate.nominal_fps() as f64,
+        }
+    }
+
+    fn frames_per_day(&self) -> u32 {
+        match self {
+            Self::Fps30Drop => FRAMES_PER_DAY_30 - 24 * 6 * 18,
+            rate => 24 * 60 * 60 * rate.nominal_fps() as u32,
+        }
+    }
+}
+
+/// A SMPTE time code, with subframes in hundredths of frame.
+///
+/// ```
+/// use coremidi::{FrameRate, SmpteTime};
+/// let time = SmpteTime::new(0, 1, 0, 2, FrameRate::Fps30Drop);
+/// assert_eq!(time.frame_count(), 1800);
+/// assert_eq!(SmpteTime::from_frame_count(1799, FrameRate::Fps30Drop).to_string(), "00:00:59;29");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SmpteTime {
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+    pub frames: u8,
+    pub subframes: u8,
+    pub rate: FrameRate,
+}
+
+impl SmpteTime {
+    /// Create a time code without subframes.
+    ///
+    pub fn new(hours: u8, minutes: u8, seconds: u8, frames: u8, rate: FrameRate) -> Self {
+        Self {
+            hours,
+            minutes,
+            seconds,
+            frames,
+            subframes: 0,
+            rate,
+        }
+    }
+
+    /// Get the time code of a number of frames from `00:00:00:00`, wrapping at 24 hours.
+    ///
+    pub fn from_frame_count(count: u32, rate: FrameRate) -> Self {
+        let mut count = count % rate.frames_per_day();
+        if rate == FrameRate::Fps30Drop {
+            let tens = count / DROP_FRAMES_PER_10_MINUTES;
+            let rest = count % DROP_FRAMES_PER_10_MINUTES;
+            count += 18 * tens;
+            if rest >= 2 {
+                count += 2 * ((rest - 2) / DROP_FRAMES_PER_MINUTE);
+            }
+        }
+        let fps = rate.nominal_fps() as u32;
+        let seconds = count / fps;
+        Self::new(
+            (seconds / 3600) as u8,
+            (seconds / 60 % 60) as u8,
+            (seconds % 60) as u8,
+            (count % fps) as u8,
+            rate,
+        )
+    }
+
+    /// Get the number of frames from `00:00:00:00`, skipping the dropped frame numbers.
+    ///
+    pub fn frame_count(&self) -> u32 {
+        let fps = self.rate.nominal_fps() as u32;
+        let minutes = self.hours as u32 * 60 + self.minutes as u32;
+        let count = (minutes * 60 + self.seconds as u32) * fps + self.frames as u32;
+        match self.rate {
+            FrameRate::Fps30Drop => count - 2 * (minutes - minutes / 10),
+            _ => count,
+        }
+    }
+
+    /// Get the time code a number of frames later (or earlier), wrapping at 24 hours.
+    ///
+    pub fn add_frames(&self, frames: i64) -> Self {
+        let per_day = self.rate.frames_per_day() as i64;
+        let count = (self.frame_count() as i64 + frames).rem_euclid(per_day);
+        Self {
+            subframes: self.subframes,
+            ..Self::from_frame_count(count as u32, self.rate)
+        }
+    }
+
+    /// Get the time in seconds from `00:00:00:00`, including the subframes.
+    ///
+    pub fn as_secs_f64(&self) -> f64 {
+        (self.frame_count() as f64 + self.subframes as f64 / 100.0) / self.rate.fps()
+    }
+
+    /// Get the hours byte with the frame rate in bits 5 and 6, as used by MTC and MMC.
+    ///
+    pub(crate) fn hours_byte(&self) -> u8 {
+        self.rate.code() << 5 | (self.hours & 0x1F)
+    }
+
+    /// Create a time code from the `hr mn sc fr` bytes used by MTC and MMC.
+    ///
+    pub(crate) fn from_bytes(hr: u8, mn: u8, sc: u8, fr: u8) -> Self {
+        Self::new(
+            hr & 0x1F,
+            mn & 0x3F,
+            sc & 0x3F,
+            fr & 0x1F,
+            FrameRate::from_code(hr >> 5),
+        )
+    }
+
+    /// Get the `hr mn sc fr ff` bytes of the standard time code used by MMC and MSC.
+    ///
+    pub(crate) fn standard_bytes(&self) -> [u8; 5] {
+        [
+            self.hours_byte(),
+            self.minutes & 0x3F,
+            self.seconds & 0x3F,
+            self.frames & 0x1F,
+            self.subframes.min(99),
+        ]
+    }
+
+    /// Create a time code from the `hr mn sc fr ff` bytes of a standard time code.
+    ///
+    pub(crate) fn from_standard_bytes(hr: u8, mn: u8, sc: u8, fr: u8, ff: u8) -> Self {
+        Self {
+            subframes: ff & 0x7F,
+            ..Self::from_bytes(hr, mn, sc, fr)
+        }
+    }
+}
+
+impl fmt::Display for SmpteTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let separator = if self.rate == FrameRate::Fps30Drop {
+            ';'
+        } else {
+            ':'
+        };
+        write!(
+            f,
+            "{:02}:{:02}:{:02}{}{:02}",
+            self.hours, self.minutes, self.seconds, separator, self.frames
+        )
+    }
+}
+
+/// A MIDI Time Code message.
+///
+/// A full time code is sent either as a full frame SysEx message, or spread along
+/// 8 quarter frame messages while the time code is running.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MtcMessage {
+    /// One of the 8 pieces of a time code, with its 4-bit value.
+    QuarterFrame { piece: u8, value: u8 },
+    /// A full time code, usually sent when locating.
+    FullFrame(SmpteTime),
+}
+
+impl MtcMessage {
+    /// Create the quarter frame message with a piece of a time code.
+    ///
+    pub fn quarter_frame(time: &SmpteTime, piece: u8) -> Self {
+        let piece = piece & 0x07;
+        let value = match piece {
+            0 => time.frames & 0x0F,
+            1 => (time.frames >> 4) & 0x01,
+            2 => time.seconds & 0x0F,
+            3 => (time.seconds >> 4) & 0x03,
+            4 => time.minutes & 0x0F,
+            5 => (time.minutes >> 4) & 0x03,
+            6 => time.hours & 0x0F,
+            _ => time.rate.code() << 1 | ((time.hours >> 4) & 0x01),
+        };
+        Self::QuarterFrame { piece, value }
+    }
+
+    /// Encode the message as MIDI 1.0 bytes.
+    ///
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            Self::QuarterFrame { piece, value } => {
+                vec![QUARTER_FRAME, (piece & 0x07) << 4 | (value & 0x0F)]
+            }
+            Self::FullFrame(time) => vec![
+                SYSEX_START,
+                0x7F,
+                0x7F,
+                0x01,
+                0x01,
+                time.hours_byte(),
+                time.minutes,
+                time.seconds,
+                time.frames,
+                SYSEX_END,
+            ],
+        }
+    }
+
+    /// Decode a complete quarter frame or full frame message.
+    ///
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        match bytes {
+            [QUARTER_FRAME, data] if *data < 0x80 => Some(Self::QuarterFrame {
+                piece: data >> 4,
+                value: data & 0x0F,
+            }),
+            [SYSEX_START, 0x7F, _, 0x01, 0x01, hr, mn, sc, fr, SYSEX_END] => {
+                Some(Self::FullFrame(SmpteTime::from_bytes(*hr, *mn, *sc, *fr)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Find the quarter frame messages in some MIDI 1.0 bytes.
+    ///
+    pub(crate) fn quarter_frames(bytes: &[u8]) -> impl Iterator<Item = Self> + '_ {
+        bytes.windows(2).filter_map(|window| match window {
+            [QUARTER_FRAME, _] => Self::decode(window),
+            _ => None,
+        })
+    }
+}
+
+/// Collects the 8 quarter frame pieces of a time code.
+///
+#[derive(Debug, Clone, Default)]
+pub(crate) struct QuarterFrames {
+    values: [u8; 8],
+    received: u8,
+}
+
+impl QuarterFrames {
+    pub(crate) fn reset(&mut self) {
+        self.received = 0;
+    }
+
+    /// Store a piece, and get the time code when the last piece of a complete sequence arrives.
+    /// The time code is the one of the frame where piece 0 was sent.
+    ///
+    pub(crate) fn push(&mut self, piece: u8, value: u8) -> Option<SmpteTime> {
+        self.push_directed(piece, value, false)
+    }
+
+    /// Like [QuarterFrames::push], but the pieces of a sequence can be sent from 7 down to 0
+    /// when the time code runs in reverse.
+    ///
+    pub(crate) fn push_directed(
+        &mut self,
+        piece: u8,
+        value: u8,
+        reverse: bool,
+    ) -> Option<SmpteTime> {
+        let (first, last) = if reverse { (7, 0) } else { (0, 7) };
+        let piece = (piece & 0x07) as usize;
+        if piece == first {
+            self.received = 0;
+        }
+        self.values[piece] = value & 0x0F;
+        self.received |= 1 << piece;
+        if piece != last || self.received != 0xFF {
+            return None;
+        }
+        let v = &self.values;
+        Some(SmpteTime::new(
+            (v[7] & 0x01) << 4 | v[6],
+            (v[5] & 0x03) << 4 | v[4],
+            (v[3] & 0x03) << 4 | v[2],
+            (v[1] & 0x01) << 4 | v[0],
+            FrameRate::from_code(v[7] >> 1),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drop_frame_counts() {
+        for rate in [
+            FrameRate::Fps24,
+            FrameRate::Fps25,
+            FrameRate::Fps30Drop,
+            FrameRate::Fps30,
+        ] {
+            for count in (0..rate.frames_per_day()).step_by(997) {
+                assert_eq!(
+                    SmpteTime::from_frame_count(count, rate).frame_count(),
+                    count
+                );
+            }
+        }
+        let time = SmpteTime::from_frame_count(17982, FrameRate::Fps30Drop);
+        assert_eq!(time, SmpteTime::new(0, 10, 0, 0, FrameRate::Fps30Drop));
+        let time = SmpteTime::new(23, 59, 59, 29, FrameRate::Fps30Drop).add_frames(1);
+        assert_eq!(time, SmpteTime::new(0, 0, 0, 0, FrameRate::Fps30Drop));
+    }
+
+    #[test]
+    fn quarter_frames_round_trip() {
+        let time = SmpteTime::new(17, 42, 33, 28, FrameRate::Fps30);
+        let mut quarter_frames = QuarterFrames::default();
+        let mut decoded = None;
+        for piece in 0..8 {
+            let bytes = MtcMessage::quarter_frame(&time, piece).encode();
+            match MtcMessage::decode(&bytes) {
+                Some(MtcMessage::QuarterFrame { piece, value }) => {
+                    decoded = quarter_frames.push(piece, value)
+                }
+                _ => panic!("Expected a quarter frame"),
+            }
+        }
+        assert_eq!(decoded, Some(time));
+    }
+
+    #[test]
+    fn full_frame_round_trip() {
+        let message = MtcMessage::FullFrame(SmpteTime::new(1, 2, 3, 4, FrameRate::Fps25));
+        let bytes = message.encode();
+        assert_eq!(bytes[5], 0x21);
+        assert_eq!(MtcMessage::decode(&bytes), Some(message));
+    }
+}
@@ -0,0 +1,759 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::events::{EventBuffer, Timestamp};
+use crate::packets::PacketBuffer;
+use crate::protocol::Protocol;
+use crate::sysex::{SYSEX_END, SYSEX_START};
+
+const NON_REAL_TIME: u8 = 0x7E;
+const REAL_TIME: u8 = 0x7F;
+const MIDI_TUNING: u8 = 0x08;
+
+const BULK_DUMP_REQUEST: u8 = 0x00;
+const BULK_DUMP: u8 = 0x01;
+const NOTE_CHANGE: u8 = 0x02;
+const NOTE_CHANGE_WITH_BANK: u8 = 0x07;
+const SCALE_OCTAVE_1_BYTE: u8 = 0x08;
+const SCALE_OCTAVE_2_BYTE: u8 = 0x09;
+
+const NAME_LEN: usize = 16;
+const FRACTION_STEPS: f64 = 16384.0;
+
+/// The tuning of a single note, as an absolute pitch with 14-bit precision within a semitone.
+///
+/// The pitch is given as the equal tempered semitone right below it, plus a fraction of
+/// semitone in units of 100/16384 cents (about 0.0061 cents).
+///
+/// ```
+/// use coremidi::NoteTuning;
+/// let a4 = NoteTuning::from_frequency(440.0);
+/// assert_eq!((a4.semitone, a4.fraction), (69, 0));
+/// assert_eq!(NoteTuning::from_cents(6050.0), NoteTuning { semitone: 60, fraction: 8192 });
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NoteTuning {
+    pub semitone: u8,
+    pub fraction: u16,
+}
+
+impl NoteTuning {
+    /// The reserved value (`7F 7F 7F`) meaning that the tuning of a note must not change.
+    pub const NO_CHANGE: NoteTuning = NoteTuning {
+        semitone: 0x7F,
+        fraction: 0x3FFF,
+    };
+
+    /// Get the equal tempered tuning of a MIDI note.
+    ///
+    pub fn equal_tempered(key: u8) -> Self {
+        Self {
+            semitone: key & 0x7F,
+            fraction: 0,
+        }
+    }
+
+    /// Create a tuning from an absolute pitch in cents above MIDI note 0,
+    /// rounded to the nearest step and clamped to the valid range.
+    ///
+    pub fn from_cents(cents: f64) -> Self {
+        let steps = (cents.max(0.0) * FRACTION_STEPS / 100.0).round() as u32;
+        let steps = steps.min(0x7F << 14 | 0x3FFE);
+        Self {
+            semitone: (steps >> 14) as u8,
+            fraction: (steps & 0x3FFF) as u16,
+        }
+    }
+
+    /// Create a tuning from a frequency in Hz, taking A4 (note 69) as 440 Hz.
+    ///
+    pub fn from_frequency(frequency: f64) -> Self {
+        Self::from_cents((69.0 + 12.0 * (frequency / 440.0).log2()) * 100.0)
+    }
+
+    /// Get the absolute pitch in cents above MIDI note 0.
+    ///
+    pub fn cents(&self) -> f64 {
+        self.semitone as f64 * 100.0 + self.fraction as f64 * 100.0 / FRACTION_STEPS
+    }
+
+    /// Get the frequency in Hz, taking A4 (note 69) as 440 Hz.
+    ///
+    pub fn frequency(&self) -> f64 {
+        440.0 * ((self.cents() / 100.0 - 69.0) / 12.0).exp2()
+    }
+
+    /// Check whether it is the reserved [NoteTuning::NO_CHANGE] value.
+    ///
+    pub fn is_no_change(&self) -> bool {
+        *self == Self::NO_CHANGE
+    }
+
+    fn push_bytes(&self, bytes: &mut Vec<u8>) {
+        bytes.extend([
+            self.semitone & 0x7F,
+            ((self.fraction >> 7) & 0x7F) as u8,
+            (self.fraction & 0x7F) as u8,
+        ]);
+    }
+
+    fn from_bytes(xx: u8, yy: u8, zz: u8) -> Self {
+        Self {
+            semitone: xx,
+            fraction: (yy as u16) << 7 | zz as u16,
+        }
+    }
+}
+
+/// The resolution used to transmit the offsets of a scale/octave tuning.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScaleResolution {
+    /// One byte per note, in 1 cent steps from -64 to +63 cents.
+    OneByte,
+    /// Two bytes per note, in 100/8192 cent steps from -100 to +100 cents.
+    TwoByte,
+}
+
+/// A message of the MIDI Tuning Standard.
+///
+/// It can be encoded into SysEx bytes with [TuningMessage::encode], or directly into a
+/// [PacketBuffer] for MIDI 1.0 destinations or an [EventBuffer] with SysEx7 packets for
+/// UMP destinations.
+///
+/// ```
+/// use coremidi::{NoteTuning, Timestamp, TuningMessage};
+/// let message = TuningMessage::NoteChange {
+///     device_id: 0x7f,
+///     realtime: true,
+///     bank: None,
+///     program: 0,
+///     changes: vec![(60, NoteTuning::from_cents(6025.0))],
+/// };
+/// let buffer = message.to_packet_buffer(Timestamp::Now);
+/// assert_eq!(
+///     buffer.iter().next().unwrap().data(),
+///     &[0xf0, 0x7f, 0x7f, 0x08, 0x02, 0x00, 0x01, 0x3c, 0x3c, 0x20, 0x00, 0xf7]
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum TuningMessage {
+    /// Ask a device to send the bulk dump of a tuning program.
+    BulkDumpRequest { device_id: u8, program: u8 },
+    /// The tuning of all 128 notes of a tuning program.
+    BulkDump {
+        device_id: u8,
+        program: u8,
+        name: String,
+        notes: Vec<NoteTuning>,
+    },
+    /// Change the tuning of some notes of a tuning program.
+    /// When there is no bank it can only be sent as a real-time message.
+    NoteChange {
+        device_id: u8,
+        realtime: bool,
+        bank: Option<u8>,
+        program: u8,
+        changes: Vec<(u8, NoteTuning)>,
+    },
+    /// Offsets in cents from equal temperament for the 12 notes of the octave, starting at C,
+    /// applied to all the channels set in the `channels` bit mask (bit 0 is channel 1).
+    ScaleOctave {
+        device_id: u8,
+        realtime: bool,
+        channels: u16,
+        resolution: ScaleResolution,
+        offsets: [f64; 12],
+    },
+}
+
+impl TuningMessage {
+    /// Encode the message as SysEx bytes.
+    ///
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(408);
+        bytes.push(SYSEX_START);
+        match self {
+            Self::BulkDumpRequest { device_id, program } => {
+                bytes.extend([NON_REAL_TIME, device_id & 0x7F, MIDI_TUNING]);
+                bytes.extend([BULK_DUMP_REQUEST, program & 0x7F]);
+            }
+            Self::BulkDump {
+                device_id,
+                program,
+                name,
+                notes,
+            } => {
+                bytes.extend([NON_REAL_TIME, device_id & 0x7F, MIDI_TUNING]);
+                bytes.extend([BULK_DUMP, program & 0x7F]);
+                let mut name_bytes = name
+                    .chars()
+                    .map(|c| {
+                        if c.is_ascii() && !c.is_ascii_control() {
+                            c as u8
+                        } else {
+                            b'?'
+                        }
+                    })
+                    .take(NAME_LEN)
+                    .collect::<Vec<u8>>();
+                name_bytes.resize(NAME_LEN, b' ');
+                bytes.extend(name_bytes);
+                for key in 0..128 {
+                    notes
+                        .get(key)
+                        .copied()
+                        .unwrap_or(NoteTuning::NO_CHANGE)
+                        .push_bytes(&mut bytes);
+                }
+                let checksum = bytes[1..].iter().fold(0, |acc, b| acc ^ b) & 0x7F;
+                bytes.push(checksum);
+            }
+            Self::NoteChange {
+                device_id,
+                realtime,
+                bank,
+                program,
+                changes,
+            } => {
+                match (realtime, bank) {
+                    (true, None) => {
+                        bytes.extend([REAL_TIME, device_id & 0x7F, MIDI_TUNING, NOTE_CHANGE]);
+                    }
+                    (realtime, bank) => {
+                        let sub_id = if *realtime { REAL_TIME } else { NON_REAL_TIME };
+                        bytes.extend([sub_id, device_id & 0x7F, MIDI_TUNING]);
+                        bytes.extend([NOTE_CHANGE_WITH_BANK, bank.unwrap_or(0) & 0x7F]);
+                    }
+                }
+                let changes = &changes[..changes.len().min(127)];
+                bytes.extend([program & 0x7F, changes.len() as u8]);
+                for (key, tuning) in changes {
+                    bytes.push(key & 0x7F);
+                    tuning.push_bytes(&mut bytes);
+                }
+            }
+            Self::ScaleOctave {
+                device_id,
+                realtime,
+                channels,
+                resolution,
+                offsets,
+            } => {
+                let sub_id = if *realtime { REAL_TIME } else { NON_REAL_TIME };
+                let format = match resolution {
+                    ScaleResolution::OneByte => SCALE_OCTAVE_1_BYTE,
+                    ScaleResolution::TwoByte => SCALE_OCTAVE_2_BYTE,
+                };
+                bytes.extend([sub_id, device_id & 0x7F, MIDI_TUNING, format]);
+                bytes.extend([
+                    ((channels >> 14) & 0x03) as u8,
+                    ((channels >> 7) & 0x7F) as u8,
+                    (channels & 0x7F) as u8,
+                ]);
+                for offset in offsets {
+                    match resolution {
+                        ScaleResolution::OneByte => {
+                            bytes.push((offset.round() + 64.0).max(0.0).min(127.0) as u8)
+                        }
+                        ScaleResolution::TwoByte => {
+                            let value = (offset / 100.0 * 8192.0 + 8192.0)
+                                .round()
+                                .max(0.0)
+                                .min(16383.0) as u16;
+                            bytes.extend([(value >> 7) as u8, (value & 0x7F) as u8]);
+                        }
+                    }
+                }
+            }
+        }
+        bytes.push(SYSEX_END);
+        bytes
+    }
+
+    /// Decode a complete SysEx message, including its `F0` and `F7` bytes.
+    ///
+    pub fn decode(bytes: &[u8]) -> Result<Self, TuningError> {
+        let (sub_id, device_id, body) = match bytes {
+            [SYSEX_START, sub_id @ (NON_REAL_TIME | REAL_TIME), device_id, MIDI_TUNING, body @ .., SYSEX_END] => {
+                (*sub_id, *device_id, body)
+            }
+            _ => return Err(TuningError::InvalidMessage),
+        };
+        if bytes[1..bytes.len() - 1].iter().any(|b| *b > 0x7F) {
+            return Err(TuningError::InvalidMessage);
+        }
+        let realtime = sub_id == REAL_TIME;
+        match (sub_id, body) {
+            (NON_REAL_TIME, [BULK_DUMP_REQUEST, program]) => Ok(Self::BulkDumpRequest {
+                device_id,
+                program: *program,
+            }),
+            (NON_REAL_TIME, [BULK_DUMP, program, rest @ ..]) if rest.len() == NAME_LEN + 385 => {
+                let checksum = bytes[1..bytes.len() - 2].iter().fold(0, |acc, b| acc ^ b) & 0x7F;
+                if checksum != rest[rest.len() - 1] {
+                    return Err(TuningError::InvalidChecksum);
+                }
+                let name = String::from_utf8_lossy(&rest[..NAME_LEN])
+                    .trim_end()
+                    .to_string();
+                let notes = rest[NAME_LEN..rest.len() - 1]
+                    .chunks_exact(3)
+                    .map(|b| NoteTuning::from_bytes(b[0], b[1], b[2]))
+                    .collect();
+                Ok(Self::BulkDump {
+                    device_id,
+                    program: *program,
+                    name,
+                    notes,
+                })
+            }
+            (REAL_TIME, [NOTE_CHANGE, program, count, changes @ ..]) => Ok(Self::NoteChange {
+                device_id,
+                realtime,
+                bank: None,
+                program: *program,
+                changes: Self::decode_changes(*count, changes)?,
+            }),
+            (_, [NOTE_CHANGE_WITH_BANK, bank, program, count, changes @ ..]) => {
+                Ok(Self::NoteChange {
+                    device_id,
+                    realtime,
+                    bank: Some(*bank),
+                    program: *program,
+                    changes: Self::decode_changes(*count, changes)?,
+                })
+            }
+            (_, [SCALE_OCTAVE_1_BYTE, ff, gg, hh, values @ ..]) if values.len() == 12 => {
+                let mut offsets = [0.0; 12];
+                for (offset, value) in offsets.iter_mut().zip(values) {
+                    *offset = *value as f64 - 64.0;
+                }
+                Ok(Self::ScaleOctave {
+                    device_id,
+                    realtime,
+                    channels: Self::decode_channels(*ff, *gg, *hh),
+                    resolution: ScaleResolution::OneByte,
+                    offsets,
+                })
+            }
+            (_, [SCALE_OCTAVE_2_BYTE, ff, gg, hh, values @ ..]) if values.len() == 24 => {
+                let mut offsets = [0.0; 12];
+                for (offset, value) in offsets.iter_mut().zip(values.chunks_exact(2)) {
+                    let value = (value[0] as u16) << 7 | value[1] as u16;
+                    *offset = (value as f64 - 8192.0) * 100.0 / 8192.0;
+                }
+                Ok(Self::ScaleOctave {
+                    device_id,
+                    realtime,
+                    channels: Self::decode_channels(*ff, *gg, *hh),
+                    resolution: ScaleResolution::TwoByte,
+                    offsets,
+                })
+            }
+            _ => Err(TuningError::InvalidMessage),
+        }
+    }
+
+    /// Create a `PacketBuffer` with the message, for MIDI 1.0 destinations.
+    ///
+    pub fn to_packet_buffer(&self, timestamp: Timestamp) -> PacketBuffer {
+        PacketBuffer::new(timestamp, &self.encode())
+    }
+
+    /// Create an `EventBuffer` with the message as SysEx7 packets in the given group,
+    /// for UMP destinations.
+    ///
+    pub fn to_event_buffer(
+        &self,
+        protocol: Protocol,
+        timestamp: Timestamp,
+        group: u8,
+    ) -> EventBuffer {
+        let mut buffer = EventBuffer::new(protocol);
+        buffer.push_sysex7(timestamp, group, &self.encode());
+        buffer
+    }
+
+    fn decode_changes(count: u8, bytes: &[u8]) -> Result<Vec<(u8, NoteTuning)>, TuningError> {
+        if bytes.len() != count as usize * 4 {
+            return Err(TuningError::InvalidMessage);
+        }
+        Ok(bytes
+            .chunks_exact(4)
+            .map(|b| (b[0], NoteTuning::from_bytes(b[1], b[2], b[3])))
+            .collect())
+    }
+
+    fn decode_channels(ff: u8, gg: u8, hh: u8) -> u16 {
+        ((ff & 0x03) as u16) << 14 | ((gg & 0x7F) as u16) << 7 | (hh & 0x7F) as u16
+    }
+}
+
+/// An error decoding a MIDI Tuning Standard message.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TuningError {
+    /// The message is not a well formed MIDI Tuning Standard message.
+    InvalidMessage,
+    /// The checksum of a bulk dump doesn't match its contents.
+    InvalidChecksum,
+}
+
+impl fmt::Display for TuningError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidMessage => write!(f, "invalid MIDI tuning message"),
+            Self::InvalidChecksum => write!(f, "invalid MIDI tuning bulk dump checksum"),
+        }
+    }
+}
+
+impl std::error::Error for TuningError {}
+
+/// The tuning of the 128 MIDI notes.
+///
+/// ```
+/// use coremidi::{NoteTuning, TuningTable};
+/// let mut table = TuningTable::default();
+/// table.set_note(60, NoteTuning::from_cents(5950.0));
+/// let dump = table.bulk_dump(0x7f, 3);
+/// let mut received = TuningTable::default();
+/// received.apply(&dump);
+/// assert_eq!(received.note(60), NoteTuning { semitone: 59, fraction: 8192 });
+/// assert!((received.frequency(69) - 440.0).abs() < 1e-9);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TuningTable {
+    name: String,
+    notes: [NoteTuning; 128],
+}
+
+impl TuningTable {
+    /// Create a table with the 12-tone equal temperament.
+    ///
+    pub fn equal_temperament() -> Self {
+        let mut notes = [NoteTuning::equal_tempered(0); 128];
+        for (key, note) in notes.iter_mut().enumerate() {
+            *note = NoteTuning::equal_tempered(key as u8);
+        }
+        Self {
+            name: String::new(),
+            notes,
+        }
+    }
+
+    /// Create a table from the frequencies in Hz of the notes, starting at note 0.
+    /// Missing notes keep their equal tempered tuning.
+    ///
+    pub fn from_frequencies(frequencies: &[f64]) -> Self {
+        let mut table = Self::equal_temperament();
+        for (note, frequency) in table.notes.iter_mut().zip(frequencies) {
+            *note = NoteTuning::from_frequency(*frequency);
+        }
+        table
+    }
+
+    /// Get the name of the tuning.
+    ///
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Set the name of the tuning. Only 16 ASCII characters are transmitted.
+    ///
+    pub fn set_name(&mut self, name: &str) {
+        self.name = name.to_string();
+    }
+
+    /// Get the tuning of a note.
+    ///
+    pub fn note(&self, key: u8) -> NoteTuning {
+        self.notes[(key & 0x7F) as usize]
+    }
+
+    /// Set the tuning of a note. [NoteTuning::NO_CHANGE] is ignored.
+    ///
+    pub fn set_note(&mut self, key: u8, tuning: NoteTuning) {
+        if !tuning.is_no_change() {
+            self.notes[(key & 0x7F) as usize] = tuning;
+        }
+    }
+
+    /// Get the frequency in Hz of a note.
+    ///
+    pub fn frequency(&self, key: u8) -> f64 {
+        self.note(key).frequency()
+    }
+
+    /// Get the frequencies in Hz of all the notes.
+    ///
+    pub fn frequencies(&self) -> Vec<f64> {
+        self.notes.iter().map(|note| note.frequency()).collect()
+    }
+
+    /// Update the table from a bulk dump or a note change message.
+    /// It returns whether the message changed the table.
+    ///
+    pub fn apply(&mut self, message: &TuningMessage) -> bool {
+        match message {
+            TuningMessage::BulkDump { name, notes, .. } => {
+                self.name = name.clone();
+                for (key, tuning) in notes.iter().enumerate().take(128) {
+                    self.set_note(key as u8, *tuning);
+                }
+                true
+            }
+            TuningMessage::NoteChange { changes, .. } => {
+                for (key, tuning) in changes {
+                    self.set_note(*key, *tuning);
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Create the bulk dump message for this table.
+    ///
+    pub fn bulk_dump(&self, device_id: u8, program: u8) -> TuningMessage {
+        TuningMessage::BulkDump {
+            device_id,
+            program,
+            name: self.name.clone(),
+            notes: self.notes.to_vec(),
+        }
+    }
+}
+
+impl Default for TuningTable {
+    fn default() -> Self {
+        Self::equal_temperament()
+    }
+}
+
+/// The tuning state of a receiver: the tuning tables of every bank and program,
+/// and the scale/octave offsets of every channel.
+///
+/// ```
+/// use coremidi::{ScaleResolution, TuningMessage, TuningPrograms};
+/// let mut programs = TuningPrograms::new();
+/// let mut offsets = [0.0; 12];
+/// offsets[9] = -10.0;
+/// programs.apply(&TuningMessage::ScaleOctave {
+///     device_id: 0x7f, realtime: true, channels: 0x0001,
+///     resolution: ScaleResolution::OneByte, offsets,
+/// });
+/// assert!((programs.frequency(0, 0, 0, 69) - 437.46).abs() < 0.01);
+/// assert_eq!(programs.frequency(1, 0, 0, 69), 440.0);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct TuningPrograms {
+    tables: HashMap<(u8, u8), TuningTable>,
+    scale_offsets: [[f64; 12]; 16],
+}
+
+impl TuningPrograms {
+    /// Create an empty set of programs, where every note is equal tempered.
+    ///
+    pub fn new() -> Self {
+        Self {
+            tables: HashMap::new(),
+            scale_offsets: [[0.0; 12]; 16],
+        }
+    }
+
+    /// Get the table of a program, if it has ever been set.
+    ///
+    pub fn table(&self, bank: u8, program: u8) -> Option<&TuningTable> {
+        self.tables.get(&(bank, program))
+    }
+
+    /// Get the table of a program for modification, creating it equal tempered if needed.
+    ///
+    pub fn table_mut(&mut self, bank: u8, program: u8) -> &mut TuningTable {
+        self.tables.entry((bank, program)).or_default()
+    }
+
+    /// Get the scale/octave offsets in cents of a channel, starting at 0 for channel 1.
+    ///
+    pub fn scale_offsets(&self, channel: u8) -> [f64; 12] {
+        self.scale_offsets[(channel & 0x0F) as usize]
+    }
+
+    /// Get the frequency of a note for a channel using a tuning program.
+    ///
+    pub fn frequency(&self, channel: u8, bank: u8, program: u8, key: u8) -> f64 {
+        let frequency = match self.table(bank, program) {
+            Some(table) => table.frequency(key),
+            None => NoteTuning::equal_tempered(key).frequency(),
+        };
+        let offset = self.scale_offsets(channel)[(key % 12) as usize];
+        frequency * (offset / 1200.0).exp2()
+    }
+
+    /// Update the state from any tuning message. Bulk dumps update the programs of bank 0.
+    /// It returns whether the message changed the state.
+    ///
+    pub fn apply(&mut self, message: &TuningMessage) -> bool {
+        match message {
+            TuningMessage::BulkDump { program, .. } => self.table_mut(0, *program).apply(message),
+            TuningMessage::NoteChange { bank, program, .. } => {
+                self.table_mut(bank.unwrap_or(0), *program).apply(message)
+            }
+            TuningMessage::ScaleOctave {
+                channels, offsets, ..
+            } => {
+                for (channel, scale_offsets) in self.scale_offsets.iter_mut().enumerate() {
+                    if channels & (1 << channel) != 0 {
+                        *scale_offsets = *offsets;
+                    }
+                }
+                true
+            }
+            TuningMessage::BulkDumpRequest { .. } => false,
+        }
+    }
+}
+
+impl Default for TuningPrograms {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::sysex::SysExAssembler;
+
+    #[test]
+    fn note_tuning_cents() {
+        assert_eq!(NoteTuning::from_cents(0.0), NoteTuning::equal_tempered(0));
+        assert_eq!(
+            NoteTuning::from_cents(6099.9999),
+            NoteTuning::equal_tempered(61)
+        );
+        assert_eq!(NoteTuning::from_cents(-5.0), NoteTuning::equal_tempered(0));
+        assert_ne!(NoteTuning::from_cents(20000.0), NoteTuning::NO_CHANGE);
+        let tuning = NoteTuning::from_cents(6012.3456);
+        assert!((tuning.cents() - 6012.3456).abs() < 100.0 / FRACTION_STEPS);
+    }
+
+    #[test]
+    fn bulk_dump_round_trip() {
+        let mut table = TuningTable::from_frequencies(&[8.0, 9.0, 10.0]);
+        table.set_name("Just intonation in C");
+        let message = table.bulk_dump(0x10, 5);
+        let bytes = message.encode();
+        assert_eq!(bytes.len(), 408);
+        assert_eq!(&bytes[6..22], b"Just intonation ");
+
+        let decoded = TuningMessage::decode(&bytes).unwrap();
+        let mut received = TuningTable::default();
+        assert!(received.apply(&decoded));
+        assert_eq!(received.name(), "Just intonation");
+        assert_eq!(received.note(1), table.note(1));
+        assert_eq!(received.note(100), NoteTuning::equal_tempered(100));
+    }
+
+    #[test]
+    fn bulk_dump_invalid_checksum() {
+        let mut bytes = TuningTable::default().bulk_dump(0, 0).encode();
+        bytes[100] ^= 0x01;
+        assert_eq!(
+            TuningMessage::decode(&bytes),
+            Err(TuningError::InvalidChecksum)
+        );
+    }
+
+    #[test]
+    fn note_change_round_trip() {
+        for (realtime, bank) in [(true, None), (true, Some(2)), (false, Some(3))] {
+            let message = TuningMessage::NoteChange {
+                device_id: 1,
+                realtime,
+                bank,
+                program: 4,
+                changes: vec![
+                    (60, NoteTuning::from_cents(6010.0)),
+                    (61, NoteTuning::NO_CHANGE),
+                ],
+            };
+            assert_eq!(TuningMessage::decode(&message.encode()), Ok(message));
+        }
+    }
+
+    #[test]
+    fn scale_octave_round_trip() {
+        let mut offsets = [0.0; 12];
+        offsets[0] = -64.0;
+        offsets[4] = -13.6865234375;
+        offsets[7] = 1.953125;
+        offsets[11] = 63.0;
+        let one_byte = TuningMessage::ScaleOctave {
+            device_id: 0x7F,
+            realtime: false,
+            channels: 0xC081,
+            resolution: ScaleResolution::OneByte,
+            offsets,
+        };
+        let bytes = one_byte.encode();
+        assert_eq!(&bytes[5..8], &[0x03, 0x01, 0x01]);
+        match TuningMessage::decode(&bytes).unwrap() {
+            TuningMessage::ScaleOctave {
+                channels, offsets, ..
+            } => {
+                assert_eq!(channels, 0xC081);
+                assert_eq!(offsets[4], -14.0);
+                assert_eq!(offsets[7], 2.0);
+            }
+            _ => panic!("Expected a scale/octave message"),
+        }
+
+        let mut offsets = [0.0; 12];
+        offsets[0] = -100.0;
+        offsets[4] = -12.5;
+        offsets[7] = 1.953125;
+        let two_byte = TuningMessage::ScaleOctave {
+            device_id: 0x7F,
+            realtime: false,
+            channels: 0xC081,
+            resolution: ScaleResolution::TwoByte,
+            offsets,
+        };
+        assert_eq!(TuningMessage::decode(&two_byte.encode()), Ok(two_byte));
+    }
+
+    #[test]
+    fn event_buffer_sysex7() {
+        let message = TuningMessage::BulkDumpRequest {
+            device_id: 0x7F,
+            program: 9,
+        };
+        let buffer = message.to_event_buffer(Protocol::Midi20, Timestamp::Now, 1);
+        let mut assembler = SysExAssembler::new();
+        let mut decoded = Vec::new();
+        assembler.push_event_list(&buffer, |bytes| {
+            decoded.push(TuningMessage::decode(bytes).unwrap())
+        });
+        assert_eq!(decoded, vec![message]);
+    }
+
+    #[test]
+    fn programs_apply() {
+        let mut programs = TuningPrograms::new();
+        programs.apply(&TuningMessage::NoteChange {
+            device_id: 0,
+            realtime: true,
+            bank: Some(1),
+            program: 2,
+            changes: vec![(69, NoteTuning::from_frequency(432.0))],
+        });
+        assert!((programs.frequency(0, 1, 2, 69) - 432.0).abs() < 0.001);
+        assert_eq!(programs.frequency(0, 0, 2, 69), 440.0);
+        assert!(programs.table(1, 2).is_some());
+        assert!(programs.table(0, 2).is_none());
+    }
+}